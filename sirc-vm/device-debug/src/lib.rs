@@ -2,14 +2,17 @@ use std::any::Any;
 
 use log::debug;
 use peripheral_bus::memory_mapped_device::MemoryMapped;
+use peripheral_bus::save_state::{decode_state, encode_state, SaveStateError};
 use peripheral_bus::{
     device::BusAssertions, device::Device, memory_mapped_device::MemoryMappedDevice,
 };
+use serde::{Deserialize, Serialize};
 
 ///
 /// A device that just exists to do integration tests on the VM
 /// Allows programs to assert interrupts etc. when they need to
 ///
+#[derive(Serialize, Deserialize)]
 pub struct DebugDevice {
     pub trigger_bus_error: bool,
     pub trigger_protection_error: bool,
@@ -60,6 +63,13 @@ impl Device for DebugDevice {
         }
         io_assertions
     }
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        *self = decode_state(state)?;
+        Ok(())
+    }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
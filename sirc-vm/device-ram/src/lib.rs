@@ -8,9 +8,11 @@ use std::{
 use log::{trace, warn};
 use memmap::{MmapMut, MmapOptions};
 use peripheral_bus::memory_mapped_device::MemoryMapped;
+use peripheral_bus::save_state::{decode_state, encode_state, SaveStateError};
 use peripheral_bus::{
     device::BusAssertions, device::Device, memory_mapped_device::MemoryMappedDevice,
 };
+use serde::{Deserialize, Serialize};
 
 pub enum SegmentMemCell {
     // At the moment, all raw segments get the maximum allowable of memory allocated
@@ -32,6 +34,14 @@ pub struct RamDevice {
     clocks_remaining: u32,
 }
 
+/// Everything needed to restore a `RamDevice` from a save state
+#[derive(Serialize, Deserialize)]
+struct RamDeviceState {
+    memory: Vec<u8>,
+    active_request: Option<BusAssertions>,
+    clocks_remaining: u32,
+}
+
 #[must_use]
 pub fn new_ram_device_standard() -> RamDevice {
     new_ram_device_with_latency(1)
@@ -113,6 +123,28 @@ impl Device for RamDevice {
             BusAssertions::default()
         }
     }
+    fn save_state(&self) -> Vec<u8> {
+        let cell = self.mem_cell.borrow();
+        let raw_memory: &[u8] = match *cell {
+            SegmentMemCell::RawMemory(ref mem) => &mem[..],
+            SegmentMemCell::FileMapped(_, ref mmap) => &mmap[..],
+        };
+        encode_state(&RamDeviceState {
+            memory: raw_memory.to_vec(),
+            active_request: self.active_request,
+            clocks_remaining: self.clocks_remaining,
+        })
+    }
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let state: RamDeviceState = decode_state(state)?;
+        // File mapped memory is left as it is, writing to it would change the file on disk
+        if matches!(*self.mem_cell.borrow(), SegmentMemCell::RawMemory(_)) {
+            self.write_raw_bytes(&state.memory);
+        }
+        self.active_request = state.active_request;
+        self.clocks_remaining = state.clocks_remaining;
+        Ok(())
+    }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
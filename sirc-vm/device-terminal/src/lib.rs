@@ -13,6 +13,8 @@ use log::error;
 use peripheral_bus::device::BusAssertions;
 use peripheral_bus::device::Device;
 use peripheral_bus::memory_mapped_device::{MemoryMapped, MemoryMappedDevice};
+use peripheral_bus::save_state::{decode_state, encode_state, SaveStateError};
+use serde::{Deserialize, Serialize};
 
 use std::any::Any;
 use std::collections::VecDeque;
@@ -49,7 +51,7 @@ fn spawn_stdin_channel() -> Receiver<String> {
     rx
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct TerminalDeviceControlRegisters {
     baud: u16,
     recv_enabled: u16,
@@ -68,6 +70,15 @@ pub struct TerminalDevice {
     control_registers: TerminalDeviceControlRegisters,
 }
 
+/// Everything needed to restore a `TerminalDevice` from a save state.
+/// The stdin channel belongs to the host so it isn't included.
+#[derive(Serialize, Deserialize)]
+struct TerminalDeviceState {
+    clock_counter: usize,
+    stdin_buffer: VecDeque<u8>,
+    control_registers: TerminalDeviceControlRegisters,
+}
+
 #[must_use]
 pub fn new_terminal_device(master_clock_freq: u32) -> TerminalDevice {
     let stdin_channel = spawn_stdin_channel();
@@ -152,6 +163,20 @@ impl Device for TerminalDevice {
         }
         io_assertions
     }
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(&TerminalDeviceState {
+            clock_counter: self.clock_counter,
+            stdin_buffer: self.stdin_buffer.clone(),
+            control_registers: self.control_registers.clone(),
+        })
+    }
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let state: TerminalDeviceState = decode_state(state)?;
+        self.clock_counter = state.clock_counter;
+        self.stdin_buffer = state.stdin_buffer;
+        self.control_registers = state.control_registers;
+        Ok(())
+    }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
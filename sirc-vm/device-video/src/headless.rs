@@ -0,0 +1,244 @@
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use image::{ImageBuffer, ImageFormat, Rgb};
+use log::{debug, info};
+
+use crate::{unpack_rgb, Renderer};
+
+/// How the headless renderer stores the frames it captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadlessFormat {
+    /// One PNG file per frame in the output directory (e.g. `frame_000060.png`)
+    Png,
+    /// All the frames appended to a single file as 24-bit RGB with no header or padding
+    /// (e.g. to be piped into ffmpeg with `-f rawvideo -pixel_format rgb24 -video_size 256x224`)
+    Raw,
+}
+
+/// Which frames the headless renderer should write out
+///
+/// Frame numbers start at zero for the first frame that is completed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum FrameSelection {
+    /// Every frame
+    #[default]
+    All,
+    /// Only the frames in these inclusive ranges
+    Ranges(Vec<(usize, usize)>),
+}
+
+impl FrameSelection {
+    /// Parses a comma separated list of frame numbers and inclusive ranges (e.g. "0,10-20,60")
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::All);
+        }
+        let parse_frame = |frame: &str| {
+            frame
+                .trim()
+                .parse::<usize>()
+                .map_err(|error| format!("Invalid frame number [{frame}]: {error}"))
+        };
+        let ranges = s
+            .split(',')
+            .map(|part| match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_frame(start)?, parse_frame(end)?);
+                    if start > end {
+                        return Err(format!("Frame range [{part}] ends before it starts"));
+                    }
+                    Ok((start, end))
+                }
+                None => parse_frame(part).map(|frame| (frame, frame)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::Ranges(ranges))
+    }
+
+    #[must_use]
+    pub fn contains(&self, frame: usize) -> bool {
+        match self {
+            Self::All => true,
+            Self::Ranges(ranges) => ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&frame)),
+        }
+    }
+}
+
+///
+/// A `Renderer` that writes frames to disk instead of opening a window.
+///
+/// Useful for running programs that use video where there is no display (e.g. CI) and for
+/// comparing the output of a program against known good images.
+///
+#[derive(Debug)]
+pub struct HeadlessRenderer {
+    format: HeadlessFormat,
+    output_path: PathBuf,
+    frames: FrameSelection,
+    frame_number: usize,
+    raw_writer: Option<BufWriter<File>>,
+}
+
+impl HeadlessRenderer {
+    ///
+    /// Creates a headless renderer.
+    ///
+    /// The output path is a directory for PNG output (created if it doesn't exist) or a file for
+    /// raw output (truncated if it exists).
+    ///
+    pub fn new(
+        format: HeadlessFormat,
+        output_path: PathBuf,
+        frames: FrameSelection,
+    ) -> std::io::Result<Self> {
+        let raw_writer = match format {
+            HeadlessFormat::Png => {
+                create_dir_all(&output_path)?;
+                None
+            }
+            HeadlessFormat::Raw => Some(BufWriter::new(File::create(&output_path)?)),
+        };
+        info!(
+            "Writing {format:?} frames {frames:?} to [{}]",
+            output_path.display()
+        );
+        Ok(Self {
+            format,
+            output_path,
+            frames,
+            frame_number: 0,
+            raw_writer,
+        })
+    }
+
+    fn write_png(&self, buffer: &[u32], width: usize, height: usize) -> Result<(), String> {
+        let (width, height) = (
+            u32::try_from(width).map_err(|error| error.to_string())?,
+            u32::try_from(height).map_err(|error| error.to_string())?,
+        );
+        let image = ImageBuffer::from_fn(width, height, |x, y| {
+            Rgb::from(<[u8; 3]>::from(unpack_rgb(
+                buffer[(x + y * width) as usize],
+            )))
+        });
+        let path = self
+            .output_path
+            .join(format!("frame_{:06}.png", self.frame_number));
+        debug!("Writing frame to [{}]", path.display());
+        image
+            .save_with_format(path, ImageFormat::Png)
+            .map_err(|error| error.to_string())
+    }
+
+    fn write_raw(&mut self, buffer: &[u32]) -> Result<(), String> {
+        let writer = self
+            .raw_writer
+            .as_mut()
+            .expect("raw writer should be open for raw output");
+        let bytes: Vec<u8> = buffer
+            .iter()
+            .flat_map(|pixel| <[u8; 3]>::from(unpack_rgb(*pixel)))
+            .collect();
+        writer
+            .write_all(&bytes)
+            .and_then(|()| writer.flush())
+            .map_err(|error| error.to_string())
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn update_with_buffer(
+        &mut self,
+        buffer: &[u32],
+        width: usize,
+        height: usize,
+    ) -> Result<(), minifb::Error> {
+        if self.frames.contains(self.frame_number) {
+            match self.format {
+                HeadlessFormat::Png => self.write_png(buffer, width, height),
+                HeadlessFormat::Raw => self.write_raw(buffer),
+            }
+            .map_err(minifb::Error::UpdateFailed)?;
+        }
+        self.frame_number += 1;
+        Ok(())
+    }
+
+    // Frames are written as fast as they are rendered
+    fn set_target_fps(&mut self, _fps: usize) {}
+
+    fn is_open(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_selection_parsing() {
+        assert_eq!(Ok(FrameSelection::All), FrameSelection::parse("all"));
+        assert_eq!(
+            Ok(FrameSelection::Ranges(vec![(0, 0), (10, 20), (60, 60)])),
+            FrameSelection::parse("0,10-20, 60")
+        );
+        assert!(FrameSelection::parse("20-10").is_err());
+        assert!(FrameSelection::parse("ten").is_err());
+
+        let selection = FrameSelection::parse("1,3-4").unwrap();
+        let selected: Vec<usize> = (0..6).filter(|f| selection.contains(*f)).collect();
+        assert_eq!(vec![1, 3, 4], selected);
+    }
+
+    #[test]
+    fn test_png_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut renderer = HeadlessRenderer::new(
+            HeadlessFormat::Png,
+            dir.path().join("frames"),
+            FrameSelection::parse("1").unwrap(),
+        )
+        .unwrap();
+
+        for frame in 0..3 {
+            renderer
+                .update_with_buffer(&[0x00FF_0000 + frame, 0x0000_FF00], 2, 1)
+                .unwrap();
+        }
+
+        let frames: Vec<_> = std::fs::read_dir(dir.path().join("frames"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(vec!["frame_000001.png"], frames);
+        let image = image::open(dir.path().join("frames/frame_000001.png"))
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(vec![0xFF, 0x00, 0x01, 0x00, 0xFF, 0x00], image.into_raw());
+    }
+
+    #[test]
+    fn test_raw_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frames.rgb");
+        let mut renderer =
+            HeadlessRenderer::new(HeadlessFormat::Raw, path.clone(), FrameSelection::All).unwrap();
+
+        renderer
+            .update_with_buffer(&[0x0012_3456, 0x00AB_CDEF], 2, 1)
+            .unwrap();
+        renderer
+            .update_with_buffer(&[0x0, 0x00FF_FFFF], 2, 1)
+            .unwrap();
+
+        assert_eq!(
+            vec![0x12, 0x34, 0x56, 0xAB, 0xCD, 0xEF, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF],
+            std::fs::read(path).unwrap()
+        );
+    }
+}
@@ -12,15 +12,21 @@
     clippy::missing_panics_doc
 )]
 
+pub mod headless;
 mod types;
 
-use crate::types::{PaletteRegister, PaletteSize, PpuPixel, PpuRegisters, PIXEL_BUFFER_SIZE};
+use crate::types::{
+    BaseConfigRegister, PaletteRegister, PaletteSize, PpuPixel, PpuRegisters, SpritePositionX,
+    SpritePositionY, TileSize, TileSizeRegister, TilemapSize, PIXEL_BUFFER_SIZE,
+};
 use log::info;
 use minifb::WindowOptions;
 use peripheral_bus::memory_mapped_device::MemoryMapped;
+use peripheral_bus::save_state::{big_array, decode_state, encode_state, SaveStateError};
 use peripheral_bus::{
     device::BusAssertions, device::Device, memory_mapped_device::MemoryMappedDevice,
 };
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use types::{Backgrounds, PixelBuffer, TileLine, TilemapEntry};
 // Some reference:
@@ -42,12 +48,32 @@ const PALETTE_SIZE: usize = 256;
 // the renderer size can be static and PAL can have black bars
 const WIDTH_PIXELS: u16 = 256;
 const HEIGHT_PIXELS: u16 = 224;
+// Each tilemap (or each screen of a double size tilemap) is 32x32 tiles
+const TILEMAP_SIZE: u16 = 32;
 
 const TOTAL_LINES: u16 = 262; // NTSC
                               // This just refers to the lines the TV can technically display
                               // the console will output less than this
                               // Number of vsync lines = TOTAL_LINES - VSYNC_LINE
 
+// The object attribute table has an entry for each sprite. Each entry is four words:
+// 0: SpritePositionX (x position and layer)
+// 1: SpritePositionY (y position)
+// 2: TilemapEntry (tile index, palette, priority and flip - the same format as the backgrounds)
+// 3: Reserved (keeps the entries aligned so the address is just a shift in hardware)
+const SPRITE_COUNT: u16 = 64;
+const SPRITE_ATTRIBUTE_SIZE_WORDS: u16 = 4;
+const SPRITE_TILEMAP_ENTRY_OFFSET: u16 = 2;
+// Each sprite takes two reads to evaluate, so 64 sprites fit into the 130 PPU clocks of front porch
+const SPRITE_EVALUATION_STEPS: u16 = SPRITE_COUNT * 2;
+// Each group of four pixels has one sprite tilemap and one sprite tile fetch slot, which gives 64 of
+// each per line. Each sprite on a line is given four groups, so that a 16x16 sprite can fetch its
+// tilemap entry and the four words that make up one line of the tile.
+const SPRITE_FETCH_GROUPS: u16 = 4;
+const SPRITES_PER_LINE: usize = 16;
+
+const MAX_SCREEN_BRIGHTNESS: u8 = 0xF;
+
 pub const VSYNC_INTERRUPT: u8 = 0x1 << 3; // l4 - 1
 fn pack_rgb(r: u8, g: u8, b: u8) -> u32 {
     (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b)
@@ -61,6 +87,18 @@ pub fn unpack_rgb(packed: u32) -> (u8, u8, u8) {
     (r, g, b)
 }
 
+/// Scales a colour by the screen brightness (0 = black, 15 = full brightness)
+#[allow(clippy::cast_possible_truncation)]
+fn apply_brightness(ppu_colour: PpuPixel, brightness: u8) -> PpuPixel {
+    let scale = |channel: u8| {
+        (u16::from(channel) * u16::from(brightness) / u16::from(MAX_SCREEN_BRIGHTNESS)) as u8
+    };
+    ppu_colour
+        .with_r(scale(ppu_colour.r()))
+        .with_g(scale(ppu_colour.g()))
+        .with_b(scale(ppu_colour.b()))
+}
+
 fn ppu_colour_to_minifb_rgb(ppu_colour: PpuPixel) -> u32 {
     // PPU pixels have 5 bit colour channels, so we need to scale them to 8 bit so they don't
     // look dark
@@ -122,9 +160,9 @@ impl Renderer for MiniFbWindow {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 enum RenderStateMachine {
-    FrontPorch, // TODO: Load sprite data in front porch
+    FrontPorch, // Sprites for the next line are evaluated during front porch
     // We have a budget of 4 x 4 = 16 master cycles = 8 ppu cycles to load a group of four pixels
     FetchTilemapEntry(Backgrounds), // 3 ppu cycles (first cycle muxes all the data loaded from last render cycle)
     FetchTile(Backgrounds),         // 3 ppu cycles
@@ -133,14 +171,300 @@ enum RenderStateMachine {
     BackPorch,
 }
 
-#[derive(Debug, Default)]
+/// The data fetched for one background for a group of four pixels.
+///
+/// The previous fetch is kept because when a background is scrolled by an amount that is not a
+/// multiple of four, each group of four output pixels is made up of pixels from two tile reads.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct BackgroundFetchRegisters {
+    tilemap: TilemapEntry,
+    tile: TileLine,
+    previous_tilemap: TilemapEntry,
+    previous_tile: TileLine,
+}
+
+impl BackgroundFetchRegisters {
+    fn latch_tilemap(&mut self, tilemap: TilemapEntry) {
+        self.previous_tilemap = self.tilemap;
+        self.tilemap = tilemap;
+    }
+
+    fn latch_tile(&mut self, tile: TileLine) {
+        self.previous_tile = self.tile;
+        self.tile = tile;
+    }
+
+    /// Returns the tilemap entry and pixel value for one of the pixels in the output group
+    fn pixel(self, fine_scroll: u16, index: u16) -> (TilemapEntry, u8) {
+        // The latest fetch holds the last pixel of the group, so with no fine scroll all four
+        // pixels come from it. Otherwise the first pixels are shifted in from the previous fetch.
+        let position = if fine_scroll == 0 {
+            PIXEL_BUFFER_SIZE
+        } else {
+            fine_scroll
+        } + index;
+        let (tilemap, tile, index) = if position < PIXEL_BUFFER_SIZE {
+            (self.previous_tilemap, self.previous_tile, position)
+        } else {
+            (self.tilemap, self.tile, position - PIXEL_BUFFER_SIZE)
+        };
+        // The mirrored read was already fetched for flipped tiles, only the pixels need swapping
+        let index = if tilemap.flip_horizontal() == 1 {
+            PIXEL_BUFFER_SIZE - 1 - index
+        } else {
+            index
+        };
+        (tilemap, tile.pixel(index))
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 struct FetchRegisters {
-    bg1_tilemap: TilemapEntry,
-    bg2_tilemap: TilemapEntry,
-    bg3_tilemap: TilemapEntry,
-    bg1_tile: TileLine,
-    bg2_tile: TileLine,
-    bg3_tile: TileLine,
+    bg1: BackgroundFetchRegisters,
+    bg2: BackgroundFetchRegisters,
+    bg3: BackgroundFetchRegisters,
+}
+
+impl FetchRegisters {
+    fn background_mut(&mut self, background: &Backgrounds) -> &mut BackgroundFetchRegisters {
+        match background {
+            Backgrounds::Bg1 => &mut self.bg1,
+            Backgrounds::Bg2 => &mut self.bg2,
+            Backgrounds::Bg3 => &mut self.bg3,
+        }
+    }
+}
+
+/// The registers that control how a background is drawn, gathered up for one background
+#[derive(Debug, Clone, Copy)]
+struct BackgroundConfig {
+    tilemap_addr: u16,
+    tile_addr: u16,
+    scroll_x: u16,
+    scroll_y: u16,
+    tilemap_double_width: bool,
+    tilemap_double_height: bool,
+    tile_size: u16,
+    palette_config: PaletteRegister,
+    disabled: bool,
+}
+
+impl BackgroundConfig {
+    fn new(ppu_registers: &PpuRegisters, background: &Backgrounds) -> Self {
+        let tile_size = &ppu_registers.tile_size;
+        let base_config = &ppu_registers.base_config;
+        let (tilemap_addr, tile_addr, scroll_x, scroll_y, palette_config) = match background {
+            Backgrounds::Bg1 => (
+                ppu_registers.b1_tilemap_addr,
+                ppu_registers.b1_tile_addr,
+                ppu_registers.b1_scroll_x,
+                ppu_registers.b1_scroll_y,
+                ppu_registers.b1_palette_config,
+            ),
+            Backgrounds::Bg2 => (
+                ppu_registers.b2_tilemap_addr,
+                ppu_registers.b2_tile_addr,
+                ppu_registers.b2_scroll_x,
+                ppu_registers.b2_scroll_y,
+                ppu_registers.b2_palette_config,
+            ),
+            Backgrounds::Bg3 => (
+                ppu_registers.b3_tilemap_addr,
+                ppu_registers.b3_tile_addr,
+                ppu_registers.b3_scroll_x,
+                ppu_registers.b3_scroll_y,
+                ppu_registers.b3_palette_config,
+            ),
+        };
+        let (size_x, size_y, size, disabled) = match background {
+            Backgrounds::Bg1 => (
+                tile_size.b1_tilemap_size_x(),
+                tile_size.b1_tilemap_size_y(),
+                tile_size.b1_size(),
+                base_config.b1_disable(),
+            ),
+            Backgrounds::Bg2 => (
+                tile_size.b2_tilemap_size_x(),
+                tile_size.b2_tilemap_size_y(),
+                tile_size.b2_size(),
+                base_config.b2_disable(),
+            ),
+            Backgrounds::Bg3 => (
+                tile_size.b3_tilemap_size_x(),
+                tile_size.b3_tilemap_size_y(),
+                tile_size.b3_size(),
+                base_config.b3_disable(),
+            ),
+        };
+        Self {
+            tilemap_addr,
+            tile_addr,
+            scroll_x: scroll_x.scroll_amount(),
+            scroll_y: scroll_y.scroll_amount(),
+            tilemap_double_width: matches!(size_x, TilemapSize::Double),
+            tilemap_double_height: matches!(size_y, TilemapSize::Double),
+            tile_size: tile_size_pixels(&size),
+            palette_config,
+            disabled: disabled == 1,
+        }
+    }
+
+    /// The number of pixels that the group of four output pixels is offset into a tile read
+    fn fine_scroll(&self) -> u16 {
+        self.scroll_x % PIXEL_BUFFER_SIZE
+    }
+}
+
+/// Where to find the data for a group of four pixels of a background
+#[derive(Debug, PartialEq, Eq)]
+struct BackgroundFetchPosition {
+    // Offset of the tilemap entry from the tilemap base address
+    tilemap_offset: u16,
+    // Line within the tile (before flipping)
+    tile_row: u16,
+    // Which of the reads that make up a line of the tile (before flipping)
+    tile_column: u16,
+}
+
+impl BackgroundFetchPosition {
+    /// The offset of the tile read from the start of the tile data for the tile.
+    ///
+    /// Flipped tiles read the mirrored line/column. The pixels within a horizontally flipped
+    /// read are swapped around when they are muxed (see `BackgroundFetchRegisters::pixel`).
+    fn tile_offset(&self, tilemap_entry: TilemapEntry, tile_size: u16) -> u16 {
+        let reads_per_tile_line = tile_size / PIXEL_BUFFER_SIZE;
+        let row = if tilemap_entry.flip_vertical() == 1 {
+            tile_size - 1 - self.tile_row
+        } else {
+            self.tile_row
+        };
+        let column = if tilemap_entry.flip_horizontal() == 1 {
+            reads_per_tile_line - 1 - self.tile_column
+        } else {
+            self.tile_column
+        };
+        row * reads_per_tile_line + column
+    }
+}
+
+fn resolve_background_fetch_position(
+    config: &BackgroundConfig,
+    group: u16,
+    line: u16,
+) -> BackgroundFetchPosition {
+    let width_screens = if config.tilemap_double_width { 2 } else { 1 };
+    let height_screens = if config.tilemap_double_height { 2 } else { 1 };
+    let map_width = TILEMAP_SIZE * config.tile_size * width_screens;
+    let map_height = TILEMAP_SIZE * config.tile_size * height_screens;
+
+    // The tile read that holds the last pixel of the group is fetched (see BackgroundFetchRegisters)
+    // Scrolling wraps around at the edge of the tilemap
+    let x = (group * PIXEL_BUFFER_SIZE + PIXEL_BUFFER_SIZE - 1 + config.scroll_x) % map_width;
+    let y = (line + config.scroll_y) % map_height;
+
+    let tile_x = x / config.tile_size;
+    let tile_y = y / config.tile_size;
+    // Double size tilemaps are made up of multiple 32x32 screens, one after the other in VRAM.
+    // They are placed side by side for double width, stacked for double height, or in a 2x2 grid
+    // (top left, top right, bottom left, bottom right) for both
+    let screen = (tile_x / TILEMAP_SIZE) + (tile_y / TILEMAP_SIZE) * width_screens;
+    let tilemap_offset = screen * TILEMAP_SIZE * TILEMAP_SIZE
+        + (tile_y % TILEMAP_SIZE) * TILEMAP_SIZE
+        + tile_x % TILEMAP_SIZE;
+
+    BackgroundFetchPosition {
+        tilemap_offset,
+        tile_row: y % config.tile_size,
+        tile_column: (x % config.tile_size) / PIXEL_BUFFER_SIZE,
+    }
+}
+
+/// A pixel in the sprite line buffer
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct SpritePixel {
+    // Already resolved to a palette address
+    colour: u8,
+    priority: bool,
+    // 0 = no sprite pixel here, 1-3 = S1-S3
+    layer: u8,
+}
+
+impl SpritePixel {
+    fn is_visible(self) -> bool {
+        self.layer != 0
+    }
+}
+
+/// A sprite that was found to be on the next line during sprite evaluation
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct LineSprite {
+    index: u16,
+    x: u16,
+    // The line of the sprite that will be drawn (before flipping)
+    row: u16,
+    layer: u8,
+    size: u16,
+    tilemap_entry: TilemapEntry,
+}
+
+/// Sprites are drawn a line ahead into a line buffer.
+///
+/// During front porch, the object attribute table is scanned for sprites that are on the next line.
+/// During the visible part of the line, the tiles for those sprites are fetched (using the sprite
+/// fetch slots between background fetches) and drawn into `line_buffer`. At the start of the next
+/// line, the buffers are swapped and the pixels in `output_buffer` are muxed with the backgrounds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SpriteRegisters {
+    evaluation_step: u16,
+    evaluation_y: SpritePositionY,
+    line_sprites: Vec<LineSprite>,
+    fetch_step: u16,
+    #[serde(with = "big_array")]
+    line_buffer: [SpritePixel; WIDTH_PIXELS as usize],
+    #[serde(with = "big_array")]
+    output_buffer: [SpritePixel; WIDTH_PIXELS as usize],
+}
+
+impl Default for SpriteRegisters {
+    fn default() -> Self {
+        Self {
+            evaluation_step: 0,
+            evaluation_y: SpritePositionY::default(),
+            line_sprites: Vec::with_capacity(SPRITES_PER_LINE),
+            fetch_step: 0,
+            line_buffer: [SpritePixel::default(); WIDTH_PIXELS as usize],
+            output_buffer: [SpritePixel::default(); WIDTH_PIXELS as usize],
+        }
+    }
+}
+
+impl SpriteRegisters {
+    fn start_line(&mut self) {
+        self.output_buffer = self.line_buffer;
+        self.line_buffer = [SpritePixel::default(); WIDTH_PIXELS as usize];
+        self.line_sprites.clear();
+        self.evaluation_step = 0;
+        self.fetch_step = 0;
+    }
+
+    fn output_pixels(&self, first_pixel: u16) -> [SpritePixel; PIXEL_BUFFER_SIZE as usize] {
+        let first_pixel = first_pixel as usize;
+        self.output_buffer[first_pixel..first_pixel + PIXEL_BUFFER_SIZE as usize]
+            .try_into()
+            .expect("pixel buffer should be in the bounds of the line")
+    }
+
+    fn draw_pixel(&mut self, x: u16, pixel: SpritePixel) {
+        let Some(existing) = self.line_buffer.get_mut(x as usize) else {
+            // Off screen
+            return;
+        };
+        // Higher sprite layers are drawn over lower ones. Within the same layer, the sprite with
+        // the lowest index is fetched first and wins.
+        if pixel.layer > existing.layer {
+            *existing = pixel;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -156,6 +480,7 @@ pub struct VideoDevice {
     palette: [PpuPixel; PALETTE_SIZE],
     ppu_registers: PpuRegisters,
     vram_fetch_register: FetchRegisters,
+    sprite_registers: SpriteRegisters,
     pixel_mux_buffer_register: PixelBuffer,
 
     // Other
@@ -175,7 +500,46 @@ pub struct VideoDevice {
     state: RenderStateMachine,
 }
 
-fn resolve_first_visible_pixel(pixel_values: [u8; 3], palette_offsets: [u8; 3]) -> u8 {
+/// Everything needed to restore a `VideoDevice` from a save state.
+/// The window belongs to the host and the timing is derived from the clock so they aren't included.
+#[derive(Serialize, Deserialize)]
+struct VideoDeviceState {
+    buffer: Vec<u32>,
+    vram: Vec<u16>,
+    #[serde(with = "big_array")]
+    palette: [PpuPixel; PALETTE_SIZE],
+    ppu_registers: PpuRegisters,
+    vram_fetch_register: FetchRegisters,
+    sprite_registers: SpriteRegisters,
+    pixel_mux_buffer_register: PixelBuffer,
+    frame_count: usize,
+    line_clock: u16,
+    line: u16,
+    state: RenderStateMachine,
+}
+
+fn resolve_first_visible_pixel(
+    pixel_values: [u8; 3],
+    palette_offsets: [u8; 3],
+    priorities: [u8; 3],
+    sprite_pixel: SpritePixel,
+) -> u8 {
+    // From front to back:
+    // 1. Sprites with the priority bit set
+    // 2. Background tiles with the priority bit set
+    // 3. Sprites without the priority bit set
+    // 4. Background tiles without the priority bit set
+    if sprite_pixel.is_visible() && sprite_pixel.priority {
+        return sprite_pixel.colour;
+    }
+    for i in 0..=2 {
+        if pixel_values[i] > 0 && priorities[i] == 1 {
+            return palette_offsets[i] + pixel_values[i];
+        }
+    }
+    if sprite_pixel.is_visible() {
+        return sprite_pixel.colour;
+    }
     for i in 0..=2 {
         if pixel_values[i] > 0 {
             return palette_offsets[i] + pixel_values[i];
@@ -202,65 +566,79 @@ fn resolve_palette_addr(palette_select: u8, palette_register: PaletteRegister) -
         .wrapping_add(palette_register.palette_offset())
 }
 
-#[allow(clippy::cast_possible_truncation)]
 fn resolve_tile_line(
     fetch_registers: &FetchRegisters,
     ppu_registers: &PpuRegisters,
-) -> (u8, u8, u8, u8) {
+    sprite_pixels: [SpritePixel; PIXEL_BUFFER_SIZE as usize],
+) -> [u8; PIXEL_BUFFER_SIZE as usize] {
     // TODO: This will be efficient in hardware but not sure how well this code will optimise
     // Would be good to check since it will be in a hot loop
-    let palette_offsets = [
-        resolve_palette_addr(
-            fetch_registers.bg3_tilemap.palette_select(),
-            ppu_registers.b3_palette_config,
+    let backgrounds = [
+        (
+            &fetch_registers.bg3,
+            BackgroundConfig::new(ppu_registers, &Backgrounds::Bg3),
         ),
-        resolve_palette_addr(
-            fetch_registers.bg2_tilemap.palette_select(),
-            ppu_registers.b2_palette_config,
+        (
+            &fetch_registers.bg2,
+            BackgroundConfig::new(ppu_registers, &Backgrounds::Bg2),
         ),
-        resolve_palette_addr(
-            fetch_registers.bg1_tilemap.palette_select(),
-            ppu_registers.b1_palette_config,
+        (
+            &fetch_registers.bg1,
+            BackgroundConfig::new(ppu_registers, &Backgrounds::Bg1),
         ),
     ];
-    let p1 = resolve_first_visible_pixel(
-        [
-            fetch_registers.bg3_tile.p1(),
-            fetch_registers.bg2_tile.p1(),
-            fetch_registers.bg1_tile.p1(),
-        ],
-        palette_offsets,
-    );
-    let p2 = resolve_first_visible_pixel(
-        [
-            fetch_registers.bg3_tile.p2(),
-            fetch_registers.bg2_tile.p2(),
-            fetch_registers.bg1_tile.p2(),
-        ],
-        palette_offsets,
-    );
-    let p3 = resolve_first_visible_pixel(
-        [
-            fetch_registers.bg3_tile.p3(),
-            fetch_registers.bg2_tile.p3(),
-            fetch_registers.bg1_tile.p3(),
-        ],
-        palette_offsets,
-    );
-    let p4 = resolve_first_visible_pixel(
-        [
-            fetch_registers.bg3_tile.p4(),
-            fetch_registers.bg2_tile.p4(),
-            fetch_registers.bg1_tile.p4(),
-        ],
-        palette_offsets,
-    );
-    (p1, p2, p3, p4)
+
+    let mut pixels = [0; PIXEL_BUFFER_SIZE as usize];
+    for (index, (pixel, sprite_pixel)) in (0..).zip(pixels.iter_mut().zip(sprite_pixels)) {
+        let mut pixel_values = [0; 3];
+        let mut palette_offsets = [0; 3];
+        let mut priorities = [0; 3];
+        for (i, (background_registers, config)) in backgrounds.iter().enumerate() {
+            if config.disabled {
+                // Left transparent
+                continue;
+            }
+            let (tilemap_entry, pixel_value) =
+                background_registers.pixel(config.fine_scroll(), index);
+            pixel_values[i] = pixel_value;
+            palette_offsets[i] =
+                resolve_palette_addr(tilemap_entry.palette_select(), config.palette_config);
+            priorities[i] = tilemap_entry.priority();
+        }
+        *pixel =
+            resolve_first_visible_pixel(pixel_values, palette_offsets, priorities, sprite_pixel);
+    }
+    pixels
+}
+
+const fn tile_size_pixels(size: &TileSize) -> u16 {
+    match size {
+        TileSize::EightByEight => 8,
+        TileSize::SixteenBySixteen => 16,
+    }
+}
+
+fn sprite_size(tile_size: TileSizeRegister, layer: u8) -> u16 {
+    let size = match layer {
+        1 => tile_size.s1_size(),
+        2 => tile_size.s2_size(),
+        _ => tile_size.s3_size(),
+    };
+    tile_size_pixels(&size)
+}
+
+fn sprite_layer_disabled(base_config: BaseConfigRegister, layer: u8) -> bool {
+    let disabled = match layer {
+        1 => base_config.s1_disable(),
+        2 => base_config.s2_disable(),
+        _ => base_config.s3_disable(),
+    };
+    disabled == 1
 }
 
 #[must_use]
 pub fn new_video_device(master_clock_freq: usize) -> VideoDevice {
-    let mut window: Box<dyn Renderer> = if cfg!(test) {
+    let window: Box<dyn Renderer> = if cfg!(test) {
         Box::new(MockWindow {})
     } else {
         Box::new(MiniFbWindow {
@@ -273,7 +651,15 @@ pub fn new_video_device(master_clock_freq: usize) -> VideoDevice {
             .unwrap(),
         })
     };
+    new_video_device_with_renderer(master_clock_freq, window)
+}
 
+/// Creates a video device that outputs each frame to the given renderer (e.g. a `HeadlessRenderer`)
+#[must_use]
+pub fn new_video_device_with_renderer(
+    master_clock_freq: usize,
+    mut window: Box<dyn Renderer>,
+) -> VideoDevice {
     // For reference - the SNES NTSC scanline timing is:
     // 12090 ns of preamble
     // 47616 ns of visible
@@ -322,6 +708,7 @@ pub fn new_video_device(master_clock_freq: usize) -> VideoDevice {
         vram: vec![0; VRAM_SIZE],
         palette: [PpuPixel::new(); PALETTE_SIZE],
         vram_fetch_register: FetchRegisters::default(),
+        sprite_registers: SpriteRegisters::default(),
         pixel_mux_buffer_register: PixelBuffer::default(),
         ppu_registers: PpuRegisters::default(),
         frame_count: 0,
@@ -383,17 +770,16 @@ impl Device for VideoDevice {
         // The PPU is outputting each of the four pixels of the output buffer in a loop
         //
 
-        let tilemap_size: u16 = 32; // 32x32 - TODO Support double tile maps
-        let tile_size: u16 = 8; // 8x8 - TODO: Support 16x16 tiles (are the tilemaps the same size?, how can BGs with different tile sizes fit together?)
-        let pixels_per_read = 4; // 4bpp - 16 bit data bus = 4 pixels with every read
-        let read_cycles_per_tile_line = tile_size / pixels_per_read;
-        let clocks_per_read_cycle = 16;
-        let clocks_per_tile = clocks_per_read_cycle * read_cycles_per_tile_line; // 32 master clocks = 16 ppu clocks = 8 ppu clocks per four pixels * 2 (8 pixels per tile line)
-        let tile_size_words = read_cycles_per_tile_line * tile_size;
+        let pixels_per_read = PIXEL_BUFFER_SIZE; // 4bpp - 16 bit data bus = 4 pixels with every read
+        let clocks_per_read_cycle = 16; // 16 master clocks = 8 ppu clocks per four pixels
+
+        // The group of four pixels that is currently being fetched
+        let group = pixel_clock / clocks_per_read_cycle;
 
         match &self.state {
             RenderStateMachine::FrontPorch => {
-                // No drawing happens in the pre/postable TODO: (should load sprite data out of memory here) and cache it in registers
+                // No drawing happens in the pre/postable, but the sprites for the next line are found
+                self.evaluate_next_sprite();
                 // TODO: Also the tilemap fetching should start before the first pixel
                 // TODO: The PPU  should get a head start accessing accessing 2-3 tiles ahead of the pixel output.
                 if self.line_clock >= self.line_preamble_clocks {
@@ -401,94 +787,78 @@ impl Device for VideoDevice {
                 }
             }
             RenderStateMachine::FetchTilemapEntry(background) => {
-                let tilemap_x = pixel_clock / clocks_per_tile; // 32 ppu cycles per tile
-                let tilemap_y = (self.line / tile_size) * tilemap_size; // 32 x 32 tile map
-                let base_address = match background {
-                    Backgrounds::Bg1 => self.ppu_registers.b1_tilemap_addr,
-                    Backgrounds::Bg2 => self.ppu_registers.b2_tilemap_addr,
-                    Backgrounds::Bg3 => self.ppu_registers.b3_tilemap_addr,
-                };
-                let tilemap_entry_address: u16 = base_address + tilemap_x + tilemap_y;
+                let config = BackgroundConfig::new(&self.ppu_registers, background);
+                let position = resolve_background_fetch_position(&config, group, self.line);
+                let tilemap_entry_address =
+                    config.tilemap_addr.wrapping_add(position.tilemap_offset);
                 let tilemap_data: TilemapEntry =
                     self.read_address(u32::from(tilemap_entry_address)).into();
-                match background {
-                    Backgrounds::Bg1 => {
-                        if tilemap_x > 0 {
-                            // First clock muxes the result from the last cycle (it is pipelined, needs an entire cycle to fill the buffer)
-                            let (p1, p2, p3, p4) =
-                                resolve_tile_line(&self.vram_fetch_register, &self.ppu_registers);
-                            self.pixel_mux_buffer_register = PixelBuffer {
-                                p1: self.palette[p1 as usize],
-                                p2: self.palette[p2 as usize],
-                                p3: self.palette[p3 as usize],
-                                p4: self.palette[p4 as usize],
-                            };
-                        } else {
-                            // Flush buffer so the last chunk of the line doesn't end up on the next line
-                            // (We won't need this when is implemented properly and the fetch starts early)
-                            self.pixel_mux_buffer_register = PixelBuffer::default();
-                        }
-
-                        self.vram_fetch_register.bg1_tilemap = tilemap_data;
-                        self.state = RenderStateMachine::FetchTilemapEntry(Backgrounds::Bg2);
-                    }
-                    Backgrounds::Bg2 => {
-                        self.vram_fetch_register.bg2_tilemap = tilemap_data;
-                        self.state = RenderStateMachine::FetchTilemapEntry(Backgrounds::Bg3);
-                    }
-                    Backgrounds::Bg3 => {
-                        self.vram_fetch_register.bg3_tilemap = tilemap_data;
-                        self.state = RenderStateMachine::FetchTile(Backgrounds::Bg1);
+
+                if *background == Backgrounds::Bg1 {
+                    let base_config = self.ppu_registers.base_config;
+                    if group > 1 && base_config.graphics_disable() == 0 {
+                        // First clock muxes the result from the last cycle (it is pipelined, needs an entire cycle to fill the buffer)
+                        let sprite_pixels =
+                            self.sprite_registers.output_pixels(group * pixels_per_read);
+                        let [p1, p2, p3, p4] = resolve_tile_line(
+                            &self.vram_fetch_register,
+                            &self.ppu_registers,
+                            sprite_pixels,
+                        );
+                        let brightness = base_config.screen_brightness();
+                        let colour = |p: u8| apply_brightness(self.palette[p as usize], brightness);
+                        self.pixel_mux_buffer_register = PixelBuffer {
+                            p1: colour(p1),
+                            p2: colour(p2),
+                            p3: colour(p3),
+                            p4: colour(p4),
+                        };
+                    } else {
+                        // When graphics are disabled the screen is just black.
+                        // Otherwise, flush buffer so the last chunk of the line doesn't end up on the next line
+                        // (We won't need this when is implemented properly and the fetch starts early)
+                        self.pixel_mux_buffer_register = PixelBuffer::default();
                     }
                 }
+
+                self.vram_fetch_register
+                    .background_mut(background)
+                    .latch_tilemap(tilemap_data);
+                self.state = match background {
+                    Backgrounds::Bg1 => RenderStateMachine::FetchTilemapEntry(Backgrounds::Bg2),
+                    Backgrounds::Bg2 => RenderStateMachine::FetchTilemapEntry(Backgrounds::Bg3),
+                    Backgrounds::Bg3 => RenderStateMachine::FetchTile(Backgrounds::Bg1),
+                };
             }
             RenderStateMachine::FetchTile(background) => {
-                let tile_address = match background {
-                    // Yes the tilemaps are fetched twice redundantly and we should probably
-                    // just fetch it once and do two tile fetches for the full 8 pixels but
-                    // I'd have to increase the pixel buffers, so for now I'll leave this redundant fetch
-                    Backgrounds::Bg1 => {
-                        self.ppu_registers.b1_tile_addr
-                            + self.vram_fetch_register.bg1_tilemap.tile_index() * tile_size_words
-                    }
-                    Backgrounds::Bg2 => {
-                        self.ppu_registers.b2_tile_addr
-                            + self.vram_fetch_register.bg2_tilemap.tile_index() * tile_size_words
-                    }
-                    Backgrounds::Bg3 => {
-                        self.ppu_registers.b3_tile_addr
-                            + self.vram_fetch_register.bg3_tilemap.tile_index() * tile_size_words
-                    }
+                // Yes the tilemaps are fetched twice redundantly and we should probably
+                // just fetch it once and do two tile fetches for the full 8 pixels but
+                // I'd have to increase the pixel buffers, so for now I'll leave this redundant fetch
+                let config = BackgroundConfig::new(&self.ppu_registers, background);
+                let position = resolve_background_fetch_position(&config, group, self.line);
+                let tilemap_entry = self.vram_fetch_register.background_mut(background).tilemap;
+                let tile_size_words = (config.tile_size / PIXEL_BUFFER_SIZE) * config.tile_size;
+                let tile_address = config
+                    .tile_addr
+                    .wrapping_add(tilemap_entry.tile_index() * tile_size_words)
+                    .wrapping_add(position.tile_offset(tilemap_entry, config.tile_size));
+                let tile_data: TileLine = self.read_address(u32::from(tile_address)).into();
+
+                self.vram_fetch_register
+                    .background_mut(background)
+                    .latch_tile(tile_data);
+                self.state = match background {
+                    Backgrounds::Bg1 => RenderStateMachine::FetchTile(Backgrounds::Bg2),
+                    Backgrounds::Bg2 => RenderStateMachine::FetchTile(Backgrounds::Bg3),
+                    Backgrounds::Bg3 => RenderStateMachine::FetchSpriteTilemapEntry,
                 };
-
-                let tile_x = ((pixel_clock - 8) / (tile_size * 2)) % 2; // minus 8 for the cycles to load the tile maps
-                let tile_y = (self.line % tile_size) * read_cycles_per_tile_line;
-                let tile_data: TileLine = self
-                    .read_address(u32::from(tile_address + tile_x + tile_y))
-                    .into();
-                match background {
-                    Backgrounds::Bg1 => {
-                        // TODO: Pixel offset per line (e.g. scrolling) (tiles are 8x8, we read one line at a time)
-                        // TODO: We probably should read the full 8 pixels at once, which is two reads for the 32 bits
-                        self.vram_fetch_register.bg1_tile = tile_data;
-                        self.state = RenderStateMachine::FetchTile(Backgrounds::Bg2);
-                    }
-                    Backgrounds::Bg2 => {
-                        self.vram_fetch_register.bg2_tile = tile_data;
-                        self.state = RenderStateMachine::FetchTile(Backgrounds::Bg3);
-                    }
-                    Backgrounds::Bg3 => {
-                        self.vram_fetch_register.bg3_tile = tile_data;
-                        self.state = RenderStateMachine::FetchSpriteTilemapEntry;
-                    }
-                }
             }
             RenderStateMachine::FetchSpriteTilemapEntry => {
-                // TODO: Sprites
+                self.fetch_sprite_tilemap_entry();
                 self.state = RenderStateMachine::FetchSpriteTile;
             }
             RenderStateMachine::FetchSpriteTile => {
-                // TODO: Sprites
+                self.fetch_sprite_tile(pixels_per_read);
                 if self.line_clock >= self.line_preamble_clocks + self.line_visible_clocks {
                     // Line is done, finish displaying pixels
                     self.state = RenderStateMachine::BackPorch;
@@ -500,6 +870,10 @@ impl Device for VideoDevice {
             RenderStateMachine::BackPorch => {
                 // No pixels rendering, can do some preparation
                 if self.line_clock == 0 {
+                    if self.line == 0 {
+                        self.ppu_registers.status.set_sprite_overflow(0);
+                    }
+                    self.sprite_registers.start_line();
                     self.state = RenderStateMachine::FrontPorch;
                 }
             }
@@ -549,11 +923,178 @@ impl Device for VideoDevice {
 
         assertions
     }
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(&VideoDeviceState {
+            buffer: self.buffer.clone(),
+            vram: self.vram.clone(),
+            palette: self.palette,
+            ppu_registers: self.ppu_registers.clone(),
+            vram_fetch_register: self.vram_fetch_register.clone(),
+            sprite_registers: self.sprite_registers.clone(),
+            pixel_mux_buffer_register: self.pixel_mux_buffer_register.clone(),
+            frame_count: self.frame_count,
+            line_clock: self.line_clock,
+            line: self.line,
+            state: self.state.clone(),
+        })
+    }
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let state: VideoDeviceState = decode_state(state)?;
+        self.buffer = state.buffer;
+        self.vram = state.vram;
+        self.palette = state.palette;
+        self.ppu_registers = state.ppu_registers;
+        self.vram_fetch_register = state.vram_fetch_register;
+        self.sprite_registers = state.sprite_registers;
+        self.pixel_mux_buffer_register = state.pixel_mux_buffer_register;
+        self.frame_count = state.frame_count;
+        self.line_clock = state.line_clock;
+        self.line = state.line;
+        self.state = state.state;
+        Ok(())
+    }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
 }
 
+impl VideoDevice {
+    /// Performs one step of sprite evaluation for the next line (one VRAM read)
+    fn evaluate_next_sprite(&mut self) {
+        let step = self.sprite_registers.evaluation_step;
+        if step >= SPRITE_EVALUATION_STEPS {
+            return;
+        }
+        self.sprite_registers.evaluation_step += 1;
+
+        let sprite_index = step / 2;
+        let attribute_address = self
+            .ppu_registers
+            .s_attribute_table_addr
+            .wrapping_add(sprite_index * SPRITE_ATTRIBUTE_SIZE_WORDS);
+
+        if step.is_multiple_of(2) {
+            self.sprite_registers.evaluation_y = self
+                .read_address(u32::from(attribute_address.wrapping_add(1)))
+                .into();
+            return;
+        }
+
+        let position_x: SpritePositionX = self.read_address(u32::from(attribute_address)).into();
+        let layer = position_x.layer();
+        if layer == 0 || sprite_layer_disabled(self.ppu_registers.base_config, layer) {
+            return;
+        }
+
+        let size = sprite_size(self.ppu_registers.tile_size, layer);
+        let next_line = (self.line + 1) % TOTAL_LINES;
+        // Y wraps around at 256 so sprites can be partially above the top of the screen
+        let row = next_line.wrapping_sub(u16::from(self.sprite_registers.evaluation_y.y())) & 0xFF;
+        if row >= size {
+            return;
+        }
+
+        if self.sprite_registers.line_sprites.len() >= SPRITES_PER_LINE {
+            self.ppu_registers.status.set_sprite_overflow(1);
+            return;
+        }
+
+        self.sprite_registers.line_sprites.push(LineSprite {
+            index: sprite_index,
+            x: position_x.x(),
+            row,
+            layer,
+            size,
+            tilemap_entry: TilemapEntry::default(),
+        });
+    }
+
+    fn current_line_sprite(&self) -> Option<(usize, u16, LineSprite)> {
+        let slot = (self.sprite_registers.fetch_step / SPRITE_FETCH_GROUPS) as usize;
+        let group = self.sprite_registers.fetch_step % SPRITE_FETCH_GROUPS;
+        self.sprite_registers
+            .line_sprites
+            .get(slot)
+            .map(|sprite| (slot, group, *sprite))
+    }
+
+    fn fetch_sprite_tilemap_entry(&mut self) {
+        let Some((slot, 0, sprite)) = self.current_line_sprite() else {
+            // Only the first group for each sprite needs to fetch the tilemap entry
+            return;
+        };
+        let tilemap_entry_address = self
+            .ppu_registers
+            .s_attribute_table_addr
+            .wrapping_add(sprite.index * SPRITE_ATTRIBUTE_SIZE_WORDS)
+            .wrapping_add(SPRITE_TILEMAP_ENTRY_OFFSET);
+        self.sprite_registers.line_sprites[slot].tilemap_entry =
+            self.read_address(u32::from(tilemap_entry_address)).into();
+    }
+
+    fn fetch_sprite_tile(&mut self, pixels_per_read: u16) {
+        let line_sprite = self.current_line_sprite();
+        self.sprite_registers.fetch_step += 1;
+
+        let Some((_, group, sprite)) = line_sprite else {
+            return;
+        };
+        let words_per_tile_line = sprite.size / pixels_per_read;
+        if group >= words_per_tile_line {
+            // 8x8 sprites only need two reads
+            return;
+        }
+
+        let tilemap_entry = sprite.tilemap_entry;
+        let row = if tilemap_entry.flip_vertical() == 1 {
+            sprite.size - 1 - sprite.row
+        } else {
+            sprite.row
+        };
+        let tile_size_words = words_per_tile_line * sprite.size;
+        let tile_address = self
+            .ppu_registers
+            .s_tile_addr
+            .wrapping_add(tilemap_entry.tile_index() * tile_size_words)
+            .wrapping_add(row * words_per_tile_line)
+            .wrapping_add(group);
+        let tile_data: TileLine = self.read_address(u32::from(tile_address)).into();
+
+        let palette_offset = resolve_palette_addr(
+            tilemap_entry.palette_select(),
+            self.ppu_registers.s_palette_config,
+        );
+        let pixel_values = [
+            tile_data.p1(),
+            tile_data.p2(),
+            tile_data.p3(),
+            tile_data.p4(),
+        ];
+        for (pixel_index, pixel_value) in (0..).zip(pixel_values) {
+            if pixel_value == 0 {
+                // Transparent
+                continue;
+            }
+            let offset = group * pixels_per_read + pixel_index;
+            let offset = if tilemap_entry.flip_horizontal() == 1 {
+                sprite.size - 1 - offset
+            } else {
+                offset
+            };
+            // X wraps around at 512, anything past the width of the screen is not drawn
+            let x = (sprite.x + offset) & 0x1FF;
+            self.sprite_registers.draw_pixel(
+                x,
+                SpritePixel {
+                    colour: palette_offset.wrapping_add(pixel_value),
+                    priority: tilemap_entry.priority() == 1,
+                    layer: sprite.layer,
+                },
+            );
+        }
+    }
+}
+
 impl MemoryMapped for VideoDevice {
     fn read_address(&self, address: u32) -> u16 {
         match address {
@@ -594,10 +1135,14 @@ mod tile_data;
 #[cfg(test)]
 mod tests {
     use crate::tile_data::{DIGIT_TILES, PALETTE_1, PALETTE_2, TEST_PATTERNS, TEST_TILEMAP};
-    use crate::types::{Backgrounds, PaletteRegister, PaletteSize, TileLine, TilemapEntry};
+    use crate::types::{
+        Backgrounds, BaseConfigRegister, PaletteRegister, PaletteSize, PpuPixel, SpritePositionX,
+        SpritePositionY, TileLine, TileSize, TileSizeRegister, TilemapEntry, TilemapSize,
+    };
     use crate::{
-        new_video_device, unpack_rgb, RenderStateMachine, VideoDevice, HEIGHT_PIXELS, TOTAL_LINES,
-        WIDTH_PIXELS,
+        new_video_device, ppu_colour_to_minifb_rgb, resolve_first_visible_pixel, unpack_rgb,
+        RenderStateMachine, SpritePixel, VideoDevice, HEIGHT_PIXELS, MAX_SCREEN_BRIGHTNESS,
+        SPRITE_ATTRIBUTE_SIZE_WORDS, TILEMAP_SIZE, TOTAL_LINES, WIDTH_PIXELS,
     };
     use image::ImageFormat;
     use oxipng::{optimize_from_memory, Options, StripChunks};
@@ -690,8 +1235,7 @@ mod tests {
         assert_eq!(tile.p4(), 4);
     }
 
-    #[test]
-    fn test_video_line_timing() {
+    fn new_test_pattern_video_device() -> VideoDevice {
         let master_clock_freq = 21_477_272;
 
         let mut video_device = new_video_device(master_clock_freq);
@@ -715,6 +1259,38 @@ mod tests {
         video_device.ppu_registers.b3_palette_config = PaletteRegister::new()
             .with_palette_size(PaletteSize::Sixteen)
             .with_palette_offset(0);
+        video_device.ppu_registers.base_config =
+            BaseConfigRegister::new().with_screen_brightness(MAX_SCREEN_BRIGHTNESS);
+        video_device
+    }
+
+    fn frame_as_png(video_device: &VideoDevice) -> Vec<u8> {
+        let mut imgbuf = image::ImageBuffer::new(u32::from(WIDTH_PIXELS), u32::from(HEIGHT_PIXELS));
+        for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+            let packed_pixel = video_device.buffer[(x + y * u32::from(WIDTH_PIXELS)) as usize];
+            *pixel = image::Rgb::from(<[u8; 3]>::from(unpack_rgb(packed_pixel)));
+        }
+
+        let mut png_buffer = Cursor::new(Vec::new());
+        imgbuf
+            .write_to(&mut png_buffer, ImageFormat::Png)
+            .expect("Failed to write PNG data to buffer");
+
+        optimize_from_memory(
+            png_buffer.into_inner().as_slice(),
+            &Options {
+                // Strip metadata (e.g. create/update date) that causes the snapshot tests to fail
+                strip: StripChunks::All,
+                force: true,
+                ..Options::default()
+            },
+        )
+        .expect("Failed to optimize PNG")
+    }
+
+    #[test]
+    fn test_video_line_timing() {
+        let mut video_device = new_test_pattern_video_device();
 
         for line in 0..TOTAL_LINES {
             // Clocks are divided by two to turn master clocks into PPU clocks
@@ -758,27 +1334,527 @@ mod tests {
             }
         }
 
-        let mut imgbuf = image::ImageBuffer::new(u32::from(WIDTH_PIXELS), u32::from(HEIGHT_PIXELS));
-        for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-            let packed_pixel = video_device.buffer[(x + y * u32::from(WIDTH_PIXELS)) as usize];
-            *pixel = image::Rgb::from(<[u8; 3]>::from(unpack_rgb(packed_pixel)));
+        insta::assert_binary_snapshot!("last_frame.png", frame_as_png(&video_device));
+    }
+
+    #[test]
+    fn test_flipped_tiles() {
+        let mut video_device = new_test_pattern_video_device();
+        // Cycle through no flip, horizontal flip, vertical flip and both for each tile
+        for entry in &mut video_device.vram[0x0100..0x0D00] {
+            let tilemap_entry = TilemapEntry::from(*entry);
+            let flip = (tilemap_entry.tile_index() + *entry) % 4;
+            *entry = tilemap_entry
+                .with_flip_horizontal(u8::from(flip & 0x1 == 0x1))
+                .with_flip_vertical(u8::from(flip & 0x2 == 0x2))
+                .into();
         }
+        // Different scrolling for each layer so that the flipped tiles are offset from each other
+        video_device.ppu_registers.b2_scroll_x = 3.into();
+        video_device.ppu_registers.b3_scroll_y = 5.into();
 
-        let mut png_buffer = Cursor::new(Vec::new());
-        imgbuf
-            .write_to(&mut png_buffer, ImageFormat::Png)
-            .expect("Failed to write PNG data to buffer");
+        render_frame(&mut video_device);
 
-        let result = optimize_from_memory(
-            png_buffer.into_inner().as_slice(),
-            &Options {
-                // Strip metadata (e.g. create/update date) that causes the snapshot tests to fail
-                strip: StripChunks::All,
-                force: true,
-                ..Options::default()
-            },
+        insta::assert_binary_snapshot!("flipped_tiles.png", frame_as_png(&video_device));
+    }
+
+    #[test]
+    fn test_large_tiles() {
+        let mut video_device = new_test_pattern_video_device();
+        // Tiles are scaled up to 16x16 so they are 64 words each
+        let all_tiles = [DIGIT_TILES, TEST_PATTERNS];
+        for (tile_set_idx, tile_set) in all_tiles.iter().enumerate() {
+            for (digit_idx, tile) in tile_set.iter().enumerate() {
+                let tile_offset = 0x1000 + ((tile_set_idx * 10) + digit_idx) * 64;
+                for line in 0..16 {
+                    for read in 0..4 {
+                        let pixel = |i: usize| {
+                            let x = read * 4 + i;
+                            tile[(line / 2) * 8 + x / 2]
+                        };
+                        video_device.vram[tile_offset + line * 4 + read] = TileLine::new()
+                            .with_p1(pixel(0))
+                            .with_p2(pixel(1))
+                            .with_p3(pixel(2))
+                            .with_p4(pixel(3))
+                            .into();
+                    }
+                }
+            }
+        }
+        video_device.ppu_registers.b1_tile_addr = 0x9000;
+        video_device.ppu_registers.b2_tile_addr = 0x9000;
+        video_device.ppu_registers.b3_tile_addr = 0x9000;
+        video_device.ppu_registers.tile_size = TileSizeRegister::new()
+            .with_b1_size(TileSize::SixteenBySixteen)
+            .with_b2_size(TileSize::SixteenBySixteen)
+            .with_b3_size(TileSize::SixteenBySixteen);
+        // The tilemap is now larger than the screen, so scroll one layer to the bottom right
+        video_device.ppu_registers.b3_scroll_x = 200.into();
+        video_device.ppu_registers.b3_scroll_y = 280.into();
+
+        render_frame(&mut video_device);
+
+        insta::assert_binary_snapshot!("large_tiles.png", frame_as_png(&video_device));
+    }
+
+    #[test]
+    fn test_screen_brightness() {
+        let mut video_device = new_test_pattern_video_device();
+        video_device.ppu_registers.base_config =
+            BaseConfigRegister::new().with_screen_brightness(0x7);
+
+        render_frame(&mut video_device);
+
+        insta::assert_binary_snapshot!("half_brightness.png", frame_as_png(&video_device));
+
+        let mut video_device = new_test_pattern_video_device();
+        video_device.ppu_registers.base_config = BaseConfigRegister::new();
+
+        render_frame(&mut video_device);
+
+        assert!(video_device.buffer.iter().all(|pixel| *pixel == 0x0));
+    }
+
+    #[test]
+    fn test_disabled_layers() {
+        let mut video_device = new_test_pattern_video_device();
+        // Offset the layers so they can be told apart
+        video_device.ppu_registers.b2_scroll_x = 4.into();
+        video_device.ppu_registers.b2_scroll_y = 4.into();
+        video_device.ppu_registers.b3_scroll_x = 8.into();
+        video_device.ppu_registers.b3_scroll_y = 8.into();
+        video_device.ppu_registers.base_config =
+            video_device.ppu_registers.base_config.with_b3_disable(1);
+
+        render_frame(&mut video_device);
+
+        insta::assert_binary_snapshot!("disabled_layers.png", frame_as_png(&video_device));
+
+        // With every background disabled only the backdrop colour is drawn
+        let mut video_device = new_test_pattern_video_device();
+        video_device.ppu_registers.base_config = video_device
+            .ppu_registers
+            .base_config
+            .with_b1_disable(1)
+            .with_b2_disable(1)
+            .with_b3_disable(1);
+        render_frame(&mut video_device);
+        let backdrop = ppu_colour_to_minifb_rgb(video_device.palette[0]);
+        assert!(
+            (0..HEIGHT_PIXELS)
+                .all(|y| (8..WIDTH_PIXELS).all(|x| pixel_at(&video_device, x, y) == backdrop))
+        );
+
+        // Disabling graphics blanks the screen
+        let mut video_device = new_test_pattern_video_device();
+        video_device.palette[0] = PpuPixel::new().with_r(0x1F);
+        video_device.ppu_registers.base_config = video_device
+            .ppu_registers
+            .base_config
+            .with_graphics_disable(1);
+        render_frame(&mut video_device);
+        assert!(video_device.buffer.iter().all(|pixel| *pixel == 0x0));
+    }
+
+    const SPRITE_OAM_VRAM_OFFSET: u16 = 0x0000;
+    const SPRITE_TILE_VRAM_OFFSET: u16 = 0x1000;
+    const SPRITE_PALETTE_OFFSET: u8 = 0x20;
+
+    fn new_sprite_test_video_device() -> VideoDevice {
+        let mut video_device = new_video_device(21_477_272);
+        video_device.ppu_registers.base_config =
+            BaseConfigRegister::new().with_screen_brightness(MAX_SCREEN_BRIGHTNESS);
+        // Backgrounds are left transparent (all zero) so only the sprites are drawn
+        video_device.ppu_registers.b1_tilemap_addr = 0xC000;
+        video_device.ppu_registers.b2_tilemap_addr = 0xC000;
+        video_device.ppu_registers.b3_tilemap_addr = 0xC000;
+        video_device.ppu_registers.b1_tile_addr = 0xC000;
+        video_device.ppu_registers.b2_tile_addr = 0xC000;
+        video_device.ppu_registers.b3_tile_addr = 0xC000;
+        video_device.ppu_registers.s_attribute_table_addr = 0x8000 + SPRITE_OAM_VRAM_OFFSET;
+        video_device.ppu_registers.s_tile_addr = 0x8000 + SPRITE_TILE_VRAM_OFFSET;
+        video_device.ppu_registers.s_palette_config = PaletteRegister::new()
+            .with_palette_size(PaletteSize::Sixteen)
+            .with_palette_offset(SPRITE_PALETTE_OFFSET);
+        for i in 0..4 {
+            video_device.palette[SPRITE_PALETTE_OFFSET as usize + i] = PpuPixel::new()
+                .with_r(0x1F)
+                .with_g(u8::try_from(i * 8).unwrap())
+                .with_b(0x0);
+        }
+
+        // 8x8 tile 1 is filled with pixel value 1, apart from the first pixel of each line which is 2
+        for line in 0..8 {
+            let tile_line_address = usize::from(SPRITE_TILE_VRAM_OFFSET) + 16 + line * 2;
+            video_device.vram[tile_line_address] = 0x2111;
+            video_device.vram[tile_line_address + 1] = 0x1111;
+        }
+        // 16x16 tile 1 is filled with pixel value 3
+        video_device.vram
+            [usize::from(SPRITE_TILE_VRAM_OFFSET + 64)..usize::from(SPRITE_TILE_VRAM_OFFSET + 128)]
+            .fill(0x3333);
+
+        video_device
+    }
+
+    fn set_sprite(
+        video_device: &mut VideoDevice,
+        index: u16,
+        x: u16,
+        y: u8,
+        layer: u8,
+        entry: TilemapEntry,
+    ) {
+        let address = usize::from(SPRITE_OAM_VRAM_OFFSET + index * SPRITE_ATTRIBUTE_SIZE_WORDS);
+        video_device.vram[address] = SpritePositionX::new().with_x(x).with_layer(layer).into();
+        video_device.vram[address + 1] = SpritePositionY::new().with_y(y).into();
+        video_device.vram[address + 2] = entry.into();
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let mut video_device = new_test_pattern_video_device();
+        video_device.ppu_registers.b1_scroll_x = 3.into();
+        let polls_per_frame = u32::from(TOTAL_LINES) * u32::from(video_device.clocks_per_line / 2);
+        // Stop part way through a line so the fetch and sprite registers are mid flight
+        for _ in 0..polls_per_frame / 2 + 123 {
+            video_device.poll(BusAssertions::default(), true);
+        }
+        let state = video_device.save_state();
+
+        let mut restored_device = new_video_device(21_477_272);
+        restored_device.restore_state(&state).unwrap();
+        assert_eq!(state, restored_device.save_state());
+
+        render_frame(&mut video_device);
+        render_frame(&mut restored_device);
+        assert_eq!(video_device.frame_count, restored_device.frame_count);
+        assert_eq!(video_device.buffer, restored_device.buffer);
+    }
+
+    fn render_frame(video_device: &mut VideoDevice) {
+        let polls_per_frame = u32::from(TOTAL_LINES) * u32::from(video_device.clocks_per_line / 2);
+        for _ in 0..polls_per_frame {
+            video_device.poll(BusAssertions::default(), true);
+        }
+    }
+
+    fn pixel_at(video_device: &VideoDevice, x: u16, y: u16) -> u32 {
+        video_device.buffer[(x + y * WIDTH_PIXELS) as usize]
+    }
+
+    fn sprite_colour(video_device: &VideoDevice, pixel_value: u8) -> u32 {
+        ppu_colour_to_minifb_rgb(
+            video_device.palette[(SPRITE_PALETTE_OFFSET + pixel_value) as usize],
         )
-        .expect("Failed to optimize PNG");
-        insta::assert_binary_snapshot!("last_frame.png", result);
+    }
+
+    #[test]
+    fn test_sprites_are_drawn() {
+        let mut video_device = new_sprite_test_video_device();
+        let tile = TilemapEntry::new().with_tile_index(1);
+        set_sprite(&mut video_device, 0, 16, 20, 1, tile);
+        set_sprite(
+            &mut video_device,
+            1,
+            40,
+            20,
+            1,
+            tile.with_flip_horizontal(1),
+        );
+        // Layer 3 is set to 16x16
+        video_device.ppu_registers.tile_size = video_device
+            .ppu_registers
+            .tile_size
+            .with_s3_size(TileSize::SixteenBySixteen);
+        set_sprite(&mut video_device, 2, 80, 40, 3, tile);
+        // Layer 2 is disabled
+        video_device.ppu_registers.base_config =
+            video_device.ppu_registers.base_config.with_s2_disable(1);
+        set_sprite(&mut video_device, 3, 120, 20, 2, tile);
+        // Hidden sprite (layer 0)
+        set_sprite(&mut video_device, 4, 140, 20, 0, tile);
+
+        render_frame(&mut video_device);
+
+        let background = ppu_colour_to_minifb_rgb(video_device.palette[0]);
+        let one = sprite_colour(&video_device, 1);
+        let two = sprite_colour(&video_device, 2);
+        let three = sprite_colour(&video_device, 3);
+
+        // Sprite 0 covers 16-23 x 20-27
+        assert_eq!(two, pixel_at(&video_device, 16, 20));
+        assert_eq!(one, pixel_at(&video_device, 17, 20));
+        assert_eq!(one, pixel_at(&video_device, 23, 27));
+        assert_eq!(background, pixel_at(&video_device, 24, 20));
+        assert_eq!(background, pixel_at(&video_device, 16, 19));
+        assert_eq!(background, pixel_at(&video_device, 16, 28));
+
+        // Sprite 1 is flipped horizontally
+        assert_eq!(one, pixel_at(&video_device, 40, 20));
+        assert_eq!(two, pixel_at(&video_device, 47, 27));
+
+        // Sprite 2 is 16x16
+        assert_eq!(three, pixel_at(&video_device, 80, 40));
+        assert_eq!(three, pixel_at(&video_device, 95, 55));
+        assert_eq!(background, pixel_at(&video_device, 96, 40));
+        assert_eq!(background, pixel_at(&video_device, 80, 56));
+
+        // Sprites 3 and 4 are not drawn
+        assert_eq!(background, pixel_at(&video_device, 120, 20));
+        assert_eq!(background, pixel_at(&video_device, 140, 20));
+    }
+
+    #[test]
+    fn test_sprite_per_line_limit() {
+        let mut video_device = new_sprite_test_video_device();
+        let tile = TilemapEntry::new().with_tile_index(1);
+        for index in 0..17 {
+            set_sprite(&mut video_device, index, 16 + 12 * index, 100, 1, tile);
+        }
+
+        render_frame(&mut video_device);
+
+        let background = ppu_colour_to_minifb_rgb(video_device.palette[0]);
+        assert_eq!(
+            sprite_colour(&video_device, 1),
+            pixel_at(&video_device, 16 + 12 * 15 + 1, 100)
+        );
+        // The 17th sprite on the line is dropped
+        assert_eq!(background, pixel_at(&video_device, 16 + 12 * 16 + 1, 100));
+        assert_eq!(1, video_device.ppu_registers.status.sprite_overflow());
+    }
+
+    #[test]
+    fn test_sprite_layers_overlap() {
+        let mut video_device = new_sprite_test_video_device();
+        // The layer 2 sprite is drawn over the layer 1 sprite, even though it has a higher index
+        set_sprite(
+            &mut video_device,
+            0,
+            32,
+            50,
+            1,
+            TilemapEntry::new().with_tile_index(1),
+        );
+        set_sprite(
+            &mut video_device,
+            1,
+            36,
+            50,
+            2,
+            TilemapEntry::new().with_tile_index(1),
+        );
+
+        render_frame(&mut video_device);
+
+        assert_eq!(
+            sprite_colour(&video_device, 1),
+            pixel_at(&video_device, 35, 50)
+        );
+        assert_eq!(
+            sprite_colour(&video_device, 2),
+            pixel_at(&video_device, 36, 50)
+        );
+        assert_eq!(0, video_device.ppu_registers.status.sprite_overflow());
+    }
+
+    #[test]
+    fn test_sprite_priority_mixing() {
+        let sprite = |priority| SpritePixel {
+            colour: 0x40,
+            priority,
+            layer: 1,
+        };
+        // Order is [BG3, BG2, BG1]
+        let pixel_values = [0x0, 0x2, 0x3];
+        let palette_offsets = [0x10, 0x20, 0x30];
+
+        // Sprites without priority go behind background tiles with priority
+        assert_eq!(
+            0x22,
+            resolve_first_visible_pixel(pixel_values, palette_offsets, [0, 1, 0], sprite(false))
+        );
+        // ...but in front of background tiles without priority
+        assert_eq!(
+            0x40,
+            resolve_first_visible_pixel(pixel_values, palette_offsets, [0, 0, 0], sprite(false))
+        );
+        // Sprites with priority are in front of everything
+        assert_eq!(
+            0x40,
+            resolve_first_visible_pixel(pixel_values, palette_offsets, [1, 1, 1], sprite(true))
+        );
+        // A priority tile on a lower background is in front of a non-priority tile on a higher one
+        assert_eq!(
+            0x33,
+            resolve_first_visible_pixel(
+                pixel_values,
+                palette_offsets,
+                [0, 0, 1],
+                SpritePixel::default()
+            )
+        );
+        assert_eq!(
+            0x22,
+            resolve_first_visible_pixel(
+                pixel_values,
+                palette_offsets,
+                [0, 0, 0],
+                SpritePixel::default()
+            )
+        );
+    }
+
+    // The first two groups of pixels on each line are flushed, and the output is delayed by a group
+    const FIRST_DRAWN_PIXEL: u16 = 8;
+    const OUTPUT_DELAY_PIXELS: u16 = 4;
+    const SINGLE_TILEMAP_PIXELS: u16 = TILEMAP_SIZE * 8;
+    // Each extra screen of a double size test tilemap is the test tilemap shifted by this many tiles
+    const SCREEN_SHIFT_COLUMNS: u16 = 16;
+    const SCREEN_SHIFT_ROWS: u16 = 8;
+
+    /// The test tilemap, shifted by a number of tiles so that each screen of a double size
+    /// tilemap can be told apart
+    fn shifted_test_tilemap(columns: u16, rows: u16) -> Vec<u16> {
+        (0..TILEMAP_SIZE * TILEMAP_SIZE)
+            .map(|i| {
+                let x = (i % TILEMAP_SIZE + columns) % TILEMAP_SIZE;
+                let y = (i / TILEMAP_SIZE + rows) % TILEMAP_SIZE;
+                TEST_TILEMAP[usize::from(x + y * TILEMAP_SIZE)]
+            })
+            .collect()
+    }
+
+    fn new_background_test_video_device() -> VideoDevice {
+        let mut video_device = new_video_device(21_477_272);
+        video_device.ppu_registers.base_config =
+            BaseConfigRegister::new().with_screen_brightness(MAX_SCREEN_BRIGHTNESS);
+        copy_palettes_to_vram(&mut video_device);
+        copy_tiles_to_vram(&mut video_device);
+
+        // Only BG1 is drawn, the other layers point to empty VRAM
+        video_device.ppu_registers.b1_tilemap_addr = 0xC000;
+        video_device.ppu_registers.b1_tile_addr = 0x9000;
+        video_device.ppu_registers.b2_tilemap_addr = 0xE000;
+        video_device.ppu_registers.b3_tilemap_addr = 0xE000;
+        video_device.ppu_registers.b2_tile_addr = 0xE000;
+        video_device.ppu_registers.b3_tile_addr = 0xE000;
+        video_device.ppu_registers.s_attribute_table_addr = 0xE000;
+        video_device.ppu_registers.b1_palette_config = PaletteRegister::new()
+            .with_palette_size(PaletteSize::Sixteen)
+            .with_palette_offset(0);
+        video_device
+    }
+
+    fn render_background(scroll: (u16, u16), double_size: (bool, bool)) -> Vec<u32> {
+        let tilemap_size = |double| {
+            if double {
+                TilemapSize::Double
+            } else {
+                TilemapSize::Single
+            }
+        };
+        let mut video_device = new_background_test_video_device();
+        // The screens of a double size tilemap are stored one after the other, left to right
+        // then top to bottom
+        let width_screens = if double_size.0 { 2 } else { 1 };
+        let height_screens = if double_size.1 { 2 } else { 1 };
+        for screen_y in 0..height_screens {
+            for screen_x in 0..width_screens {
+                let start = 0x4000 + usize::from(screen_x + screen_y * width_screens) * 0x400;
+                video_device.vram[start..start + 0x400].copy_from_slice(
+                    shifted_test_tilemap(
+                        screen_x * SCREEN_SHIFT_COLUMNS,
+                        screen_y * SCREEN_SHIFT_ROWS,
+                    )
+                    .as_slice(),
+                );
+            }
+        }
+        video_device.ppu_registers.b1_scroll_x = scroll.0.into();
+        video_device.ppu_registers.b1_scroll_y = scroll.1.into();
+        video_device.ppu_registers.tile_size = video_device
+            .ppu_registers
+            .tile_size
+            .with_b1_tilemap_size_x(tilemap_size(double_size.0))
+            .with_b1_tilemap_size_y(tilemap_size(double_size.1));
+        render_frame(&mut video_device);
+        video_device.buffer
+    }
+
+    /// Renders BG1 with a scroll and checks every pixel against the unscrolled frame.
+    ///
+    /// Extra screens of a double size tilemap are the test tilemap shifted, so every pixel in
+    /// the tilemap can be mapped back to a pixel in the single screen test tilemap.
+    fn assert_background_scroll(scroll: (u16, u16), double_size: (bool, bool)) {
+        let reference = render_background((0, 0), (false, false));
+        let scrolled = render_background(scroll, double_size);
+
+        let map_width = SINGLE_TILEMAP_PIXELS * if double_size.0 { 2 } else { 1 };
+        let map_height = SINGLE_TILEMAP_PIXELS * if double_size.1 { 2 } else { 1 };
+        let mut compared_pixels = 0;
+        for y in 0..HEIGHT_PIXELS {
+            for x in FIRST_DRAWN_PIXEL..WIDTH_PIXELS {
+                let map_x = (x - OUTPUT_DELAY_PIXELS + scroll.0) % map_width;
+                let map_y = (y + scroll.1) % map_height;
+                let screen_x = map_x / SINGLE_TILEMAP_PIXELS;
+                let screen_y = map_y / SINGLE_TILEMAP_PIXELS;
+                let reference_x = (map_x + screen_x * SCREEN_SHIFT_COLUMNS * 8)
+                    % SINGLE_TILEMAP_PIXELS
+                    + OUTPUT_DELAY_PIXELS;
+                let reference_y =
+                    (map_y + screen_y * SCREEN_SHIFT_ROWS * 8) % SINGLE_TILEMAP_PIXELS;
+                if !(FIRST_DRAWN_PIXEL..WIDTH_PIXELS).contains(&reference_x)
+                    || reference_y >= HEIGHT_PIXELS
+                {
+                    // Not visible in the unscrolled frame
+                    continue;
+                }
+                assert_eq!(
+                    reference[usize::from(reference_x + reference_y * WIDTH_PIXELS)],
+                    scrolled[usize::from(x + y * WIDTH_PIXELS)],
+                    "Pixel ({x}, {y}) with scroll {scroll:?} should match unscrolled pixel ({reference_x}, {reference_y})"
+                );
+                compared_pixels += 1;
+            }
+        }
+        assert!(compared_pixels > 0);
+    }
+
+    #[test]
+    fn test_background_coarse_scroll() {
+        assert_background_scroll((8, 0), (false, false));
+        assert_background_scroll((0, 8), (false, false));
+        assert_background_scroll((32, 16), (false, false));
+    }
+
+    #[test]
+    fn test_background_fine_scroll() {
+        assert_background_scroll((1, 0), (false, false));
+        assert_background_scroll((3, 0), (false, false));
+        assert_background_scroll((6, 0), (false, false));
+        assert_background_scroll((0, 5), (false, false));
+        assert_background_scroll((13, 7), (false, false));
+    }
+
+    #[test]
+    fn test_background_scroll_wraps_around() {
+        assert_background_scroll((200, 0), (false, false));
+        assert_background_scroll((0, 240), (false, false));
+        assert_background_scroll((255, 255), (false, false));
+        // Scroll values are larger than the tilemap
+        assert_background_scroll((0x3FF, 0x2F0), (false, false));
+    }
+
+    #[test]
+    fn test_background_double_size_tilemaps() {
+        assert_background_scroll((256, 0), (true, false));
+        assert_background_scroll((383, 0), (true, false));
+        assert_background_scroll((0, 250), (false, true));
+        assert_background_scroll((0, 500), (false, true));
+        assert_background_scroll((300, 200), (true, true));
+        assert_background_scroll((450, 450), (true, true));
     }
 }
@@ -3,6 +3,7 @@
 
 use modular_bitfield::prelude::*;
 use peripheral_bus::memory_mapped_device::MemoryMapped;
+use serde::{Deserialize, Serialize};
 use std::ops::{Index, IndexMut};
 
 const READONLY_STATUS_REGISTER_ADDR: u32 = 0x0017;
@@ -13,7 +14,7 @@ const READONLY_STATUS_REGISTER_ADDR: u32 = 0x0017;
 /// The PPU supports 15 bit colour (5 bbp)
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct PpuPixel {
     pub b: B5,
     pub g: B5,
@@ -23,7 +24,7 @@ pub struct PpuPixel {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct TilemapEntry {
     pub tile_index: B10,     // Bits 0-9 (LSB)
     pub palette_select: B3,  // Bits 10-12
@@ -34,7 +35,7 @@ pub struct TilemapEntry {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct TileLine {
     // TODO: Rename these to pixel because p could mean palette or pixel
     pub p4: B4,
@@ -43,8 +44,21 @@ pub struct TileLine {
     pub p1: B4,
 }
 
+impl TileLine {
+    /// Returns the pixel value at an index (0-3, left to right)
+    pub fn pixel(self, index: u16) -> u8 {
+        match index {
+            0 => self.p1(),
+            1 => self.p2(),
+            2 => self.p3(),
+            3 => self.p4(),
+            _ => panic!("Fatal: No pixel in tile line for index [{index}]"),
+        }
+    }
+}
+
 pub const PIXEL_BUFFER_SIZE: u16 = 4;
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PixelBuffer {
     pub p1: PpuPixel,
     pub p2: PpuPixel,
@@ -89,7 +103,26 @@ impl IndexMut<u8> for PixelBuffer {
 // Palette Selection
 // Tile index
 
-#[derive(Debug, Eq, PartialEq)]
+/// The first word of an object attribute table entry
+#[bitfield(bits = 16)]
+#[repr(u16)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct SpritePositionX {
+    pub x: B9,        // Bits 0-8 (LSB) (256-511 are off the left edge of the screen)
+    pub reserved: B5, // Bits 9-13
+    pub layer: B2,    // Bits 14-15 (MSB) (0 = hidden, 1-3 = S1-S3)
+}
+
+/// The second word of an object attribute table entry
+#[bitfield(bits = 16)]
+#[repr(u16)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct SpritePositionY {
+    pub y: B8,        // Bits 0-7 (LSB) (wraps around, so 255 is one line above the screen)
+    pub reserved: B8, // Bits 8-15 (MSB)
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Backgrounds {
     Bg1,
     Bg2,
@@ -98,7 +131,7 @@ pub enum Backgrounds {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct BaseConfigRegister {
     // Note: Fields declared in reverse order from documentation!
     pub reserved_3: B3,        // Bits 0-2
@@ -136,7 +169,7 @@ pub enum PaletteSize {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct TileSizeRegister {
     // Note: Fields declared in reverse order from documentation!
     pub reserved_4: B1,
@@ -159,7 +192,7 @@ pub struct TileSizeRegister {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct ScrollRegister {
     // Note: Fields declared in reverse order from documentation!
     pub scroll_amount: B10,
@@ -168,7 +201,7 @@ pub struct ScrollRegister {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct PaletteRegister {
     // Note: Fields declared in reverse order from documentation!
     pub palette_size: PaletteSize,
@@ -184,23 +217,25 @@ pub enum OutputMode {
 
 #[bitfield(bits = 16)]
 #[repr(u16)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct StatusRegister {
     // Note: Fields declared in reverse order from documentation!
     pub ppu_version: B4,
     pub output_mode: OutputMode,
-    pub reserved: B11,
+    // Set when more sprites than the per-line limit were found on a line. Cleared at the start of each frame.
+    pub sprite_overflow: B1,
+    pub reserved: B10,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PpuRegisters {
     pub base_config: BaseConfigRegister,
     pub tile_size: TileSizeRegister,
     pub b1_tilemap_addr: u16, // Base address to read the tilemap from (the tile definitions)
     pub b2_tilemap_addr: u16,
     pub b3_tilemap_addr: u16,
-    pub reserved: u16,
-    pub b1_tile_addr: u16, // Base address to read the actual tile data from
+    pub s_attribute_table_addr: u16, // Base address of the object attribute table (the sprite definitions)
+    pub b1_tile_addr: u16,           // Base address to read the actual tile data from
     pub b2_tile_addr: u16,
     pub b3_tile_addr: u16,
     pub reserved2: u16,
@@ -215,7 +250,7 @@ pub struct PpuRegisters {
     pub b1_palette_config: PaletteRegister,
     pub b2_palette_config: PaletteRegister,
     pub b3_palette_config: PaletteRegister,
-    pub reserved5: u16,
+    pub s_palette_config: PaletteRegister,
     pub s_tile_addr: u16,
     pub status: StatusRegister,
 }
@@ -230,7 +265,7 @@ impl MemoryMapped for PpuRegisters {
             0x0002 => self.b1_tilemap_addr,
             0x0003 => self.b2_tilemap_addr,
             0x0004 => self.b3_tilemap_addr,
-            0x0005 => self.reserved,
+            0x0005 => self.s_attribute_table_addr,
             0x0006 => self.b1_tile_addr,
             0x0007 => self.b2_tile_addr,
             0x0008 => self.b3_tile_addr,
@@ -246,7 +281,7 @@ impl MemoryMapped for PpuRegisters {
             0x0012 => self.b1_palette_config.into(),
             0x0013 => self.b2_palette_config.into(),
             0x0014 => self.b3_palette_config.into(),
-            0x0015 => self.reserved5,
+            0x0015 => self.s_palette_config.into(),
             0x0016 => self.s_tile_addr,
             READONLY_STATUS_REGISTER_ADDR => u16::from_be_bytes(self.status.bytes),
             _ => 0x0, // Open bus
@@ -260,7 +295,7 @@ impl MemoryMapped for PpuRegisters {
             0x0002 => self.b1_tilemap_addr = value,
             0x0003 => self.b2_tilemap_addr = value,
             0x0004 => self.b3_tilemap_addr = value,
-            0x0005 => self.reserved = value,
+            0x0005 => self.s_attribute_table_addr = value,
             0x0006 => self.b1_tile_addr = value,
             0x0007 => self.b2_tile_addr = value,
             0x0008 => self.b3_tile_addr = value,
@@ -276,7 +311,7 @@ impl MemoryMapped for PpuRegisters {
             0x0012 => self.b1_palette_config = value.into(),
             0x0013 => self.b2_palette_config = value.into(),
             0x0014 => self.b3_palette_config = value.into(),
-            0x0015 => self.reserved5 = value,
+            0x0015 => self.s_palette_config = value.into(),
             0x0016 => self.s_tile_addr = value,
             // 0x0017 Read Only (status)
             _ => {} // Open bus
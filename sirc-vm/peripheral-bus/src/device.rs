@@ -1,6 +1,10 @@
 use std::any::Any;
 use std::ops::BitOr;
 
+use serde::{Deserialize, Serialize};
+
+use crate::save_state::SaveStateError;
+
 // TODO: Make sure at some point to not have duplicate exception level definitions
 // category=Refactoring
 // The CPU is technically the source of truth because it exposes the pins that the bus connects to
@@ -15,7 +19,7 @@ pub const LEVEL_FIVE_INTERRUPT: u8 = 0x10;
 /// Bus Access Type bits (BAT0-BAT2): output by the CPU to describe what I/O operation it is performing.
 /// Spec: chapter 02 §Bus Access Type Bits (BAT0-BAT2).
 #[repr(u8)]
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BusAccessType {
     #[default]
     None = 0b000,
@@ -56,7 +60,7 @@ impl BitOr for BusAccessType {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub enum BusOperation {
     #[default]
     Read,
@@ -116,7 +120,7 @@ impl BitOr for BusAssertions {
 /// This means that if you capture the state of the bus in a debugger it is not going to electrically
 /// match what the bus on real hardware would look like. This will probably be fixed up at some point
 /// now that the reference manual specifies active high/active low for different pins.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct BusAssertions {
     /// Pins: A0-A23
@@ -217,6 +221,17 @@ pub trait Device {
     fn dump_diagnostic(&self) -> String {
         String::from("TODO")
     }
+    /// Serializes the internal state of the device (registers, memory, counters etc.) so that it
+    /// can be written to a save state. See `peripheral_bus::save_state::encode_state`.
+    /// Default is empty for devices that don't have any state that needs to be saved.
+    fn save_state(&self) -> Vec<u8> {
+        vec![]
+    }
+    /// Restores the internal state of the device from the output of `save_state`.
+    /// Host resources (windows, files, stdin etc.) are left as they are.
+    fn restore_state(&mut self, _state: &[u8]) -> Result<(), SaveStateError> {
+        Ok(())
+    }
     // TODO: Refactor bus device interfaces to not need `Any`
     // category=Refactoring
     // This is a hopefully temporary hack that should only be used for testing - remove once a proper way to access CPU registers has been found
@@ -22,16 +22,20 @@ pub mod device;
 pub mod helpers;
 pub mod memory_mapped_device;
 pub mod reset_unit;
+pub mod save_state;
+pub mod write_journal;
 
 use std::fs::read;
 use std::path::Path;
 
 use std::ops::BitOr;
 
-use device::{BusAssertions, Device};
+use device::{BusAssertions, BusOperation, Device};
 use log::{debug, warn};
 use memory_mapped_device::MemoryMappedDevice;
 use reset_unit::ResetUnit;
+use save_state::{BusState, SaveStateError, SegmentState, SAVE_STATE_VERSION};
+use write_journal::JournalledWrite;
 
 pub struct Segment {
     pub label: String,
@@ -54,6 +58,7 @@ pub struct BusPeripheral {
     pub bus_master: Box<dyn Device>,
     segments: Vec<Segment>,
     reset_unit: ResetUnit,
+    write_journal: Option<Vec<JournalledWrite>>,
 }
 
 #[must_use]
@@ -62,6 +67,7 @@ pub fn new_bus_peripheral(bus_master: Box<dyn Device>) -> BusPeripheral {
         bus_master,
         segments: vec![],
         reset_unit: ResetUnit::new(),
+        write_journal: None,
     }
 }
 
@@ -221,6 +227,41 @@ impl BusPeripheral {
         });
     }
 
+    ///
+    /// Starts recording every write that the bus master makes (see `take_write_journal`)
+    ///
+    pub fn enable_write_journal(&mut self) {
+        self.write_journal.get_or_insert_with(Vec::new);
+    }
+
+    ///
+    /// Returns the writes that have been recorded since the last call and clears the journal.
+    /// Always empty if `enable_write_journal` hasn't been called.
+    ///
+    pub fn take_write_journal(&mut self) -> Vec<JournalledWrite> {
+        self.write_journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    ///
+    /// Puts back the values that were overwritten by the given writes, in reverse order.
+    ///
+    /// Unlike `write_address`, this ignores whether a segment is writable because the bus master
+    /// could have written to it in the first place.
+    ///
+    pub fn undo_writes(&mut self, writes: &[JournalledWrite]) {
+        for write in writes.iter().rev() {
+            if let Some(segment) = self.get_segment_for_address(write.address) {
+                let relative_address = write.address - segment.address;
+                segment
+                    .device
+                    .write_address(relative_address, write.previous_value);
+            }
+        }
+    }
+
     ///
     /// Runs each device, and then combines all their bus assertions into a single one.
     ///
@@ -238,6 +279,26 @@ impl BusPeripheral {
             self.bus_master.poll(assertions, true)
         };
         let segments = &mut self.segments;
+        if let Some(write_journal) = self.write_journal.as_mut() {
+            if master_assertions.bus_access_strobe
+                && matches!(master_assertions.op, BusOperation::Write)
+            {
+                // Devices may take more than one poll to perform a write, so the same write can be
+                // journalled more than once. Undoing them in reverse order still ends up with the
+                // value from before the first one.
+                if let Some(segment) = segments
+                    .iter()
+                    .find(|s| s.address_is_in_segment_range(master_assertions.address))
+                {
+                    write_journal.push(JournalledWrite {
+                        address: master_assertions.address,
+                        previous_value: segment
+                            .device
+                            .read_address(master_assertions.address - segment.address),
+                    });
+                }
+            }
+        }
         let out = segments
             .iter_mut()
             .map(|segment| {
@@ -255,6 +316,83 @@ impl BusPeripheral {
         out
     }
 
+    ///
+    /// Captures the state of the bus master, the reset unit and every mapped device so that it can
+    /// be restored with `restore_state`.
+    ///
+    /// `bus_assertions` is the output of the last `poll_all` (the input to the next one), which
+    /// includes any bus requests that are in flight.
+    ///
+    #[must_use]
+    pub fn save_state(&self, bus_assertions: BusAssertions) -> Vec<u8> {
+        save_state::encode_state(&BusState {
+            version: SAVE_STATE_VERSION,
+            bus_assertions,
+            reset_hold_cycles: self.reset_unit.hold_cycles(),
+            bus_master_state: self.bus_master.save_state(),
+            segments: self
+                .segments
+                .iter()
+                .map(|segment| SegmentState {
+                    label: segment.label.clone(),
+                    address: segment.address,
+                    size: segment.size,
+                    device_state: segment.device.save_state(),
+                })
+                .collect(),
+        })
+    }
+
+    ///
+    /// Restores the state captured by `save_state` and returns the bus assertions to feed into
+    /// the next `poll_all`.
+    ///
+    /// The segments must already be mapped in the same way as when the state was saved.
+    ///
+    pub fn restore_state(&mut self, state: &[u8]) -> Result<BusAssertions, SaveStateError> {
+        let state: BusState = save_state::decode_state(state)?;
+        if state.version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion {
+                found: state.version,
+                expected: SAVE_STATE_VERSION,
+            });
+        }
+        let layout_matches = state.segments.len() == self.segments.len()
+            && state
+                .segments
+                .iter()
+                .zip(&self.segments)
+                .all(|(saved, segment)| {
+                    saved.label == segment.label
+                        && saved.address == segment.address
+                        && saved.size == segment.size
+                });
+        if !layout_matches {
+            let expected: Vec<SegmentState> = self
+                .segments
+                .iter()
+                .map(|segment| SegmentState {
+                    label: segment.label.clone(),
+                    address: segment.address,
+                    size: segment.size,
+                    device_state: vec![],
+                })
+                .collect();
+            return Err(SaveStateError::SegmentMismatch {
+                found: SegmentState::describe_layout(&state.segments),
+                expected: SegmentState::describe_layout(&expected),
+            });
+        }
+
+        self.bus_master.restore_state(&state.bus_master_state)?;
+        self.reset_unit.set_hold_cycles(state.reset_hold_cycles);
+        for (saved, segment) in state.segments.iter().zip(&mut self.segments) {
+            debug!("Restoring segment {}", segment.label);
+            segment.device.restore_state(&saved.device_state)?;
+        }
+        Ok(state.bus_assertions)
+    }
+
     /// Runs the CPU for six cycles. Only to keep tests functioning at the moment. Will be removed
     ///
     /// # Panics
@@ -16,6 +16,16 @@ impl ResetUnit {
         Self { hold_cycles: 0 }
     }
 
+    #[must_use]
+    pub const fn hold_cycles(&self) -> u8 {
+        self.hold_cycles
+    }
+
+    /// Only used to restore save states
+    pub fn set_hold_cycles(&mut self, hold_cycles: u8) {
+        self.hold_cycles = hold_cycles;
+    }
+
     pub fn should_reset(&mut self, assertions: BusAssertions, bus_master: &mut dyn Device) -> bool {
         if assertions.reset_requested {
             bus_master.reset();
@@ -0,0 +1,104 @@
+//!
+//! Support for saving the state of a running system so it can be restored later.
+//!
+//! Each device serializes its own internal state (see `Device::save_state`) into an opaque blob
+//! of bytes, and the `BusPeripheral` bundles them all up along with the layout of the segments.
+//! This keeps the `Device` trait object safe and means the bus doesn't need to know anything
+//! about the devices that are connected to it.
+//!
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::device::BusAssertions;
+
+/// Bumped whenever the save state format changes in a way that old files can't be read
+pub const SAVE_STATE_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum SaveStateError {
+    #[error("Save state version {found} is not supported (expected {expected})")]
+    UnsupportedVersion { found: u32, expected: u32 },
+    #[error("Save state segments [{found}] do not match the segments of this system [{expected}]")]
+    SegmentMismatch { found: String, expected: String },
+    #[error("Save state does not match this device: {0}")]
+    DeviceMismatch(String),
+    #[error("Could not decode save state: {0}")]
+    Decode(#[from] postcard::Error),
+}
+
+///
+/// Encodes the state of a device into bytes. Used to implement `Device::save_state`.
+///
+/// # Panics
+/// Will panic if the state cannot be serialized, which should only happen if the state contains
+/// a type that serde can't handle (which would be a bug)
+///
+#[must_use]
+pub fn encode_state<T: Serialize>(state: &T) -> Vec<u8> {
+    postcard::to_allocvec(state).expect("device state should always be serializable")
+}
+
+/// Decodes the state of a device from bytes. Used to implement `Device::restore_state`.
+pub fn decode_state<T: DeserializeOwned>(state: &[u8]) -> Result<T, SaveStateError> {
+    Ok(postcard::from_bytes(state)?)
+}
+
+/// The state of a single segment and the device mapped to it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SegmentState {
+    pub label: String,
+    pub address: u32,
+    pub size: u32,
+    pub device_state: Vec<u8>,
+}
+
+impl SegmentState {
+    pub(crate) fn describe_layout(segments: &[Self]) -> String {
+        segments
+            .iter()
+            .map(|segment| {
+                format!(
+                    "{}:{:08X}:{:X}",
+                    segment.label, segment.address, segment.size
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The state of everything connected to the bus
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BusState {
+    pub version: u32,
+    /// The assertions that will be fed into the next poll of the bus
+    pub bus_assertions: BusAssertions,
+    pub reset_hold_cycles: u8,
+    pub bus_master_state: Vec<u8>,
+    pub segments: Vec<SegmentState>,
+}
+
+/// Serde helpers for fixed size arrays that are too big for serde's built in support (> 32 items)
+///
+/// Use with `#[serde(with = "peripheral_bus::save_state::big_array")]`
+pub mod big_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, T: Serialize, const N: usize>(
+        array: &[T; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        array.as_slice().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[T; N], D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        let length = items.len();
+        items
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(length, &format!("{N} items").as_str()))
+    }
+}
@@ -0,0 +1,14 @@
+//!
+//! Records the memory writes made by the bus master so that they can be undone later
+//! (e.g. to step backwards in the debugger).
+//!
+//! The journal is disabled by default so that it doesn't slow down the VM when it isn't needed.
+//!
+
+/// A single write made by the bus master
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalledWrite {
+    pub address: u32,
+    /// The value at the address before the write happened
+    pub previous_value: u16,
+}
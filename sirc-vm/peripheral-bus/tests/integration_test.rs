@@ -21,9 +21,10 @@ use std::{fs::OpenOptions, io::Write, path::Path};
 
 use peripheral_bus::{
     conversion::{bytes_to_words, words_to_bytes},
-    device::new_stub_device,
+    device::{new_stub_device, BusAssertions},
     memory_mapped_device::new_stub_memory_mapped_device,
     new_bus_peripheral,
+    save_state::SaveStateError,
 };
 use quickcheck::TestResult;
 use tempfile::tempdir;
@@ -90,6 +91,43 @@ fn readonly_segment_test() {
     assert_eq!(0x0, mem.read_address(in_bounds_address));
 }
 
+#[test]
+fn save_state_round_trip_test() {
+    let new_bus = |segment_address: u32| {
+        let mut mem = new_bus_peripheral(Box::new(new_stub_device()));
+        mem.map_segment(
+            "some_segment",
+            segment_address,
+            0xF,
+            true,
+            Box::new(new_stub_memory_mapped_device()),
+        );
+        mem
+    };
+
+    let state = new_bus(0xCAFE_BEEF).save_state(BusAssertions {
+        address: 0x00CA_FEBE,
+        data: 0xF00D,
+        bus_access_strobe: true,
+        ..BusAssertions::default()
+    });
+
+    let restored_assertions = new_bus(0xCAFE_BEEF).restore_state(&state).unwrap();
+    assert_eq!(0x00CA_FEBE, restored_assertions.address);
+    assert_eq!(0xF00D, restored_assertions.data);
+    assert!(restored_assertions.bus_access_strobe);
+
+    // A state can only be restored into a bus that has the same layout
+    let result = new_bus(0xCAFE_0000).restore_state(&state);
+    assert!(matches!(
+        result,
+        Err(SaveStateError::SegmentMismatch { .. })
+    ));
+
+    let result = new_bus(0xCAFE_BEEF).restore_state(&state[..4]);
+    assert!(matches!(result, Err(SaveStateError::Decode(_))));
+}
+
 // TODO: Uncomment test and move to `RamDevice` where it belongs
 // category=Testing
 // #[test]
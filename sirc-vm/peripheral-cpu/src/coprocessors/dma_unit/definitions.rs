@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+// DMAR/DMAW operand layout (bits 7-0 of the coprocessor command)
+// Bits 7-6 select the address register, bit 5 selects the direction, bits 4-3 are reserved and
+// bits 2-0 hold the count magnitude.
+pub const DMA_ADDRESS_REGISTER_MASK: u8 = 0b1100_0000;
+pub const DMA_ADDRESS_REGISTER_LENGTH: u8 = 6;
+pub const DMA_DIRECTION_MASK: u8 = 0b0010_0000;
+pub const DMA_RESERVED_OPERAND_MASK: u8 = 0b0001_1000;
+pub const DMA_REGISTER_COUNT_MASK: u8 = 0b0000_0111;
+
+/// The number of general purpose registers (r1-r7) that the DMA unit can use as a transfer window.
+///
+/// DMAT transfers are split into chunks of this size.
+pub const DMA_TRANSFER_WINDOW_SIZE: u8 = 7;
+
+#[repr(u8)]
+#[derive(
+    Default, Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize, Deserialize,
+)]
+pub enum DmaUnitOpCodes {
+    #[default]
+    None = 0x0,
+
+    // Privileged
+    /// Read memory at an address register into r1-rn (DMAR)
+    ReadToRegisters = 0x8,
+    /// Write r1-rn to memory at an address register (DMAW)
+    WriteFromRegisters = 0x9,
+    /// Copy memory at a to memory at l using r1-r7 as a transfer window (DMAT)
+    MemoryTransfer = 0xA,
+}
+
+/// The address registers that DMAR/DMAW can operate on (encoded in bits 7-6 of the operand)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum DmaAddressRegister {
+    Address = 0b00,
+    Link = 0b01,
+    StackPointer = 0b10,
+}
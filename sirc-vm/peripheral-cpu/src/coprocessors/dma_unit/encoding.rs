@@ -0,0 +1,15 @@
+const OP_CODE_LENGTH: u16 = 8; // bits
+const OP_CODE_MASK: u16 = 0x0F00;
+const VALUE_MASK: u16 = 0x00FF;
+
+#[derive(Default, Debug)]
+pub struct DmaUnitInstruction {
+    pub op_code: u8,
+    pub value: u8,
+}
+
+pub fn decode_dma_unit_instruction(cause_register_value: u16) -> DmaUnitInstruction {
+    let op_code = ((cause_register_value & OP_CODE_MASK) >> OP_CODE_LENGTH) as u8;
+    let value = (cause_register_value & VALUE_MASK) as u8;
+    DmaUnitInstruction { op_code, value }
+}
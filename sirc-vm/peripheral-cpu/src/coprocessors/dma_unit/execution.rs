@@ -0,0 +1,404 @@
+use log::{debug, trace, warn};
+use peripheral_bus::device::{BusAccessType, BusAssertions, BusOperation};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    definitions::{
+        DmaAddressRegister, DmaUnitOpCodes, DMA_ADDRESS_REGISTER_LENGTH, DMA_ADDRESS_REGISTER_MASK,
+        DMA_DIRECTION_MASK, DMA_REGISTER_COUNT_MASK, DMA_RESERVED_OPERAND_MASK,
+        DMA_TRANSFER_WINDOW_SIZE,
+    },
+    encoding::decode_dma_unit_instruction,
+};
+use crate::{
+    coprocessors::{
+        exception_unit::definitions::Faults,
+        shared::{ExecutionPhase, Executor},
+    },
+    raise_fault,
+    registers::{
+        sr_bit_is_set, ExceptionUnitRegisters, RegisterName, Registers, SegmentedAddress,
+        StatusRegisterFields,
+    },
+};
+
+/// An internal copy of an address register that the DMA unit walks through memory with.
+///
+/// Only the low word is ever advanced, the high word (segment) is fixed for the whole transfer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DmaPointer {
+    /// Index of the high word of the address register the pointer was loaded from (e.g. `ah`).
+    /// The low word is always the next register.
+    pub register_index: u8,
+    /// If set, the address register is advanced along with the pointer.
+    /// Backward transfers move the address register once up front and then leave it alone.
+    pub advance_register: bool,
+    pub high: u16,
+    pub low: u16,
+}
+
+impl DmaPointer {
+    fn load(registers: &Registers, register_index: u8, advance_register: bool) -> Self {
+        Self {
+            register_index,
+            advance_register,
+            high: registers[register_index],
+            low: registers[register_index + 1],
+        }
+    }
+
+    fn full_address(self) -> u32 {
+        (self.high, self.low).to_full_address()
+    }
+}
+
+/// The state of a DMA command that has been accepted but not yet completed.
+///
+/// Transfers are broken up into chunks that fit into the r1-r7 transfer window. Within a chunk, all
+/// the reads happen first (into r1, r2, ...) followed by all the writes (from r1, r2, ...).
+/// DMAR only reads and DMAW only writes, so they always fit into a single chunk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DmaTransfer {
+    pub op_code: DmaUnitOpCodes,
+    pub source: DmaPointer,
+    pub destination: DmaPointer,
+    /// Words that have not been scheduled into a chunk yet
+    pub remaining: u8,
+    pub chunk_size: u8,
+    /// Index of the next bus access within the current chunk
+    pub position: u8,
+}
+
+impl DmaTransfer {
+    fn reads(&self) -> bool {
+        matches!(
+            self.op_code,
+            DmaUnitOpCodes::ReadToRegisters | DmaUnitOpCodes::MemoryTransfer
+        )
+    }
+
+    fn writes(&self) -> bool {
+        matches!(
+            self.op_code,
+            DmaUnitOpCodes::WriteFromRegisters | DmaUnitOpCodes::MemoryTransfer
+        )
+    }
+
+    fn accesses_per_chunk(&self) -> u8 {
+        self.chunk_size * (u8::from(self.reads()) + u8::from(self.writes()))
+    }
+
+    /// Returns the operation and the general purpose register index used by the next bus access
+    fn next_access(&self) -> (BusOperation, u8) {
+        if self.reads() && self.position < self.chunk_size {
+            (BusOperation::Read, self.position + 1)
+        } else {
+            let read_count = if self.reads() { self.chunk_size } else { 0 };
+            (BusOperation::Write, self.position - read_count + 1)
+        }
+    }
+}
+
+const fn bus_access_type_for_operation(op: BusOperation) -> BusAccessType {
+    match op {
+        BusOperation::Read => BusAccessType::DmaReadBurst,
+        BusOperation::Write => BusAccessType::DmaWriteBurst,
+    }
+}
+
+fn dma_address_register_index(address_register: DmaAddressRegister) -> u8 {
+    match address_register {
+        DmaAddressRegister::Address => RegisterName::Ah.to_register_index(),
+        DmaAddressRegister::Link => RegisterName::Lh.to_register_index(),
+        DmaAddressRegister::StackPointer => RegisterName::Sh.to_register_index(),
+    }
+}
+
+///
+/// The DMA unit (coprocessor 2).
+///
+/// Once a DMA command is accepted, the CPU holds the `InstructionFetchLow` phase and calls into
+/// this executor every cycle until the transfer is complete. Each cycle performs at most one
+/// bus access. The data from a DMA read is latched into the target register when the bus
+/// acknowledges the access (see `complete_bus_access`), which happens on the same cycle as the
+/// next access is asserted, so the transfer overlaps with whatever the CPU does next.
+///
+#[derive(Default, Serialize, Deserialize)]
+pub struct DmaUnitExecutor {
+    pub transfer: Option<DmaTransfer>,
+    /// The register that the data from the in flight DMA read (if any) will be latched into
+    pub pending_read_register: Option<u8>,
+}
+
+impl DmaUnitExecutor {
+    /// True while a DMA command is holding up instruction fetch
+    pub fn is_busy(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// Stops the current transfer (e.g. because a fault was raised).
+    ///
+    /// Any side effects that have already happened are not rolled back.
+    pub fn abort(&mut self, registers: &mut Registers) {
+        debug!("Aborting DMA transfer: {:?}", self.transfer);
+        self.transfer = None;
+        self.pending_read_register = None;
+        registers.pending_coprocessor_command = 0x0;
+    }
+
+    /// Called by the CPU when a bus access that it asserted completes.
+    ///
+    /// If the access was a DMA read that was acknowledged, the data on the bus is latched into the
+    /// target register.
+    pub fn complete_bus_access(
+        &mut self,
+        registers: &mut Registers,
+        bus_assertions: BusAssertions,
+    ) {
+        if let Some(register_index) = self.pending_read_register.take() {
+            let access_failed = bus_assertions.bus_error || bus_assertions.bus_protection_error;
+            if bus_assertions.bus_acknowledge && !access_failed {
+                registers[register_index] = bus_assertions.data;
+            }
+        }
+    }
+
+    fn raise_invalid_opcode(
+        registers: &mut Registers,
+        eu_registers: &mut ExceptionUnitRegisters,
+        cause_register_value: u16,
+        bus_assertions: BusAssertions,
+    ) {
+        warn!("Invalid DMA unit command: 0x{cause_register_value:X}");
+        // Same as an absent coprocessor, the command is stored so that it could be emulated
+        eu_registers.pending_fault = raise_fault(
+            eu_registers,
+            Faults::InvalidOpCode,
+            &BusAssertions {
+                address: u32::from(cause_register_value),
+                ..bus_assertions
+            },
+        );
+        registers.pending_coprocessor_command = 0x0;
+    }
+
+    fn raise_segment_overflow(
+        registers: &mut Registers,
+        eu_registers: &mut ExceptionUnitRegisters,
+        address: u32,
+        op: BusOperation,
+    ) {
+        eu_registers.pending_fault = raise_fault(
+            eu_registers,
+            Faults::SegmentOverflow,
+            &BusAssertions {
+                address,
+                op,
+                bus_access_type: bus_access_type_for_operation(op),
+                ..BusAssertions::default()
+            },
+        );
+        registers.pending_coprocessor_command = 0x0;
+    }
+
+    /// Decodes a DMA command and sets up the transfer.
+    ///
+    /// Returns None if the command completes immediately (zero count) or is invalid.
+    fn accept(
+        cause_register_value: u16,
+        registers: &mut Registers,
+        eu_registers: &mut ExceptionUnitRegisters,
+        bus_assertions: BusAssertions,
+    ) -> Option<DmaTransfer> {
+        let instruction = decode_dma_unit_instruction(cause_register_value);
+        let op_code: Option<DmaUnitOpCodes> = num::FromPrimitive::from_u8(instruction.op_code);
+
+        trace!("DMA command: 0x{cause_register_value:X} op_code: {op_code:?}");
+
+        let transfer = match op_code {
+            Some(
+                op_code @ (DmaUnitOpCodes::ReadToRegisters | DmaUnitOpCodes::WriteFromRegisters),
+            ) => {
+                let address_register: Option<DmaAddressRegister> = num::FromPrimitive::from_u8(
+                    (instruction.value & DMA_ADDRESS_REGISTER_MASK) >> DMA_ADDRESS_REGISTER_LENGTH,
+                );
+                let reserved_bits = instruction.value & DMA_RESERVED_OPERAND_MASK;
+                let (Some(address_register), 0) = (address_register, reserved_bits) else {
+                    Self::raise_invalid_opcode(
+                        registers,
+                        eu_registers,
+                        cause_register_value,
+                        bus_assertions,
+                    );
+                    return None;
+                };
+
+                let count = instruction.value & DMA_REGISTER_COUNT_MASK;
+                let backward = instruction.value & DMA_DIRECTION_MASK != 0;
+                let register_index = dma_address_register_index(address_register);
+                let mut pointer = DmaPointer::load(registers, register_index, !backward);
+
+                if count > 0 && backward {
+                    // Negative counts move the address register back first and then transfer
+                    // the ascending block from the new address
+                    let (low, overflowed) = pointer.low.overflowing_sub(u16::from(count));
+                    if overflowed
+                        && sr_bit_is_set(StatusRegisterFields::TrapOnAddressOverflow, registers)
+                    {
+                        let op = if op_code == DmaUnitOpCodes::ReadToRegisters {
+                            BusOperation::Read
+                        } else {
+                            BusOperation::Write
+                        };
+                        Self::raise_segment_overflow(
+                            registers,
+                            eu_registers,
+                            pointer.full_address(),
+                            op,
+                        );
+                        return None;
+                    }
+                    pointer.low = low;
+                    registers[register_index + 1] = low;
+                }
+
+                DmaTransfer {
+                    op_code,
+                    source: pointer,
+                    destination: pointer,
+                    remaining: count,
+                    ..DmaTransfer::default()
+                }
+            }
+            Some(op_code @ DmaUnitOpCodes::MemoryTransfer) => DmaTransfer {
+                op_code,
+                source: DmaPointer::load(registers, RegisterName::Ah.to_register_index(), true),
+                destination: DmaPointer::load(
+                    registers,
+                    RegisterName::Lh.to_register_index(),
+                    true,
+                ),
+                remaining: instruction.value,
+                ..DmaTransfer::default()
+            },
+            Some(DmaUnitOpCodes::None) | None => {
+                Self::raise_invalid_opcode(
+                    registers,
+                    eu_registers,
+                    cause_register_value,
+                    bus_assertions,
+                );
+                return None;
+            }
+        };
+
+        if transfer.remaining == 0 {
+            // Zero counts are a no-op, the command is just cleared so instruction fetch can resume
+            registers.pending_coprocessor_command = 0x0;
+            return None;
+        }
+
+        let chunk_size = transfer.remaining.min(DMA_TRANSFER_WINDOW_SIZE);
+        Some(DmaTransfer {
+            chunk_size,
+            remaining: transfer.remaining - chunk_size,
+            ..transfer
+        })
+    }
+}
+
+impl Executor for DmaUnitExecutor {
+    const COPROCESSOR_ID: u8 = 2;
+
+    fn step<'a>(
+        &mut self,
+        phase: &ExecutionPhase,
+        cause_register_value: u16,
+        registers: &'a mut Registers,
+        eu_registers: &'a mut ExceptionUnitRegisters,
+        bus_assertions: BusAssertions,
+    ) -> BusAssertions {
+        // The CPU holds the first phase while DMA runs, so there is nothing to do in other phases
+        if *phase != ExecutionPhase::InstructionFetchLow {
+            return BusAssertions::default();
+        }
+
+        if eu_registers.pending_fault.is_some() {
+            self.abort(registers);
+            return BusAssertions::default();
+        }
+
+        let mut transfer = match self.transfer {
+            Some(transfer) => transfer,
+            None => {
+                match Self::accept(
+                    cause_register_value,
+                    registers,
+                    eu_registers,
+                    bus_assertions,
+                ) {
+                    Some(transfer) => transfer,
+                    None => return BusAssertions::default(),
+                }
+            }
+        };
+
+        let (op, register_index) = transfer.next_access();
+        let pointer = match op {
+            BusOperation::Read => &mut transfer.source,
+            BusOperation::Write => &mut transfer.destination,
+        };
+        let address = pointer.full_address();
+
+        let (next_low, overflowed) = pointer.low.overflowing_add(1);
+        if overflowed && sr_bit_is_set(StatusRegisterFields::TrapOnAddressOverflow, registers) {
+            // The address register would end up outside the segment, so the transfer cannot
+            // complete. Fault before the access is made.
+            Self::raise_segment_overflow(registers, eu_registers, address, op);
+            self.transfer = None;
+            return BusAssertions::default();
+        }
+        pointer.low = next_low;
+        if pointer.advance_register {
+            registers[pointer.register_index + 1] = next_low;
+        }
+
+        let data = match op {
+            BusOperation::Read => {
+                self.pending_read_register = Some(register_index);
+                0x0
+            }
+            BusOperation::Write => registers[register_index],
+        };
+
+        trace!("DMA {op:?} r{register_index} @ 0x{address:X} (data: 0x{data:X})");
+
+        transfer.position += 1;
+        if transfer.position == transfer.accesses_per_chunk() {
+            if transfer.remaining == 0 {
+                // Last access has been asserted. The CPU can resume fetching on the next cycle.
+                self.transfer = None;
+                registers.pending_coprocessor_command = 0x0;
+            } else {
+                let chunk_size = transfer.remaining.min(DMA_TRANSFER_WINDOW_SIZE);
+                self.transfer = Some(DmaTransfer {
+                    remaining: transfer.remaining - chunk_size,
+                    chunk_size,
+                    position: 0,
+                    ..transfer
+                });
+            }
+        } else {
+            self.transfer = Some(transfer);
+        }
+
+        BusAssertions {
+            address,
+            data,
+            op,
+            bus_access_strobe: true,
+            bus_access_type: bus_access_type_for_operation(op),
+            ..BusAssertions::default()
+        }
+    }
+}
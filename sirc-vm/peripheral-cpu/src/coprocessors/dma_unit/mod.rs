@@ -0,0 +1,3 @@
+pub mod definitions;
+pub mod encoding;
+pub mod execution;
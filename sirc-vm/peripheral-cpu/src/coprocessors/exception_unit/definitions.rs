@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 pub const EXCEPTION_UNIT_TRANSFER_EU_REGISTER_MASK: u8 = 0xF;
 pub const EXCEPTION_UNIT_TRANSFER_EU_REGISTER_LENGTH: u8 = 4;
 pub const EXCEPTION_UNIT_TRANSFER_REGISTER_SELECT_MASK: u8 = 0x03;
@@ -162,7 +164,9 @@ pub enum ExceptionUnitOpCodes {
 }
 
 #[repr(u8)]
-#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[derive(
+    Default, Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize, Deserialize,
+)]
 pub enum Faults {
     #[default]
     Bus = 0x1,
@@ -1,5 +1,6 @@
 use log::{debug, trace};
 use peripheral_bus::device::{BusAccessType, BusAssertions, BusOperation};
+use serde::{Deserialize, Serialize};
 
 use super::{
     super::shared::Executor,
@@ -270,7 +271,7 @@ fn handle_exception(
     eu_registers.current_exception_level = exception_level;
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ExceptionUnitExecutor {
     pub vector_address: u32,
     pub vector_value: u32,
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+// Bits of the maths status word that is written to r3 after every maths operation
+/// Set if any maths error occurred
+pub const MATHS_STATUS_ERROR: u16 = 0b0001;
+/// Set if a division was attempted with a divisor of zero
+pub const MATHS_STATUS_DIVIDE_BY_ZERO: u16 = 0b0010;
+/// Set if the quotient of a division does not fit into 16 bits
+pub const MATHS_STATUS_OVERFLOW: u16 = 0b0100;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize, Deserialize)]
+pub enum MathsUnitOpCodes {
+    /// Unsigned 16 x 16 -> 32 multiply (MULU)
+    MultiplyUnsigned = 0x0,
+    /// Signed 16 x 16 -> 32 multiply (MULS)
+    MultiplySigned = 0x1,
+    /// Unsigned 32 / 16 divide (DIVU)
+    DivideUnsigned = 0x2,
+    /// Signed 32 / 16 divide (DIVS)
+    DivideSigned = 0x3,
+}
@@ -0,0 +1,196 @@
+use log::{debug, trace, warn};
+use peripheral_bus::device::BusAssertions;
+use serde::{Deserialize, Serialize};
+
+use super::definitions::{
+    MathsUnitOpCodes, MATHS_STATUS_DIVIDE_BY_ZERO, MATHS_STATUS_ERROR, MATHS_STATUS_OVERFLOW,
+};
+use crate::{
+    coprocessors::{
+        exception_unit::definitions::Faults,
+        shared::{ExecutionPhase, Executor},
+    },
+    raise_fault,
+    registers::{ExceptionUnitRegisters, FullAddressRegisterAccess, Registers},
+    CAUSE_OPCODE_ID_LENGTH, CAUSE_OPCODE_ID_MASK,
+};
+
+/// The registers that are written back after a maths operation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MathsResult {
+    pub r1: u16,
+    pub r2: u16,
+    pub r3: u16,
+}
+
+///
+/// Performs a maths unit operation on the input registers and returns what should be written back.
+///
+/// Multiplies take r1 and r2 and return the 32-bit product in r1:r2 (high word first).
+/// Divides take a 32-bit dividend in r1:r2 and a divisor in r3 and return the remainder in r1 and
+/// the quotient in r2. Errors leave r1 and r2 untouched and are reported in r3.
+///
+/// ```
+/// use peripheral_cpu::coprocessors::maths_unit::definitions::MathsUnitOpCodes;
+/// use peripheral_cpu::coprocessors::maths_unit::execution::{execute_maths_operation, MathsResult};
+///
+/// assert_eq!(
+///     MathsResult { r1: 0x0001, r2: 0x5F90, r3: 0x0 },
+///     execute_maths_operation(MathsUnitOpCodes::MultiplyUnsigned, 0x12C, 0x12C, 0x0)
+/// );
+/// assert_eq!(
+///     MathsResult { r1: 0xFFFE, r2: 0xFFFD, r3: 0x0 },
+///     execute_maths_operation(MathsUnitOpCodes::DivideSigned, 0xFFFF, 0xFFF5, 0x3)
+/// );
+/// // Divide by zero
+/// assert_eq!(
+///     MathsResult { r1: 0x1234, r2: 0x5678, r3: 0x3 },
+///     execute_maths_operation(MathsUnitOpCodes::DivideUnsigned, 0x1234, 0x5678, 0x0)
+/// );
+/// ```
+///
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn execute_maths_operation(
+    op_code: MathsUnitOpCodes,
+    r1: u16,
+    r2: u16,
+    r3: u16,
+) -> MathsResult {
+    let split = |value: u32| MathsResult {
+        r1: (value >> u16::BITS) as u16,
+        r2: value as u16,
+        r3: 0x0,
+    };
+    let error = |status: u16| MathsResult {
+        r1,
+        r2,
+        r3: MATHS_STATUS_ERROR | status,
+    };
+    let dividend = (u32::from(r1) << u16::BITS) | u32::from(r2);
+
+    match op_code {
+        MathsUnitOpCodes::MultiplyUnsigned => split(u32::from(r1) * u32::from(r2)),
+        MathsUnitOpCodes::MultiplySigned => {
+            split((i32::from(r1.cast_signed()) * i32::from(r2.cast_signed())) as u32)
+        }
+        MathsUnitOpCodes::DivideUnsigned => {
+            if r3 == 0 {
+                return error(MATHS_STATUS_DIVIDE_BY_ZERO);
+            }
+            let divisor = u32::from(r3);
+            u16::try_from(dividend / divisor).map_or_else(
+                |_| error(MATHS_STATUS_OVERFLOW),
+                |quotient| MathsResult {
+                    r1: (dividend % divisor) as u16,
+                    r2: quotient,
+                    r3: 0x0,
+                },
+            )
+        }
+        MathsUnitOpCodes::DivideSigned => {
+            if r3 == 0 {
+                return error(MATHS_STATUS_DIVIDE_BY_ZERO);
+            }
+            // Widen so that i32::MIN / -1 can be calculated and then caught as an overflow
+            let dividend = i64::from(dividend.cast_signed());
+            let divisor = i64::from(r3.cast_signed());
+            // Rust integer division truncates toward zero and the remainder takes the sign of
+            // the dividend, which is what the spec asks for
+            i16::try_from(dividend / divisor).map_or_else(
+                |_| error(MATHS_STATUS_OVERFLOW),
+                |quotient| MathsResult {
+                    r1: (dividend % divisor) as u16,
+                    r2: quotient.cast_unsigned(),
+                    r3: 0x0,
+                },
+            )
+        }
+    }
+}
+
+///
+/// The optional integer maths unit (coprocessor 3).
+///
+/// Like the exception unit, it runs through the standard six phases once a command has been
+/// dispatched to it. The operation itself is calculated in the execution phase and the results are
+/// written back to r1-r3 in the write back phase. The status register is never touched.
+///
+#[derive(Default, Serialize, Deserialize)]
+pub struct MathsUnitExecutor {
+    pub op_code: Option<MathsUnitOpCodes>,
+    pub result: MathsResult,
+}
+
+impl MathsUnitExecutor {
+    /// Decodes the op code nibble of a maths unit command.
+    ///
+    /// Returns None if the maths unit does not implement the operation.
+    pub fn decode_op_code(cause_register_value: u16) -> Option<MathsUnitOpCodes> {
+        let op_code_id = (cause_register_value & CAUSE_OPCODE_ID_MASK) >> CAUSE_OPCODE_ID_LENGTH;
+        num::FromPrimitive::from_u16(op_code_id)
+    }
+}
+
+impl Executor for MathsUnitExecutor {
+    const COPROCESSOR_ID: u8 = 3;
+
+    fn step<'a>(
+        &mut self,
+        phase: &ExecutionPhase,
+        cause_register_value: u16,
+        registers: &'a mut Registers,
+        eu_registers: &'a mut ExceptionUnitRegisters,
+        bus_assertions: BusAssertions,
+    ) -> BusAssertions {
+        if eu_registers.pending_fault.is_some() {
+            // Wait for the exception unit to be dispatched on the next instruction
+            return BusAssertions::default();
+        }
+
+        match phase {
+            ExecutionPhase::InstructionFetchLow => {
+                self.op_code = Self::decode_op_code(cause_register_value);
+                if self.op_code.is_none() {
+                    warn!("Invalid maths unit command: 0x{cause_register_value:X}");
+                    // Same as an absent coprocessor, the command is stored so that it could be emulated
+                    eu_registers.pending_fault = raise_fault(
+                        eu_registers,
+                        Faults::InvalidOpCode,
+                        &BusAssertions {
+                            address: u32::from(cause_register_value),
+                            ..bus_assertions
+                        },
+                    );
+                    registers.pending_coprocessor_command = 0x0;
+                }
+            }
+            ExecutionPhase::InstructionFetchHigh
+            | ExecutionPhase::InstructionDecode
+            | ExecutionPhase::MemoryAccessExecutor => {}
+            ExecutionPhase::ExecutionEffectiveAddressExecutor => {
+                if let Some(op_code) = self.op_code {
+                    self.result =
+                        execute_maths_operation(op_code, registers.r1, registers.r2, registers.r3);
+                    trace!(
+                        "{op_code:?} r1: 0x{:X} r2: 0x{:X} r3: 0x{:X} -> {:X?}",
+                        registers.r1,
+                        registers.r2,
+                        registers.r3,
+                        self.result
+                    );
+                }
+            }
+            ExecutionPhase::WriteBackExecutor => {
+                if let Some(op_code) = self.op_code.take() {
+                    debug!("0x{:X}: {:?}", registers.get_full_pc_address(), op_code);
+                    registers.r1 = self.result.r1;
+                    registers.r2 = self.result.r2;
+                    registers.r3 = self.result.r3;
+                }
+                registers.pending_coprocessor_command = 0x0;
+            }
+        }
+
+        BusAssertions::default()
+    }
+}
@@ -0,0 +1,2 @@
+pub mod definitions;
+pub mod execution;
@@ -1,3 +1,5 @@
+pub mod dma_unit;
 pub mod exception_unit;
+pub mod maths_unit;
 pub mod processing_unit;
 pub mod shared;
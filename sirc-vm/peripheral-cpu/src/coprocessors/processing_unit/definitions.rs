@@ -34,6 +34,8 @@
 // 0x00 0004 : DW Base System RAM (for storing in interrupt vectors etc.)
 // ...
 
+use serde::{Deserialize, Serialize};
+
 use crate::registers::{sr_bit_is_set, Registers, StatusRegisterFields};
 
 // 32 bits = 2x 16 bit
@@ -44,7 +46,9 @@ pub const MAX_SHIFT_COUNT: u32 = 15; // 4 bits (the size of the shift count in t
 
 // Condition Flags
 
-#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(
+    Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize,
+)]
 #[cfg_attr(test, derive(strum::EnumIter))]
 pub enum ConditionFlags {
     #[default]
@@ -66,7 +70,9 @@ pub enum ConditionFlags {
     Never = 0b1111,
 }
 
-#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(
+    Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize,
+)]
 #[cfg_attr(test, derive(strum::EnumIter))]
 pub enum ShiftType {
     #[default]
@@ -80,7 +86,9 @@ pub enum ShiftType {
     Reserved,
 }
 
-#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(
+    Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize,
+)]
 #[cfg_attr(test, derive(strum::EnumIter))]
 pub enum ShiftOperand {
     #[default]
@@ -92,7 +100,9 @@ pub enum ShiftOperand {
 // In the future it can be leveraged to disable status register updates for ALU
 // instructions to be able to chain multiple instructions off the one CMP
 // It is supported by the decoded, I just need to work out a syntax for the assembler
-#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(
+    Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize,
+)]
 pub enum StatusRegisterUpdateSource {
     #[default]
     None = 0b00,
@@ -202,7 +212,19 @@ pub enum InstructionData {
     Register(RegisterInstructionData),
 }
 
-#[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive, Default, Clone, Hash, Copy)]
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Default,
+    Clone,
+    Hash,
+    Copy,
+    Serialize,
+    Deserialize,
+)]
 #[cfg_attr(test, derive(strum::EnumIter))]
 pub enum Instruction {
     // ALU (Immediate)
@@ -0,0 +1,677 @@
+//!
+//! Prints decoded instructions in the same syntax that the assembler accepts.
+//!
+//! This lives in the CPU crate (rather than the toolchain printers) so that the VM can disassemble
+//! memory for a debugger without depending on the toolchain.
+//!
+//! Any instruction that can't be represented in assembler syntax (e.g. undocumented opcodes or
+//! fields that the assembler would never set) is printed as a raw `.DQ` so that the output is
+//! still accurate.
+//!
+
+use crate::coprocessors::processing_unit::definitions::{
+    ConditionFlags, ImmediateInstructionData, Instruction, InstructionData,
+    RegisterInstructionData, ShiftOperand, ShiftType, ShortImmediateInstructionData,
+    StatusRegisterUpdateSource,
+};
+use crate::coprocessors::processing_unit::encoding::encode_instruction;
+use crate::registers::{address_register_index_to_name, register_index_to_name};
+
+const PROGRAM_COUNTER_INDEX: u8 = 3;
+const LINK_REGISTER_INDEX: u8 = 0;
+
+///
+/// Converts a decoded instruction into a line of assembly.
+///
+/// ```
+/// use peripheral_cpu::coprocessors::processing_unit::definitions::{
+///     ConditionFlags, ImmediateInstructionData, Instruction, InstructionData,
+/// };
+/// use peripheral_cpu::coprocessors::processing_unit::disassembly::disassemble_instruction;
+///
+/// let instruction = InstructionData::Immediate(ImmediateInstructionData {
+///     op_code: Instruction::AddImmediate,
+///     register: 1,
+///     value: 0xCAFE,
+///     condition_flag: ConditionFlags::NotEqual,
+///     additional_flags: 0x1,
+/// });
+///
+/// assert_eq!("ADDI|!= r1, #0xCAFE", disassemble_instruction(&instruction));
+/// ```
+///
+#[must_use]
+pub fn disassemble_instruction(instruction_data: &InstructionData) -> String {
+    disassemble_documented_instruction(instruction_data).unwrap_or_else(|| {
+        format!(
+            ".DQ #0x{:08X}",
+            u32::from_be_bytes(encode_instruction(instruction_data))
+        )
+    })
+}
+
+///
+/// Whether the instruction can be written in assembler syntax. Undocumented opcodes (and fields that
+/// the assembler would never set) are disassembled as a raw `.DQ` instead.
+///
+/// ```
+/// use peripheral_cpu::coprocessors::processing_unit::definitions::{
+///     ConditionFlags, ImmediateInstructionData, Instruction, InstructionData,
+/// };
+/// use peripheral_cpu::coprocessors::processing_unit::disassembly::is_documented_instruction;
+///
+/// let instruction = ImmediateInstructionData {
+///     op_code: Instruction::AddImmediate,
+///     register: 1,
+///     value: 0xCAFE,
+///     condition_flag: ConditionFlags::Always,
+///     additional_flags: 0x1,
+/// };
+///
+/// assert!(is_documented_instruction(&InstructionData::Immediate(instruction.clone())));
+/// assert!(!is_documented_instruction(&InstructionData::Immediate(ImmediateInstructionData {
+///     op_code: Instruction::AddImmediate,
+///     additional_flags: 0x3,
+///     ..instruction
+/// })));
+/// ```
+///
+#[must_use]
+pub fn is_documented_instruction(instruction_data: &InstructionData) -> bool {
+    disassemble_documented_instruction(instruction_data).is_some()
+}
+
+fn disassemble_documented_instruction(instruction_data: &InstructionData) -> Option<String> {
+    match instruction_data {
+        InstructionData::Immediate(data) => disassemble_immediate_instruction(data),
+        InstructionData::ShortImmediate(data) => disassemble_short_immediate_instruction(data),
+        InstructionData::Register(data) => disassemble_register_instruction(data),
+    }
+}
+
+const fn alu_tag(op_code: Instruction) -> Option<&'static str> {
+    match op_code {
+        Instruction::AddImmediate | Instruction::AddShortImmediate => Some("ADDI"),
+        Instruction::AddImmediateWithCarry | Instruction::AddShortImmediateWithCarry => {
+            Some("ADCI")
+        }
+        Instruction::SubtractImmediate | Instruction::SubtractShortImmediate => Some("SUBI"),
+        Instruction::SubtractImmediateWithCarry | Instruction::SubtractShortImmediateWithCarry => {
+            Some("SBCI")
+        }
+        Instruction::AndImmediate | Instruction::AndShortImmediate => Some("ANDI"),
+        Instruction::OrImmediate | Instruction::OrShortImmediate => Some("ORRI"),
+        Instruction::XorImmediate | Instruction::XorShortImmediate => Some("XORI"),
+        Instruction::CompareImmediate | Instruction::CompareShortImmediate => Some("CMPI"),
+        Instruction::TestAndImmediate | Instruction::TestAndShortImmediate => Some("TSAI"),
+        Instruction::TestXorImmediate | Instruction::TestXorShortImmediate => Some("TSXI"),
+        Instruction::AddRegister => Some("ADDR"),
+        Instruction::AddRegisterWithCarry => Some("ADCR"),
+        Instruction::SubtractRegister => Some("SUBR"),
+        Instruction::SubtractRegisterWithCarry => Some("SBCR"),
+        Instruction::AndRegister => Some("ANDR"),
+        Instruction::OrRegister => Some("ORRR"),
+        Instruction::XorRegister => Some("XORR"),
+        Instruction::CompareRegister => Some("CMPR"),
+        Instruction::TestAndRegister => Some("TSAR"),
+        Instruction::TestXorRegister => Some("TSXR"),
+        _ => None,
+    }
+}
+
+const fn condition_suffix(condition_flag: ConditionFlags) -> &'static str {
+    match condition_flag {
+        ConditionFlags::Always => "",
+        ConditionFlags::Equal => "|==",
+        ConditionFlags::NotEqual => "|!=",
+        ConditionFlags::CarrySet => "|CS",
+        ConditionFlags::CarryClear => "|CC",
+        ConditionFlags::NegativeSet => "|NS",
+        ConditionFlags::NegativeClear => "|NC",
+        ConditionFlags::OverflowSet => "|OS",
+        ConditionFlags::OverflowClear => "|OC",
+        ConditionFlags::UnsignedHigher => "|HI",
+        ConditionFlags::UnsignedLowerOrSame => "|LO",
+        ConditionFlags::GreaterOrEqual => "|>=",
+        ConditionFlags::LessThan => "|<<",
+        ConditionFlags::GreaterThan => "|>>",
+        ConditionFlags::LessThanOrEqual => "|<=",
+        ConditionFlags::Never => "|NV",
+    }
+}
+
+/// The status register update source only needs to be printed if it isn't the default for the
+/// instruction. Returns None if the flags can't be represented in assembly.
+fn status_suffix(
+    additional_flags: u8,
+    default_source: StatusRegisterUpdateSource,
+) -> Option<&'static str> {
+    if additional_flags == default_source.to_flags() {
+        return Some("");
+    }
+    match additional_flags {
+        0b00 => Some("[N]"),
+        0b01 => Some("[A]"),
+        0b10 => Some("[S]"),
+        _ => None,
+    }
+}
+
+const fn has_shift(shift_operand: ShiftOperand, shift_type: ShiftType, shift_count: u8) -> bool {
+    !matches!(
+        (shift_operand, shift_type, shift_count),
+        (ShiftOperand::Immediate, ShiftType::None, 0)
+    )
+}
+
+fn format_shift(
+    shift_operand: ShiftOperand,
+    shift_type: ShiftType,
+    shift_count: u8,
+) -> Option<String> {
+    let shift_tag = match shift_type {
+        ShiftType::None => "NUL",
+        ShiftType::LogicalLeftShift => "LSL",
+        ShiftType::LogicalRightShift => "LSR",
+        ShiftType::ArithmeticLeftShift => "ASL",
+        ShiftType::ArithmeticRightShift => "ASR",
+        ShiftType::RotateLeft => "RTL",
+        ShiftType::RotateRight => "RTR",
+        ShiftType::Reserved => return None,
+    };
+    match shift_operand {
+        ShiftOperand::Immediate => Some(format!("{shift_tag} #{shift_count}")),
+        ShiftOperand::Register => {
+            register_index_to_name(shift_count).map(|name| format!("{shift_tag} {name}"))
+        }
+    }
+}
+
+/// Formats a shift definition as an optional trailing operand
+fn optional_shift_operand(
+    shift_operand: ShiftOperand,
+    shift_type: ShiftType,
+    shift_count: u8,
+) -> Option<String> {
+    if has_shift(shift_operand, shift_type, shift_count) {
+        format_shift(shift_operand, shift_type, shift_count).map(|shift| format!(", {shift}"))
+    } else {
+        Some(String::new())
+    }
+}
+
+fn disassemble_coprocessor_call(value: u16, condition: &str) -> String {
+    let dma_address_register = match (value >> 6) & 0b11 {
+        0b00 => Some("a"),
+        0b01 => Some("l"),
+        0b10 => Some("s"),
+        _ => None,
+    };
+    let dma_direction_set = value & 0b0010_0000 != 0;
+    let dma_magnitude = value & 0b0111;
+
+    match value {
+        0x1100..=0x11FF => format!("EXCP{condition} #{}", value & 0x00FF),
+        0x1900 => format!("WAIT{condition}"),
+        0x1A00 => format!("RETE{condition}"),
+        0x1B00 => format!("RSET{condition}"),
+        0x1C10..=0x1C1F => format!("ETFR{condition} a, #{}", value & 0x000F),
+        0x1C20..=0x1C2F => format!("ETFR{condition} r7, #{}", value & 0x000F),
+        0x1C30..=0x1C3F => format!("ETFR{condition} #{}", value & 0x000F),
+        0x1D10..=0x1D1F => format!("ETTR{condition} #{}, a", value & 0x000F),
+        0x1D20..=0x1D2F => format!("ETTR{condition} #{}, r7", value & 0x000F),
+        0x1D30..=0x1D3F => format!("ETTR{condition} #{}", value & 0x000F),
+        0x2800..=0x29FF
+            if value & 0b0001_1000 == 0
+                && dma_address_register.is_some()
+                && !(dma_direction_set && dma_magnitude == 0) =>
+        {
+            let tag = if value & 0x0F00 == 0x0800 {
+                "DMAR"
+            } else {
+                "DMAW"
+            };
+            let sign = if dma_direction_set { "-" } else { "" };
+            format!(
+                "{tag}{condition} {}, #{sign}{dma_magnitude}",
+                dma_address_register.unwrap_or_default()
+            )
+        }
+        0x2A00..=0x2AFF => format!("DMAT{condition} a, l, #{}", value & 0x00FF),
+        0x3000 => format!("MULU{condition}"),
+        0x3100 => format!("MULS{condition}"),
+        0x3200 => format!("DIVU{condition}"),
+        0x3300 => format!("DIVS{condition}"),
+        _ => format!("COPI{condition} #0x{value:04X}"),
+    }
+}
+
+fn disassemble_immediate_instruction(data: &ImmediateInstructionData) -> Option<String> {
+    let condition = condition_suffix(data.condition_flag);
+    let value = data.value;
+
+    if let Some(tag) = alu_tag(data.op_code) {
+        if data.op_code == Instruction::AddImmediate
+            && data.register == 0
+            && value == 0
+            && data.additional_flags == 0
+        {
+            return Some(format!("NOOP{condition}"));
+        }
+        let status = status_suffix(data.additional_flags, StatusRegisterUpdateSource::Alu)?;
+        let register = register_index_to_name(data.register)?;
+        return Some(format!(
+            "{tag}{status}{condition} {register}, #0x{value:04X}"
+        ));
+    }
+
+    match data.op_code {
+        Instruction::LoadRegisterFromImmediate if data.additional_flags == 0 => {
+            let register = register_index_to_name(data.register)?;
+            Some(format!("LOAD{condition} {register}, #0x{value:04X}"))
+        }
+        Instruction::CoprocessorCallImmediate
+            if data.register == 0 && data.additional_flags == 0 =>
+        {
+            Some(disassemble_coprocessor_call(value, condition))
+        }
+        Instruction::StoreRegisterToIndirectImmediate
+        | Instruction::StoreRegisterToIndirectImmediatePreDecrement
+        | Instruction::LoadRegisterFromIndirectImmediate
+        | Instruction::LoadRegisterFromIndirectImmediatePostIncrement => {
+            let register = register_index_to_name(data.register)?;
+            let address_register = address_register_index_to_name(data.additional_flags)?;
+            Some(match data.op_code {
+                Instruction::StoreRegisterToIndirectImmediate => {
+                    format!("STOR{condition} (#0x{value:04X}, {address_register}), {register}")
+                }
+                Instruction::StoreRegisterToIndirectImmediatePreDecrement => {
+                    format!("STOR{condition} -(#0x{value:04X}, {address_register}), {register}")
+                }
+                Instruction::LoadRegisterFromIndirectImmediate => {
+                    format!("LOAD{condition} {register}, (#0x{value:04X}, {address_register})")
+                }
+                _ => format!("LOAD{condition} {register}, (#0x{value:04X}, {address_register})+"),
+            })
+        }
+        Instruction::LoadEffectiveAddressFromIndirectImmediate
+        | Instruction::LoadEffectiveAddressFromIndirectImmediatePreDecrement
+        | Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate
+        | Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediatePostIncrement => {
+            disassemble_immediate_address_instruction(data, condition)
+        }
+        _ => None,
+    }
+}
+
+/// LDEA/LDEL with an immediate displacement, which also covers the branch and jump meta
+/// instructions when the destination is the program counter
+fn disassemble_immediate_address_instruction(
+    data: &ImmediateInstructionData,
+    condition: &str,
+) -> Option<String> {
+    let value = data.value;
+    let destination = address_register_index_to_name(data.register)?;
+    let source = address_register_index_to_name(data.additional_flags)?;
+    let destination_is_pc = data.register == PROGRAM_COUNTER_INDEX;
+    let source_is_pc = data.additional_flags == PROGRAM_COUNTER_INDEX;
+    let offset_operand = if value == 0 {
+        String::new()
+    } else {
+        format!(", #0x{value:04X}")
+    };
+
+    Some(match data.op_code {
+        Instruction::LoadEffectiveAddressFromIndirectImmediate => {
+            if destination_is_pc && source_is_pc {
+                format!("BRAN{condition} #0x{value:04X}")
+            } else if destination_is_pc
+                && data.additional_flags == LINK_REGISTER_INDEX
+                && value == 0
+            {
+                format!("RETS{condition}")
+            } else if destination_is_pc {
+                format!("LJMP{condition} {source}{offset_operand}")
+            } else {
+                format!("LDEA{condition} {destination}, (#0x{value:04X}, {source})")
+            }
+        }
+        Instruction::LoadEffectiveAddressFromIndirectImmediatePreDecrement => {
+            format!("LDEA{condition} {destination}, -(#0x{value:04X}, {source})")
+        }
+        Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate => {
+            if destination_is_pc && source_is_pc {
+                format!("BRSR{condition} #0x{value:04X}")
+            } else if destination_is_pc {
+                format!("LJSR{condition} {source}{offset_operand}")
+            } else {
+                format!("LDEL{condition} {destination}, (#0x{value:04X}, {source})")
+            }
+        }
+        _ => {
+            if destination_is_pc {
+                format!("LJSR{condition} (#0x{value:04X}, {source})+")
+            } else {
+                format!("LDEL{condition} {destination}, (#0x{value:04X}, {source})+")
+            }
+        }
+    })
+}
+
+fn disassemble_short_immediate_instruction(data: &ShortImmediateInstructionData) -> Option<String> {
+    let condition = condition_suffix(data.condition_flag);
+    let tag = alu_tag(data.op_code)?;
+    let register = register_index_to_name(data.register)?;
+    let shift = format_shift(data.shift_operand, data.shift_type, data.shift_count)?;
+
+    // SHFT is just an ORRI with zero that updates the status register from the shift
+    if data.op_code == Instruction::OrShortImmediate
+        && data.value == 0
+        && data.additional_flags == StatusRegisterUpdateSource::Shift.to_flags()
+    {
+        return Some(format!("SHFT{condition} {register}, {shift}"));
+    }
+
+    let status = status_suffix(data.additional_flags, StatusRegisterUpdateSource::Alu)?;
+    Some(format!(
+        "{tag}{status}{condition} {register}, #0x{:02X}, {shift}",
+        data.value
+    ))
+}
+
+fn disassemble_register_instruction(data: &RegisterInstructionData) -> Option<String> {
+    let condition = condition_suffix(data.condition_flag);
+    let shift = optional_shift_operand(data.shift_operand, data.shift_type, data.shift_count)?;
+    let no_shift = !has_shift(data.shift_operand, data.shift_type, data.shift_count);
+    let r1 = register_index_to_name(data.r1)?;
+    let r2 = register_index_to_name(data.r2)?;
+    let r3 = register_index_to_name(data.r3)?;
+
+    if let Some(tag) = alu_tag(data.op_code) {
+        let status = status_suffix(data.additional_flags, StatusRegisterUpdateSource::Alu)?;
+        return Some(if data.r1 == data.r2 {
+            format!("{tag}{status}{condition} {r1}, {r3}{shift}")
+        } else {
+            format!("{tag}{status}{condition} {r1}, {r2}, {r3}{shift}")
+        });
+    }
+
+    match data.op_code {
+        Instruction::LoadRegisterFromRegister
+            if data.r2 == 0 && data.additional_flags == 0 && no_shift =>
+        {
+            Some(format!("LOAD{condition} {r1}, {r3}"))
+        }
+        Instruction::CoprocessorCallRegister
+            if data.r1 == 0 && data.r2 == 0 && data.additional_flags == 0 && no_shift =>
+        {
+            Some(format!("COPR{condition} {r3}"))
+        }
+        Instruction::StoreRegisterToIndirectRegister
+        | Instruction::StoreRegisterToIndirectRegisterPreDecrement
+            if data.r1 == 0 =>
+        {
+            let address_register = address_register_index_to_name(data.additional_flags)?;
+            let pre_decrement =
+                if data.op_code == Instruction::StoreRegisterToIndirectRegisterPreDecrement {
+                    "-"
+                } else {
+                    ""
+                };
+            Some(format!(
+                "STOR{condition} {pre_decrement}({r3}, {address_register}), {r2}{shift}"
+            ))
+        }
+        Instruction::LoadRegisterFromIndirectRegister
+        | Instruction::LoadRegisterFromIndirectRegisterPostIncrement
+            if data.r2 == 0 =>
+        {
+            let address_register = address_register_index_to_name(data.additional_flags)?;
+            let post_increment =
+                if data.op_code == Instruction::LoadRegisterFromIndirectRegisterPostIncrement {
+                    "+"
+                } else {
+                    ""
+                };
+            Some(format!(
+                "LOAD{condition} {r1}, ({r3}, {address_register}){post_increment}{shift}"
+            ))
+        }
+        Instruction::LoadEffectiveAddressFromIndirectRegister
+        | Instruction::LoadEffectiveAddressFromIndirectRegisterPreDecrement
+        | Instruction::LoadEffectiveAddressAndLinkFromIndirectRegister
+        | Instruction::LoadEffectiveAddressAndLinkFromIndirectRegisterPostIncrement
+            if data.r2 == 0 && no_shift =>
+        {
+            disassemble_register_address_instruction(data, condition, r3)
+        }
+        _ => None,
+    }
+}
+
+/// LDEA/LDEL with a register displacement, which also covers the jump meta instructions when the
+/// destination is the program counter
+fn disassemble_register_address_instruction(
+    data: &RegisterInstructionData,
+    condition: &str,
+    displacement: &str,
+) -> Option<String> {
+    let destination = address_register_index_to_name(data.r1)?;
+    let source = address_register_index_to_name(data.additional_flags)?;
+    let destination_is_pc = data.r1 == PROGRAM_COUNTER_INDEX;
+
+    Some(match data.op_code {
+        Instruction::LoadEffectiveAddressFromIndirectRegister => {
+            if destination_is_pc {
+                format!("LJMP{condition} {source}, {displacement}")
+            } else {
+                format!("LDEA{condition} {destination}, ({displacement}, {source})")
+            }
+        }
+        Instruction::LoadEffectiveAddressFromIndirectRegisterPreDecrement => {
+            format!("LDEA{condition} {destination}, -({displacement}, {source})")
+        }
+        Instruction::LoadEffectiveAddressAndLinkFromIndirectRegister => {
+            if destination_is_pc {
+                format!("LJSR{condition} {source}, {displacement}")
+            } else {
+                format!("LDEL{condition} {destination}, ({displacement}, {source})")
+            }
+        }
+        _ => {
+            if destination_is_pc {
+                format!("LJSR{condition} ({displacement}, {source})+")
+            } else {
+                format!("LDEL{condition} {destination}, ({displacement}, {source})+")
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coprocessors::processing_unit::encoding::decode_instruction;
+
+    fn disassemble_raw(raw_instruction: u32) -> String {
+        disassemble_instruction(&decode_instruction(raw_instruction.to_be_bytes()))
+    }
+
+    #[test]
+    fn test_empty_memory_is_noop() {
+        assert_eq!("NOOP", disassemble_raw(0x0000_0000));
+    }
+
+    #[test]
+    fn test_undocumented_opcode_is_raw_data() {
+        let instruction = InstructionData::Immediate(ImmediateInstructionData {
+            op_code: Instruction::_Undocumented0x08,
+            register: 1,
+            value: 0x1234,
+            condition_flag: ConditionFlags::Always,
+            additional_flags: 0,
+        });
+        let raw = u32::from_be_bytes(encode_instruction(&instruction));
+
+        assert_eq!(
+            format!(".DQ #0x{raw:08X}"),
+            disassemble_instruction(&instruction)
+        );
+    }
+
+    #[test]
+    fn test_branch_meta_instructions() {
+        let branch = |op_code, register, additional_flags, value| {
+            disassemble_instruction(&InstructionData::Immediate(ImmediateInstructionData {
+                op_code,
+                register,
+                value,
+                condition_flag: ConditionFlags::Equal,
+                additional_flags,
+            }))
+        };
+        let load_address = Instruction::LoadEffectiveAddressFromIndirectImmediate;
+        let load_address_and_link = Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate;
+
+        assert_eq!("BRAN|== #0xFFFC", branch(load_address, 3, 3, 0xFFFC));
+        assert_eq!("RETS|==", branch(load_address, 3, 0, 0));
+        assert_eq!("LJMP|== a", branch(load_address, 3, 1, 0));
+        assert_eq!("LJMP|== a, #0x0010", branch(load_address, 3, 1, 0x10));
+        assert_eq!("LDEA|== s, (#0x0010, a)", branch(load_address, 2, 1, 0x10));
+        assert_eq!("BRSR|== #0x0004", branch(load_address_and_link, 3, 3, 0x4));
+        assert_eq!("LJSR|== a", branch(load_address_and_link, 3, 1, 0));
+        // Address register indexes only go up to 3
+        assert!(branch(load_address, 4, 1, 0).starts_with(".DQ"));
+    }
+
+    #[test]
+    fn test_status_register_update_source() {
+        let instruction = |op_code, value, additional_flags| {
+            disassemble_instruction(&InstructionData::ShortImmediate(
+                ShortImmediateInstructionData {
+                    op_code,
+                    register: 2,
+                    value,
+                    shift_operand: ShiftOperand::Immediate,
+                    shift_type: ShiftType::LogicalLeftShift,
+                    shift_count: 3,
+                    condition_flag: ConditionFlags::Always,
+                    additional_flags,
+                },
+            ))
+        };
+
+        assert_eq!(
+            "SHFT r2, LSL #3",
+            instruction(Instruction::OrShortImmediate, 0, 0b10)
+        );
+        assert_eq!(
+            "ORRI r2, #0x00, LSL #3",
+            instruction(Instruction::OrShortImmediate, 0, 0b01)
+        );
+        assert_eq!(
+            "SUBI[N] r2, #0x12, LSL #3",
+            instruction(Instruction::SubtractShortImmediate, 0x12, 0b00)
+        );
+        assert!(instruction(Instruction::SubtractShortImmediate, 0x12, 0b11).starts_with(".DQ"));
+    }
+
+    #[test]
+    fn test_coprocessor_meta_instructions() {
+        let copi = |value| {
+            disassemble_instruction(&InstructionData::Immediate(ImmediateInstructionData {
+                op_code: Instruction::CoprocessorCallImmediate,
+                register: 0,
+                value,
+                condition_flag: ConditionFlags::Always,
+                additional_flags: 0,
+            }))
+        };
+
+        assert_eq!("EXCP #5", copi(0x1105));
+        assert_eq!("RETE", copi(0x1A00));
+        assert_eq!("ETFR r7, #2", copi(0x1C22));
+        assert_eq!("ETTR #2, a", copi(0x1D12));
+        assert_eq!("DMAR l, #-3", copi(0x2863));
+        assert_eq!("DMAW s, #7", copi(0x2987));
+        assert_eq!("DMAT a, l, #16", copi(0x2A10));
+        assert_eq!("DIVS", copi(0x3300));
+        assert_eq!("COPI #0x1234", copi(0x1234));
+    }
+
+    #[test]
+    fn test_register_instructions() {
+        let instruction = |op_code, r1, r2, r3, shift_type, shift_count| {
+            disassemble_instruction(&InstructionData::Register(RegisterInstructionData {
+                op_code,
+                r1,
+                r2,
+                r3,
+                shift_operand: ShiftOperand::Immediate,
+                shift_type,
+                shift_count,
+                condition_flag: ConditionFlags::LessThan,
+                additional_flags: 1,
+            }))
+        };
+
+        assert_eq!(
+            "ADDR|<< r1, r3",
+            instruction(Instruction::AddRegister, 1, 1, 3, ShiftType::None, 0)
+        );
+        assert_eq!(
+            "SUBR|<< r1, r2, r3, ASR #4",
+            instruction(
+                Instruction::SubtractRegister,
+                1,
+                2,
+                3,
+                ShiftType::ArithmeticRightShift,
+                4
+            )
+        );
+        assert_eq!(
+            "STOR|<< -(r3, a), r2",
+            instruction(
+                Instruction::StoreRegisterToIndirectRegisterPreDecrement,
+                0,
+                2,
+                3,
+                ShiftType::None,
+                0
+            )
+        );
+        assert_eq!(
+            "LOAD|<< r1, (r3, a)+, LSL #1",
+            instruction(
+                Instruction::LoadRegisterFromIndirectRegisterPostIncrement,
+                1,
+                0,
+                3,
+                ShiftType::LogicalLeftShift,
+                1
+            )
+        );
+        assert_eq!(
+            "LJSR|<< (r3, a)+",
+            instruction(
+                Instruction::LoadEffectiveAddressAndLinkFromIndirectRegisterPostIncrement,
+                3,
+                0,
+                3,
+                ShiftType::None,
+                0
+            )
+        );
+        // The unused register field is set so it can't be represented in assembly
+        assert!(instruction(
+            Instruction::LoadRegisterFromIndirectRegister,
+            1,
+            2,
+            3,
+            ShiftType::None,
+            0
+        )
+        .starts_with(".DQ"));
+    }
+}
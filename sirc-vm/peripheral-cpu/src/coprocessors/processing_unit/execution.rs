@@ -1,5 +1,6 @@
 use log::trace;
 use num::Integer;
+use serde::{Deserialize, Serialize};
 // use log::trace;
 use peripheral_bus::device::{BusAccessType, BusAssertions, BusOperation};
 
@@ -50,7 +51,7 @@ pub fn check_privilege(instruction: &DecodedInstruction, registers: &Registers)
     writing_to_privileged_registers || calling_privileged_cop_opcode
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ProcessingUnitExecutor {
     pub instruction: u32,
     pub decoded_instruction: DecodedInstruction,
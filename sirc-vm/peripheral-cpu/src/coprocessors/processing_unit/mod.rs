@@ -1,4 +1,5 @@
 pub mod definitions;
+pub mod disassembly;
 pub mod encoding;
 pub mod execution;
 pub mod stages;
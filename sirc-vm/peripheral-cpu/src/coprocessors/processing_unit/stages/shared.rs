@@ -1,4 +1,5 @@
 use peripheral_bus::device::BusAssertions;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     coprocessors::processing_unit::definitions::{
@@ -7,7 +8,7 @@ use crate::{
     registers::{ExceptionUnitRegisters, Registers},
 };
 
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct ShiftParameters {
     pub shift_count: u8,
     pub shift_operand: ShiftOperand,
@@ -30,7 +31,7 @@ pub struct ShiftParameters {
 * only the relevant registers are available for each instruction type.
 
 */
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DecodedInstruction {
     // Raw Instruction Decode
     pub ins: Instruction,
@@ -58,7 +59,7 @@ pub struct DecodedInstruction {
     pub npc_h_: u16,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct IntermediateRegisters {
     pub alu_output: u16,
     pub address_output: u16,
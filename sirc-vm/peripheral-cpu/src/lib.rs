@@ -38,6 +38,7 @@ use std::{any::Any, fmt::Write};
 use log::{debug, error, trace, warn};
 
 use coprocessors::{
+    dma_unit::execution::{DmaTransfer, DmaUnitExecutor},
     exception_unit::{
         definitions::{
             vectors::DOUBLE_FAULT_VECTOR, ExceptionPriorities, ExceptionUnitOpCodes, Faults,
@@ -47,21 +48,24 @@ use coprocessors::{
             ExceptionUnitExecutor,
         },
     },
+    maths_unit::execution::MathsUnitExecutor,
     processing_unit::execution::ProcessingUnitExecutor,
     shared::{ExecutionPhase, Executor},
 };
 use num::ToPrimitive;
 use num_traits::FromPrimitive;
 use peripheral_bus::device::{BusAccessType, BusAssertions, Device};
+use peripheral_bus::save_state::{decode_state, encode_state, SaveStateError};
 use registers::ExceptionUnitRegisters;
+use serde::{Deserialize, Serialize};
 
 use crate::registers::{
     get_hardware_interrupt_enable, sr_bit_is_set, ExceptionLinkRegister, Registers,
     StatusRegisterFields,
 };
 
-// The 8th exception link register stores metadata about faults
-const FAULT_METADATA_LINK_REGISTER_INDEX: usize = 7;
+/// The 8th exception link register stores metadata about faults
+pub const FAULT_METADATA_LINK_REGISTER_INDEX: usize = 7;
 
 /// Its always six baby!
 pub const CYCLES_PER_INSTRUCTION: u8 = 6;
@@ -87,18 +91,41 @@ pub const CURRENT_FAULT_LENGTH: u16 = 4;
 pub const PREVIOUS_FAULT_MASK: u16 = 0xF00;
 pub const PREVIOUS_FAULT_LENGTH: u16 = 8;
 
+/// The SIRC-1 models that can be emulated.
+///
+/// All models have the processing, exception and DMA units. Models differ by which optional
+/// coprocessors they have (see the coprocessor list in the reference manual).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CpuModel {
+    /// Only the mandatory coprocessors. Maths commands fault so that they can be emulated in software.
+    #[default]
+    Base,
+    /// Includes the optional integer maths unit (coprocessor 3)
+    Maths,
+}
+
+impl CpuModel {
+    pub fn has_maths_unit(self) -> bool {
+        self == Self::Maths
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     ProcessorHalted(Registers),
     InvalidInstruction(Registers),
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct CpuPeripheral {
     pub registers: Registers,
     pub eu_registers: ExceptionUnitRegisters,
     pub phase: u8,
     pub processing_unit_executor: ProcessingUnitExecutor,
     pub exception_unit_executor: ExceptionUnitExecutor,
+    pub dma_unit_executor: DmaUnitExecutor,
+    // Optional coprocessors are None if the CPU model does not include them
+    pub maths_unit_executor: Option<MathsUnitExecutor>,
     pub cause_register_value: u16,
     // T bit sampled at InstructionFetchLow before any SR writes for the current instruction.
     // Used at WriteBackExecutor to decide whether to raise InstructionTrace.
@@ -116,6 +143,29 @@ pub struct CpuPeripheral {
     reset_pending: bool,
 }
 
+///
+/// The parts of the CPU state that carry over from one instruction to the next.
+///
+/// This is much cheaper to take than a save state, so it can be taken on every instruction (e.g.
+/// to step backwards in the debugger). Everything else in the executors is overwritten before it
+/// is read again, so it doesn't need to be restored.
+///
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct CpuCheckpoint {
+    pub registers: Registers,
+    pub eu_registers: ExceptionUnitRegisters,
+    phase: u8,
+    cause_register_value: u16,
+    trace_mode_sampled: bool,
+    pending_bus_request: Option<BusAssertions>,
+    is_halted: bool,
+    reset_pending: bool,
+    next_instruction_fetch_is_overflow: bool,
+    dma_transfer: Option<DmaTransfer>,
+    dma_pending_read_register: Option<u8>,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct FaultMetadataRegister {
     pub bus_access_type: BusAccessType, // 3 bits (bits 0-2, BAT0-BAT2)
@@ -195,6 +245,17 @@ pub fn raise_fault(
 ///
 #[must_use]
 pub fn new_cpu_peripheral(system_ram_offset: u32) -> CpuPeripheral {
+    new_cpu_peripheral_with_model(system_ram_offset, CpuModel::default())
+}
+
+///
+/// Instantiates a new `CpuPeripheral` for a specific CPU model.
+///
+/// The model decides which optional coprocessors are available. Commands sent to a coprocessor that
+/// the model does not have raise an invalid opcode fault.
+///
+#[must_use]
+pub fn new_cpu_peripheral_with_model(system_ram_offset: u32, model: CpuModel) -> CpuPeripheral {
     CpuPeripheral {
         registers: Registers {
             system_ram_offset,
@@ -204,6 +265,8 @@ pub fn new_cpu_peripheral(system_ram_offset: u32) -> CpuPeripheral {
         phase: 0,
         processing_unit_executor: ProcessingUnitExecutor::default(),
         exception_unit_executor: ExceptionUnitExecutor::default(),
+        dma_unit_executor: DmaUnitExecutor::default(),
+        maths_unit_executor: model.has_maths_unit().then(MathsUnitExecutor::default),
         cause_register_value: 0,
         trace_mode_sampled: false,
         pending_bus_request: None,
@@ -237,6 +300,24 @@ impl Device for CpuPeripheral {
         s
     }
 
+    fn save_state(&self) -> Vec<u8> {
+        encode_state(self)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let restored: Self = decode_state(state)?;
+        // The model comes from the command line, so a state from another model is a mistake
+        if restored.model() != self.model() {
+            return Err(SaveStateError::DeviceMismatch(format!(
+                "it was saved from the {:?} CPU model but this CPU is the {:?} model",
+                restored.model(),
+                self.model()
+            )));
+        }
+        *self = restored;
+        Ok(())
+    }
+
     fn reset(&mut self) {
         Self::reset(self);
     }
@@ -264,12 +345,16 @@ impl CpuPeripheral {
                 || bus_assertions.bus_protection_error;
             if !bus_access_complete {
                 return BusAssertions {
-                    instruction_sync: self.phase == ExecutionPhase::InstructionFetchLow as u8,
+                    instruction_sync: self.phase == ExecutionPhase::InstructionFetchLow as u8
+                        && !Self::is_dma_access(pending_request),
                     ..pending_request
                 };
             }
             // Access complete: advance to the next phase and fall through to run it
             self.pending_bus_request = None;
+            // DMA reads are latched into their target register as soon as the bus acknowledges them
+            self.dma_unit_executor
+                .complete_bus_access(&mut self.registers, bus_assertions);
             Some(pending_request)
         } else {
             None
@@ -280,7 +365,8 @@ impl CpuPeripheral {
 
         // Check for halt at instruction boundaries (phase 0). Latch halt when first asserted;
         // stall each subsequent cycle while still asserted; resume when deasserted.
-        if phase == ExecutionPhase::InstructionFetchLow {
+        // A DMA transfer holds phase 0 but is still part of the current instruction.
+        if phase == ExecutionPhase::InstructionFetchLow && !self.dma_unit_executor.is_busy() {
             if bus_assertions.halt_requested {
                 self.is_halted = true;
             }
@@ -323,7 +409,17 @@ impl CpuPeripheral {
                 raise_fault(&mut self.eu_registers, bus_fault, &fault_bus_assertions);
         }
 
-        if phase == ExecutionPhase::InstructionFetchLow {
+        if self.dma_unit_executor.is_busy() && self.eu_registers.pending_fault.is_some() {
+            // Faults abort the DMA transfer so the exception unit can be dispatched straight away
+            self.dma_unit_executor.abort(&mut self.registers);
+        }
+
+        // The DMA unit holds the first phase until its transfer is complete. The cause register
+        // must not be recalculated in the meantime, otherwise an interrupt could cut in mid-transfer.
+        let instruction_boundary =
+            phase == ExecutionPhase::InstructionFetchLow && !self.dma_unit_executor.is_busy();
+
+        if instruction_boundary {
             // Only reset the cause register every full instruction cycle
             let pending_coprocessor_command = self.registers.pending_coprocessor_command;
             let pending_cop_opcode =
@@ -346,7 +442,7 @@ impl CpuPeripheral {
         // In hardware, the trace decision reads the registered (committed) SR value from the
         // previous clock edge, not the combinational new value being written this cycle.
         // Only sample for processing-unit instructions; EU exception dispatch is not traced.
-        if phase == ExecutionPhase::InstructionFetchLow {
+        if instruction_boundary {
             self.trace_mode_sampled = coprocessor_id == ProcessingUnitExecutor::COPROCESSOR_ID
                 && (sr_bit_is_set(StatusRegisterFields::TraceMode, &self.registers)
                     || bus_assertions.force_trace_mode);
@@ -372,26 +468,24 @@ impl CpuPeripheral {
                 &mut self.eu_registers,
                 bus_assertions,
             ),
-            _ => {
-                if self.eu_registers.pending_fault.is_none()
-                    && phase == ExecutionPhase::InstructionFetchLow
-                {
-                    warn!("Invalid COP coprocessor ID detected: {coprocessor_id}");
-                    let invalid_coprocessor_command = self.cause_register_value;
-                    // Can be used for forwards compatibility if co-processors are added in later models
-                    self.eu_registers.pending_fault = raise_fault(
-                        &mut self.eu_registers,
-                        Faults::InvalidOpCode,
-                        &BusAssertions {
-                            address: u32::from(invalid_coprocessor_command),
-                            ..bus_assertions
-                        },
-                    );
-                    self.registers.pending_coprocessor_command = 0x0;
-                }
-
-                BusAssertions::default()
-            }
+            DmaUnitExecutor::COPROCESSOR_ID => self.dma_unit_executor.step(
+                &phase,
+                self.cause_register_value,
+                &mut self.registers,
+                &mut self.eu_registers,
+                bus_assertions,
+            ),
+            MathsUnitExecutor::COPROCESSOR_ID => match self.maths_unit_executor.as_mut() {
+                Some(maths_unit_executor) => maths_unit_executor.step(
+                    &phase,
+                    self.cause_register_value,
+                    &mut self.registers,
+                    &mut self.eu_registers,
+                    bus_assertions,
+                ),
+                None => self.handle_absent_coprocessor(coprocessor_id, phase, bus_assertions),
+            },
+            _ => self.handle_absent_coprocessor(coprocessor_id, phase, bus_assertions),
         };
 
         if phase == ExecutionPhase::WriteBackExecutor
@@ -411,11 +505,15 @@ impl CpuPeripheral {
             self.pending_bus_request = Some(result);
         }
 
-        self.advance_phase();
+        // DMA commands keep the CPU in the first phase. Once the DMA unit clears the pending
+        // command, the next cycle recalculates the cause register and fetch resumes.
+        if coprocessor_id != DmaUnitExecutor::COPROCESSOR_ID {
+            self.advance_phase();
+        }
 
         // instruction_sync uses the phase captured before advance_phase() above.
         let result = BusAssertions {
-            instruction_sync: phase == ExecutionPhase::InstructionFetchLow,
+            instruction_sync: instruction_boundary,
             ..result
         };
 
@@ -438,6 +536,39 @@ impl CpuPeripheral {
         ((cause_register_value & COPROCESSOR_ID_MASK) >> COPROCESSOR_ID_LENGTH) as u8
     }
 
+    /// Called when a command is sent to a coprocessor that this CPU model does not have
+    fn handle_absent_coprocessor(
+        &mut self,
+        coprocessor_id: u8,
+        phase: ExecutionPhase,
+        bus_assertions: BusAssertions,
+    ) -> BusAssertions {
+        if self.eu_registers.pending_fault.is_none() && phase == ExecutionPhase::InstructionFetchLow
+        {
+            warn!("Invalid COP coprocessor ID detected: {coprocessor_id}");
+            let invalid_coprocessor_command = self.cause_register_value;
+            // Can be used for forwards compatibility if co-processors are added in later models
+            self.eu_registers.pending_fault = raise_fault(
+                &mut self.eu_registers,
+                Faults::InvalidOpCode,
+                &BusAssertions {
+                    address: u32::from(invalid_coprocessor_command),
+                    ..bus_assertions
+                },
+            );
+            self.registers.pending_coprocessor_command = 0x0;
+        }
+
+        BusAssertions::default()
+    }
+
+    fn is_dma_access(bus_assertions: BusAssertions) -> bool {
+        matches!(
+            bus_assertions.bus_access_type,
+            BusAccessType::DmaReadBurst | BusAccessType::DmaWriteBurst
+        )
+    }
+
     fn fault_from_bus_assertions(bus_assertions: BusAssertions) -> Option<Faults> {
         if bus_assertions.bus_error {
             Some(Faults::Bus)
@@ -523,6 +654,10 @@ impl CpuPeripheral {
 
     pub fn reset(&mut self) {
         self.pending_bus_request = None;
+        self.dma_unit_executor = DmaUnitExecutor::default();
+        if let Some(maths_unit_executor) = self.maths_unit_executor.as_mut() {
+            *maths_unit_executor = MathsUnitExecutor::default();
+        }
         self.is_halted = false;
         self.reset_pending = false;
         self.eu_registers.waiting_for_exception = false;
@@ -535,6 +670,49 @@ impl CpuPeripheral {
             construct_cause_value(&ExceptionUnitOpCodes::Reset, 0x0);
     }
 
+    /// Which model of the CPU this is, from the optional coprocessors that it has
+    pub fn model(&self) -> CpuModel {
+        if self.maths_unit_executor.is_some() {
+            CpuModel::Maths
+        } else {
+            CpuModel::Base
+        }
+    }
+
+    /// Takes a checkpoint that can be restored with `restore_checkpoint`
+    pub fn checkpoint(&self) -> CpuCheckpoint {
+        CpuCheckpoint {
+            registers: self.registers,
+            eu_registers: self.eu_registers,
+            phase: self.phase,
+            cause_register_value: self.cause_register_value,
+            trace_mode_sampled: self.trace_mode_sampled,
+            pending_bus_request: self.pending_bus_request,
+            is_halted: self.is_halted,
+            reset_pending: self.reset_pending,
+            next_instruction_fetch_is_overflow: self
+                .processing_unit_executor
+                .next_instruction_fetch_is_overflow,
+            dma_transfer: self.dma_unit_executor.transfer,
+            dma_pending_read_register: self.dma_unit_executor.pending_read_register,
+        }
+    }
+
+    pub fn restore_checkpoint(&mut self, checkpoint: &CpuCheckpoint) {
+        self.registers = checkpoint.registers;
+        self.eu_registers = checkpoint.eu_registers;
+        self.phase = checkpoint.phase;
+        self.cause_register_value = checkpoint.cause_register_value;
+        self.trace_mode_sampled = checkpoint.trace_mode_sampled;
+        self.pending_bus_request = checkpoint.pending_bus_request;
+        self.is_halted = checkpoint.is_halted;
+        self.reset_pending = checkpoint.reset_pending;
+        self.processing_unit_executor
+            .next_instruction_fetch_is_overflow = checkpoint.next_instruction_fetch_is_overflow;
+        self.dma_unit_executor.transfer = checkpoint.dma_transfer;
+        self.dma_unit_executor.pending_read_register = checkpoint.dma_pending_read_register;
+    }
+
     /// Runs the CPU for six cycles. Only to keep tests functioning at the moment. Will be removed
     ///
     /// # Panics
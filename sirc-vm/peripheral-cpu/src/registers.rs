@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::mem::size_of;
 use std::ops::{Index, IndexMut};
 
@@ -135,7 +136,7 @@ pub trait FullAddress {
     fn to_segmented_address(self) -> (u16, u16);
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Copy)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub struct Registers {
     // Status Register
     pub sr: u16,
@@ -555,6 +556,45 @@ pub fn register_name_to_index(name: &str) -> u8 {
     }
 }
 
+///
+/// Converts the index used in instruction encoding back to the string version of a register.
+///
+/// The opposite of `register_name_to_index`, used when disassembling instructions.
+///
+/// ```
+/// use peripheral_cpu::registers::register_index_to_name;
+///
+/// assert_eq!(Some("r1"), register_index_to_name(1));
+/// assert_eq!(Some("pl"), register_index_to_name(15));
+/// assert_eq!(None, register_index_to_name(16));
+/// ```
+///
+#[must_use]
+pub fn register_index_to_name(index: u8) -> Option<&'static str> {
+    const REGISTER_NAMES: [&str; 16] = [
+        "sr", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "lh", "ll", "ah", "al", "sh", "sl", "ph",
+        "pl",
+    ];
+    REGISTER_NAMES.get(usize::from(index)).copied()
+}
+
+///
+/// Converts the index used to encode an address register (see `AddressRegisterName`) back to the
+/// string version used in assembly.
+///
+/// ```
+/// use peripheral_cpu::registers::address_register_index_to_name;
+///
+/// assert_eq!(Some("a"), address_register_index_to_name(1));
+/// assert_eq!(None, address_register_index_to_name(4));
+/// ```
+///
+#[must_use]
+pub fn address_register_index_to_name(index: u8) -> Option<&'static str> {
+    const ADDRESS_REGISTER_NAMES: [&str; 4] = ["l", "a", "s", "p"];
+    ADDRESS_REGISTER_NAMES.get(usize::from(index)).copied()
+}
+
 ///
 /// Returns true if `start_index/end_index` form a valid range of registers,
 /// otherwise false.
@@ -578,7 +618,7 @@ pub fn is_valid_register_range(start_index: u8, end_index: u8) -> bool {
     start_index < register_count && end_index < register_count && end_index > start_index
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Copy)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub struct ExceptionLinkRegister {
     pub return_address: u32,
     pub return_status_register: u16,
@@ -587,7 +627,7 @@ pub struct ExceptionLinkRegister {
     pub saved_exception_level: u8,
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Copy)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub struct ExceptionUnitRegisters {
     // TODO: Consider capturing bus information when exception occurs
     // category=Hardware
@@ -8,7 +8,9 @@ use peripheral_cpu::coprocessors::processing_unit::definitions::{
 use peripheral_cpu::coprocessors::processing_unit::encoding::encode_instruction;
 use peripheral_cpu::registers::FullAddress;
 use peripheral_cpu::CYCLES_PER_INSTRUCTION;
-use peripheral_cpu::{new_cpu_peripheral, registers::Registers, CpuPeripheral};
+use peripheral_cpu::{
+    new_cpu_peripheral_with_model, registers::Registers, CpuModel, CpuPeripheral,
+};
 
 static DUMMY_SEGMENT: &str = "DUMMY";
 static PROGRAM_SEGMENT: &str = "PROGRAM";
@@ -21,13 +23,21 @@ pub struct TestCpuState {
     pub memory_dump: Vec<u8>,
 }
 
-#[allow(clippy::cast_lossless)]
 pub fn set_up_instruction_test(
     instruction_data: &InstructionData,
     program_offset: u32,
+) -> BusPeripheral {
+    set_up_instruction_test_with_model(instruction_data, program_offset, CpuModel::default())
+}
+
+#[allow(clippy::cast_lossless)]
+pub fn set_up_instruction_test_with_model(
+    instruction_data: &InstructionData,
+    program_offset: u32,
+    cpu_model: CpuModel,
 ) -> BusPeripheral {
     // TODO TODO I guess give the bus ownership again instead of reference (FACEPALM)
-    let cpu = new_cpu_peripheral(0x0);
+    let cpu = new_cpu_peripheral_with_model(0x0, cpu_model);
     let mut bus_peripheral = new_bus_peripheral(Box::new(cpu));
 
     let program_data = encode_instruction(instruction_data);
@@ -0,0 +1,406 @@
+use peripheral_bus::{device::BusAccessType, BusPeripheral};
+use peripheral_cpu::{
+    coprocessors::{
+        exception_unit::definitions::Faults,
+        processing_unit::definitions::{
+            ConditionFlags, ImmediateInstructionData, Instruction, InstructionData,
+        },
+    },
+    decode_fault_metadata_register,
+    registers::{set_sr_bit, FullAddressRegisterAccess, Registers, StatusRegisterFields},
+    CpuPeripheral, CYCLES_PER_INSTRUCTION,
+};
+
+use crate::instructions::common::{self, SCRATCH_SEGMENT_BEGIN};
+
+const PROGRAM_OFFSET: u32 = 0x00CC_0000;
+const DMAR_A: u16 = 0x2800;
+const DMAR_S: u16 = 0x2880;
+const DMAW_L: u16 = 0x2940;
+const DMAW_S: u16 = 0x2980;
+const DMAT: u16 = 0x2A00;
+const BACKWARD: u16 = 0x0020;
+
+struct DmaResult {
+    registers: Registers,
+    pending_fault: Option<Faults>,
+    dma_busy: bool,
+    phase: u8,
+    bus: BusPeripheral,
+}
+
+fn dma_instruction(command: u16) -> InstructionData {
+    InstructionData::Immediate(ImmediateInstructionData {
+        op_code: Instruction::CoprocessorCallImmediate,
+        register: 0x0,
+        value: command,
+        condition_flag: ConditionFlags::Always,
+        additional_flags: 0x0,
+    })
+}
+
+fn run_dma_instruction<F>(command: u16, register_setup: F, cycles: u32) -> DmaResult
+where
+    F: Fn(&mut Registers, &mut BusPeripheral),
+{
+    let mut bus = common::set_up_instruction_test(&dma_instruction(command), PROGRAM_OFFSET);
+    common::setup_test(&mut bus, register_setup, PROGRAM_OFFSET);
+    bus.run_full_cycle(cycles);
+
+    let cpu: &mut CpuPeripheral = bus
+        .bus_master
+        .as_any()
+        .downcast_mut::<CpuPeripheral>()
+        .expect("failed to downcast");
+
+    DmaResult {
+        registers: cpu.registers,
+        pending_fault: cpu.eu_registers.pending_fault,
+        dma_busy: cpu.dma_unit_executor.is_busy(),
+        phase: cpu.phase,
+        bus,
+    }
+}
+
+/// Cycles for the COPI dispatch, the DMA transfer and the cycle where the last access is latched
+fn cycles_to_completion(dma_cycles: u32) -> u32 {
+    u32::from(CYCLES_PER_INSTRUCTION) + dma_cycles + 1
+}
+
+fn fill_scratch(bus: &mut BusPeripheral, offset: u32, values: &[u16]) {
+    for (index, value) in (0..).zip(values) {
+        bus.write_address(SCRATCH_SEGMENT_BEGIN + offset + index, *value);
+    }
+}
+
+#[test]
+fn test_dmar_reads_into_registers_and_advances_address() {
+    let result = run_dma_instruction(
+        DMAR_A | 3,
+        |registers, bus| {
+            registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN + 0x10);
+            fill_scratch(bus, 0x10, &[0x1111, 0x2222, 0x3333, 0x4444]);
+            registers.r4 = 0xCAFE;
+        },
+        cycles_to_completion(3),
+    );
+
+    assert_eq!(None, result.pending_fault);
+    assert_eq!(
+        (0x1111, 0x2222, 0x3333, 0xCAFE),
+        (
+            result.registers.r1,
+            result.registers.r2,
+            result.registers.r3,
+            result.registers.r4
+        )
+    );
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x13,
+        result.registers.get_full_address_address()
+    );
+    assert_eq!(0x0, result.registers.pending_coprocessor_command);
+    assert_eq!(PROGRAM_OFFSET + 2, result.registers.get_full_pc_address());
+}
+
+#[test]
+fn test_dmar_negative_count_pre_decrements_address() {
+    let result = run_dma_instruction(
+        DMAR_S | BACKWARD | 2,
+        |registers, bus| {
+            registers.set_full_sp_address(SCRATCH_SEGMENT_BEGIN + 0x22);
+            fill_scratch(bus, 0x20, &[0xAAAA, 0xBBBB]);
+        },
+        cycles_to_completion(2),
+    );
+
+    assert_eq!(None, result.pending_fault);
+    assert_eq!((0xAAAA, 0xBBBB), (result.registers.r1, result.registers.r2));
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x20,
+        result.registers.get_full_sp_address()
+    );
+}
+
+#[test]
+fn test_dmaw_writes_registers_and_advances_address() {
+    let mut result = run_dma_instruction(
+        DMAW_L | 2,
+        |registers, _| {
+            registers.set_full_link_address(SCRATCH_SEGMENT_BEGIN + 0x30);
+            registers.r1 = 0xBEEF;
+            registers.r2 = 0xF00D;
+        },
+        cycles_to_completion(2),
+    );
+
+    assert_eq!(None, result.pending_fault);
+    assert_eq!(
+        0xBEEF,
+        result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0x30)
+    );
+    assert_eq!(
+        0xF00D,
+        result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0x31)
+    );
+    assert_eq!(0x0, result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0x32));
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x32,
+        result.registers.get_full_link_address()
+    );
+}
+
+#[test]
+fn test_dmaw_then_dmar_on_stack_restores_registers() {
+    let mut result = run_dma_instruction(
+        DMAW_S | BACKWARD | 3,
+        |registers, _| {
+            registers.set_full_sp_address(SCRATCH_SEGMENT_BEGIN + 0x100);
+            registers.r1 = 0x0001;
+            registers.r2 = 0x0002;
+            registers.r3 = 0x0003;
+        },
+        cycles_to_completion(3),
+    );
+
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0xFD,
+        result.registers.get_full_sp_address()
+    );
+    // Register order always corresponds to ascending memory addresses
+    assert_eq!(
+        0x0001,
+        result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0xFD)
+    );
+    assert_eq!(
+        0x0002,
+        result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0xFE)
+    );
+    assert_eq!(
+        0x0003,
+        result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0xFF)
+    );
+
+    let result = run_dma_instruction(
+        DMAR_S | 3,
+        |registers, bus| {
+            registers.set_full_sp_address(SCRATCH_SEGMENT_BEGIN + 0xFD);
+            fill_scratch(bus, 0xFD, &[0x0001, 0x0002, 0x0003]);
+        },
+        cycles_to_completion(3),
+    );
+
+    assert_eq!(
+        (0x0001, 0x0002, 0x0003),
+        (
+            result.registers.r1,
+            result.registers.r2,
+            result.registers.r3
+        )
+    );
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x100,
+        result.registers.get_full_sp_address()
+    );
+}
+
+#[test]
+fn test_dmat_copies_memory_in_chunks() {
+    let source: Vec<u16> = (0..10).map(|i| 0x1000 + i).collect();
+    let mut result = run_dma_instruction(
+        DMAT | 0xA,
+        |registers, bus| {
+            registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN + 0x200);
+            registers.set_full_link_address(SCRATCH_SEGMENT_BEGIN + 0x400);
+            fill_scratch(bus, 0x200, &source);
+        },
+        cycles_to_completion(20),
+    );
+
+    assert_eq!(None, result.pending_fault);
+    for (index, value) in (0..).zip(&source) {
+        assert_eq!(
+            *value,
+            result
+                .bus
+                .read_address(SCRATCH_SEGMENT_BEGIN + 0x400 + index)
+        );
+    }
+    assert_eq!(0x0, result.bus.read_address(SCRATCH_SEGMENT_BEGIN + 0x40A));
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x20A,
+        result.registers.get_full_address_address()
+    );
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x40A,
+        result.registers.get_full_link_address()
+    );
+    // The last chunk was three words long, the rest of the window is from the first chunk
+    assert_eq!(
+        [0x1007, 0x1008, 0x1009, 0x1003, 0x1004, 0x1005, 0x1006],
+        [
+            result.registers.r1,
+            result.registers.r2,
+            result.registers.r3,
+            result.registers.r4,
+            result.registers.r5,
+            result.registers.r6,
+            result.registers.r7,
+        ]
+    );
+}
+
+#[test]
+fn test_dma_timing() {
+    // (command, DMA cycles after the six cycle dispatch)
+    let cases = [
+        (DMAR_A, 1),
+        (DMAR_A | 1, 1),
+        (DMAR_A | 7, 7),
+        (DMAW_L | BACKWARD | 5, 5),
+        (DMAT, 1),
+        (DMAT | 1, 2),
+        (DMAT | 9, 18),
+    ];
+
+    for (command, dma_cycles) in cases {
+        let setup = |registers: &mut Registers, _: &mut BusPeripheral| {
+            registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN + 0x10);
+            registers.set_full_link_address(SCRATCH_SEGMENT_BEGIN + 0x100);
+        };
+        let in_progress = run_dma_instruction(
+            command,
+            setup,
+            u32::from(CYCLES_PER_INSTRUCTION) + dma_cycles - 1,
+        );
+        assert!(
+            in_progress.dma_busy || dma_cycles == 1,
+            "DMA command 0x{command:X} should still be running"
+        );
+        let completed = run_dma_instruction(
+            command,
+            setup,
+            u32::from(CYCLES_PER_INSTRUCTION) + dma_cycles,
+        );
+        assert!(
+            !completed.dma_busy && completed.phase == 0,
+            "DMA command 0x{command:X} should take {dma_cycles} cycles after dispatch"
+        );
+        assert_eq!(0x0, completed.registers.pending_coprocessor_command);
+        // The next instruction fetch starts on the following cycle
+        let next = run_dma_instruction(command, setup, cycles_to_completion(dma_cycles));
+        assert_eq!(1, next.phase);
+    }
+}
+
+#[test]
+fn test_dma_zero_count_is_a_no_op() {
+    let setup = |registers: &mut Registers, _: &mut BusPeripheral| {
+        registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN + 0x10);
+        registers.r1 = 0x1234;
+    };
+    let result = run_dma_instruction(DMAR_A | BACKWARD, setup, cycles_to_completion(1));
+
+    assert_eq!(None, result.pending_fault);
+    assert_eq!(0x1234, result.registers.r1);
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x10,
+        result.registers.get_full_address_address()
+    );
+}
+
+#[test]
+fn test_dma_reserved_operands_raise_invalid_opcode() {
+    for command in [DMAR_A | 0x0008, DMAW_L | 0x00C0 | 1, 0x2000, 0x2B00] {
+        let result = run_dma_instruction(
+            command,
+            |registers, _| {
+                registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN);
+            },
+            u32::from(CYCLES_PER_INSTRUCTION) + 1,
+        );
+
+        assert_eq!(
+            Some(Faults::InvalidOpCode),
+            result.pending_fault,
+            "Expected 0x{command:X} to be rejected"
+        );
+    }
+}
+
+#[test]
+fn test_dma_is_privileged() {
+    let result = run_dma_instruction(
+        DMAR_A | 1,
+        |registers, _| {
+            set_sr_bit(StatusRegisterFields::ProtectedMode, registers);
+            registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN);
+        },
+        u32::from(CYCLES_PER_INSTRUCTION),
+    );
+
+    assert_eq!(Some(Faults::PrivilegeViolation), result.pending_fault);
+}
+
+#[test]
+fn test_dma_segment_overflow_with_trap_enabled() {
+    let result = run_dma_instruction(
+        DMAR_A | 2,
+        |registers, _| {
+            set_sr_bit(StatusRegisterFields::TrapOnAddressOverflow, registers);
+            registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN + 0xFFFE);
+        },
+        u32::from(CYCLES_PER_INSTRUCTION) + 2,
+    );
+
+    assert_eq!(Some(Faults::SegmentOverflow), result.pending_fault);
+    assert!(!result.dma_busy);
+}
+
+#[test]
+fn test_dma_address_wraps_without_trap() {
+    let result = run_dma_instruction(
+        DMAR_A | 2,
+        |registers, bus| {
+            registers.set_full_address_address(SCRATCH_SEGMENT_BEGIN + 0xFFFF);
+            fill_scratch(bus, 0xFFFF, &[0x0F0F]);
+            fill_scratch(bus, 0x0, &[0xF0F0]);
+        },
+        cycles_to_completion(2),
+    );
+
+    assert_eq!(None, result.pending_fault);
+    assert_eq!((0x0F0F, 0xF0F0), (result.registers.r1, result.registers.r2));
+    assert_eq!(
+        SCRATCH_SEGMENT_BEGIN + 0x1,
+        result.registers.get_full_address_address()
+    );
+}
+
+#[test]
+fn test_dma_bus_error_raises_bus_fault() {
+    let mut result = run_dma_instruction(
+        DMAW_L | 2,
+        |registers, _| {
+            // Nothing is mapped here
+            registers.set_full_link_address(0x00EE_0000);
+        },
+        u32::from(CYCLES_PER_INSTRUCTION) + 2,
+    );
+
+    assert_eq!(Some(Faults::Bus), result.pending_fault);
+    assert!(!result.dma_busy);
+
+    let cpu: &mut CpuPeripheral = result
+        .bus
+        .bus_master
+        .as_any()
+        .downcast_mut::<CpuPeripheral>()
+        .expect("failed to downcast");
+    let metadata = cpu.eu_registers.link_registers[7];
+    assert_eq!(0x00EE_0000, metadata.return_address);
+    assert_eq!(
+        BusAccessType::DmaWriteBurst,
+        decode_fault_metadata_register(metadata.return_status_register).bus_access_type
+    );
+}
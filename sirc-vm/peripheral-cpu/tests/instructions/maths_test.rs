@@ -0,0 +1,302 @@
+use peripheral_cpu::{
+    coprocessors::{
+        exception_unit::definitions::Faults,
+        processing_unit::definitions::{
+            ConditionFlags, ImmediateInstructionData, Instruction, InstructionData,
+        },
+    },
+    registers::{set_sr_bit, FullAddressRegisterAccess, Registers, StatusRegisterFields},
+    CpuModel, CpuPeripheral, CYCLES_PER_INSTRUCTION,
+};
+
+use crate::instructions::common;
+
+const PROGRAM_OFFSET: u32 = 0x00CC_0000;
+const MULU: u16 = 0x3000;
+const MULS: u16 = 0x3100;
+const DIVU: u16 = 0x3200;
+const DIVS: u16 = 0x3300;
+
+// Maths status word bits (r3)
+const E: u16 = 0b0001;
+const DZ: u16 = 0b0010;
+const OV: u16 = 0b0100;
+
+struct MathsResult {
+    registers: Registers,
+    pending_fault: Option<Faults>,
+    fault_metadata_address: u32,
+    phase: u8,
+}
+
+fn maths_instruction(command: u16) -> InstructionData {
+    InstructionData::Immediate(ImmediateInstructionData {
+        op_code: Instruction::CoprocessorCallImmediate,
+        register: 0x0,
+        value: command,
+        condition_flag: ConditionFlags::Always,
+        additional_flags: 0x0,
+    })
+}
+
+fn run_maths_instruction_on_model(
+    cpu_model: CpuModel,
+    command: u16,
+    inputs: (u16, u16, u16),
+    cycles: u32,
+) -> MathsResult {
+    let mut bus = common::set_up_instruction_test_with_model(
+        &maths_instruction(command),
+        PROGRAM_OFFSET,
+        cpu_model,
+    );
+    common::setup_test(
+        &mut bus,
+        |registers, _| {
+            (registers.r1, registers.r2, registers.r3) = inputs;
+            set_sr_bit(StatusRegisterFields::Carry, registers);
+            set_sr_bit(StatusRegisterFields::Negative, registers);
+        },
+        PROGRAM_OFFSET,
+    );
+    bus.run_full_cycle(cycles);
+
+    let cpu: &mut CpuPeripheral = bus
+        .bus_master
+        .as_any()
+        .downcast_mut::<CpuPeripheral>()
+        .expect("failed to downcast");
+
+    MathsResult {
+        registers: cpu.registers,
+        pending_fault: cpu.eu_registers.pending_fault,
+        fault_metadata_address: cpu.eu_registers.link_registers[7].return_address,
+        phase: cpu.phase,
+    }
+}
+
+/// Cycles for the COPI dispatch plus the six maths unit phases
+fn cycles_to_completion() -> u32 {
+    u32::from(CYCLES_PER_INSTRUCTION) * 2
+}
+
+fn run_maths_instruction(command: u16, inputs: (u16, u16, u16)) -> (u16, u16, u16) {
+    let result =
+        run_maths_instruction_on_model(CpuModel::Maths, command, inputs, cycles_to_completion());
+    assert_eq!(None, result.pending_fault);
+    (
+        result.registers.r1,
+        result.registers.r2,
+        result.registers.r3,
+    )
+}
+
+#[test]
+fn test_mulu() {
+    assert_eq!(
+        (0x0001, 0x5F90, 0x0),
+        run_maths_instruction(MULU, (300, 300, 0xFFFF))
+    );
+    assert_eq!(
+        (0xFFFE, 0x0001, 0x0),
+        run_maths_instruction(MULU, (0xFFFF, 0xFFFF, 0x0))
+    );
+}
+
+#[test]
+fn test_muls() {
+    // -2 * 3 = -6
+    assert_eq!(
+        (0xFFFF, 0xFFFA, 0x0),
+        run_maths_instruction(MULS, (0xFFFE, 0x0003, 0x0))
+    );
+    // -32768 * -32768 = 2^30
+    assert_eq!(
+        (0x4000, 0x0000, 0x0),
+        run_maths_instruction(MULS, (0x8000, 0x8000, 0x0))
+    );
+}
+
+#[test]
+fn test_divu() {
+    // 100000 / 7 = 14285 r 5
+    assert_eq!(
+        (0x0005, 0x37CD, 0x0),
+        run_maths_instruction(DIVU, (0x0001, 0x86A0, 0x0007))
+    );
+}
+
+#[test]
+fn test_divu_errors_leave_operands_untouched() {
+    assert_eq!(
+        (0x1234, 0x5678, E | DZ),
+        run_maths_instruction(DIVU, (0x1234, 0x5678, 0x0))
+    );
+    // 0x10000 / 1 does not fit in 16 bits
+    assert_eq!(
+        (0x0001, 0x0000, E | OV),
+        run_maths_instruction(DIVU, (0x0001, 0x0000, 0x0001))
+    );
+}
+
+#[test]
+fn test_divs_truncates_toward_zero() {
+    // -11 / 3 = -3 r -2
+    assert_eq!(
+        (0xFFFE, 0xFFFD, 0x0),
+        run_maths_instruction(DIVS, (0xFFFF, 0xFFF5, 0x0003))
+    );
+    // 11 / -3 = -3 r 2
+    assert_eq!(
+        (0x0002, 0xFFFD, 0x0),
+        run_maths_instruction(DIVS, (0x0000, 0x000B, 0xFFFD))
+    );
+}
+
+#[test]
+fn test_divs_errors_leave_operands_untouched() {
+    assert_eq!(
+        (0xFFFF, 0xFFF5, E | DZ),
+        run_maths_instruction(DIVS, (0xFFFF, 0xFFF5, 0x0))
+    );
+    // -2147483648 / -1
+    assert_eq!(
+        (0x8000, 0x0000, E | OV),
+        run_maths_instruction(DIVS, (0x8000, 0x0000, 0xFFFF))
+    );
+    // 32768 / 1 does not fit in a signed 16 bit value
+    assert_eq!(
+        (0x0000, 0x8000, E | OV),
+        run_maths_instruction(DIVS, (0x0000, 0x8000, 0x0001))
+    );
+}
+
+#[test]
+fn test_maths_preserves_status_register() {
+    let result = run_maths_instruction_on_model(
+        CpuModel::Maths,
+        MULU,
+        (0x0, 0x0, 0x0),
+        cycles_to_completion(),
+    );
+
+    assert_eq!(
+        StatusRegisterFields::Carry as u16 | StatusRegisterFields::Negative as u16,
+        result.registers.sr
+    );
+}
+
+#[test]
+fn test_maths_timing() {
+    let before_write_back = run_maths_instruction_on_model(
+        CpuModel::Maths,
+        MULU,
+        (0x2, 0x3, 0xFFFF),
+        cycles_to_completion() - 1,
+    );
+    assert_eq!(
+        (0x2, 0x3, 0xFFFF),
+        (
+            before_write_back.registers.r1,
+            before_write_back.registers.r2,
+            before_write_back.registers.r3
+        )
+    );
+    assert_eq!(5, before_write_back.phase);
+
+    let complete = run_maths_instruction_on_model(
+        CpuModel::Maths,
+        MULU,
+        (0x2, 0x3, 0xFFFF),
+        cycles_to_completion(),
+    );
+    assert_eq!(
+        (0x0, 0x6, 0x0),
+        (
+            complete.registers.r1,
+            complete.registers.r2,
+            complete.registers.r3
+        )
+    );
+    assert_eq!(0, complete.phase);
+    assert_eq!(0x0, complete.registers.pending_coprocessor_command);
+    assert_eq!(PROGRAM_OFFSET + 2, complete.registers.get_full_pc_address());
+}
+
+#[test]
+fn test_maths_is_user_callable() {
+    let mut bus = common::set_up_instruction_test_with_model(
+        &maths_instruction(MULU),
+        PROGRAM_OFFSET,
+        CpuModel::Maths,
+    );
+    common::setup_test(
+        &mut bus,
+        |registers, _| {
+            set_sr_bit(StatusRegisterFields::ProtectedMode, registers);
+            (registers.r1, registers.r2) = (0x10, 0x10);
+        },
+        PROGRAM_OFFSET,
+    );
+    bus.run_full_cycle(cycles_to_completion());
+
+    let cpu: &mut CpuPeripheral = bus
+        .bus_master
+        .as_any()
+        .downcast_mut::<CpuPeripheral>()
+        .expect("failed to downcast");
+    assert_eq!(None, cpu.eu_registers.pending_fault);
+    assert_eq!((0x0, 0x100), (cpu.registers.r1, cpu.registers.r2));
+}
+
+#[test]
+fn test_maths_without_hardware_raises_invalid_opcode() {
+    for command in [MULU, MULS, DIVU, DIVS] {
+        let result = run_maths_instruction_on_model(
+            CpuModel::Base,
+            command,
+            (0x2, 0x3, 0x4),
+            u32::from(CYCLES_PER_INSTRUCTION) + 1,
+        );
+
+        assert_eq!(Some(Faults::InvalidOpCode), result.pending_fault);
+        // The command is stored in the fault metadata so that it can be emulated
+        assert_eq!(u32::from(command), result.fault_metadata_address);
+        assert_eq!(
+            (0x2, 0x3, 0x4),
+            (
+                result.registers.r1,
+                result.registers.r2,
+                result.registers.r3
+            )
+        );
+        assert_eq!(0x0, result.registers.pending_coprocessor_command);
+    }
+}
+
+#[test]
+fn test_unsupported_maths_op_raises_invalid_opcode() {
+    for command in [0x3400, 0x3700, 0x3800, 0x3F00] {
+        let result = run_maths_instruction_on_model(
+            CpuModel::Maths,
+            command,
+            (0x2, 0x3, 0x4),
+            u32::from(CYCLES_PER_INSTRUCTION) + 1,
+        );
+
+        assert_eq!(
+            Some(Faults::InvalidOpCode),
+            result.pending_fault,
+            "Expected 0x{command:X} to be rejected"
+        );
+        assert_eq!(u32::from(command), result.fault_metadata_address);
+        assert_eq!(
+            (0x2, 0x3, 0x4),
+            (
+                result.registers.r1,
+                result.registers.r2,
+                result.registers.r3
+            )
+        );
+    }
+}
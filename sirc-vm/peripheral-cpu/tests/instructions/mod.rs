@@ -2,9 +2,11 @@ mod arithmetic_immediate_test;
 mod arithmetic_register_test;
 mod arithmetic_short_immediate_test;
 mod common;
+mod dma_test;
 mod ldea_test;
 mod ldel_test;
 mod ljmp_test;
 mod load_test;
+mod maths_test;
 mod protected_mode_test;
 mod store_test;
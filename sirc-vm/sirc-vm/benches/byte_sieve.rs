@@ -2,7 +2,7 @@ use std::{cell::RefCell, fs, path::PathBuf, time::Duration};
 
 use criterion::{criterion_group, criterion_main, Criterion, SamplingMode};
 use device_ram::{new_ram_device_file_mapped, new_ram_device_standard};
-use peripheral_bus::new_bus_peripheral;
+use peripheral_bus::{device::BusAssertions, new_bus_peripheral};
 use peripheral_cpu::new_cpu_peripheral;
 use sirc_vm::{run_vm, Vm};
 
@@ -38,6 +38,9 @@ fn setup_vm(program: &[u8], mapped_file_path: PathBuf) -> Vm {
     Vm {
         bus_peripheral: RefCell::new(bus_peripheral),
         vsync_frequency: 60f64,
+        bus_assertions: BusAssertions::default(),
+        save_state: None,
+        trace: None,
     }
 }
 
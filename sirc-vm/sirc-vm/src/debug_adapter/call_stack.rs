@@ -0,0 +1,161 @@
+//!
+//! Best effort call stack reconstruction.
+//!
+//! The CPU doesn't keep a call stack. Subroutine calls (LDEL/LJSR/BRSR) only save the return
+//! address in the link register, and by convention a subroutine that calls other subroutines
+//! pushes `lh` then `ll` to the stack first. Exceptions save the return address in the banked
+//! exception link registers instead.
+//!
+//! The unwinder follows the exception link registers, which are always accurate, and looks for
+//! return addresses in the link register and the stack. An address is only treated as a return
+//! address if the instruction before it is a call, but the frames can still be wrong if the stack
+//! happens to contain something that looks like one.
+//!
+
+use peripheral_cpu::{
+    coprocessors::processing_unit::{
+        definitions::{Instruction, InstructionData, INSTRUCTION_SIZE_WORDS},
+        encoding::decode_instruction,
+    },
+    registers::{ExceptionUnitRegisters, FullAddressRegisterAccess, Registers, ADDRESS_MASK},
+};
+
+use super::symbols::nearest_symbol;
+use super::types::SymbolTable;
+
+/// Stops the unwinder going on forever if the stack is full of things that look like return addresses
+pub const MAX_FRAMES: usize = 32;
+/// How many words above the stack pointer are searched for saved link registers
+pub const STACK_SCAN_WORDS: u32 = 64;
+
+/// The address register index of the PC (`ph`/`pl`) in LDEL instructions
+const PROGRAM_COUNTER_INDEX: u8 = 3;
+/// The highest exception level, which has a banked link register
+const MAX_EXCEPTION_LEVEL: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// Where the VM is paused
+    Current,
+    /// A return address found in the link register or on the stack
+    Subroutine,
+    /// Where the program was when an exception at this level was taken
+    Exception(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub pc: u32,
+    pub kind: FrameKind,
+}
+
+/// Returns true if the instruction is a subroutine call (i.e. an LDEL with the PC as the destination)
+#[must_use]
+pub fn is_call_instruction(words: [u16; 2]) -> bool {
+    let [high, low] = words.map(u16::to_be_bytes);
+    match decode_instruction([high[0], high[1], low[0], low[1]]) {
+        InstructionData::Immediate(data) => {
+            matches!(
+                data.op_code,
+                Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate
+                    | Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediatePostIncrement
+            ) && data.register == PROGRAM_COUNTER_INDEX
+        }
+        InstructionData::Register(data) => {
+            matches!(
+                data.op_code,
+                Instruction::LoadEffectiveAddressAndLinkFromIndirectRegister
+                    | Instruction::LoadEffectiveAddressAndLinkFromIndirectRegisterPostIncrement
+            ) && data.r1 == PROGRAM_COUNTER_INDEX
+        }
+        InstructionData::ShortImmediate(_) => false,
+    }
+}
+
+const fn subroutine_frame(pc: u32) -> CallFrame {
+    CallFrame {
+        pc,
+        kind: FrameKind::Subroutine,
+    }
+}
+
+fn follows_call(address: u32, read_memory: &mut impl FnMut(u32, u32) -> Vec<u16>) -> bool {
+    address >= INSTRUCTION_SIZE_WORDS
+        && read_memory(address - INSTRUCTION_SIZE_WORDS, INSTRUCTION_SIZE_WORDS)
+            .try_into()
+            .is_ok_and(is_call_instruction)
+}
+
+///
+/// Unwinds the call stack, starting with the frame at the PC.
+///
+/// `read_memory` reads up to `count` words from an address, stopping early at unmapped memory.
+///
+/// The frames go: the current frame, the return address in the link register, where the program
+/// was when each of the active exceptions were taken (innermost first), and then the return
+/// addresses on the stack. Exceptions don't touch the stack, so if an exception handler calls
+/// subroutines that save the link register, those frames end up after the exception frames.
+///
+pub fn unwind_call_stack(
+    registers: &Registers,
+    eu_registers: &ExceptionUnitRegisters,
+    symbols: &SymbolTable,
+    mut read_memory: impl FnMut(u32, u32) -> Vec<u16>,
+) -> Vec<CallFrame> {
+    let pc = registers.get_full_pc_address();
+
+    let mut exception_frames = vec![];
+    let mut level = eu_registers.current_exception_level;
+    while (1..=MAX_EXCEPTION_LEVEL).contains(&level) {
+        let link_register = eu_registers.link_registers[usize::from(level - 1)];
+        exception_frames.push(CallFrame {
+            pc: link_register.return_address & ADDRESS_MASK,
+            kind: FrameKind::Exception(level),
+        });
+        // Levels only nest upwards, so this can't go around in circles
+        if link_register.saved_exception_level >= level {
+            break;
+        }
+        level = link_register.saved_exception_level;
+    }
+
+    // The link register isn't cleared on return, so it could be left over from a call that has
+    // already returned to the current function (or the code an exception interrupted)
+    let link = registers.get_full_link_address();
+    let function_of = |address| nearest_symbol(symbols, address).map(|(name, _)| name);
+    let link_is_stale = std::iter::once(pc)
+        .chain(exception_frames.iter().map(|frame| frame.pc))
+        .any(|known_pc| link <= known_pc && function_of(link) == function_of(known_pc));
+    let link_frame = (!link_is_stale && follows_call(link, &mut read_memory)).then_some(link);
+
+    // Look for `lh`/`ll` pairs that were pushed to the stack (`ll` ends up at the lower address)
+    let stack_pointer = registers.get_full_sp_address();
+    let stack = read_memory(stack_pointer, STACK_SCAN_WORDS);
+    let mut stack_frames = vec![];
+    let mut index = 0;
+    while index + 1 < stack.len() {
+        let address =
+            ((u32::from(stack[index + 1]) << u16::BITS) | u32::from(stack[index])) & ADDRESS_MASK;
+        if address != 0 && follows_call(address, &mut read_memory) {
+            stack_frames.push(address);
+            index += 2;
+        } else {
+            index += 1;
+        }
+    }
+    // A subroutine that saved the link register but hasn't called anything yet has the same
+    // return address in both places
+    if link_frame.is_some() && stack_frames.first().copied() == link_frame {
+        stack_frames.remove(0);
+    }
+
+    std::iter::once(CallFrame {
+        pc,
+        kind: FrameKind::Current,
+    })
+    .chain(link_frame.into_iter().map(subroutine_frame))
+    .chain(exception_frames)
+    .chain(stack_frames.into_iter().map(subroutine_frame))
+    .take(MAX_FRAMES)
+    .collect()
+}
@@ -0,0 +1,128 @@
+//!
+//! Works around differences between what debug clients send and what the `dap` crate expects.
+//!
+//! The `SourceBreakpoint` type in the `dap` crate is missing `#[serde(rename_all = "camelCase")]`
+//! so it only reads `hit_condition` and `log_message`, while clients (correctly) send
+//! `hitCondition` and `logMessage`. Without this, hit conditions and logpoints on source
+//! breakpoints would be silently ignored.
+//!
+
+// TODO: Remove the `dap` request workarounds once the crate is fixed upstream
+// category=Debugging
+
+use std::io::{BufRead, Read};
+
+use serde_json::Value;
+
+const CONTENT_LENGTH_HEADER: &str = "content-length:";
+
+/// Renamed in the `breakpoints` of `setBreakpoints` requests
+static SOURCE_BREAKPOINT_RENAMES: [(&str, &str); 2] = [
+    ("hitCondition", "hit_condition"),
+    ("logMessage", "log_message"),
+];
+
+///
+/// Rewrites the body of a request so that the `dap` crate can parse it. Anything that isn't a
+/// `setBreakpoints` request (or isn't valid JSON) is returned unchanged.
+///
+/// ```
+/// use sirc_vm::debug_adapter::compat::normalise_request;
+///
+/// assert_eq!(
+///     r#"{"arguments":{"breakpoints":[{"line":1,"log_message":"{r1}"}]},"command":"setBreakpoints"}"#,
+///     normalise_request(
+///         r#"{"command":"setBreakpoints","arguments":{"breakpoints":[{"line":1,"logMessage":"{r1}"}]}}"#
+///     )
+/// );
+/// assert_eq!(r#"{"command":"next"}"#, normalise_request(r#"{"command":"next"}"#));
+/// ```
+///
+#[must_use]
+pub fn normalise_request(body: &str) -> String {
+    let Ok(mut request) = serde_json::from_str::<Value>(body) else {
+        return body.to_string();
+    };
+    if request["command"] != "setBreakpoints" {
+        return body.to_string();
+    }
+    let Some(breakpoints) = request
+        .pointer_mut("/arguments/breakpoints")
+        .and_then(Value::as_array_mut)
+    else {
+        return body.to_string();
+    };
+    for breakpoint in breakpoints.iter_mut().filter_map(Value::as_object_mut) {
+        for (from, to) in SOURCE_BREAKPOINT_RENAMES {
+            if let Some(value) = breakpoint.remove(from) {
+                breakpoint.insert(to.to_string(), value);
+            }
+        }
+    }
+    request.to_string()
+}
+
+///
+/// Wraps the input stream of the debug server and passes each message through
+/// `normalise_request`, fixing up the content length to match.
+///
+pub struct NormalisingReader<R: BufRead> {
+    inner: R,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl<R: BufRead> NormalisingReader<R> {
+    pub const fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending: vec![],
+            position: 0,
+        }
+    }
+
+    /// Reads the next message from the inner stream, returns false if the stream has ended
+    fn read_message(&mut self) -> std::io::Result<bool> {
+        let mut content_length = None;
+        loop {
+            let mut line = String::new();
+            if self.inner.read_line(&mut line)? == 0 {
+                return Ok(false);
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                // The headers end with an empty line (skipping any blank lines before them)
+                if content_length.is_some() {
+                    break;
+                }
+                continue;
+            }
+            if let Some(length) = line
+                .to_ascii_lowercase()
+                .strip_prefix(CONTENT_LENGTH_HEADER)
+            {
+                content_length = length.trim().parse::<usize>().ok();
+            }
+        }
+
+        let mut body = vec![0; content_length.unwrap_or_default()];
+        self.inner.read_exact(&mut body)?;
+        let body = normalise_request(&String::from_utf8_lossy(&body));
+
+        self.pending = format!("Content-Length: {}\r\n\r\n{body}", body.len()).into_bytes();
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for NormalisingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.pending.len() && !self.read_message()? {
+            return Ok(0);
+        }
+        let count = buf.len().min(self.pending.len() - self.position);
+        buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
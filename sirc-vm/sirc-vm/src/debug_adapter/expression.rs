@@ -0,0 +1,676 @@
+//!
+//! A small expression language for the debugger (e.g. breakpoint conditions and log messages).
+//!
+//! Expressions are parsed by the debug adapter (so that syntax errors can be reported straight
+//! away) and evaluated in the VM thread, where the registers and memory are available.
+//!
+//! - Numbers can be decimal, hex (`0x`) or binary (`0b`), optionally with a `#` prefix like in
+//!   assembly (e.g. `#0xCAFE`)
+//! - Register names (e.g. `r1`, `al`, `sr`) evaluate to the value of the register
+//! - Address register names (`l`, `a`, `s`, `p`) evaluate to the full 24 bit address
+//! - Symbols (e.g. labels) evaluate to their address, if the debug map has them
+//! - `[expression]` reads the word at an address
+//! - The usual C operators are supported, with the same precedence. Comparisons evaluate to 1
+//!   or 0 and all arithmetic wraps at 32 bits.
+//!
+
+use std::fmt::{Display, Formatter};
+
+use peripheral_cpu::registers::{
+    address_register_index_to_name, register_index_to_name, AddressRegisterIndexing, Registers,
+    ADDRESS_MASK,
+};
+use thiserror::Error;
+
+use super::types::{MemoryAccessError, SymbolTable};
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ExpressionError {
+    #[error("Invalid expression: {0}")]
+    Syntax(String),
+    #[error("Unknown identifier [{0}]")]
+    UnknownIdentifier(String),
+    #[error(transparent)]
+    Memory(#[from] MemoryAccessError),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("[{0}] can't be assigned to, only registers and memory (e.g. [a + 4]) can be")]
+    NotAssignable(String),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum UnaryOperator {
+    Negate,
+    LogicalNot,
+    BitwiseNot,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum BinaryOperator {
+    Multiply,
+    Divide,
+    Remainder,
+    Add,
+    Subtract,
+    ShiftLeft,
+    ShiftRight,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Equal,
+    NotEqual,
+    BitwiseAnd,
+    BitwiseXor,
+    BitwiseOr,
+    LogicalAnd,
+    LogicalOr,
+}
+
+impl BinaryOperator {
+    /// Higher binds tighter (the same order as C)
+    const fn precedence(self) -> u8 {
+        match self {
+            Self::Multiply | Self::Divide | Self::Remainder => 10,
+            Self::Add | Self::Subtract => 9,
+            Self::ShiftLeft | Self::ShiftRight => 8,
+            Self::LessThan
+            | Self::LessThanOrEqual
+            | Self::GreaterThan
+            | Self::GreaterThanOrEqual => 7,
+            Self::Equal | Self::NotEqual => 6,
+            Self::BitwiseAnd => 5,
+            Self::BitwiseXor => 4,
+            Self::BitwiseOr => 3,
+            Self::LogicalAnd => 2,
+            Self::LogicalOr => 1,
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "*" => Some(Self::Multiply),
+            "/" => Some(Self::Divide),
+            "%" => Some(Self::Remainder),
+            "+" => Some(Self::Add),
+            "-" => Some(Self::Subtract),
+            "<<" => Some(Self::ShiftLeft),
+            ">>" => Some(Self::ShiftRight),
+            "<" => Some(Self::LessThan),
+            "<=" => Some(Self::LessThanOrEqual),
+            ">" => Some(Self::GreaterThan),
+            ">=" => Some(Self::GreaterThanOrEqual),
+            "==" => Some(Self::Equal),
+            "!=" => Some(Self::NotEqual),
+            "&" => Some(Self::BitwiseAnd),
+            "^" => Some(Self::BitwiseXor),
+            "|" => Some(Self::BitwiseOr),
+            "&&" => Some(Self::LogicalAnd),
+            "||" => Some(Self::LogicalOr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Expression {
+    Number(u32),
+    /// A register (or anything else the evaluation context knows about)
+    Identifier(String),
+    /// Reads the word at an address
+    Dereference(Box<Self>),
+    Unary(UnaryOperator, Box<Self>),
+    Binary(BinaryOperator, Box<Self>, Box<Self>),
+}
+
+impl Expression {
+    /// Only registers and memory can be assigned to (e.g. `r1` or `[a + 4]`)
+    #[must_use]
+    pub fn is_assignable(&self) -> bool {
+        match self {
+            Self::Identifier(name) => is_register_name(name),
+            Self::Dereference(_) => true,
+            Self::Number(_) | Self::Unary(..) | Self::Binary(..) => false,
+        }
+    }
+
+    ///
+    /// Replaces identifiers that name a symbol (e.g. a label) with its address. Register names
+    /// always refer to the register.
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use sirc_vm::debug_adapter::expression::{parse_expression, Expression};
+    ///
+    /// let symbols = BTreeMap::from([("init".to_string(), 0x200), ("a".to_string(), 0x300)]);
+    ///
+    /// assert_eq!(
+    ///     Expression::Number(0x200),
+    ///     parse_expression("init").unwrap().resolve_symbols(&symbols)
+    /// );
+    /// assert_eq!(
+    ///     Expression::Identifier("a".to_string()),
+    ///     parse_expression("a").unwrap().resolve_symbols(&symbols)
+    /// );
+    /// ```
+    ///
+    #[must_use]
+    pub fn resolve_symbols(self, symbols: &SymbolTable) -> Self {
+        match self {
+            Self::Identifier(name) if !is_register_name(&name) => symbols
+                .get(&name)
+                .map_or(Self::Identifier(name), |address| Self::Number(*address)),
+            Self::Dereference(address) => {
+                Self::Dereference(Box::new(address.resolve_symbols(symbols)))
+            }
+            Self::Unary(operator, operand) => {
+                Self::Unary(operator, Box::new(operand.resolve_symbols(symbols)))
+            }
+            Self::Binary(operator, left, right) => Self::Binary(
+                operator,
+                Box::new(left.resolve_symbols(symbols)),
+                Box::new(right.resolve_symbols(symbols)),
+            ),
+            Self::Number(_) | Self::Identifier(_) => self,
+        }
+    }
+}
+
+/// Provides the state of the VM to expressions when they are evaluated
+pub trait EvaluationContext {
+    fn identifier(&self, name: &str) -> Option<u32>;
+    /// Returns None if nothing is mapped to the address
+    fn read_memory(&mut self, address: u32) -> Option<u16>;
+}
+
+enum RegisterRef {
+    Register(u8),
+    AddressRegister(u8),
+}
+
+fn find_register(name: &str) -> Option<RegisterRef> {
+    let name = name.to_ascii_lowercase();
+    (0..=u8::MAX)
+        .map_while(|index| register_index_to_name(index).map(|n| (index, n)))
+        .find(|(_, register_name)| *register_name == name)
+        .map(|(index, _)| RegisterRef::Register(index))
+        .or_else(|| {
+            (0..=u8::MAX)
+                .map_while(|index| address_register_index_to_name(index).map(|n| (index, n)))
+                .find(|(_, register_name)| *register_name == name)
+                .map(|(index, _)| RegisterRef::AddressRegister(index))
+        })
+}
+
+#[must_use]
+pub fn is_register_name(name: &str) -> bool {
+    find_register(name).is_some()
+}
+
+///
+/// Gets the value of a register by the name used in assembly. Address register names (e.g. `a`)
+/// give the full 24 bit address.
+///
+/// ```
+/// use peripheral_cpu::registers::Registers;
+/// use sirc_vm::debug_adapter::expression::register_value;
+///
+/// let registers = Registers { r1: 0xCAFE, ah: 0x0012, al: 0x3456, ..Registers::default() };
+///
+/// assert_eq!(Some(0xCAFE), register_value(&registers, "r1"));
+/// assert_eq!(Some(0x12_3456), register_value(&registers, "a"));
+/// assert_eq!(None, register_value(&registers, "r8"));
+/// ```
+///
+#[must_use]
+pub fn register_value(registers: &Registers, name: &str) -> Option<u32> {
+    find_register(name).map(|register| match register {
+        RegisterRef::Register(index) => u32::from(registers[index]),
+        RegisterRef::AddressRegister(index) => registers.get_address_register_at_index(index),
+    })
+}
+
+///
+/// Sets a register by the name used in assembly. The value is truncated to the size of the
+/// register and the value that was actually stored is returned.
+///
+/// ```
+/// use peripheral_cpu::registers::Registers;
+/// use sirc_vm::debug_adapter::expression::set_register_value;
+///
+/// let mut registers = Registers::default();
+///
+/// assert_eq!(Some(0xFFFF), set_register_value(&mut registers, "r1", 0xFFFF_FFFF));
+/// assert_eq!(Some(0xFF_FFFF), set_register_value(&mut registers, "a", 0xFFFF_FFFF));
+/// assert_eq!((0xFFFF, 0x00FF, 0xFFFF), (registers.r1, registers.ah, registers.al));
+/// assert_eq!(None, set_register_value(&mut registers, "r8", 1));
+/// ```
+///
+pub fn set_register_value(registers: &mut Registers, name: &str, value: u32) -> Option<u32> {
+    find_register(name).map(|register| match register {
+        RegisterRef::Register(index) => {
+            let value = (value & 0xFFFF) as u16;
+            registers[index] = value;
+            u32::from(value)
+        }
+        RegisterRef::AddressRegister(index) => {
+            registers.set_address_register_at_index(index, value & ADDRESS_MASK);
+            registers.get_address_register_at_index(index)
+        }
+    })
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Number(u32),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(value) => write!(f, "{value}"),
+            Self::Identifier(name) => write!(f, "{name}"),
+            Self::Symbol(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+/// Longer symbols come first so that e.g. "<<" isn't tokenized as two "<"
+static SYMBOLS: [&str; 24] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*", "/", "%", "+", "-", "<", ">", "&", "^",
+    "|", "!", "~", "(", ")", "[", "]",
+];
+
+fn parse_number(input: &str) -> Result<u32, ExpressionError> {
+    let digits = input.replace('_', "");
+    let (digits, radix) = digits
+        .strip_prefix("0x")
+        .map(|hex| (hex, 16))
+        .or_else(|| digits.strip_prefix("0b").map(|binary| (binary, 2)))
+        .unwrap_or((digits.as_str(), 10));
+    u32::from_str_radix(digits, radix)
+        .map_err(|_| ExpressionError::Syntax(format!("[{input}] is not a valid number")))
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ExpressionError> {
+    let mut tokens = vec![];
+    let mut remaining = input.trim_start();
+    while let Some(next) = remaining.chars().next() {
+        let token_length = if next.is_ascii_digit() || next == '#' {
+            let number = remaining.trim_start_matches('#');
+            let length = number
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(number.len());
+            tokens.push(Token::Number(parse_number(&number[..length])?));
+            length + remaining.len() - number.len()
+        } else if next.is_ascii_alphabetic() || next == '_' {
+            let length = remaining
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(remaining.len());
+            tokens.push(Token::Identifier(remaining[..length].to_string()));
+            length
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| remaining.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else {
+            return Err(ExpressionError::Syntax(format!(
+                "Unexpected character [{next}]"
+            )));
+        };
+        remaining = remaining[token_length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ExpressionError> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            Some(token) => Err(ExpressionError::Syntax(format!(
+                "Expected [{symbol}] but found [{token}]"
+            ))),
+            None => Err(ExpressionError::Syntax(format!(
+                "Expected [{symbol}] but found the end of the expression"
+            ))),
+        }
+    }
+
+    /// Precedence climbing, only binds operators at or above `minimum_precedence`
+    fn parse_binary(&mut self, minimum_precedence: u8) -> Result<Expression, ExpressionError> {
+        let mut left = self.parse_unary()?;
+        while let Some(operator) = match self.peek() {
+            Some(Token::Symbol(symbol)) => BinaryOperator::from_token(symbol),
+            _ => None,
+        } {
+            if operator.precedence() < minimum_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.parse_binary(operator.precedence() + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, ExpressionError> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::Identifier(name)) => Ok(Expression::Identifier(name)),
+            Some(Token::Symbol("-")) => Ok(Expression::Unary(
+                UnaryOperator::Negate,
+                Box::new(self.parse_unary()?),
+            )),
+            Some(Token::Symbol("!")) => Ok(Expression::Unary(
+                UnaryOperator::LogicalNot,
+                Box::new(self.parse_unary()?),
+            )),
+            Some(Token::Symbol("~")) => Ok(Expression::Unary(
+                UnaryOperator::BitwiseNot,
+                Box::new(self.parse_unary()?),
+            )),
+            Some(Token::Symbol("(")) => {
+                let expression = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Symbol("[")) => {
+                let expression = self.parse_binary(0)?;
+                self.expect("]")?;
+                Ok(Expression::Dereference(Box::new(expression)))
+            }
+            Some(token) => Err(ExpressionError::Syntax(format!("Unexpected [{token}]"))),
+            None => Err(ExpressionError::Syntax(
+                "Unexpected end of the expression".to_string(),
+            )),
+        }
+    }
+}
+
+///
+/// Parses an expression, e.g. a breakpoint condition.
+///
+/// ```
+/// use sirc_vm::debug_adapter::expression::{parse_expression, BinaryOperator, Expression};
+///
+/// assert_eq!(
+///     Ok(Expression::Binary(
+///         BinaryOperator::Add,
+///         Box::new(Expression::Identifier("a".to_string())),
+///         Box::new(Expression::Number(4)),
+///     )),
+///     parse_expression("a + 4")
+/// );
+/// assert!(parse_expression("(r1").is_err());
+/// ```
+///
+pub fn parse_expression(input: &str) -> Result<Expression, ExpressionError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+    };
+    let expression = parser.parse_binary(0)?;
+    parser.peek().map_or(Ok(expression), |token| {
+        Err(ExpressionError::Syntax(format!("Unexpected [{token}]")))
+    })
+}
+
+fn evaluate_binary(
+    operator: BinaryOperator,
+    left: &Expression,
+    right: &Expression,
+    context: &mut impl EvaluationContext,
+) -> Result<u32, ExpressionError> {
+    let left = evaluate(left, context)?;
+    // The right hand side isn't evaluated if it doesn't need to be (e.g. to avoid reading memory)
+    match operator {
+        BinaryOperator::LogicalAnd if left == 0 => return Ok(0),
+        BinaryOperator::LogicalOr if left != 0 => return Ok(1),
+        _ => {}
+    }
+    let right = evaluate(right, context)?;
+    Ok(match operator {
+        BinaryOperator::Multiply => left.wrapping_mul(right),
+        BinaryOperator::Divide => left
+            .checked_div(right)
+            .ok_or(ExpressionError::DivisionByZero)?,
+        BinaryOperator::Remainder => left
+            .checked_rem(right)
+            .ok_or(ExpressionError::DivisionByZero)?,
+        BinaryOperator::Add => left.wrapping_add(right),
+        BinaryOperator::Subtract => left.wrapping_sub(right),
+        BinaryOperator::ShiftLeft => left.checked_shl(right).unwrap_or(0),
+        BinaryOperator::ShiftRight => left.checked_shr(right).unwrap_or(0),
+        BinaryOperator::LessThan => u32::from(left < right),
+        BinaryOperator::LessThanOrEqual => u32::from(left <= right),
+        BinaryOperator::GreaterThan => u32::from(left > right),
+        BinaryOperator::GreaterThanOrEqual => u32::from(left >= right),
+        BinaryOperator::Equal => u32::from(left == right),
+        BinaryOperator::NotEqual => u32::from(left != right),
+        BinaryOperator::BitwiseAnd => left & right,
+        BinaryOperator::BitwiseXor => left ^ right,
+        BinaryOperator::BitwiseOr => left | right,
+        BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => u32::from(right != 0),
+    })
+}
+
+///
+/// Evaluates an expression against the current state of the VM.
+///
+/// ```
+/// use peripheral_cpu::registers::Registers;
+/// use sirc_vm::debug_adapter::expression::{
+///     evaluate, parse_expression, register_value, EvaluationContext,
+/// };
+///
+/// struct Context(Registers);
+///
+/// impl EvaluationContext for Context {
+///     fn identifier(&self, name: &str) -> Option<u32> {
+///         register_value(&self.0, name)
+///     }
+///     fn read_memory(&mut self, address: u32) -> Option<u16> {
+///         Some((address & 0xFFFF) as u16)
+///     }
+/// }
+///
+/// let mut context = Context(Registers { r1: 5, al: 0x100, ..Registers::default() });
+///
+/// assert_eq!(Ok(1), evaluate(&parse_expression("r1 == 5 && [a + 4] == 0x104").unwrap(), &mut context));
+/// ```
+///
+pub fn evaluate(
+    expression: &Expression,
+    context: &mut impl EvaluationContext,
+) -> Result<u32, ExpressionError> {
+    match expression {
+        Expression::Number(value) => Ok(*value),
+        Expression::Identifier(name) => context
+            .identifier(name)
+            .ok_or_else(|| ExpressionError::UnknownIdentifier(name.clone())),
+        Expression::Dereference(address) => {
+            // The CPU only has 24 address lines
+            let address = evaluate(address, context)? & ADDRESS_MASK;
+            context
+                .read_memory(address)
+                .map(u32::from)
+                .ok_or(ExpressionError::Memory(MemoryAccessError::Unmapped {
+                    address,
+                }))
+        }
+        Expression::Unary(operator, operand) => {
+            let value = evaluate(operand, context)?;
+            Ok(match operator {
+                UnaryOperator::Negate => value.wrapping_neg(),
+                UnaryOperator::LogicalNot => u32::from(value == 0),
+                UnaryOperator::BitwiseNot => !value,
+            })
+        }
+        Expression::Binary(operator, left, right) => {
+            evaluate_binary(*operator, left, right, context)
+        }
+    }
+}
+
+/// When a breakpoint with a hit condition should pause, based on the number of times it was hit
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum HitCondition {
+    Equal(u64),
+    GreaterThan(u64),
+    GreaterThanOrEqual(u64),
+    LessThan(u64),
+    LessThanOrEqual(u64),
+    /// Every nth hit
+    Multiple(u64),
+}
+
+impl HitCondition {
+    #[must_use]
+    pub const fn is_met(self, hit_count: u64) -> bool {
+        match self {
+            Self::Equal(count) => hit_count == count,
+            Self::GreaterThan(count) => hit_count > count,
+            Self::GreaterThanOrEqual(count) => hit_count >= count,
+            Self::LessThan(count) => hit_count < count,
+            Self::LessThanOrEqual(count) => hit_count <= count,
+            Self::Multiple(count) => hit_count.is_multiple_of(count),
+        }
+    }
+}
+
+type HitConditionConstructor = fn(u64) -> HitCondition;
+
+///
+/// Parses a hit condition (e.g. `>= 5` or `% 2`). A plain number pauses once the breakpoint has
+/// been hit that many times.
+///
+/// ```
+/// use sirc_vm::debug_adapter::expression::{parse_hit_condition, HitCondition};
+///
+/// assert_eq!(Ok(HitCondition::GreaterThanOrEqual(5)), parse_hit_condition("5"));
+/// assert_eq!(Ok(HitCondition::Multiple(2)), parse_hit_condition("% 2"));
+/// assert!(parse_hit_condition("%0").is_err());
+/// ```
+///
+pub fn parse_hit_condition(input: &str) -> Result<HitCondition, ExpressionError> {
+    let input = input.trim();
+    let prefixes: [(&str, HitConditionConstructor); 6] = [
+        (">=", HitCondition::GreaterThanOrEqual),
+        ("<=", HitCondition::LessThanOrEqual),
+        ("==", HitCondition::Equal),
+        (">", HitCondition::GreaterThan),
+        ("<", HitCondition::LessThan),
+        ("%", HitCondition::Multiple),
+    ];
+    let (count, constructor) = prefixes
+        .iter()
+        .find_map(|(prefix, constructor)| {
+            input
+                .strip_prefix(prefix)
+                .map(|count| (count, *constructor))
+        })
+        .unwrap_or((input, HitCondition::GreaterThanOrEqual));
+    let count = parse_number(count.trim())?;
+    let hit_condition = constructor(u64::from(count));
+    if hit_condition == HitCondition::Multiple(0) {
+        return Err(ExpressionError::DivisionByZero);
+    }
+    Ok(hit_condition)
+}
+
+/// A log message is text with expressions in braces that are filled in when it is logged
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum LogMessagePart {
+    Text(String),
+    Expression(Expression),
+}
+
+///
+/// Parses the message of a logpoint, e.g. `r1 is {r1}`. Braces can be escaped by doubling them.
+///
+/// ```
+/// use sirc_vm::debug_adapter::expression::{parse_log_message, Expression, LogMessagePart};
+///
+/// assert_eq!(
+///     Ok(vec![
+///         LogMessagePart::Text("{r1} is ".to_string()),
+///         LogMessagePart::Expression(Expression::Identifier("r1".to_string())),
+///     ]),
+///     parse_log_message("{{r1}} is {r1}")
+/// );
+/// ```
+///
+pub fn parse_log_message(input: &str) -> Result<Vec<LogMessagePart>, ExpressionError> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                text.push(c);
+            }
+            ('{', _) => {
+                let mut expression = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => expression.push(c),
+                        None => {
+                            return Err(ExpressionError::Syntax(
+                                "Unmatched [{] in log message, use [{{] to log a brace".to_string(),
+                            ))
+                        }
+                    }
+                }
+                if !text.is_empty() {
+                    parts.push(LogMessagePart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(LogMessagePart::Expression(parse_expression(&expression)?));
+            }
+            ('}', _) => {
+                return Err(ExpressionError::Syntax(
+                    "Unmatched [}] in log message, use [}}] to log a brace".to_string(),
+                ))
+            }
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(LogMessagePart::Text(text));
+    }
+    Ok(parts)
+}
+
+/// Fills in the expressions in a log message. Errors are logged in place of the value.
+pub fn format_log_message(
+    parts: &[LogMessagePart],
+    context: &mut impl EvaluationContext,
+) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            LogMessagePart::Text(text) => text.clone(),
+            LogMessagePart::Expression(expression) => match evaluate(expression, context) {
+                Ok(value) => format!("0x{value:X}"),
+                Err(error) => format!("<{error}>"),
+            },
+        })
+        .collect()
+}
@@ -0,0 +1,715 @@
+//!
+//! A GDB remote serial protocol (RSP) server, so that the VM can be driven by GDB or any other RSP
+//! client (e.g. a script) instead of VS Code.
+//!
+//! It uses the same channels as the debug adapter, so it supports the same things: reading and
+//! writing registers and memory, breakpoints, watchpoints, stepping (forwards and backwards) and
+//! continuing.
+//!
+//! GDB doesn't know about the SIRC CPU, so the registers are described with a target description
+//! (`qXfer:features:read`). They are the 16 bit CPU registers in the order they are encoded in
+//! instructions (`sr`, `r1`-`r7`, `lh`, `ll`, `ah`, `al`, `sh`, `sl`, `ph`, `pl`), sent big endian
+//! like everything else on the SIRC.
+//!
+//! RSP addresses are in bytes but the SIRC address space is made of 16 bit words, so byte address
+//! `n` is in the word at address `n / 2` (the high byte comes first). Breakpoint addresses are byte
+//! addresses too, so a breakpoint at `0x400` is on the instruction at word `0x200`.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use log::{debug, info, warn};
+use peripheral_cpu::registers::{register_index_to_name, Registers};
+
+use super::expression::Expression;
+use super::listener::{accept_connection, bind_listener, reject_connections_during};
+use super::memory::{bytes_from_words, merge_bytes_into_words, word_range_for_bytes};
+use super::types::{
+    BreakpointConditions, BreakpointRef, DataAccessType, DataBreakpointRef, DebuggerChannels,
+    DebuggerMessage, ResumeCondition, ReverseCondition, VmMessage, VmPauseReason, VmState,
+};
+
+/// The registers in the order they are sent (the same as the instruction encoding)
+pub const REGISTER_COUNT: u8 = 16;
+/// The biggest packet the server accepts or sends
+const MAX_PACKET_SIZE: usize = 0x4000;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// GDB only shows the error number, these are the closest errno values
+const ERROR_INVALID: &str = "E16"; // EINVAL
+const ERROR_MEMORY: &str = "E0E"; // EFAULT
+const ERROR_VM: &str = "E05"; // EIO
+const OK: &str = "OK";
+/// An empty reply means that a packet isn't supported
+const UNSUPPORTED: &str = "";
+
+const INTERRUPT: u8 = 0x03;
+
+/// Something sent by an RSP client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientEvent {
+    Packet(String),
+    /// A packet with a bad checksum, which the client should send again
+    Corrupt,
+    /// The client wants the VM to stop (Ctrl-C)
+    Interrupt,
+    Closed,
+}
+
+#[derive(Debug, Default)]
+enum DecoderState {
+    #[default]
+    Idle,
+    Data,
+    Checksum,
+}
+
+/// Splits the bytes from an RSP client into packets (`$data#checksum`) and interrupts
+#[derive(Debug, Default)]
+pub struct PacketDecoder {
+    state: DecoderState,
+    data: Vec<u8>,
+    checksum: Vec<u8>,
+}
+
+impl PacketDecoder {
+    pub fn push(&mut self, byte: u8) -> Option<ClientEvent> {
+        match self.state {
+            // Acknowledgements ('+' and '-') are ignored, TCP is reliable enough
+            DecoderState::Idle => match byte {
+                b'$' => {
+                    self.data.clear();
+                    self.state = DecoderState::Data;
+                    None
+                }
+                INTERRUPT => Some(ClientEvent::Interrupt),
+                _ => None,
+            },
+            DecoderState::Data => {
+                if byte == b'#' {
+                    self.checksum.clear();
+                    self.state = DecoderState::Checksum;
+                } else if self.data.len() < MAX_PACKET_SIZE {
+                    self.data.push(byte);
+                }
+                None
+            }
+            DecoderState::Checksum => {
+                self.checksum.push(byte);
+                if self.checksum.len() < 2 {
+                    return None;
+                }
+                self.state = DecoderState::Idle;
+                let expected = std::str::from_utf8(&self.checksum)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                Some(if expected == Some(checksum(&self.data)) {
+                    ClientEvent::Packet(String::from_utf8_lossy(&self.data).into_owned())
+                } else {
+                    ClientEvent::Corrupt
+                })
+            }
+        }
+    }
+}
+
+#[must_use]
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Wraps the data of a packet with the start marker and checksum (e.g. `$OK#9a`)
+#[must_use]
+pub fn frame_packet(data: &str) -> String {
+    format!("${data}#{:02x}", checksum(data.as_bytes()))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encodes the registers for a `g` packet
+#[must_use]
+pub fn encode_registers(registers: &Registers) -> String {
+    (0..REGISTER_COUNT).fold(String::new(), |mut hex, index| {
+        let _ = write!(hex, "{:04x}", registers[index]);
+        hex
+    })
+}
+
+/// Parses the `address,length` arguments used by memory and `qXfer` packets
+fn parse_address_length(args: &str) -> Option<(u32, u32)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        u32::from_str_radix(address, 16).ok()?,
+        u32::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+///
+/// Parses the arguments of a `Z`/`z` packet (e.g. `2,800,2` to watch writes to the word at
+/// 0x400) into the breakpoint type, byte address and kind/length. Any conditions at the end are
+/// ignored.
+///
+#[must_use]
+pub fn parse_breakpoint_packet(args: &str) -> Option<(u8, u32, u32)> {
+    let args = args.split(';').next()?;
+    let (breakpoint_type, rest) = args.split_once(',')?;
+    let (address, length) = parse_address_length(rest)?;
+    Some((breakpoint_type.parse().ok()?, address, length))
+}
+
+const fn watch_access_type(breakpoint_type: u8) -> Option<DataAccessType> {
+    match breakpoint_type {
+        2 => Some(DataAccessType::Write),
+        3 => Some(DataAccessType::Read),
+        4 => Some(DataAccessType::ReadWrite),
+        _ => None,
+    }
+}
+
+const fn watch_stop_reason(access_type: DataAccessType) -> &'static str {
+    match access_type {
+        DataAccessType::Write => "watch",
+        DataAccessType::Read => "rwatch",
+        DataAccessType::ReadWrite => "awatch",
+    }
+}
+
+/// Describes the registers to GDB, which has no built in support for the SIRC
+#[must_use]
+pub fn target_description() -> String {
+    let registers = (0..REGISTER_COUNT).filter_map(register_index_to_name).fold(
+        String::new(),
+        |mut registers, name| {
+            let _ = writeln!(
+                registers,
+                "    <reg name=\"{name}\" bitsize=\"16\" type=\"uint16\"/>"
+            );
+            registers
+        },
+    );
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  <feature name=\"org.sirc.cpu\">\n{registers}  </feature>\n</target>\n"
+    )
+}
+
+/// Everything the session loop waits on, so that it can block on client packets and the VM at
+/// the same time
+enum Event {
+    /// Something from the client connected to the session with this ID. Events from old
+    /// sessions can still be queued when the next one starts, so they are ignored.
+    Client(usize, ClientEvent),
+    Vm(VmMessage),
+    VmExited,
+}
+
+enum SessionEnd {
+    Detached,
+    VmExited,
+}
+
+struct Session<'a> {
+    id: usize,
+    stream: TcpStream,
+    debugger: &'a Sender<DebuggerMessage>,
+    /// The state of the VM when it last paused, or `None` while it is running
+    vm_state: Option<VmState>,
+    last_stop_reply: String,
+    /// Set when the client resumed the VM and is waiting for a stop reply
+    waiting_for_stop: bool,
+    /// Set when the client asked the VM to stop (so that it is reported as SIGINT)
+    interrupted: bool,
+    no_ack: bool,
+    /// Keyed by the word address of the instruction
+    breakpoints: BTreeMap<u32, BreakpointRef>,
+    watchpoints: Vec<DataBreakpointRef>,
+    next_breakpoint_id: i64,
+}
+
+impl<'a> Session<'a> {
+    fn new(id: usize, stream: TcpStream, debugger: &'a Sender<DebuggerMessage>) -> Self {
+        Self {
+            id,
+            stream,
+            debugger,
+            vm_state: None,
+            last_stop_reply: format!("S{SIGTRAP:02x}"),
+            waiting_for_stop: false,
+            interrupted: false,
+            no_ack: false,
+            breakpoints: BTreeMap::new(),
+            watchpoints: vec![],
+            next_breakpoint_id: 1,
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        debug!("-> {data}");
+        self.stream.write_all(frame_packet(data).as_bytes())
+    }
+
+    fn run(&mut self, events: &Receiver<Event>) -> io::Result<SessionEnd> {
+        // The sender is held by the server so this only stops if something has gone very wrong
+        while let Ok(event) = events.recv() {
+            match event {
+                Event::Client(id, _) if id != self.id => {}
+                Event::Client(_, ClientEvent::Packet(packet)) => {
+                    debug!("<- {packet}");
+                    if !self.no_ack {
+                        self.stream.write_all(b"+")?;
+                    }
+                    if let Some(end) = self.handle_packet(&packet)? {
+                        return Ok(end);
+                    }
+                }
+                Event::Client(_, ClientEvent::Corrupt) => {
+                    if !self.no_ack {
+                        self.stream.write_all(b"-")?;
+                    }
+                }
+                Event::Client(_, ClientEvent::Interrupt) => {
+                    if self.vm_state.is_none() {
+                        self.interrupted = true;
+                        self.send(DebuggerMessage::PauseVm);
+                    }
+                }
+                Event::Client(_, ClientEvent::Closed) => return Ok(SessionEnd::Detached),
+                Event::Vm(VmMessage::Paused(reason, vm_state)) => {
+                    self.vm_state = Some(*vm_state);
+                    self.last_stop_reply = self.stop_reply(&reason);
+                    self.interrupted = false;
+                    if self.waiting_for_stop {
+                        self.waiting_for_stop = false;
+                        let reply = self.last_stop_reply.clone();
+                        self.write_packet(&reply)?;
+                    }
+                }
+                Event::Vm(VmMessage::Log(message)) => {
+                    // Console output can only be sent while the client is waiting for the VM
+                    if self.waiting_for_stop {
+                        self.write_packet(&format!(
+                            "O{}",
+                            encode_hex(format!("{message}\n").as_bytes())
+                        ))?;
+                    } else {
+                        info!("{message}");
+                    }
+                }
+                Event::VmExited => {
+                    self.write_packet("W00")?;
+                    return Ok(SessionEnd::VmExited);
+                }
+            }
+        }
+        Ok(SessionEnd::VmExited)
+    }
+
+    fn stop_reply(&self, reason: &VmPauseReason) -> String {
+        let signal = match reason {
+            _ if self.interrupted => SIGINT,
+            VmPauseReason::Fault(_) => SIGSEGV,
+            _ => SIGTRAP,
+        };
+        let stop_reason = match reason {
+            VmPauseReason::DataBreakpoint(data_breakpoint) => format!(
+                "{}:{:x};",
+                watch_stop_reason(data_breakpoint.access_type),
+                data_breakpoint.address * 2
+            ),
+            VmPauseReason::StartOfHistory => "replaylog:begin;".to_string(),
+            _ => String::new(),
+        };
+        format!("T{signal:02x}{stop_reason}thread:1;")
+    }
+
+    fn send(&self, message: DebuggerMessage) {
+        // If the VM has gone the session ends when the VmExited event comes through
+        let _ = self.debugger.send(message);
+    }
+
+    fn request<T>(&self, message: impl FnOnce(Sender<T>) -> DebuggerMessage) -> Option<T> {
+        let (reply, response) = channel();
+        self.debugger.send(message(reply)).ok()?;
+        response.recv().ok()
+    }
+
+    /// Returns `Some` if the packet ended the session
+    fn handle_packet(&mut self, packet: &str) -> io::Result<Option<SessionEnd>> {
+        let reply = match packet {
+            "?" => {
+                if self.vm_state.is_some() {
+                    self.last_stop_reply.clone()
+                } else {
+                    // Attached to a VM that is running, the client expects it to be stopped
+                    self.waiting_for_stop = true;
+                    self.send(DebuggerMessage::PauseVm);
+                    return Ok(None);
+                }
+            }
+            "g" => self.read_registers(),
+            "c" => return self.resume(DebuggerMessage::ResumeVm(ResumeCondition::None)),
+            "s" => return self.resume(DebuggerMessage::ResumeVm(ResumeCondition::UntilNextStep)),
+            "bc" => return self.resume(DebuggerMessage::ReverseVm(ReverseCondition::None)),
+            "bs" => {
+                return self.resume(DebuggerMessage::ReverseVm(
+                    ReverseCondition::UntilPreviousStep,
+                ))
+            }
+            "D" => {
+                self.write_packet(OK)?;
+                return Ok(Some(SessionEnd::Detached));
+            }
+            // The VM outlives the debugger, so killing it just detaches
+            "k" => return Ok(Some(SessionEnd::Detached)),
+            "QStartNoAckMode" => {
+                self.write_packet(OK)?;
+                self.no_ack = true;
+                return Ok(None);
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ if packet.starts_with("qSupported") => format!(
+                "PacketSize={MAX_PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+;ReverseStep+;ReverseContinue+"
+            ),
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                read_target_description(&packet["qXfer:features:read:target.xml:".len()..])
+            }
+            _ if packet.starts_with('G') => self.write_registers(&packet[1..]),
+            _ if packet.starts_with('p') => self.read_register(&packet[1..]),
+            _ if packet.starts_with('P') => self.write_register(&packet[1..]),
+            _ if packet.starts_with('m') => self.read_memory(&packet[1..]),
+            _ if packet.starts_with('M') => self.write_memory(&packet[1..]),
+            _ if packet.starts_with('Z') => self.update_breakpoint(&packet[1..], true),
+            _ if packet.starts_with('z') => self.update_breakpoint(&packet[1..], false),
+            // There is only one thread
+            _ if packet.starts_with('H') || packet.starts_with('T') => OK.to_string(),
+            _ => UNSUPPORTED.to_string(),
+        };
+        self.write_packet(&reply)?;
+        Ok(None)
+    }
+
+    fn resume(&mut self, message: DebuggerMessage) -> io::Result<Option<SessionEnd>> {
+        if self.vm_state.is_none() {
+            self.write_packet(ERROR_VM)?;
+            return Ok(None);
+        }
+        self.vm_state = None;
+        self.waiting_for_stop = true;
+        self.send(message);
+        Ok(None)
+    }
+
+    fn read_registers(&self) -> String {
+        self.vm_state.as_ref().map_or_else(
+            || ERROR_VM.to_string(),
+            |vm_state| encode_registers(&vm_state.registers),
+        )
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        let (Some(vm_state), Ok(index)) = (&self.vm_state, u8::from_str_radix(args, 16)) else {
+            return ERROR_INVALID.to_string();
+        };
+        if index >= REGISTER_COUNT {
+            return ERROR_INVALID.to_string();
+        }
+        format!("{:04x}", vm_state.registers[index])
+    }
+
+    fn assign_register(&mut self, index: u8, value: u16) -> bool {
+        let Some(name) = register_index_to_name(index) else {
+            return false;
+        };
+        match self.request(|reply| DebuggerMessage::Assign {
+            target: Expression::Identifier(name.to_string()),
+            value: Expression::Number(u32::from(value)),
+            reply,
+        }) {
+            Some(Ok((_, vm_state))) => {
+                self.vm_state = Some(vm_state);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let Some((index, value)) = args.split_once('=').and_then(|(index, value)| {
+            Some((
+                u8::from_str_radix(index, 16).ok()?,
+                u16::from_str_radix(value, 16).ok()?,
+            ))
+        }) else {
+            return ERROR_INVALID.to_string();
+        };
+        if self.vm_state.is_none() || !self.assign_register(index, value) {
+            return ERROR_VM.to_string();
+        }
+        OK.to_string()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let Some(bytes) =
+            decode_hex(args).filter(|bytes| bytes.len() == usize::from(REGISTER_COUNT) * 2)
+        else {
+            return ERROR_INVALID.to_string();
+        };
+        if self.vm_state.is_none() {
+            return ERROR_VM.to_string();
+        }
+        for (index, pair) in (0..REGISTER_COUNT).zip(bytes.chunks_exact(2)) {
+            if !self.assign_register(index, u16::from_be_bytes([pair[0], pair[1]])) {
+                return ERROR_VM.to_string();
+            }
+        }
+        OK.to_string()
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((address, length)) = parse_address_length(args) else {
+            return ERROR_INVALID.to_string();
+        };
+        // Each byte takes two characters in the reply
+        let byte_count = (length as usize).min(MAX_PACKET_SIZE / 2);
+        let Some(word_range) = word_range_for_bytes(0, i64::from(address), byte_count) else {
+            return ERROR_MEMORY.to_string();
+        };
+        let Some(Ok(words)) = self.request(|reply| DebuggerMessage::ReadMemory {
+            address: word_range.address,
+            count: word_range.count,
+            reply,
+        }) else {
+            return ERROR_VM.to_string();
+        };
+        let bytes = bytes_from_words(&words, word_range.byte_offset, byte_count);
+        // A partial read is fine, but there has to be something
+        if bytes.is_empty() && byte_count > 0 {
+            return ERROR_MEMORY.to_string();
+        }
+        encode_hex(&bytes)
+    }
+
+    fn write_memory(&self, args: &str) -> String {
+        let Some((bytes, (address, length))) = args
+            .split_once(':')
+            .and_then(|(range, data)| Some((decode_hex(data)?, parse_address_length(range)?)))
+            .filter(|(bytes, (_, length))| bytes.len() == *length as usize)
+        else {
+            return ERROR_INVALID.to_string();
+        };
+        let Some(word_range) = word_range_for_bytes(0, i64::from(address), length as usize)
+            .filter(|word_range| word_range.bytes_out_of_range == 0)
+        else {
+            return ERROR_MEMORY.to_string();
+        };
+        // Words are read first so that the other byte is kept when a write doesn't line up with
+        // word boundaries
+        let Some(Ok(existing_words)) = self.request(|reply| DebuggerMessage::ReadMemory {
+            address: word_range.address,
+            count: word_range.count,
+            reply,
+        }) else {
+            return ERROR_VM.to_string();
+        };
+        if existing_words.len() < word_range.count as usize {
+            return ERROR_MEMORY.to_string();
+        }
+        let words = merge_bytes_into_words(&existing_words, word_range.byte_offset, &bytes);
+        match self.request(|reply| DebuggerMessage::WriteMemory {
+            address: word_range.address,
+            words,
+            reply,
+        }) {
+            Some(Ok(())) => OK.to_string(),
+            Some(Err(_)) => ERROR_MEMORY.to_string(),
+            None => ERROR_VM.to_string(),
+        }
+    }
+
+    fn update_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let Some((breakpoint_type, address, length)) = parse_breakpoint_packet(args) else {
+            return ERROR_INVALID.to_string();
+        };
+        match (breakpoint_type, watch_access_type(breakpoint_type)) {
+            // Software and hardware breakpoints are the same thing to the VM
+            (0 | 1, _) => {
+                if address % 2 != 0 {
+                    return ERROR_INVALID.to_string();
+                }
+                let pc = address / 2;
+                if insert {
+                    let breakpoint_id = self.next_breakpoint_id;
+                    self.next_breakpoint_id += 1;
+                    self.breakpoints.insert(
+                        pc,
+                        BreakpointRef {
+                            breakpoint_id,
+                            pc,
+                            conditions: BreakpointConditions::default(),
+                        },
+                    );
+                } else {
+                    self.breakpoints.remove(&pc);
+                }
+                self.send(DebuggerMessage::UpdateBreakpoints(
+                    self.breakpoints.values().cloned().collect(),
+                ));
+            }
+            (_, Some(access_type)) => {
+                let Some(word_range) = word_range_for_bytes(0, i64::from(address), length as usize)
+                else {
+                    return ERROR_MEMORY.to_string();
+                };
+                let words = word_range.address..word_range.address + word_range.count;
+                if insert {
+                    for word_address in words {
+                        let breakpoint_id = self.next_breakpoint_id;
+                        self.next_breakpoint_id += 1;
+                        self.watchpoints.push(DataBreakpointRef {
+                            breakpoint_id,
+                            address: word_address,
+                            access_type,
+                            conditions: BreakpointConditions::default(),
+                        });
+                    }
+                } else {
+                    self.watchpoints.retain(|watchpoint| {
+                        watchpoint.access_type != access_type
+                            || !words.contains(&watchpoint.address)
+                    });
+                }
+                self.send(DebuggerMessage::UpdateDataBreakpoints(
+                    self.watchpoints.clone(),
+                ));
+            }
+            _ => return UNSUPPORTED.to_string(),
+        }
+        OK.to_string()
+    }
+}
+
+fn read_target_description(args: &str) -> String {
+    let Some((offset, length)) = parse_address_length(args) else {
+        return ERROR_INVALID.to_string();
+    };
+    let description = target_description();
+    let chunk: String = description
+        .chars()
+        .skip(offset as usize)
+        .take(length as usize)
+        .collect();
+    // 'l' marks the last chunk
+    let marker = if offset as usize + chunk.len() >= description.len() {
+        'l'
+    } else {
+        'm'
+    };
+    format!("{marker}{chunk}")
+}
+
+fn spawn_reader(stream: TcpStream, session_id: usize, events: Sender<Event>) {
+    thread::spawn(move || {
+        let mut decoder = PacketDecoder::default();
+        for byte in BufReader::new(stream).bytes() {
+            let Ok(byte) = byte else {
+                break;
+            };
+            if let Some(event) = decoder.push(byte) {
+                if events.send(Event::Client(session_id, event)).is_err() {
+                    return;
+                }
+            }
+        }
+        let _ = events.send(Event::Client(session_id, ClientEvent::Closed));
+    });
+}
+
+/// Tells a client that connects while another one is attached that it can't attach
+fn reject_client(mut stream: TcpStream) {
+    // The error is the reply to the first packet (normally `qSupported`)
+    let mut decoder = PacketDecoder::default();
+    let got_packet = BufReader::new(&stream)
+        .bytes()
+        .map_while(Result::ok)
+        .any(|byte| matches!(decoder.push(byte), Some(ClientEvent::Packet(_))));
+    if got_packet {
+        let _ = stream.write_all(format!("+{}", frame_packet(ERROR_VM)).as_bytes());
+    }
+}
+
+///
+/// Listens for RSP clients on `address` until the VM exits.
+///
+/// Like the debug adapter, only one client can be attached at a time (any others get an error),
+/// and detaching (or dropping the connection) clears the breakpoints and leaves the VM running.
+///
+pub fn start_gdb_server(channels: DebuggerChannels, address: SocketAddr) -> io::Result<()> {
+    let listener = bind_listener(address)?;
+    let DebuggerChannels { rx, tx } = channels;
+
+    let (event_tx, events) = channel();
+    let vm_exited = Arc::new(AtomicBool::new(false));
+    let vm_exited_ref = vm_exited.clone();
+    let vm_event_tx = event_tx.clone();
+    thread::spawn(move || {
+        for message in rx {
+            if vm_event_tx.send(Event::Vm(message)).is_err() {
+                return;
+            }
+        }
+        // The VM has closed its side
+        vm_exited_ref.store(true, Ordering::Relaxed);
+        let _ = vm_event_tx.send(Event::VmExited);
+    });
+
+    let mut session_id = 0;
+    while let Some(stream) = accept_connection(&listener, &vm_exited)? {
+        session_id += 1;
+        spawn_reader(stream.try_clone()?, session_id, event_tx.clone());
+        let mut session = Session::new(session_id, stream, &tx);
+        let result = reject_connections_during(&listener, reject_client, || session.run(&events))?;
+        // Stops the reader thread if the client is still connected (e.g. after a detach packet)
+        let _ = session.stream.shutdown(Shutdown::Both);
+        match result {
+            Ok(SessionEnd::VmExited) => return Ok(()),
+            Ok(SessionEnd::Detached) => {}
+            Err(error) => warn!("GDB session ended with an error: {error}"),
+        }
+
+        if tx.send(DebuggerMessage::Disconnect).is_err() {
+            // The VM has already exited
+            return Ok(());
+        }
+        info!("Debugger detached");
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//!
+//! The TCP listener shared by the debug servers (DAP and GDB).
+//!
+//! The listener is polled rather than blocking so that a server can stop when the VM exits while
+//! no debugger is attached.
+//!
+
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+
+/// How often the listener checks whether the VM has exited while no debugger is attached
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait for a debugger that is being turned away to send its first request
+const REJECTED_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn bind_listener(address: SocketAddr) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+///
+/// Waits for the next debugger to connect, or returns `None` if `vm_exited` is set first.
+///
+/// Only one debugger is attached at a time, see `reject_connections_during` for what happens to
+/// the others.
+///
+pub fn accept_connection(
+    listener: &TcpListener,
+    vm_exited: &AtomicBool,
+) -> io::Result<Option<TcpStream>> {
+    info!(
+        "Waiting for socket connection on [{}]...",
+        listener.local_addr()?
+    );
+    loop {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                info!("Connection established with {addr:?}");
+                stream.set_nonblocking(false)?;
+                return Ok(Some(stream));
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                if vm_exited.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+///
+/// Runs a debug session, and turns away any other debuggers that connect before it ends.
+///
+/// `reject` is called with each of their connections so that it can tell them that a debugger is
+/// already attached (instead of leaving them waiting in the listen backlog with no reply). Reads
+/// from the connection time out, so a debugger that never sends anything can't hold it up.
+///
+pub fn reject_connections_during<T>(
+    listener: &TcpListener,
+    reject: fn(TcpStream),
+    session: impl FnOnce() -> T,
+) -> io::Result<T> {
+    let listener = listener.try_clone()?;
+    let session_ended = Arc::new(AtomicBool::new(false));
+    let session_ended_ref = session_ended.clone();
+    let rejecter = thread::spawn(move || {
+        while !session_ended_ref.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    info!("Turning away {addr:?} because a debugger is already attached");
+                    let result = stream
+                        .set_nonblocking(false)
+                        .and_then(|()| stream.set_read_timeout(Some(REJECTED_CONNECTION_TIMEOUT)));
+                    match result {
+                        Ok(()) => reject(stream),
+                        Err(error) => warn!("Could not turn away {addr:?}: {error}"),
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+                Err(error) => {
+                    warn!("Stopped turning away debuggers: {error}");
+                    return;
+                }
+            }
+        }
+    });
+
+    let result = session();
+    session_ended.store(true, Ordering::Relaxed);
+    // The rejecter only ever blocks for a short time, so this doesn't hold up the next session
+    let _ = rejecter.join();
+    Ok(result)
+}
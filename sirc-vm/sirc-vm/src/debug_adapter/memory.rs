@@ -0,0 +1,146 @@
+//!
+//! Translating between the memory requests of the debug adapter protocol, which deal with bytes,
+//! and the bus, which only deals with 16 bit words.
+//!
+//! A memory reference is the address of a word on the bus (e.g. "0x00F000"), and offsets/counts
+//! in requests are in bytes, with the high byte of each word coming first (big endian, the same
+//! as program binaries).
+//!
+
+use peripheral_bus::conversion::words_to_bytes;
+
+/// Addressing is only 24 bit, so nothing can be read past this
+const ADDRESS_SPACE_SIZE: u64 = 0x0100_0000;
+
+static MEMORY_REFERENCE_PREFIX: &str = "0x";
+
+/// The words that need to be read from the bus to cover a range of bytes requested by a debugger
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct WordRange {
+    /// The address of the first word
+    pub address: u32,
+    /// The number of words to read
+    pub count: u32,
+    /// The number of bytes to skip at the start of the first word (either 0 or 1)
+    pub byte_offset: usize,
+    /// The number of bytes requested that are out of the address space
+    pub bytes_out_of_range: usize,
+}
+
+#[must_use]
+pub fn format_memory_reference(address: u32) -> String {
+    format!("{MEMORY_REFERENCE_PREFIX}{address:06X}")
+}
+
+/// Parses a memory reference from a debugger. The prefix is optional so that users can type in
+/// plain hex addresses.
+#[must_use]
+pub fn parse_memory_reference(memory_reference: &str) -> Option<u32> {
+    let trimmed = memory_reference.trim();
+    let digits = trimmed
+        .strip_prefix(MEMORY_REFERENCE_PREFIX)
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .filter(|address| u64::from(*address) < ADDRESS_SPACE_SIZE)
+}
+
+///
+/// Applies a signed offset (in words) to an address, wrapping around the 24 bit address space.
+///
+/// ```
+/// use sirc_vm::debug_adapter::memory::offset_address;
+///
+/// assert_eq!(0x000104, offset_address(0x000100, 4));
+/// assert_eq!(0xFFFFFE, offset_address(0x000000, -2));
+/// ```
+///
+#[must_use]
+pub fn offset_address(address: u32, word_offset: i64) -> u32 {
+    #[allow(
+        clippy::cast_possible_wrap,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let wrapped = (i64::from(address) + word_offset).rem_euclid(ADDRESS_SPACE_SIZE as i64) as u32;
+    wrapped
+}
+
+///
+/// Works out which words cover `byte_count` bytes starting `byte_offset` bytes after the word
+/// at `address`.
+///
+/// Returns `None` if the range starts outside the address space. Bytes past the end of the
+/// address space are counted in `bytes_out_of_range`.
+///
+/// ```
+/// use sirc_vm::debug_adapter::memory::{word_range_for_bytes, WordRange};
+///
+/// assert_eq!(
+///     Some(WordRange { address: 0x11, count: 3, byte_offset: 1, bytes_out_of_range: 0 }),
+///     word_range_for_bytes(0x10, 3, 5)
+/// );
+/// ```
+///
+#[must_use]
+pub fn word_range_for_bytes(
+    address: u32,
+    byte_offset: i64,
+    byte_count: usize,
+) -> Option<WordRange> {
+    let start_byte = i64::from(address) * 2 + byte_offset;
+    let start_byte = u64::try_from(start_byte).ok()?;
+    let address_space_bytes = ADDRESS_SPACE_SIZE * 2;
+    if start_byte >= address_space_bytes {
+        return None;
+    }
+    let end_byte = (start_byte + byte_count as u64).min(address_space_bytes);
+    let bytes_in_range = end_byte - start_byte;
+    let first_word = start_byte / 2;
+    let last_word = end_byte.div_ceil(2);
+
+    Some(WordRange {
+        address: u32::try_from(first_word).ok()?,
+        count: u32::try_from(last_word - first_word).ok()?,
+        byte_offset: usize::try_from(start_byte % 2).ok()?,
+        bytes_out_of_range: byte_count - usize::try_from(bytes_in_range).ok()?,
+    })
+}
+
+///
+/// Extracts the requested bytes from words that were read for a `WordRange`.
+///
+/// If fewer words were read than requested (e.g. the range ran into unmapped memory) the result
+/// will be shorter than `byte_count`.
+///
+#[must_use]
+pub fn bytes_from_words(words: &[u16], byte_offset: usize, byte_count: usize) -> Vec<u8> {
+    words_to_bytes(words)
+        .into_iter()
+        .skip(byte_offset)
+        .take(byte_count)
+        .collect()
+}
+
+///
+/// Overwrites part of some words with bytes from a debugger, keeping any bytes in the first and
+/// last word that are outside the range being written.
+///
+/// ```
+/// use sirc_vm::debug_adapter::memory::merge_bytes_into_words;
+///
+/// assert_eq!(vec![0x12AB, 0xCD78], merge_bytes_into_words(&[0x1234, 0x5678], 1, &[0xAB, 0xCD]));
+/// ```
+///
+#[must_use]
+pub fn merge_bytes_into_words(words: &[u16], byte_offset: usize, bytes: &[u8]) -> Vec<u16> {
+    let mut existing_bytes = words_to_bytes(words);
+    for (existing_byte, byte) in existing_bytes.iter_mut().skip(byte_offset).zip(bytes) {
+        *existing_byte = *byte;
+    }
+    existing_bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
@@ -1,3 +1,10 @@
+pub mod call_stack;
+pub mod compat;
 pub mod debug_map;
+pub mod expression;
+pub mod gdb_server;
+pub mod listener;
+pub mod memory;
 pub mod server;
+pub mod symbols;
 pub mod types;
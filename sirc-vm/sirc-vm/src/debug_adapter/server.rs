@@ -1,25 +1,55 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufReader, BufWriter};
-use std::net::TcpListener;
-use std::sync::mpsc::channel;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
-use std::vec;
+use std::{thread, vec};
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use dap::prelude::*;
-use events::StoppedEventBody;
-use log::info;
+use dap::server::ServerOutput;
+use events::{OutputEventBody, StoppedEventBody};
+use log::{info, warn};
+use peripheral_cpu::coprocessors::exception_unit::definitions::Faults;
+use peripheral_cpu::coprocessors::processing_unit::definitions::INSTRUCTION_SIZE_WORDS;
+use peripheral_cpu::coprocessors::processing_unit::disassembly::disassemble_instruction;
+use peripheral_cpu::coprocessors::processing_unit::encoding::decode_instruction;
+use peripheral_cpu::registers::{FullAddressRegisterAccess, ADDRESS_MASK};
+use requests::{
+    DataBreakpointInfoArguments, DisassembleArguments, ReadMemoryArguments,
+    SetBreakpointsArguments, SetDataBreakpointsArguments, SetExceptionBreakpointsArguments,
+    SetInstructionBreakpointsArguments, WriteMemoryArguments,
+};
 use responses::{
-    ContinueResponse, ScopesResponse, SetBreakpointsResponse, SetExceptionBreakpointsResponse,
-    SourceResponse, StackTraceResponse, ThreadsResponse, VariablesResponse,
+    ContinueResponse, DataBreakpointInfoResponse, DisassembleResponse, EvaluateResponse,
+    ExceptionInfoResponse, ReadMemoryResponse, ScopesResponse, SetBreakpointsResponse,
+    SetDataBreakpointsResponse, SetExceptionBreakpointsResponse, SetExpressionResponse,
+    SetInstructionBreakpointsResponse, SetVariableResponse, SourceResponse, StackTraceResponse,
+    ThreadsResponse, VariablesResponse, WriteMemoryResponse,
 };
 use thiserror::Error;
 use types::{
-    Breakpoint, Capabilities, Checksum, ChecksumAlgorithm, Scope, Source, StackFrame,
-    StoppedEventReason, Thread, Variable,
+    Breakpoint, Capabilities, Checksum, ChecksumAlgorithm, DataBreakpointAccessType,
+    DisassembledInstruction, ExceptionBreakMode, ExceptionBreakpointsFilter, OutputEventCategory,
+    Scope, Source, StackFrame, StoppedEventReason, Thread, Variable,
 };
 
+use crate::debug_adapter::call_stack::{unwind_call_stack, CallFrame, FrameKind};
+use crate::debug_adapter::compat::NormalisingReader;
+use crate::debug_adapter::expression::{
+    parse_expression, parse_hit_condition, parse_log_message, ExpressionError,
+};
+use crate::debug_adapter::listener::{accept_connection, bind_listener, reject_connections_during};
+use crate::debug_adapter::memory::{
+    bytes_from_words, format_memory_reference, merge_bytes_into_words, offset_address,
+    parse_memory_reference, word_range_for_bytes, WordRange,
+};
+use crate::debug_adapter::symbols::{format_symbolised_address, nearest_symbol};
 use crate::debug_adapter::types::{
-    BreakpointRef, ResumeCondition, ServerState, VmPauseReason, VmState,
+    BreakpointConditions, BreakpointRef, DataAccessType, DataBreakpointRef, FaultDetails,
+    MemoryAccessError, ResumeCondition, ReverseCondition, ServerState, SymbolTable, VmPauseReason,
+    VmState,
 };
 use crate::utils::lines::{translate_line_column_to_pc, translate_pc_to_line_column};
 
@@ -27,26 +57,37 @@ use super::types::{
     DebuggerChannels, DebuggerMessage, ProgramDebugInfo, ServerChannels, VmChannels, VmMessage,
 };
 
-// TODO: Make debug server options configurable with args or environment variables
-// category=Debugging
-// E.g. listener address
-static LISTENER_ADDRESS: &str = "0.0.0.0:9090";
 /// The thread ID for the program running in the VM, not an actual thread on the
 /// system the VM is running on
 /// The CPU can only has one thread, it is a single core CPU, so this should never change
 const DEFAULT_THREAD_ID: i64 = 1;
 const DEFAULT_STACK_FRAME_ID: i64 = 1;
+/// The variables reference of the CPU registers, the other scopes follow on from it
 const DEFAULT_VARIABLES_ID: i64 = 1;
 
 static INSTRUCTION_REF_PREFIX: &str = "pc:";
 
+/// The faults that can be picked as exception breakpoints. Instruction trace faults are left out
+/// because they are raised after every instruction in trace mode, which is what stepping is for.
+const EXCEPTION_BREAKPOINT_FAULTS: [Faults; 7] = [
+    Faults::Bus,
+    Faults::Alignment,
+    Faults::SegmentOverflow,
+    Faults::InvalidOpCode,
+    Faults::PrivilegeViolation,
+    Faults::DoubleFault,
+    Faults::BusProtection,
+];
+
 // TODO: Consider moving the DAP to a separate module?
 // category=Debugging
 
 #[derive(Error, Debug)]
 enum DebugAdapterError {
-    #[error("Missing command")]
-    MissingCommandError,
+    #[error("Invalid memory reference [{0}]")]
+    InvalidMemoryReference(String),
+    #[error("Memory at [{0}] is outside the address space")]
+    MemoryOutOfRange(String),
 }
 
 type DynResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -69,6 +110,596 @@ pub fn parse_instruction_ref(instruction_ref: &str) -> u32 {
     u32::from_str_radix(&instruction_ref[prefix_length..], 16).unwrap()
 }
 
+///
+/// Parses either an instruction ref (e.g. "pc:ABC") or a memory reference (e.g. "0x000ABC").
+///
+/// Debuggers use both interchangeably, e.g. the instruction pointer reference of a stack frame is
+/// used as the memory reference when requesting disassembly.
+///
+#[must_use]
+pub fn parse_reference(reference: &str) -> Option<u32> {
+    parse_memory_reference(
+        reference
+            .strip_prefix(INSTRUCTION_REF_PREFIX)
+            .unwrap_or(reference),
+    )
+}
+
+fn request_memory_read(tx: &Sender<DebuggerMessage>, word_range: WordRange) -> DynResult<Vec<u16>> {
+    let (reply, response) = channel();
+    tx.send(DebuggerMessage::ReadMemory {
+        address: word_range.address,
+        count: word_range.count,
+        reply,
+    })?;
+    Ok(response
+        .recv()
+        .map_err(|_| MemoryAccessError::VmDisconnected)??)
+}
+
+/// Expressions are parsed here, but evaluated by the VM
+fn request_evaluate(
+    tx: &Sender<DebuggerMessage>,
+    expression: &str,
+    symbols: &SymbolTable,
+) -> DynResult<u32> {
+    let (reply, response) = channel();
+    tx.send(DebuggerMessage::Evaluate {
+        expression: parse_expression(expression)?.resolve_symbols(symbols),
+        reply,
+    })?;
+    Ok(response
+        .recv()
+        .map_err(|_| MemoryAccessError::VmDisconnected)??)
+}
+
+fn request_assign(
+    tx: &Sender<DebuggerMessage>,
+    target: &str,
+    value: &str,
+    symbols: &SymbolTable,
+) -> DynResult<(u32, VmState)> {
+    let target_expression = parse_expression(target)?.resolve_symbols(symbols);
+    if !target_expression.is_assignable() {
+        return Err(Box::new(ExpressionError::NotAssignable(target.to_string())));
+    }
+    let (reply, response) = channel();
+    tx.send(DebuggerMessage::Assign {
+        target: target_expression,
+        value: parse_expression(value)?.resolve_symbols(symbols),
+        reply,
+    })?;
+    Ok(response
+        .recv()
+        .map_err(|_| MemoryAccessError::VmDisconnected)??)
+}
+
+fn word_range_for_request(
+    memory_reference: &str,
+    offset: Option<i64>,
+    byte_count: usize,
+) -> DynResult<WordRange> {
+    let address = parse_memory_reference(memory_reference)
+        .ok_or_else(|| DebugAdapterError::InvalidMemoryReference(memory_reference.to_string()))?;
+    Ok(
+        word_range_for_bytes(address, offset.unwrap_or_default(), byte_count)
+            .ok_or_else(|| DebugAdapterError::MemoryOutOfRange(memory_reference.to_string()))?,
+    )
+}
+
+fn read_memory(
+    tx: &Sender<DebuggerMessage>,
+    args: &ReadMemoryArguments,
+) -> DynResult<ReadMemoryResponse> {
+    let byte_count = usize::try_from(args.count)?;
+    let word_range = word_range_for_request(&args.memory_reference, args.offset, byte_count)?;
+    let words = request_memory_read(tx, word_range)?;
+    let bytes = bytes_from_words(&words, word_range.byte_offset, byte_count);
+
+    Ok(ReadMemoryResponse {
+        address: format_memory_reference(word_range.address),
+        unreadable_bytes: Some(i64::try_from(byte_count - bytes.len())?),
+        data: Some(BASE64.encode(bytes)),
+    })
+}
+
+fn write_memory(
+    tx: &Sender<DebuggerMessage>,
+    args: &WriteMemoryArguments,
+) -> DynResult<WriteMemoryResponse> {
+    let bytes = BASE64.decode(&args.data)?;
+    let word_range = word_range_for_request(&args.memory_reference, args.offset, bytes.len())?;
+    if word_range.bytes_out_of_range > 0 {
+        return Err(Box::new(DebugAdapterError::MemoryOutOfRange(
+            args.memory_reference.clone(),
+        )));
+    }
+    // Words are read first so that bytes outside the range can be preserved when the range
+    // doesn't line up with word boundaries
+    let existing_words = request_memory_read(tx, word_range)?;
+    if existing_words.len() < word_range.count as usize {
+        let unmapped_address = word_range.address + u32::try_from(existing_words.len())?;
+        return Err(Box::new(MemoryAccessError::Unmapped {
+            address: unmapped_address,
+        }));
+    }
+    let words = merge_bytes_into_words(&existing_words, word_range.byte_offset, &bytes);
+
+    let (reply, response) = channel();
+    tx.send(DebuggerMessage::WriteMemory {
+        address: word_range.address,
+        words,
+        reply,
+    })?;
+    response
+        .recv()
+        .map_err(|_| MemoryAccessError::VmDisconnected)??;
+
+    Ok(WriteMemoryResponse {
+        offset: None,
+        bytes_written: Some(i64::try_from(bytes.len())?),
+    })
+}
+
+/// Source breakpoints and instruction breakpoints are set separately by the debugger, but the VM
+/// only cares about the set of addresses to stop at
+fn breakpoint_refs(server_state: &ServerState) -> HashSet<BreakpointRef> {
+    server_state
+        .breakpoints
+        .iter()
+        .chain(server_state.instruction_breakpoints.iter())
+        .cloned()
+        .collect()
+}
+
+///
+/// Parses the optional condition, hit condition and log message of a breakpoint.
+///
+/// Empty strings are ignored because some clients send them when a condition is cleared.
+///
+/// # Errors
+///
+/// Returns an error if any of them can't be parsed, in which case the breakpoint can't be set.
+///
+pub fn parse_breakpoint_conditions(
+    condition: Option<&str>,
+    hit_condition: Option<&str>,
+    log_message: Option<&str>,
+    symbols: &SymbolTable,
+) -> Result<BreakpointConditions, ExpressionError> {
+    fn non_empty(value: Option<&str>) -> Option<&str> {
+        value.filter(|value| !value.trim().is_empty())
+    }
+    let conditions = BreakpointConditions {
+        condition: non_empty(condition).map(parse_expression).transpose()?,
+        hit_condition: non_empty(hit_condition)
+            .map(parse_hit_condition)
+            .transpose()?,
+        log_message: non_empty(log_message).map(parse_log_message).transpose()?,
+    };
+    Ok(conditions.resolve_symbols(symbols))
+}
+
+/// Pairs up the breakpoints to send back to the debugger with what to send to the VM (if the
+/// breakpoint could be set)
+fn split_breakpoints<T>(
+    breakpoints: impl Iterator<Item = (Breakpoint, Option<T>)>,
+) -> (Vec<Breakpoint>, Vec<T>) {
+    breakpoints.fold(
+        (vec![], vec![]),
+        |(mut breakpoints, mut refs), (breakpoint, breakpoint_ref)| {
+            breakpoints.push(breakpoint);
+            refs.extend(breakpoint_ref);
+            (breakpoints, refs)
+        },
+    )
+}
+
+fn source_breakpoints(
+    args: &SetBreakpointsArguments,
+    program_debug_info: &ProgramDebugInfo,
+    mut get_new_id: impl FnMut() -> i64,
+) -> (Vec<Breakpoint>, Vec<BreakpointRef>) {
+    let Some(source_breakpoints) = args.breakpoints.as_ref() else {
+        return (vec![], vec![]);
+    };
+    split_breakpoints(source_breakpoints.iter().map(|b| {
+        let breakpoint_id = get_new_id();
+        let pc = args.source.name.as_ref().and_then(|c| {
+            translate_line_column_to_pc(
+                program_debug_info,
+                c.as_str(),
+                (b.line, b.column.unwrap_or(1)),
+            )
+        });
+        let conditions = parse_breakpoint_conditions(
+            b.condition.as_deref(),
+            b.hit_condition.as_deref(),
+            b.log_message.as_deref(),
+            &program_debug_info.symbols,
+        );
+
+        let breakpoint = Breakpoint {
+            id: Some(breakpoint_id),
+            verified: pc.is_some() && conditions.is_ok(),
+            message: conditions.as_ref().err().map(ToString::to_string),
+            source: Some(args.source.clone()),
+            line: Some(b.line),
+            column: b.column,
+            end_line: None,
+            end_column: None,
+            instruction_reference: pc.map(format_instruction_ref),
+            offset: Some(0),
+        };
+        let breakpoint_ref = pc
+            .zip(conditions.ok())
+            .map(|(pc, conditions)| BreakpointRef {
+                breakpoint_id,
+                pc,
+                conditions,
+            });
+        (breakpoint, breakpoint_ref)
+    }))
+}
+
+fn source_location(
+    program_debug_info: &ProgramDebugInfo,
+    sources: &HashMap<String, (Source, String)>,
+    address: u32,
+) -> Option<(Source, i64, i64)> {
+    let (line, column, original_filename) =
+        translate_pc_to_line_column(program_debug_info, address)?;
+    let (source, _) = sources.get(&original_filename)?;
+    Some((source.clone(), line, column))
+}
+
+fn unwind(
+    tx: &Sender<DebuggerMessage>,
+    vm_state: &VmState,
+    program_debug_info: &ProgramDebugInfo,
+) -> Vec<CallFrame> {
+    unwind_call_stack(
+        &vm_state.registers,
+        &vm_state.eu_registers,
+        &program_debug_info.symbols,
+        |address, count| {
+            // If memory can't be read, the unwinder just finds fewer frames
+            request_memory_read(
+                tx,
+                WordRange {
+                    address,
+                    count,
+                    byte_offset: 0,
+                    bytes_out_of_range: 0,
+                },
+            )
+            .unwrap_or_default()
+        },
+    )
+}
+
+fn frame_name(symbols: &SymbolTable, frame: CallFrame) -> String {
+    let location = format_symbolised_address(symbols, frame.pc);
+    match frame.kind {
+        FrameKind::Exception(level) => format!("{location} [exception level {level}]"),
+        FrameKind::Current | FrameKind::Subroutine => location,
+    }
+}
+
+fn stack_trace(
+    tx: &Sender<DebuggerMessage>,
+    vm_state: &VmState,
+    program_debug_info: &ProgramDebugInfo,
+    sources: &HashMap<String, (Source, String)>,
+) -> Vec<StackFrame> {
+    unwind(tx, vm_state, program_debug_info)
+        .iter()
+        .zip(DEFAULT_STACK_FRAME_ID..)
+        .map(|(frame, id)| {
+            // Callers are shown at the call rather than the instruction it returns to
+            let pc = match frame.kind {
+                FrameKind::Subroutine => frame.pc - INSTRUCTION_SIZE_WORDS,
+                FrameKind::Current | FrameKind::Exception(_) => frame.pc,
+            };
+            // Code without any debug info (e.g. copied into RAM at runtime) still
+            // gets a frame so that it can be debugged with the disassembly
+            let location = source_location(program_debug_info, sources, pc);
+            StackFrame {
+                id,
+                name: frame_name(&program_debug_info.symbols, *frame),
+                source: location.as_ref().map(|(source, _, _)| source.clone()),
+                line: location.as_ref().map_or(0, |(_, line, _)| *line),
+                column: location.as_ref().map_or(0, |(_, _, column)| *column),
+                end_line: None,
+                end_column: None,
+                can_restart: None,
+                instruction_pointer_reference: Some(format_instruction_ref(pc)),
+                module_id: None,
+                presentation_hint: None,
+            }
+        })
+        .collect()
+}
+
+/// Runs until the current frame returns to its caller, or just steps if there isn't one
+fn step_out_condition(
+    tx: &Sender<DebuggerMessage>,
+    vm_state: &VmState,
+    program_debug_info: &ProgramDebugInfo,
+) -> ResumeCondition {
+    unwind(tx, vm_state, program_debug_info).get(1).map_or(
+        ResumeCondition::UntilNextStep,
+        |caller| ResumeCondition::UntilReturn {
+            address: caller.pc,
+            stack_pointer: vm_state.registers.get_full_sp_address(),
+        },
+    )
+}
+
+fn disassemble(
+    tx: &Sender<DebuggerMessage>,
+    args: &DisassembleArguments,
+    program_debug_info: &ProgramDebugInfo,
+    sources: &HashMap<String, (Source, String)>,
+) -> DynResult<DisassembleResponse> {
+    let reference = parse_reference(&args.memory_reference)
+        .ok_or_else(|| DebugAdapterError::InvalidMemoryReference(args.memory_reference.clone()))?;
+    let instruction_size = i64::from(INSTRUCTION_SIZE_WORDS);
+    let start_address = offset_address(
+        reference,
+        args.offset.unwrap_or_default().div_euclid(2)
+            + args.instruction_offset.unwrap_or_default() * instruction_size,
+    );
+
+    // The debugger expects exactly the number of instructions it asked for, even if some of them
+    // are in unmapped memory, so each instruction is read separately
+    let instructions = (0..args.instruction_count)
+        .map(|index| {
+            let address = offset_address(start_address, index * instruction_size);
+            let words = request_memory_read(
+                tx,
+                WordRange {
+                    address,
+                    count: INSTRUCTION_SIZE_WORDS,
+                    byte_offset: 0,
+                    bytes_out_of_range: 0,
+                },
+            )?;
+            let location = source_location(program_debug_info, sources, address);
+
+            let (instruction_bytes, instruction) = match words.as_slice() {
+                [high, low] => {
+                    let raw_instruction = (u32::from(*high) << 16) | u32::from(*low);
+                    let decoded = decode_instruction(raw_instruction.to_be_bytes());
+                    (
+                        Some(format!("{high:04X} {low:04X}")),
+                        disassemble_instruction(&decoded),
+                    )
+                }
+                _ => (None, "??".to_string()),
+            };
+
+            Ok(DisassembledInstruction {
+                address: format_memory_reference(address),
+                instruction_bytes,
+                instruction,
+                // Debuggers show the symbol as a heading whenever it changes
+                symbol: nearest_symbol(&program_debug_info.symbols, address)
+                    .map(|(name, _)| name.to_string()),
+                location: location.as_ref().map(|(source, _, _)| source.clone()),
+                line: location.as_ref().map(|(_, line, _)| *line),
+                column: location.as_ref().map(|(_, _, column)| *column),
+                end_line: None,
+                end_column: None,
+            })
+        })
+        .collect::<DynResult<Vec<_>>>()?;
+
+    Ok(DisassembleResponse { instructions })
+}
+
+fn instruction_breakpoints(
+    args: &SetInstructionBreakpointsArguments,
+    program_debug_info: &ProgramDebugInfo,
+    sources: &HashMap<String, (Source, String)>,
+    mut get_new_id: impl FnMut() -> i64,
+) -> (Vec<Breakpoint>, Vec<BreakpointRef>) {
+    split_breakpoints(args.breakpoints.iter().map(|b| {
+        let breakpoint_id = get_new_id();
+        let pc = parse_reference(&b.instruction_reference)
+            .map(|address| offset_address(address, b.offset.unwrap_or_default().div_euclid(2)));
+        let location = pc.and_then(|pc| source_location(program_debug_info, sources, pc));
+        let conditions = parse_breakpoint_conditions(
+            b.condition.as_deref(),
+            b.hit_condition.as_deref(),
+            None,
+            &program_debug_info.symbols,
+        );
+
+        let breakpoint = Breakpoint {
+            id: Some(breakpoint_id),
+            verified: pc.is_some() && conditions.is_ok(),
+            message: pc.map_or_else(
+                || {
+                    Some(format!(
+                        "Invalid instruction reference [{}]",
+                        b.instruction_reference
+                    ))
+                },
+                |_| conditions.as_ref().err().map(ToString::to_string),
+            ),
+            source: location.as_ref().map(|(source, _, _)| source.clone()),
+            line: location.as_ref().map(|(_, line, _)| *line),
+            column: location.as_ref().map(|(_, _, column)| *column),
+            end_line: None,
+            end_column: None,
+            instruction_reference: pc.map(format_instruction_ref),
+            offset: Some(0),
+        };
+        let breakpoint_ref = pc
+            .zip(conditions.ok())
+            .map(|(pc, conditions)| BreakpointRef {
+                breakpoint_id,
+                pc,
+                conditions,
+            });
+        (breakpoint, breakpoint_ref)
+    }))
+}
+
+#[must_use]
+pub fn data_breakpoint_info(args: &DataBreakpointInfoArguments) -> DataBreakpointInfoResponse {
+    // Registers are inside the CPU and never go over the bus, so only addresses can be watched.
+    // Names in a variables container (e.g. register names like "a") aren't treated as addresses
+    // because they would be parsed as hex.
+    let address = args
+        .variables_reference
+        .is_none()
+        .then(|| parse_reference(&args.name))
+        .flatten();
+
+    address.map_or_else(
+        || DataBreakpointInfoResponse {
+            data_id: None,
+            description: format!(
+                "Only memory addresses can be watched, [{}] is not an address",
+                args.name
+            ),
+            access_types: None,
+            can_persist: None,
+        },
+        |address| DataBreakpointInfoResponse {
+            data_id: Some(format_memory_reference(address)),
+            description: format!("Word at {}", format_memory_reference(address)),
+            access_types: Some(vec![
+                DataBreakpointAccessType::Read,
+                DataBreakpointAccessType::Write,
+                DataBreakpointAccessType::ReadWrite,
+            ]),
+            can_persist: Some(true),
+        },
+    )
+}
+
+#[must_use]
+pub fn data_breakpoints(
+    args: &SetDataBreakpointsArguments,
+    symbols: &SymbolTable,
+    mut get_new_id: impl FnMut() -> i64,
+) -> (Vec<Breakpoint>, Vec<DataBreakpointRef>) {
+    split_breakpoints(args.breakpoints.iter().map(|b| {
+        let breakpoint_id = get_new_id();
+        let address = parse_reference(&b.data_id);
+        let conditions = parse_breakpoint_conditions(
+            b.condition.as_deref(),
+            b.hit_condition.as_deref(),
+            None,
+            symbols,
+        );
+        let breakpoint = Breakpoint {
+            id: Some(breakpoint_id),
+            verified: address.is_some() && conditions.is_ok(),
+            message: address.map_or_else(
+                || Some(format!("Invalid data breakpoint address [{}]", b.data_id)),
+                |_| conditions.as_ref().err().map(ToString::to_string),
+            ),
+            source: None,
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+            instruction_reference: None,
+            offset: None,
+        };
+        let data_breakpoint_ref =
+            address
+                .zip(conditions.ok())
+                .map(|(address, conditions)| DataBreakpointRef {
+                    breakpoint_id,
+                    address,
+                    // Watching for writes is the most common use so it is the default
+                    access_type: match b.access_type {
+                        Some(DataBreakpointAccessType::Read) => DataAccessType::Read,
+                        Some(DataBreakpointAccessType::ReadWrite) => DataAccessType::ReadWrite,
+                        Some(DataBreakpointAccessType::Write) | None => DataAccessType::Write,
+                    },
+                    conditions,
+                });
+        (breakpoint, data_breakpoint_ref)
+    }))
+}
+
+/// The exception breakpoint filter ID of a fault (e.g. "Bus")
+#[must_use]
+pub fn fault_filter_id(fault: Faults) -> String {
+    format!("{fault:?}")
+}
+
+#[must_use]
+pub fn parse_fault_filter_id(filter_id: &str) -> Option<Faults> {
+    EXCEPTION_BREAKPOINT_FAULTS
+        .into_iter()
+        .find(|fault| fault_filter_id(*fault) == filter_id)
+}
+
+fn exception_breakpoint_filters() -> Vec<ExceptionBreakpointsFilter> {
+    EXCEPTION_BREAKPOINT_FAULTS
+        .into_iter()
+        .map(|fault| ExceptionBreakpointsFilter {
+            filter: fault_filter_id(fault),
+            label: format!("{fault:?} Fault"),
+            description: Some(format!(
+                "Pause when the CPU raises a {fault:?} fault, before the fault handler runs"
+            )),
+            default: Some(false),
+            supports_condition: None,
+            condition_description: None,
+        })
+        .collect()
+}
+
+fn exception_breakpoints(
+    args: &SetExceptionBreakpointsArguments,
+) -> (Vec<Breakpoint>, Vec<Faults>) {
+    // Filter options are accepted for clients that always send them, but conditions aren't supported
+    let filter_ids = args.filters.iter().chain(
+        args.filter_options
+            .iter()
+            .flatten()
+            .map(|filter_options| &filter_options.filter_id),
+    );
+    split_breakpoints(filter_ids.map(|filter_id| {
+        let fault = parse_fault_filter_id(filter_id);
+        let breakpoint = Breakpoint {
+            id: None,
+            verified: fault.is_some(),
+            message: fault
+                .is_none()
+                .then(|| format!("Unknown exception filter [{filter_id}]")),
+            source: None,
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+            instruction_reference: None,
+            offset: None,
+        };
+        (breakpoint, fault)
+    }))
+}
+
+fn describe_fault(fault: FaultDetails) -> String {
+    let original_fault = fault.original_fault.map_or_else(String::new, |original| {
+        format!(" while handling a {original:?} fault")
+    });
+    format!(
+        "{:?} fault{original_fault} ({:?} access to {})",
+        fault.fault,
+        fault.bus_access_type,
+        format_memory_reference(fault.address)
+    )
+}
+
 #[must_use]
 pub fn create_server_channels() -> ServerChannels {
     let (debugger_tx, debugger_rx) = channel::<DebuggerMessage>();
@@ -85,36 +716,66 @@ pub fn create_server_channels() -> ServerChannels {
     }
 }
 
-pub fn start_server(
-    channels: DebuggerChannels,
-    program_debug_info: &ProgramDebugInfo,
-) -> DynResult<()> {
-    info!("Waiting for socket connection on [{LISTENER_ADDRESS}]...");
-    let listener = TcpListener::bind(LISTENER_ADDRESS)?;
+type SessionOutput = Arc<Mutex<ServerOutput<TcpStream>>>;
 
-    // TODO: Work out what happens if there is more than one connection attempt to the debug server
-    // category=Debugging
-    let (stream, addr) = listener.accept()?;
+/// Where the events from the VM go. Sessions come and go but the VM keeps running.
+#[derive(Default)]
+struct EventSink {
+    /// The attached debugger, if there is one
+    output: Option<SessionOutput>,
+    /// The last time the VM paused while no debugger was attached (e.g. at the start of the
+    /// program), which is sent to the next debugger that attaches
+    pending_stop: Option<Event>,
+}
 
-    info!("Connection established with {addr:?}");
+fn send_event(event_sink: &Mutex<EventSink>, event: Event) {
+    let mut event_sink = event_sink.lock().unwrap();
+    if let Some(output) = &event_sink.output {
+        // If the connection has gone away the session will end when it next polls for a request
+        let result = output.lock().unwrap().send_event(event);
+        if let Err(error) = result {
+            warn!("Could not send event to debugger: {error}");
+        }
+    } else if matches!(event, Event::Stopped(_)) {
+        event_sink.pending_stop = Some(event);
+    }
+}
 
-    let output = BufWriter::new(stream.try_clone().unwrap());
-    let input = BufReader::new(stream);
-    let mut server = Server::new(input, output);
-    let mut server_state = ServerState {
-        breakpoints: vec![],
-    };
+/// The state that outlives a single debug session
+struct SharedState<'a> {
+    program_debug_info: &'a ProgramDebugInfo,
+    sources: HashMap<String, (Source, String)>,
     // TODO: Investigate whether there is a better way to share the VM state than a mutex
     // category=Refactoring
     // Maybe channels would be better?
+    vm_state: Arc<Mutex<Option<VmState>>>,
+    /// The fault that the VM last paused on, for exception info requests
+    last_fault: Arc<Mutex<Option<FaultDetails>>>,
+    event_sink: Arc<Mutex<EventSink>>,
+}
+
+///
+/// Listens for debuggers on `address` until the VM exits.
+///
+/// Only one debugger can be attached at a time, other debuggers that connect are told that they
+/// can't attach. Detaching (or dropping the connection) clears the breakpoints and leaves
+/// the VM running, ready for the next debugger to attach.
+///
+pub fn start_server(
+    channels: DebuggerChannels,
+    program_debug_info: &ProgramDebugInfo,
+    address: SocketAddr,
+) -> DynResult<()> {
+    let listener = bind_listener(address)?;
+
     let vm_state: Arc<Mutex<Option<VmState>>> = Arc::new(Mutex::new(None));
-    let mut next_id: i64 = 1;
-    let mut get_new_id = || {
-        let id = next_id;
-        next_id += 1;
-        id
-    };
     let vm_state_ref = vm_state.clone();
+    let last_fault: Arc<Mutex<Option<FaultDetails>>> = Arc::new(Mutex::new(None));
+    let last_fault_ref = last_fault.clone();
+    let event_sink: Arc<Mutex<EventSink>> = Arc::default();
+    let event_sink_ref = event_sink.clone();
+    let vm_exited = Arc::new(AtomicBool::new(false));
+    let vm_exited_ref = vm_exited.clone();
     let sources: HashMap<String, (Source, String)> = program_debug_info
         .debug_info_map
         .values()
@@ -141,23 +802,31 @@ pub fn start_server(
         })
         .collect();
 
-    let server_output = server.output.clone();
-    std::thread::spawn(move || loop {
+    thread::spawn(move || loop {
         if let Ok(data) = channels.rx.recv() {
             match data {
                 VmMessage::Paused(reason, new_vm_state) => {
                     let mut vm_state = vm_state_ref.lock().unwrap();
-                    *vm_state = Some(new_vm_state);
+                    *vm_state = Some(*new_vm_state);
                     drop(vm_state);
+                    *last_fault_ref.lock().unwrap() = match reason {
+                        VmPauseReason::Fault(fault) => Some(fault),
+                        _ => None,
+                    };
 
-                    server_output
-                        .lock()
-                        .unwrap()
-                        .send_event(Event::Stopped(StoppedEventBody {
+                    send_event(
+                        &event_sink_ref,
+                        Event::Stopped(StoppedEventBody {
                             reason: match reason {
                                 VmPauseReason::Init => StoppedEventReason::Entry,
                                 VmPauseReason::Breakpoint(_) => StoppedEventReason::Breakpoint,
-                                VmPauseReason::Step => StoppedEventReason::Step,
+                                VmPauseReason::DataBreakpoint(_) => {
+                                    StoppedEventReason::String("data breakpoint".to_string())
+                                }
+                                VmPauseReason::Fault(_) => StoppedEventReason::Exception,
+                                VmPauseReason::Step | VmPauseReason::StartOfHistory => {
+                                    StoppedEventReason::Step
+                                }
                             },
                             description: match reason {
                                 VmPauseReason::Init => {
@@ -166,7 +835,19 @@ pub fn start_server(
                                 VmPauseReason::Breakpoint(_) => {
                                     Some("Paused on breakpoint".to_string())
                                 }
+                                VmPauseReason::DataBreakpoint(ref data_breakpoint) => {
+                                    Some(format!(
+                                        "Paused after access to {}",
+                                        format_memory_reference(data_breakpoint.address)
+                                    ))
+                                }
+                                VmPauseReason::Fault(fault) => {
+                                    Some(format!("Paused on {}", describe_fault(fault)))
+                                }
                                 VmPauseReason::Step => Some("Paused after step".to_string()),
+                                VmPauseReason::StartOfHistory => Some(
+                                    "Paused at the oldest instruction in the history".to_string(),
+                                ),
                             },
                             thread_id: Some(DEFAULT_THREAD_ID),
                             preserve_focus_hint: None,
@@ -176,31 +857,123 @@ pub fn start_server(
                                 VmPauseReason::Breakpoint(breakpoint) => {
                                     Some(vec![breakpoint.breakpoint_id])
                                 }
-                                VmPauseReason::Step | VmPauseReason::Init => None,
+                                VmPauseReason::DataBreakpoint(data_breakpoint) => {
+                                    Some(vec![data_breakpoint.breakpoint_id])
+                                }
+                                VmPauseReason::Fault(_)
+                                | VmPauseReason::Step
+                                | VmPauseReason::Init
+                                | VmPauseReason::StartOfHistory => None,
                             },
-                        }))
-                        .unwrap();
+                        }),
+                    );
+                }
+                VmMessage::Log(message) => {
+                    send_event(
+                        &event_sink_ref,
+                        Event::Output(OutputEventBody {
+                            category: Some(OutputEventCategory::Console),
+                            output: format!("{message}\n"),
+                            ..OutputEventBody::default()
+                        }),
+                    );
                 }
             }
         } else {
-            // The VM has closed it side so we should terminate the debug session
-            // TODO: Fix up behaviour when debug server connection closes
-            // category=Debugger
-            // Why Error occurred in debug server: SendError { .. } is logged when this happens?
-            server_output
-                .lock()
-                .unwrap()
-                .send_event(Event::Terminated(None))
-                .unwrap();
+            // The VM has closed its side so we should terminate the debug session
+            send_event(&event_sink_ref, Event::Terminated(None));
+            vm_exited_ref.store(true, Ordering::Relaxed);
 
             // Stop looping
             return;
         }
     });
 
+    let shared_state = SharedState {
+        program_debug_info,
+        sources,
+        vm_state,
+        last_fault,
+        event_sink,
+    };
+
+    while let Some(stream) = accept_connection(&listener, &vm_exited)? {
+        let result = reject_connections_during(&listener, reject_session, || {
+            run_session(stream, &channels.tx, &shared_state)
+        })?;
+        shared_state.event_sink.lock().unwrap().output = None;
+        // The VM is running again once it knows that the debugger has gone
+        *shared_state.vm_state.lock().unwrap() = None;
+        if let Err(error) = result {
+            warn!("Debug session ended with an error: {error}");
+        }
+
+        if channels.tx.send(DebuggerMessage::Disconnect).is_err() {
+            // The VM has already exited
+            return Ok(());
+        }
+        info!("Debugger detached");
+    }
+    Ok(())
+}
+
+/// Tells a debugger that connects while another one is attached that it can't attach
+fn reject_session(stream: TcpStream) {
+    let reject = || -> DynResult<()> {
+        let output = BufWriter::new(stream.try_clone()?);
+        let input = BufReader::new(NormalisingReader::new(BufReader::new(stream)));
+        let mut server = Server::new(input, output);
+        // The first request is normally `initialize`, which is the debugger's cue to give up
+        if let Some(req) = server.poll_request()? {
+            server.respond(req.error("Another debugger is already attached to the VM"))?;
+        }
+        server.send_event(Event::Terminated(None))?;
+        Ok(())
+    };
+    if let Err(error) = reject() {
+        warn!("Could not tell the debugger that it can't attach: {error}");
+    }
+}
+
+fn run_session(
+    stream: TcpStream,
+    tx: &Sender<DebuggerMessage>,
+    shared_state: &SharedState,
+) -> DynResult<()> {
+    let SharedState {
+        program_debug_info,
+        sources,
+        vm_state,
+        last_fault,
+        event_sink,
+    } = shared_state;
+
+    let output = BufWriter::new(stream.try_clone()?);
+    let input = BufReader::new(NormalisingReader::new(BufReader::new(stream)));
+    let mut server = Server::new(input, output);
+    let mut server_state = ServerState {
+        breakpoints: vec![],
+        instruction_breakpoints: vec![],
+    };
+    let mut next_id: i64 = 1;
+    let mut get_new_id = || {
+        let id = next_id;
+        next_id += 1;
+        id
+    };
+
+    {
+        let mut event_sink = event_sink.lock().unwrap();
+        if let Some(event) = event_sink.pending_stop.take() {
+            server.send_event(event)?;
+        }
+        event_sink.output = Some(server.output.clone());
+    }
+
     loop {
         let Some(req) = server.poll_request()? else {
-            return Err(Box::new(DebugAdapterError::MissingCommandError));
+            // The debugger closed the connection without disconnecting first
+            return Ok(());
         };
 
         match req.command {
@@ -218,27 +991,77 @@ pub fn start_server(
                 }));
                 server.respond(rsp)?;
 
-                channels
-                    .tx
-                    .send(DebuggerMessage::ResumeVm(ResumeCondition::None))?;
+                tx.send(DebuggerMessage::ResumeVm(ResumeCondition::None))?;
+            }
+            Command::DataBreakpointInfo(ref args) => {
+                let body = data_breakpoint_info(args);
+                let rsp = req.success(ResponseBody::DataBreakpointInfo(body));
+                server.respond(rsp)?;
+            }
+            Command::Disassemble(ref args) => {
+                let rsp = match disassemble(tx, args, program_debug_info, sources) {
+                    Ok(body) => req.success(ResponseBody::Disassemble(body)),
+                    Err(error) => req.error(&error.to_string()),
+                };
+                server.respond(rsp)?;
             }
-            Command::DataBreakpointInfo(_) => todo!(),
-            Command::Disassemble(_) => todo!(),
             Command::Disconnect(_) => {
                 let rsp = req.success(ResponseBody::Disconnect);
                 server.respond(rsp)?;
 
-                channels.tx.send(DebuggerMessage::Disconnect)?;
-
-                break;
+                return Ok(());
+            }
+            Command::Evaluate(ref args) => {
+                let rsp = match request_evaluate(tx, &args.expression, &program_debug_info.symbols)
+                {
+                    Ok(value) => req.success(ResponseBody::Evaluate(EvaluateResponse {
+                        result: format!("0x{value:X}"),
+                        type_field: None,
+                        presentation_hint: None,
+                        variables_reference: 0,
+                        named_variables: None,
+                        indexed_variables: None,
+                        // So that the memory view can be opened at the result (e.g. for "a + 4")
+                        memory_reference: Some(format_memory_reference(value & ADDRESS_MASK)),
+                    })),
+                    Err(error) => req.error(&error.to_string()),
+                };
+                server.respond(rsp)?;
+            }
+            Command::ExceptionInfo(_) => {
+                let fault = *last_fault.lock().unwrap();
+                let rsp = match fault {
+                    Some(fault) => {
+                        req.success(ResponseBody::ExceptionInfo(ExceptionInfoResponse {
+                            exception_id: fault_filter_id(fault.fault),
+                            description: Some(describe_fault(fault)),
+                            break_mode: ExceptionBreakMode::Always,
+                            details: None,
+                        }))
+                    }
+                    None => req.error("The VM is not paused on a fault"),
+                };
+                server.respond(rsp)?;
             }
-            Command::Evaluate(_) => todo!(),
-            Command::ExceptionInfo(_) => todo!(),
             Command::Goto(_) => todo!(),
             Command::GotoTargets(_) => todo!(),
             Command::Initialize(_) => {
                 let rsp = req.success(ResponseBody::Initialize(Capabilities {
                     supported_checksum_algorithms: Some(vec![ChecksumAlgorithm::SHA256]),
+                    supports_step_back: Some(true),
+                    supports_read_memory_request: Some(true),
+                    supports_write_memory_request: Some(true),
+                    supports_disassemble_request: Some(true),
+                    supports_instruction_breakpoints: Some(true),
+                    supports_data_breakpoints: Some(true),
+                    supports_conditional_breakpoints: Some(true),
+                    supports_hit_conditional_breakpoints: Some(true),
+                    supports_log_points: Some(true),
+                    supports_evaluate_for_hovers: Some(true),
+                    supports_set_variable: Some(true),
+                    supports_set_expression: Some(true),
+                    exception_breakpoint_filters: Some(exception_breakpoint_filters()),
+                    supports_exception_info_request: Some(true),
                     ..Capabilities::default()
                 }));
 
@@ -256,9 +1079,7 @@ pub fn start_server(
             Command::LoadedSources => todo!(),
             Command::Modules(_) => todo!(),
             Command::Next(_) => {
-                channels
-                    .tx
-                    .send(DebuggerMessage::ResumeVm(ResumeCondition::UntilNextStep))?;
+                tx.send(DebuggerMessage::ResumeVm(ResumeCondition::UntilNextStep))?;
 
                 let rsp = req.success(ResponseBody::Next);
                 server.respond(rsp)?;
@@ -267,102 +1088,150 @@ pub fn start_server(
                 let rsp = req.success(ResponseBody::Pause);
                 server.respond(rsp)?;
 
-                channels.tx.send(DebuggerMessage::PauseVm)?;
+                tx.send(DebuggerMessage::PauseVm)?;
+            }
+            Command::ReadMemory(ref args) => {
+                let rsp = match read_memory(tx, args) {
+                    Ok(body) => req.success(ResponseBody::ReadMemory(body)),
+                    Err(error) => req.error(&error.to_string()),
+                };
+                server.respond(rsp)?;
             }
-            Command::ReadMemory(_) => todo!(),
             Command::Restart(_) => todo!(),
             Command::RestartFrame(_) => todo!(),
-            Command::ReverseContinue(_) => todo!(),
+            Command::ReverseContinue(_) => {
+                let rsp = req.success(ResponseBody::ReverseContinue);
+                server.respond(rsp)?;
+
+                tx.send(DebuggerMessage::ReverseVm(ReverseCondition::None))?;
+            }
             Command::Scopes(_) => {
-                let rsp = req.success(ResponseBody::Scopes(ScopesResponse {
-                    scopes: vec![
-                        Scope {
-                            name: "cpu registers".to_string(),
-                            presentation_hint: None,
-                            variables_reference: DEFAULT_VARIABLES_ID,
-                            named_variables: Some(16),
-                            indexed_variables: Some(0),
-                            expensive: false,
-                            source: None,
-                            line: None,
-                            column: None,
-                            end_line: None,
-                            end_column: None,
-                        },
-                        // TODO Misc:
-                        // cause
-                        // system_ram_offset
-                        // pending_coprocessor_command
-                        // ExceptionUnitRegisters
-                    ],
-                }));
+                let scopes = vm_state
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map_or_else(Vec::new, |vm_state| {
+                        vm_state
+                            .scopes
+                            .iter()
+                            .zip(DEFAULT_VARIABLES_ID..)
+                            .map(|(scope, variables_reference)| Scope {
+                                name: scope.name.clone(),
+                                presentation_hint: None,
+                                variables_reference,
+                                named_variables: i64::try_from(scope.variables.len()).ok(),
+                                indexed_variables: Some(0),
+                                expensive: false,
+                                source: None,
+                                line: None,
+                                column: None,
+                                end_line: None,
+                                end_column: None,
+                            })
+                            .collect()
+                    });
+                let rsp = req.success(ResponseBody::Scopes(ScopesResponse { scopes }));
                 server.respond(rsp)?;
             }
             Command::SetBreakpoints(ref args) => {
                 if args.source_modified.is_some_and(|x| x) {
                     unimplemented!("Source modification is not supported");
                 }
-                let breakpoints: Vec<Breakpoint> = args.breakpoints.as_ref().map_or_else(
-                    std::vec::Vec::new,
-                    |source_breakpoints| {
-                        source_breakpoints
-                            .iter()
-                            .map(|b| {
-                                let pc = args.source.name.as_ref().and_then(|c| {
-                                    translate_line_column_to_pc(
-                                        program_debug_info,
-                                        c.as_str(),
-                                        (b.line, b.column.unwrap_or(1)),
-                                    )
-                                });
-
-                                Breakpoint {
-                                    id: Some(get_new_id()),
-                                    verified: pc.is_some(),
-                                    message: None,
-                                    source: Some(args.source.clone()),
-                                    line: Some(b.line),
-                                    column: b.column,
-                                    end_line: None,
-                                    end_column: None,
-                                    instruction_reference: pc.map(format_instruction_ref),
-                                    offset: Some(0),
-                                }
-                            })
-                            .collect()
-                    },
-                );
-
-                server_state.breakpoints.clone_from(&breakpoints);
-
-                channels.tx.send(DebuggerMessage::UpdateBreakpoints(
-                    breakpoints
-                        .iter()
-                        .map(|b| BreakpointRef {
-                            breakpoint_id: b.id.unwrap(),
-                            pc: parse_instruction_ref(
-                                b.instruction_reference.as_ref().unwrap().as_str(),
-                            ),
-                        })
-                        .collect(),
-                ))?;
+                let (breakpoints, refs) =
+                    source_breakpoints(args, program_debug_info, &mut get_new_id);
+                server_state.breakpoints = refs;
+
+                tx.send(DebuggerMessage::UpdateBreakpoints(breakpoint_refs(
+                    &server_state,
+                )))?;
 
                 let rsp = req.success(ResponseBody::SetBreakpoints(SetBreakpointsResponse {
-                    breakpoints: breakpoints.clone(),
+                    breakpoints,
                 }));
                 server.respond(rsp)?;
             }
-            Command::SetDataBreakpoints(_) => todo!(),
-            Command::SetExceptionBreakpoints(_) => {
+            Command::SetDataBreakpoints(ref args) => {
+                let (breakpoints, data_breakpoint_refs) =
+                    data_breakpoints(args, &program_debug_info.symbols, &mut get_new_id);
+
+                tx.send(DebuggerMessage::UpdateDataBreakpoints(data_breakpoint_refs))?;
+
+                let rsp = req.success(ResponseBody::SetDataBreakpoints(
+                    SetDataBreakpointsResponse { breakpoints },
+                ));
+                server.respond(rsp)?;
+            }
+            Command::SetExceptionBreakpoints(ref args) => {
+                let (breakpoints, faults) = exception_breakpoints(args);
+
+                tx.send(DebuggerMessage::UpdateExceptionBreakpoints(faults))?;
+
                 let rsp = req.success(ResponseBody::SetExceptionBreakpoints(
-                    SetExceptionBreakpointsResponse { breakpoints: None },
+                    SetExceptionBreakpointsResponse {
+                        breakpoints: Some(breakpoints),
+                    },
                 ));
                 server.respond(rsp)?;
             }
-            Command::SetExpression(_) => todo!(),
+            Command::SetExpression(ref args) => {
+                let rsp = match request_assign(
+                    tx,
+                    &args.expression,
+                    &args.value,
+                    &program_debug_info.symbols,
+                ) {
+                    Ok((value, new_vm_state)) => {
+                        *vm_state.lock().unwrap() = Some(new_vm_state);
+                        req.success(ResponseBody::SetExpression(SetExpressionResponse {
+                            value: format!("0x{value:X}"),
+                            type_field: None,
+                            presentation_hint: None,
+                            variables_reference: None,
+                            named_variables: None,
+                            indexed_variables: None,
+                        }))
+                    }
+                    Err(error) => req.error(&error.to_string()),
+                };
+                server.respond(rsp)?;
+            }
             Command::SetFunctionBreakpoints(_) => todo!(),
-            Command::SetInstructionBreakpoints(_) => todo!(),
-            Command::SetVariable(_) => todo!(),
+            Command::SetInstructionBreakpoints(ref args) => {
+                let (breakpoints, refs) =
+                    instruction_breakpoints(args, program_debug_info, sources, &mut get_new_id);
+                server_state.instruction_breakpoints = refs;
+
+                tx.send(DebuggerMessage::UpdateBreakpoints(breakpoint_refs(
+                    &server_state,
+                )))?;
+
+                let rsp = req.success(ResponseBody::SetInstructionBreakpoints(
+                    SetInstructionBreakpointsResponse { breakpoints },
+                ));
+                server.respond(rsp)?;
+            }
+            Command::SetVariable(ref args) => {
+                // The variable names in the registers scope are the register names
+                let result = if args.variables_reference == DEFAULT_VARIABLES_ID {
+                    request_assign(tx, &args.name, &args.value, &program_debug_info.symbols)
+                } else {
+                    Err("Only the CPU registers can be edited".into())
+                };
+                let rsp = match result {
+                    Ok((value, new_vm_state)) => {
+                        *vm_state.lock().unwrap() = Some(new_vm_state);
+                        req.success(ResponseBody::SetVariable(SetVariableResponse {
+                            value: format!("0x{value:X}"),
+                            type_field: None,
+                            variables_reference: None,
+                            named_variables: None,
+                            indexed_variables: None,
+                        }))
+                    }
+                    Err(error) => req.error(&error.to_string()),
+                };
+                server.respond(rsp)?;
+            }
             Command::Source(ref args) => {
                 let content = args
                     .source
@@ -378,48 +1247,43 @@ pub fn start_server(
                 server.respond(rsp)?;
             }
             Command::StackTrace(_) => {
-                if let Some(vm_state) = vm_state.lock().unwrap().as_ref() {
-                    if let Some((line, column, original_filename)) =
-                        translate_pc_to_line_column(program_debug_info, vm_state.pc)
-                    {
-                        let source = sources
-                            .get(&original_filename)
-                            .map(|(source, _)| source.to_owned());
-
-                        let rsp = req.success(ResponseBody::StackTrace(StackTraceResponse {
-                            stack_frames: vec![StackFrame {
-                                id: DEFAULT_STACK_FRAME_ID,
-                                name: "main".to_string(),
-                                source,
-                                line,
-                                column,
-                                end_line: None,
-                                end_column: None,
-                                can_restart: None,
-                                instruction_pointer_reference: Some(format_instruction_ref(
-                                    vm_state.pc,
-                                )),
-                                module_id: None,
-                                presentation_hint: None,
-                            }],
-                            total_frames: Some(1),
-                        }));
-                        server.respond(rsp)?;
-
-                        continue;
-                    }
-                }
+                let stack_frames = vm_state
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map_or_else(Vec::new, |vm_state| {
+                        stack_trace(tx, vm_state, program_debug_info, sources)
+                    });
 
                 let rsp = req.success(ResponseBody::StackTrace(StackTraceResponse {
-                    stack_frames: vec![],
-                    total_frames: None,
+                    total_frames: Some(i64::try_from(stack_frames.len())?),
+                    stack_frames,
                 }));
                 server.respond(rsp)?;
             }
-            Command::StepBack(_) => todo!(),
+            Command::StepBack(_) => {
+                tx.send(DebuggerMessage::ReverseVm(
+                    ReverseCondition::UntilPreviousStep,
+                ))?;
+
+                let rsp = req.success(ResponseBody::StepBack);
+                server.respond(rsp)?;
+            }
             Command::StepIn(_) => todo!(),
             Command::StepInTargets(_) => todo!(),
-            Command::StepOut(_) => todo!(),
+            Command::StepOut(_) => {
+                let condition = vm_state
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map_or(ResumeCondition::UntilNextStep, |vm_state| {
+                        step_out_condition(tx, vm_state, program_debug_info)
+                    });
+                tx.send(DebuggerMessage::ResumeVm(condition))?;
+
+                let rsp = req.success(ResponseBody::StepOut);
+                server.respond(rsp)?;
+            }
             Command::Terminate(_) => todo!(),
             Command::TerminateThreads(_) => todo!(),
             Command::Threads => {
@@ -433,40 +1297,47 @@ pub fn start_server(
                 server.respond(rsp)?;
             }
             Command::Variables(ref args) => {
-                assert!(
-                    args.variables_reference == DEFAULT_VARIABLES_ID,
-                    "Only a single variable reference is supported at this time"
-                );
-
-                let rsp = if let Some(vm_state) = vm_state.lock().unwrap().as_ref() {
-                    req.success(ResponseBody::Variables(VariablesResponse {
-                        variables: vm_state
-                            .variables
-                            .iter()
-                            .map(|(name, value)| Variable {
-                                name: name.clone(),
-                                value: value.clone(),
-                                type_field: None,
-                                presentation_hint: None,
-                                evaluate_name: None,
-                                variables_reference: 0,
-                                named_variables: None,
-                                indexed_variables: None,
-                                memory_reference: None,
+                let variables =
+                    vm_state
+                        .lock()
+                        .unwrap()
+                        .as_ref()
+                        .map_or_else(Vec::new, |vm_state| {
+                            let scope =
+                                usize::try_from(args.variables_reference - DEFAULT_VARIABLES_ID)
+                                    .ok()
+                                    .and_then(|index| vm_state.scopes.get(index));
+                            let is_registers = args.variables_reference == DEFAULT_VARIABLES_ID;
+                            scope.map_or_else(Vec::new, |scope| {
+                                scope
+                                    .variables
+                                    .iter()
+                                    .map(|(name, value)| Variable {
+                                        name: name.clone(),
+                                        value: value.clone(),
+                                        type_field: None,
+                                        presentation_hint: None,
+                                        // Only registers can be used in expressions
+                                        evaluate_name: is_registers.then(|| name.clone()),
+                                        variables_reference: 0,
+                                        named_variables: None,
+                                        indexed_variables: None,
+                                        memory_reference: None,
+                                    })
+                                    .collect()
                             })
-                            .collect(),
-                    }))
-                } else {
-                    req.success(ResponseBody::Variables(VariablesResponse {
-                        variables: vec![],
-                    }))
+                        });
+                let rsp = req.success(ResponseBody::Variables(VariablesResponse { variables }));
+                server.respond(rsp)?;
+            }
+            Command::WriteMemory(ref args) => {
+                let rsp = match write_memory(tx, args) {
+                    Ok(body) => req.success(ResponseBody::WriteMemory(body)),
+                    Err(error) => req.error(&error.to_string()),
                 };
                 server.respond(rsp)?;
             }
-            Command::WriteMemory(_) => todo!(),
             Command::Cancel(_) => todo!(),
         }
     }
-
-    Ok(())
 }
@@ -0,0 +1,46 @@
+//!
+//! Looks up the labels from the program's symbol table so that addresses can be shown relative
+//! to them (e.g. `main+0x4`), which is easier to follow than a raw address.
+//!
+
+use super::memory::format_memory_reference;
+use super::types::SymbolTable;
+
+///
+/// Finds the symbol at or before an address, and how far the address is past it.
+///
+/// If more than one symbol is at the same address, the first one alphabetically is used.
+///
+#[must_use]
+pub fn nearest_symbol(symbols: &SymbolTable, address: u32) -> Option<(&str, u32)> {
+    symbols
+        .iter()
+        .filter(|(_, position)| **position <= address)
+        // max_by_key returns the last maximum, so reverse to prefer the first name
+        .rev()
+        .max_by_key(|(_, position)| **position)
+        .map(|(name, position)| (name.as_str(), address - position))
+}
+
+///
+/// Formats an address relative to the nearest symbol before it, or as a plain address if there
+/// isn't one.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use sirc_vm::debug_adapter::symbols::format_symbolised_address;
+///
+/// let symbols = BTreeMap::from([("main".to_string(), 0x10)]);
+/// assert_eq!("main", format_symbolised_address(&symbols, 0x10));
+/// assert_eq!("main+0x4", format_symbolised_address(&symbols, 0x14));
+/// assert_eq!("0x00000C", format_symbolised_address(&symbols, 0xC));
+/// ```
+///
+#[must_use]
+pub fn format_symbolised_address(symbols: &SymbolTable, address: u32) -> String {
+    match nearest_symbol(symbols, address) {
+        Some((name, 0)) => name.to_string(),
+        Some((name, offset)) => format!("{name}+0x{offset:X}"),
+        None => format_memory_reference(address),
+    }
+}
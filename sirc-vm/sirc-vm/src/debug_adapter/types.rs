@@ -3,12 +3,23 @@ use std::{
     sync::mpsc::{Receiver, Sender},
 };
 
-use dap::types::Breakpoint;
+use peripheral_bus::device::{BusAccessType, BusOperation};
+use peripheral_cpu::{
+    coprocessors::exception_unit::definitions::Faults,
+    registers::{ExceptionUnitRegisters, Registers},
+};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::expression::{Expression, ExpressionError, HitCondition, LogMessagePart};
 
 pub type ProgramPosition = u32;
 pub type InputPosition = usize;
+/// The debug info for each source file, keyed by the position of its first instruction. Included
+/// files can be in the middle of other files, so the ranges can overlap.
 pub type ObjectDebugInfoMap = BTreeMap<ProgramPosition, ObjectDebugInfo>;
+/// Symbol names (e.g. labels) to where they are in the program
+pub type SymbolTable = BTreeMap<String, ProgramPosition>;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
 pub struct ObjectDebugInfo {
@@ -19,47 +30,210 @@ pub struct ObjectDebugInfo {
     pub program_to_input_offset_mapping: BTreeMap<ProgramPosition, InputPosition>,
 }
 
+/// Optional extras on a breakpoint, which are checked in the VM thread when it is hit
+#[derive(Debug, Clone, Eq, PartialEq, Default, Hash)]
+pub struct BreakpointConditions {
+    /// Only pause if this evaluates to a non-zero value
+    pub condition: Option<Expression>,
+    /// Only pause if the number of times the breakpoint was hit (while the condition was met)
+    /// matches this
+    pub hit_condition: Option<HitCondition>,
+    /// Log this message instead of pausing (a "logpoint")
+    pub log_message: Option<Vec<LogMessagePart>>,
+}
+
+impl BreakpointConditions {
+    /// Symbols are resolved before the conditions are sent to the VM, which doesn't know about them
+    #[must_use]
+    pub fn resolve_symbols(self, symbols: &SymbolTable) -> Self {
+        Self {
+            condition: self
+                .condition
+                .map(|condition| condition.resolve_symbols(symbols)),
+            log_message: self.log_message.map(|parts| {
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        LogMessagePart::Expression(expression) => {
+                            LogMessagePart::Expression(expression.resolve_symbols(symbols))
+                        }
+                        LogMessagePart::Text(_) => part,
+                    })
+                    .collect()
+            }),
+            ..self
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Default, Hash)]
 pub struct BreakpointRef {
     pub breakpoint_id: i64,
     pub pc: u32,
+    pub conditions: BreakpointConditions,
+}
+
+/// The kind of bus access that triggers a data breakpoint
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DataAccessType {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl DataAccessType {
+    ///
+    /// Returns true if a bus operation should trigger a data breakpoint with this access type.
+    ///
+    /// ```
+    /// use peripheral_bus::device::BusOperation;
+    /// use sirc_vm::debug_adapter::types::DataAccessType;
+    ///
+    /// assert!(DataAccessType::Write.matches(BusOperation::Write));
+    /// assert!(!DataAccessType::Write.matches(BusOperation::Read));
+    /// assert!(DataAccessType::ReadWrite.matches(BusOperation::Read));
+    /// ```
+    ///
+    #[must_use]
+    pub const fn matches(self, op: BusOperation) -> bool {
+        matches!(
+            (self, op),
+            (Self::ReadWrite, _)
+                | (Self::Read, BusOperation::Read)
+                | (Self::Write, BusOperation::Write)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DataBreakpointRef {
+    pub breakpoint_id: i64,
+    /// The address of the word to watch
+    pub address: u32,
+    pub access_type: DataAccessType,
+    pub conditions: BreakpointConditions,
+}
+
+/// A group of variables that are shown together in the debugger (e.g. the CPU registers)
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct VariableScope {
+    pub name: String,
+    /// Names and values, in the order they should be shown
+    pub variables: Vec<(String, String)>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 pub struct VmState {
     pub pc: u32,
-    // btree map to keep variables in some kind of order by name
-    // maybe could sort them some other way
-    pub variables: BTreeMap<String, String>,
+    /// The CPU registers are always the first scope
+    pub scopes: Vec<VariableScope>,
+    /// The raw register values, used to unwind the call stack
+    pub registers: Registers,
+    pub eu_registers: ExceptionUnitRegisters,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
 pub struct ProgramDebugInfo {
     pub debug_info_map: ObjectDebugInfoMap,
+    pub symbols: SymbolTable,
 }
 
 #[derive(Debug)]
 pub enum ResumeCondition {
     None,
     UntilNextStep,
+    /// Run until the PC reaches the return address of a frame. The stack pointer has to be at
+    /// least where it is now so that a recursive call to the same place doesn't count.
+    UntilReturn {
+        address: u32,
+        stack_pointer: u32,
+    },
 }
 
+#[derive(Debug)]
+pub enum ReverseCondition {
+    /// Run backwards until a breakpoint (or the start of the history)
+    None,
+    UntilPreviousStep,
+}
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum MemoryAccessError {
+    #[error("Memory can only be accessed while the VM is paused")]
+    VmRunning,
+    #[error("The VM is not responding")]
+    VmDisconnected,
+    #[error("No segment mapped to address 0x{address:06X}")]
+    Unmapped { address: u32 },
+    #[error("Segment {label} at address 0x{address:06X} is read-only")]
+    ReadOnly { address: u32, label: String },
+}
+
+pub type MemoryReadResult = Result<Vec<u16>, MemoryAccessError>;
+pub type MemoryWriteResult = Result<(), MemoryAccessError>;
+pub type EvaluateResult = Result<u32, ExpressionError>;
+/// The value that was stored and the state of the VM after it was stored
+pub type AssignResult = Result<(u32, VmState), ExpressionError>;
+
 #[derive(Debug)]
 pub enum DebuggerMessage {
     UpdateBreakpoints(HashSet<BreakpointRef>),
+    UpdateDataBreakpoints(Vec<DataBreakpointRef>),
     PauseVm,
     ResumeVm(ResumeCondition),
+    ReverseVm(ReverseCondition),
+    /// Reads up to `count` words, stopping early if there is no segment mapped to an address
+    ReadMemory {
+        address: u32,
+        count: u32,
+        reply: Sender<MemoryReadResult>,
+    },
+    WriteMemory {
+        address: u32,
+        words: Vec<u16>,
+        reply: Sender<MemoryWriteResult>,
+    },
+    Evaluate {
+        expression: Expression,
+        reply: Sender<EvaluateResult>,
+    },
+    /// Stores the value of an expression in a register or memory (e.g. `r1` or `[a + 4]`)
+    Assign {
+        target: Expression,
+        value: Expression,
+        reply: Sender<AssignResult>,
+    },
+    /// The fault classes that the VM should pause on when they are raised
+    UpdateExceptionBreakpoints(Vec<Faults>),
     Disconnect,
 }
 
+/// A fault raised by the CPU, as recorded in the fault metadata link register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultDetails {
+    pub fault: Faults,
+    /// If `fault` is a double fault, the fault that was being handled when it happened
+    pub original_fault: Option<Faults>,
+    pub address: u32,
+    pub bus_access_type: BusAccessType,
+}
+
 pub enum VmPauseReason {
     Init,
     Breakpoint(BreakpointRef),
+    /// A watched address was accessed by the previous instruction
+    DataBreakpoint(DataBreakpointRef),
+    /// A fault that the debugger asked to break on was raised by the previous instruction
+    Fault(FaultDetails),
     Step,
+    /// Stepped back as far as the execution history goes
+    StartOfHistory,
 }
 
 pub enum VmMessage {
-    Paused(VmPauseReason, VmState),
+    Paused(VmPauseReason, Box<VmState>),
+    /// Output from a logpoint (or a breakpoint condition that could not be evaluated)
+    Log(String),
 }
 
 #[derive(Debug)]
@@ -81,5 +255,6 @@ pub struct ServerChannels {
 }
 
 pub struct ServerState {
-    pub breakpoints: Vec<Breakpoint>,
+    pub breakpoints: Vec<BreakpointRef>,
+    pub instruction_breakpoints: Vec<BreakpointRef>,
 }
@@ -1,16 +1,71 @@
-use std::{collections::BTreeMap, sync::mpsc::TryRecvError};
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    sync::mpsc::{Sender, TryRecvError},
+};
 
+use super::debug_adapter::expression::{
+    evaluate, format_log_message, register_value, set_register_value, EvaluationContext,
+    Expression, ExpressionError,
+};
 use super::debug_adapter::types::{
-    DebuggerMessage, ResumeCondition, VmMessage, VmPauseReason, VmState,
+    AssignResult, BreakpointConditions, DebuggerMessage, EvaluateResult, FaultDetails,
+    MemoryAccessError, MemoryWriteResult, ResumeCondition, ReverseCondition, VariableScope,
+    VmMessage, VmPauseReason, VmState,
+};
+use super::utils::cpu_from_bus::{cpu_from_bus, cpu_from_bus_mut};
+use super::{DebugState, StepOutTarget};
+use log::warn;
+use num_traits::FromPrimitive;
+use peripheral_bus::{
+    device::{BusAccessType, BusAssertions},
+    BusPeripheral,
+};
+use peripheral_cpu::{
+    coprocessors::{
+        exception_unit::definitions::Faults,
+        processing_unit::{disassembly::disassemble_instruction, encoding::decode_instruction},
+        shared::ExecutionPhase,
+    },
+    registers::{
+        register_index_to_name, sr_bit_is_set_value, ExceptionUnitRegisters,
+        FullAddressRegisterAccess, Registers, StatusRegisterFields, ADDRESS_MASK,
+    },
+    CpuPeripheral, BUS_ACCESS_TYPE_LENGTH, BUS_ACCESS_TYPE_MASK, CURRENT_FAULT_LENGTH,
+    CURRENT_FAULT_MASK, DOUBLE_FAULT_FLAG_LENGTH, DOUBLE_FAULT_FLAG_MASK,
+    FAULT_METADATA_LINK_REGISTER_INDEX, PREVIOUS_FAULT_LENGTH, PREVIOUS_FAULT_MASK,
 };
-use super::{utils::cpu_from_bus::cpu_from_bus, DebugState};
-use peripheral_bus::BusPeripheral;
-use peripheral_cpu::registers::FullAddressRegisterAccess;
+
+/// Evaluates breakpoint conditions against the state of the VM
+struct VmEvaluationContext<'a> {
+    registers: Registers,
+    bus_peripheral: &'a mut BusPeripheral,
+}
+
+impl EvaluationContext for VmEvaluationContext<'_> {
+    fn identifier(&self, name: &str) -> Option<u32> {
+        register_value(&self.registers, name)
+    }
+
+    fn read_memory(&mut self, address: u32) -> Option<u16> {
+        read_memory(self.bus_peripheral, address, 1)
+            .first()
+            .copied()
+    }
+}
 
 pub fn handle_debug_message(message: DebuggerMessage, debug_state: &mut DebugState) {
     match message {
         DebuggerMessage::UpdateBreakpoints(breakpoints) => {
             debug_state.breakpoints = breakpoints;
+            prune_hit_counts(debug_state);
+        }
+        DebuggerMessage::UpdateDataBreakpoints(data_breakpoints) => {
+            debug_state.data_breakpoints = data_breakpoints;
+            prune_hit_counts(debug_state);
+        }
+        DebuggerMessage::UpdateExceptionBreakpoints(faults) => {
+            debug_state.exception_breakpoints = faults;
         }
         DebuggerMessage::PauseVm => {
             debug_state.is_stepping = true;
@@ -21,42 +76,386 @@ pub fn handle_debug_message(message: DebuggerMessage, debug_state: &mut DebugSta
                 ResumeCondition::UntilNextStep => {
                     debug_state.is_stepping = true;
                 }
+                ResumeCondition::UntilReturn {
+                    address,
+                    stack_pointer,
+                } => {
+                    debug_state.step_out_target = Some(StepOutTarget {
+                        address,
+                        stack_pointer,
+                    });
+                }
                 ResumeCondition::None => {}
             }
         }
-        DebuggerMessage::Disconnect => debug_state.disconnected = true,
+        DebuggerMessage::ReverseVm(condition) => {
+            // The VM can only be rewound while it is paused (see `rewind`)
+            warn!("Ignoring request to reverse ({condition:?}) while the VM is running");
+        }
+        // Memory can only be accessed while the VM is paused (see `read_memory`/`write_memory`)
+        DebuggerMessage::ReadMemory { reply, .. } => {
+            // If the debugger has stopped listening there is no one to tell
+            let _ = reply.send(Err(MemoryAccessError::VmRunning));
+        }
+        DebuggerMessage::WriteMemory { reply, .. } => {
+            let _ = reply.send(Err(MemoryAccessError::VmRunning));
+        }
+        DebuggerMessage::Evaluate { reply, .. } => {
+            let _ = reply.send(Err(MemoryAccessError::VmRunning.into()));
+        }
+        DebuggerMessage::Assign { reply, .. } => {
+            let _ = reply.send(Err(MemoryAccessError::VmRunning.into()));
+        }
+        DebuggerMessage::Disconnect => detach(debug_state),
     }
 }
 
-pub fn yield_to_debugger(bus_peripheral: &mut BusPeripheral, debug_state: &mut DebugState) {
-    let cpu = cpu_from_bus(bus_peripheral);
-    let pc = cpu.registers.get_full_pc_address();
-    let capture_vm_state = || {
-        VmState {
-            pc,
-            // TODO: Expose more CPU state to debugger
-            // category=Debugging
-            // There is a heap more state than just registers that we could expose to help debug
-            variables: BTreeMap::from([
-                ("sr".to_string(), format!("0x{:X}", cpu.registers.sr)),
-                ("r1".to_string(), format!("0x{:X}", cpu.registers.r1)),
-                ("r2".to_string(), format!("0x{:X}", cpu.registers.r2)),
-                ("r3".to_string(), format!("0x{:X}", cpu.registers.r3)),
-                ("r4".to_string(), format!("0x{:X}", cpu.registers.r4)),
-                ("r5".to_string(), format!("0x{:X}", cpu.registers.r5)),
-                ("r6".to_string(), format!("0x{:X}", cpu.registers.r6)),
-                ("r7".to_string(), format!("0x{:X}", cpu.registers.r7)),
-                ("lh".to_string(), format!("0x{:X}", cpu.registers.lh)),
-                ("ll".to_string(), format!("0x{:X}", cpu.registers.ll)),
-                ("ah".to_string(), format!("0x{:X}", cpu.registers.ah)),
-                ("al".to_string(), format!("0x{:X}", cpu.registers.al)),
-                ("sh".to_string(), format!("0x{:X}", cpu.registers.sh)),
-                ("sl".to_string(), format!("0x{:X}", cpu.registers.sl)),
-                ("ph".to_string(), format!("0x{:X}", cpu.registers.ph)),
-                ("pl".to_string(), format!("0x{:X}", cpu.registers.pl)),
-            ]),
-        }
+///
+/// Forgets everything the debugger set up and lets the VM carry on running, so that a debugger
+/// can attach again later and start from a clean slate.
+///
+fn detach(debug_state: &mut DebugState) {
+    debug_state.breakpoints.clear();
+    debug_state.data_breakpoints.clear();
+    debug_state.triggered_data_breakpoint = None;
+    debug_state.exception_breakpoints.clear();
+    debug_state.triggered_fault = None;
+    debug_state.hit_counts.clear();
+    debug_state.should_pause_for_init = false;
+    debug_state.is_stepping = false;
+    debug_state.step_out_target = None;
+    debug_state.paused = false;
+}
+
+/// Forgets the hit counts of breakpoints that have been removed
+fn prune_hit_counts(debug_state: &mut DebugState) {
+    let DebugState {
+        breakpoints,
+        data_breakpoints,
+        hit_counts,
+        ..
+    } = debug_state;
+    hit_counts.retain(|breakpoint_id, _| {
+        breakpoints
+            .iter()
+            .any(|b| b.breakpoint_id == *breakpoint_id)
+            || data_breakpoints
+                .iter()
+                .any(|b| b.breakpoint_id == *breakpoint_id)
+    });
+}
+
+fn register_variables(registers: &Registers) -> Vec<(String, String)> {
+    (0..=u8::MAX)
+        .map_while(|index| {
+            register_index_to_name(index)
+                .map(|name| (name.to_string(), format!("0x{:X}", registers[index])))
+        })
+        .collect()
+}
+
+fn status_flag_variables(sr: u16) -> Vec<(String, String)> {
+    (0..u16::BITS)
+        .filter_map(|bit| StatusRegisterFields::from_u16(1 << bit))
+        .map(|field| {
+            (
+                format!("{field:?}"),
+                sr_bit_is_set_value(field, sr).to_string(),
+            )
+        })
+        .collect()
+}
+
+fn exception_unit_variables(eu_registers: &ExceptionUnitRegisters) -> Vec<(String, String)> {
+    let mut variables = vec![
+        (
+            "current_exception_level".to_string(),
+            eu_registers.current_exception_level.to_string(),
+        ),
+        (
+            "pending_hardware_exceptions".to_string(),
+            format!("0b{:08b}", eu_registers.pending_hardware_exceptions),
+        ),
+        (
+            "pending_fault".to_string(),
+            format!("{:?}", eu_registers.pending_fault),
+        ),
+        (
+            "waiting_for_exception".to_string(),
+            eu_registers.waiting_for_exception.to_string(),
+        ),
+        (
+            "cpu_halted".to_string(),
+            eu_registers.cpu_halted.to_string(),
+        ),
+    ];
+    // Exception levels start at 1 (0 is no exception)
+    variables.extend(
+        eu_registers.link_registers[..FAULT_METADATA_LINK_REGISTER_INDEX]
+            .iter()
+            .zip(1..)
+            .map(|(link_register, level): (_, u8)| {
+                (
+                    format!("link_register_{level}"),
+                    format!(
+                        "return_address: 0x{:06X}, return_sr: 0x{:X}, saved_level: {}",
+                        link_register.return_address,
+                        link_register.return_status_register,
+                        link_register.saved_exception_level
+                    ),
+                )
+            }),
+    );
+    variables
+}
+
+/// The fault metadata register, decoded without assuming that it holds valid faults
+struct CheckedFaultMetadata {
+    bus_access_type: BusAccessType,
+    double_fault: bool,
+    /// The raw value if it isn't a valid fault
+    fault: Result<Faults, u16>,
+    original_fault: Result<Faults, u16>,
+}
+
+///
+/// Decodes the fault metadata register like `decode_fault_metadata_register`, but doesn't panic
+/// if a fault field isn't a valid fault.
+///
+/// The CPU only ever writes valid faults, but a program can write anything to the register
+/// (e.g. with ETTR), and the debugger shouldn't bring down the VM when it does.
+///
+fn decode_fault_metadata(value: u16) -> CheckedFaultMetadata {
+    let decode_fault = |mask: u16, shift: u16| {
+        let raw = (value & mask) >> shift;
+        Faults::from_u16(raw).ok_or(raw)
+    };
+    CheckedFaultMetadata {
+        bus_access_type: BusAccessType::from(
+            ((value & BUS_ACCESS_TYPE_MASK) >> BUS_ACCESS_TYPE_LENGTH) as u8,
+        ),
+        double_fault: ((value & DOUBLE_FAULT_FLAG_MASK) >> DOUBLE_FAULT_FLAG_LENGTH) == 0x1,
+        fault: decode_fault(CURRENT_FAULT_MASK, CURRENT_FAULT_LENGTH),
+        original_fault: decode_fault(PREVIOUS_FAULT_MASK, PREVIOUS_FAULT_LENGTH),
+    }
+}
+
+fn format_fault(fault: Result<Faults, u16>) -> String {
+    fault.map_or_else(
+        |raw| format!("Unknown(0x{raw:X})"),
+        |fault| format!("{fault:?}"),
+    )
+}
+
+fn fault_variables(eu_registers: &ExceptionUnitRegisters) -> Vec<(String, String)> {
+    let metadata = eu_registers.link_registers[FAULT_METADATA_LINK_REGISTER_INDEX];
+    // The metadata register is only written when a fault is raised, and there is no fault with
+    // the value zero
+    if metadata.return_status_register == 0 {
+        return vec![("fault".to_string(), "None".to_string())];
+    }
+    let fault_metadata = decode_fault_metadata(metadata.return_status_register);
+    vec![
+        ("fault".to_string(), format_fault(fault_metadata.fault)),
+        (
+            "original_fault".to_string(),
+            format_fault(fault_metadata.original_fault),
+        ),
+        (
+            "double_fault".to_string(),
+            fault_metadata.double_fault.to_string(),
+        ),
+        (
+            "bus_access_type".to_string(),
+            format!("{:?}", fault_metadata.bus_access_type),
+        ),
+        (
+            "address".to_string(),
+            format!("0x{:06X}", metadata.return_address),
+        ),
+    ]
+}
+
+fn pipeline_variables(cpu: &CpuPeripheral) -> Vec<(String, String)> {
+    let instruction = cpu.processing_unit_executor.instruction;
+    vec![
+        (
+            "phase".to_string(),
+            ExecutionPhase::from_u8(cpu.phase)
+                .map_or_else(|| cpu.phase.to_string(), |phase| format!("{phase:?}")),
+        ),
+        // The VM pauses between instructions, so this is the instruction that just finished
+        (
+            "instruction_register".to_string(),
+            format!("0x{instruction:08X}"),
+        ),
+        (
+            "decoded_instruction".to_string(),
+            disassemble_instruction(&decode_instruction(instruction.to_be_bytes())),
+        ),
+        (
+            "pending_coprocessor_command".to_string(),
+            format!("0x{:04X}", cpu.registers.pending_coprocessor_command),
+        ),
+        (
+            "cause_register".to_string(),
+            format!("0x{:04X}", cpu.cause_register_value),
+        ),
+    ]
+}
+
+fn capture_vm_state(cpu: &CpuPeripheral) -> VmState {
+    let scope = |name: &str, variables| VariableScope {
+        name: name.to_string(),
+        variables,
     };
+    VmState {
+        pc: cpu.registers.get_full_pc_address(),
+        scopes: vec![
+            scope("CPU Registers", register_variables(&cpu.registers)),
+            scope("Status Flags", status_flag_variables(cpu.registers.sr)),
+            scope(
+                "Exception Unit",
+                exception_unit_variables(&cpu.eu_registers),
+            ),
+            scope("Last Fault", fault_variables(&cpu.eu_registers)),
+            scope("Pipeline", pipeline_variables(cpu)),
+        ],
+        registers: cpu.registers,
+        eu_registers: cpu.eu_registers,
+    }
+}
+
+///
+/// Checks whether a bus access that completed in the last poll hit a data breakpoint.
+///
+/// The VM doesn't pause straight away, but at the start of the next instruction (see
+/// `yield_to_debugger`) so that it is always paused on an instruction boundary. This means that
+/// the access has already happened when the debugger is told about it.
+///
+pub fn check_data_breakpoints(debug_state: &mut DebugState, bus_assertions: &BusAssertions) {
+    // Instruction fetches are covered by instruction breakpoints
+    let data_access_completed = bus_assertions.bus_access_strobe
+        && bus_assertions.bus_acknowledge
+        && bus_assertions.bus_access_type != BusAccessType::InstructionFetch;
+    if !data_access_completed || debug_state.triggered_data_breakpoint.is_some() {
+        return;
+    }
+    debug_state.triggered_data_breakpoint = debug_state
+        .data_breakpoints
+        .iter()
+        .find(|b| b.address == bus_assertions.address && b.access_type.matches(bus_assertions.op))
+        .cloned();
+}
+
+///
+/// Checks whether the CPU raised a fault in the last poll that the debugger wants to break on.
+///
+/// Like data breakpoints, the VM pauses at the start of the next instruction (see
+/// `yield_to_debugger`). The exception unit only dispatches the fault at an instruction boundary,
+/// so this is before the handler runs.
+///
+pub fn check_exception_breakpoints(
+    bus_peripheral: &mut BusPeripheral,
+    debug_state: &mut DebugState,
+) {
+    // Avoids looking at the CPU on every poll when no one is interested
+    if debug_state.exception_breakpoints.is_empty() {
+        return;
+    }
+    let eu_registers = &cpu_from_bus(bus_peripheral).eu_registers;
+    let pending_fault = eu_registers.pending_fault;
+    let previous_pending_fault = debug_state.previous_pending_fault;
+    debug_state.previous_pending_fault = pending_fault;
+
+    let Some(fault) = pending_fault else {
+        return;
+    };
+    if Some(fault) == previous_pending_fault
+        || debug_state.triggered_fault.is_some()
+        || !debug_state.exception_breakpoints.contains(&fault)
+    {
+        return;
+    }
+    debug_state.triggered_fault = Some(fault_details(eu_registers, fault));
+}
+
+fn fault_details(eu_registers: &ExceptionUnitRegisters, fault: Faults) -> FaultDetails {
+    let metadata = eu_registers.link_registers[FAULT_METADATA_LINK_REGISTER_INDEX];
+    // The metadata register is written whenever a fault is raised, but guard against decoding
+    // an empty one anyway (see `fault_variables`)
+    if metadata.return_status_register == 0 {
+        return FaultDetails {
+            fault,
+            original_fault: None,
+            address: metadata.return_address,
+            bus_access_type: BusAccessType::default(),
+        };
+    }
+    let fault_metadata = decode_fault_metadata(metadata.return_status_register);
+    FaultDetails {
+        fault,
+        original_fault: fault_metadata
+            .double_fault
+            .then_some(fault_metadata.original_fault.ok())
+            .flatten(),
+        address: metadata.return_address,
+        bus_access_type: fault_metadata.bus_access_type,
+    }
+}
+
+///
+/// Checks the conditions of a breakpoint that was hit, and returns true if the VM should pause.
+///
+/// Logpoints never pause, their message is sent to the debugger instead. If the condition can't
+/// be evaluated (e.g. it reads unmapped memory) the VM pauses so that the problem isn't missed.
+///
+pub fn should_pause_on_breakpoint<S: BuildHasher>(
+    tx: &Sender<VmMessage>,
+    hit_counts: &mut HashMap<i64, u64, S>,
+    breakpoint_id: i64,
+    conditions: &BreakpointConditions,
+    context: &mut impl EvaluationContext,
+) -> bool {
+    match conditions
+        .condition
+        .as_ref()
+        .map_or(Ok(1), |condition| evaluate(condition, context))
+    {
+        Ok(0) => return false,
+        Ok(_) => {}
+        Err(error) => {
+            tx.send(VmMessage::Log(format!(
+                "Could not evaluate the condition of breakpoint {breakpoint_id}: {error}"
+            )))
+            .unwrap();
+            return true;
+        }
+    }
+
+    let hit_count = hit_counts.entry(breakpoint_id).or_default();
+    *hit_count += 1;
+    if conditions
+        .hit_condition
+        .is_some_and(|hit_condition| !hit_condition.is_met(*hit_count))
+    {
+        return false;
+    }
+
+    conditions.log_message.as_ref().is_none_or(|log_message| {
+        tx.send(VmMessage::Log(format_log_message(log_message, context)))
+            .unwrap();
+        false
+    })
+}
+
+pub fn yield_to_debugger(
+    bus_peripheral: &mut BusPeripheral,
+    debug_state: &mut DebugState,
+    bus_assertions: &mut BusAssertions,
+) {
+    let registers = cpu_from_bus(bus_peripheral).registers;
+    let pc = registers.get_full_pc_address();
 
     // Check for pending messages (e.g. update breakpoints)
     loop {
@@ -75,14 +474,69 @@ pub fn yield_to_debugger(bus_peripheral: &mut BusPeripheral, debug_state: &mut D
         }
     }
 
+    // Breakpoint conditions are evaluated here rather than in the debug adapter so that a
+    // breakpoint in a hot loop doesn't need a round trip for every hit
+    let mut context = VmEvaluationContext {
+        registers,
+        bus_peripheral,
+    };
+    let triggered_data_breakpoint = debug_state.triggered_data_breakpoint.take().filter(|b| {
+        should_pause_on_breakpoint(
+            &debug_state.channels.tx,
+            &mut debug_state.hit_counts,
+            b.breakpoint_id,
+            &b.conditions,
+            &mut context,
+        )
+    });
+    // Every breakpoint at the PC is checked so that all logpoints are logged
+    let triggered_breakpoint =
+        debug_state
+            .breakpoints
+            .iter()
+            .filter(|b| b.pc == pc)
+            .fold(None, |triggered, b| {
+                let should_pause = should_pause_on_breakpoint(
+                    &debug_state.channels.tx,
+                    &mut debug_state.hit_counts,
+                    b.breakpoint_id,
+                    &b.conditions,
+                    &mut context,
+                );
+                triggered.or_else(|| should_pause.then(|| b.clone()))
+            });
+    let cpu = cpu_from_bus(bus_peripheral);
+
     // Check if any conditions are met
-    if let Some(breakpoint) = debug_state.breakpoints.iter().find(|b| b.pc == pc) {
+    if let Some(fault) = debug_state.triggered_fault.take() {
+        debug_state
+            .channels
+            .tx
+            .send(VmMessage::Paused(
+                VmPauseReason::Fault(fault),
+                Box::new(capture_vm_state(cpu)),
+            ))
+            .unwrap();
+        debug_state.paused = true;
+        debug_state.is_stepping = false;
+    } else if let Some(data_breakpoint) = triggered_data_breakpoint {
+        debug_state
+            .channels
+            .tx
+            .send(VmMessage::Paused(
+                VmPauseReason::DataBreakpoint(data_breakpoint),
+                Box::new(capture_vm_state(cpu)),
+            ))
+            .unwrap();
+        debug_state.paused = true;
+        debug_state.is_stepping = false;
+    } else if let Some(breakpoint) = triggered_breakpoint {
         debug_state
             .channels
             .tx
             .send(VmMessage::Paused(
-                VmPauseReason::Breakpoint(breakpoint.clone()),
-                capture_vm_state(),
+                VmPauseReason::Breakpoint(breakpoint),
+                Box::new(capture_vm_state(cpu)),
             ))
             .unwrap();
         debug_state.paused = true;
@@ -90,27 +544,214 @@ pub fn yield_to_debugger(bus_peripheral: &mut BusPeripheral, debug_state: &mut D
         debug_state
             .channels
             .tx
-            .send(VmMessage::Paused(VmPauseReason::Init, capture_vm_state()))
+            .send(VmMessage::Paused(
+                VmPauseReason::Init,
+                Box::new(capture_vm_state(cpu)),
+            ))
             .unwrap();
         debug_state.paused = true;
         debug_state.should_pause_for_init = false;
-    } else if debug_state.is_stepping {
+    } else if debug_state.is_stepping
+        || debug_state.step_out_target.is_some_and(|target| {
+            target.address == pc && registers.get_full_sp_address() >= target.stack_pointer
+        })
+    {
         debug_state
             .channels
             .tx
-            .send(VmMessage::Paused(VmPauseReason::Step, capture_vm_state()))
+            .send(VmMessage::Paused(
+                VmPauseReason::Step,
+                Box::new(capture_vm_state(cpu)),
+            ))
             .unwrap();
         debug_state.paused = true;
         debug_state.is_stepping = false;
     }
+    if debug_state.paused {
+        // Stepping out is abandoned if the VM pauses for anything else on the way
+        debug_state.step_out_target = None;
+    }
 
     // Block waiting for debug adapter to resume
     while !debug_state.disconnected && debug_state.paused {
-        if let Ok(data) = debug_state.channels.rx.recv() {
-            handle_debug_message(data, debug_state);
-        } else {
-            debug_state.disconnected = true;
-            return;
+        match debug_state.channels.rx.recv() {
+            Ok(DebuggerMessage::ReverseVm(condition)) => {
+                rewind(bus_peripheral, debug_state, bus_assertions, &condition);
+            }
+            Ok(DebuggerMessage::ReadMemory {
+                address,
+                count,
+                reply,
+            }) => {
+                let _ = reply.send(Ok(read_memory(bus_peripheral, address, count)));
+            }
+            Ok(DebuggerMessage::WriteMemory {
+                address,
+                words,
+                reply,
+            }) => {
+                let _ = reply.send(write_memory(bus_peripheral, address, &words));
+            }
+            Ok(DebuggerMessage::Evaluate { expression, reply }) => {
+                let _ = reply.send(evaluate_expression(bus_peripheral, &expression));
+            }
+            Ok(DebuggerMessage::Assign {
+                target,
+                value,
+                reply,
+            }) => {
+                let _ = reply.send(assign(bus_peripheral, &target, &value));
+            }
+            Ok(data) => handle_debug_message(data, debug_state),
+            Err(_) => {
+                debug_state.disconnected = true;
+                return;
+            }
         }
     }
 }
+
+///
+/// Steps the VM backwards through the execution history until the condition is met or
+/// there is no more history, and then reports the new state to the debugger.
+///
+/// The VM stays paused, so it will pick up from the rewound state when it is resumed.
+///
+/// Breakpoint conditions are checked when reversing, but hit conditions aren't (the hit counts
+/// only go forwards) and logpoints are skipped.
+///
+fn rewind(
+    bus_peripheral: &mut BusPeripheral,
+    debug_state: &mut DebugState,
+    bus_assertions: &mut BusAssertions,
+    condition: &ReverseCondition,
+) {
+    let reason = loop {
+        let Some(rewound_bus_assertions) = debug_state.history.step_back(bus_peripheral) else {
+            break VmPauseReason::StartOfHistory;
+        };
+        *bus_assertions = rewound_bus_assertions;
+
+        let registers = cpu_from_bus(bus_peripheral).registers;
+        let pc = registers.get_full_pc_address();
+        match condition {
+            ReverseCondition::UntilPreviousStep => break VmPauseReason::Step,
+            ReverseCondition::None => {
+                let mut context = VmEvaluationContext {
+                    registers,
+                    bus_peripheral,
+                };
+                if let Some(breakpoint) = debug_state.breakpoints.iter().find(|b| {
+                    b.pc == pc
+                        && b.conditions.log_message.is_none()
+                        && b.conditions
+                            .condition
+                            .as_ref()
+                            .is_none_or(|condition| evaluate(condition, &mut context) != Ok(0))
+                }) {
+                    break VmPauseReason::Breakpoint(breakpoint.clone());
+                }
+            }
+        }
+    };
+
+    debug_state
+        .channels
+        .tx
+        .send(VmMessage::Paused(
+            reason,
+            Box::new(capture_vm_state(cpu_from_bus(bus_peripheral))),
+        ))
+        .unwrap();
+}
+
+///
+/// Reads memory on behalf of the debugger, stopping at the first address that has no segment
+/// mapped to it. Device registers are read the same way the CPU would read them.
+///
+fn read_memory(bus_peripheral: &mut BusPeripheral, address: u32, count: u32) -> Vec<u16> {
+    (address..address.saturating_add(count))
+        .map_while(|word_address| {
+            bus_peripheral
+                .get_segment_for_address(word_address)
+                .is_some()
+                .then(|| bus_peripheral.read_address(word_address))
+        })
+        .collect()
+}
+
+///
+/// Writes memory on behalf of the debugger. Nothing is written unless every address is mapped
+/// to a writable segment.
+///
+fn write_memory(
+    bus_peripheral: &mut BusPeripheral,
+    address: u32,
+    words: &[u16],
+) -> MemoryWriteResult {
+    let addresses = address..address.saturating_add(u32::try_from(words.len()).unwrap_or(u32::MAX));
+    for word_address in addresses.clone() {
+        match bus_peripheral.get_segment_for_address(word_address) {
+            None => {
+                return Err(MemoryAccessError::Unmapped {
+                    address: word_address,
+                })
+            }
+            Some(segment) if !segment.writable => {
+                return Err(MemoryAccessError::ReadOnly {
+                    address: word_address,
+                    label: segment.label.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (word_address, word) in addresses.zip(words) {
+        bus_peripheral.write_address(word_address, *word);
+    }
+    Ok(())
+}
+
+fn evaluate_expression(
+    bus_peripheral: &mut BusPeripheral,
+    expression: &Expression,
+) -> EvaluateResult {
+    let registers = cpu_from_bus(bus_peripheral).registers;
+    evaluate(
+        expression,
+        &mut VmEvaluationContext {
+            registers,
+            bus_peripheral,
+        },
+    )
+}
+
+///
+/// Stores the value of an expression in a register or a word of memory on behalf of the
+/// debugger (e.g. when a register is edited in the variables pane).
+///
+/// Values are truncated to fit the target, like they would be if the CPU stored them.
+///
+fn assign(
+    bus_peripheral: &mut BusPeripheral,
+    target: &Expression,
+    value: &Expression,
+) -> AssignResult {
+    let value = evaluate_expression(bus_peripheral, value)?;
+    let stored = match target {
+        Expression::Identifier(name) if target.is_assignable() => {
+            let registers = &mut cpu_from_bus_mut(bus_peripheral).registers;
+            set_register_value(registers, name, value)
+                .ok_or_else(|| ExpressionError::UnknownIdentifier(name.clone()))?
+        }
+        Expression::Dereference(address) => {
+            let address = evaluate_expression(bus_peripheral, address)? & ADDRESS_MASK;
+            let word = (value & 0xFFFF) as u16;
+            write_memory(bus_peripheral, address, &[word])?;
+            u32::from(word)
+        }
+        // The debug adapter checks this first so that the error can quote the expression
+        _ => return Err(ExpressionError::NotAssignable(format!("{target:?}"))),
+    };
+    Ok((stored, capture_vm_state(cpu_from_bus(bus_peripheral))))
+}
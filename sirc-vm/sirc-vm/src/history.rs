@@ -0,0 +1,140 @@
+//!
+//! A bounded record of the most recent instructions executed by the VM so that the debugger can
+//! step backwards.
+//!
+//! Each entry holds the state of the CPU at the start of an instruction along with the memory
+//! writes that the instruction made (captured at the bus). Stepping back restores the CPU and
+//! undoes the writes. Other devices (e.g. video timing) are not rewound.
+//!
+
+use std::collections::VecDeque;
+
+use peripheral_bus::{device::BusAssertions, write_journal::JournalledWrite, BusPeripheral};
+use peripheral_cpu::CpuCheckpoint;
+
+use crate::utils::cpu_from_bus::{cpu_from_bus, cpu_from_bus_mut};
+
+/// The number of instructions that can be stepped back through
+pub const HISTORY_LENGTH: usize = 10_000;
+
+#[derive(Debug)]
+struct HistoryEntry {
+    /// The state of the CPU (which is always the bus master in the VM)
+    cpu_checkpoint: CpuCheckpoint,
+    /// The assertions that were fed into the next poll of the bus
+    bus_assertions: BusAssertions,
+    /// Filled in when the next instruction starts
+    writes: Vec<JournalledWrite>,
+}
+
+#[derive(Debug)]
+pub struct ExecutionHistory {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    instruction_fetch_in_flight: bool,
+}
+
+impl Default for ExecutionHistory {
+    fn default() -> Self {
+        Self::new(HISTORY_LENGTH)
+    }
+}
+
+impl ExecutionHistory {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            instruction_fetch_in_flight: false,
+        }
+    }
+
+    /// The number of instructions that have been recorded (including the current one)
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///
+    /// Should be called after every poll of the bus with its output. Records the start of each
+    /// instruction and returns true when it does.
+    ///
+    /// The CPU only asserts `instruction_sync` on the poll that starts fetching an instruction,
+    /// but slow devices might still be in the middle of the fetch at that point. The instruction
+    /// is recorded when the fetch is acknowledged instead, so that the devices are in sync with
+    /// the CPU after stepping back.
+    ///
+    pub fn record_poll(
+        &mut self,
+        bus_peripheral: &mut BusPeripheral,
+        bus_assertions: BusAssertions,
+    ) -> bool {
+        if bus_assertions.instruction_sync {
+            self.instruction_fetch_in_flight = true;
+        }
+        if !self.instruction_fetch_in_flight {
+            return false;
+        }
+        if bus_assertions.bus_error || bus_assertions.bus_protection_error {
+            // The fetch faulted so the instruction never started
+            self.instruction_fetch_in_flight = false;
+            return false;
+        }
+        if !bus_assertions.bus_acknowledge {
+            return false;
+        }
+        self.instruction_fetch_in_flight = false;
+        self.record_instruction_start(bus_peripheral, bus_assertions);
+        true
+    }
+
+    /// Records the start of an instruction. The oldest entry is dropped if the history is full.
+    fn record_instruction_start(
+        &mut self,
+        bus_peripheral: &mut BusPeripheral,
+        bus_assertions: BusAssertions,
+    ) {
+        bus_peripheral.enable_write_journal();
+        let writes = bus_peripheral.take_write_journal();
+        if let Some(previous) = self.entries.back_mut() {
+            previous.writes = writes;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            cpu_checkpoint: cpu_from_bus(bus_peripheral).checkpoint(),
+            bus_assertions,
+            writes: vec![],
+        });
+    }
+
+    ///
+    /// Rewinds the CPU and memory to the start of the previous instruction.
+    ///
+    /// Returns the bus assertions to feed into the next poll of the bus, or `None` if there is no
+    /// previous instruction in the history.
+    ///
+    pub fn step_back(&mut self, bus_peripheral: &mut BusPeripheral) -> Option<BusAssertions> {
+        if self.entries.len() < 2 {
+            return None;
+        }
+        self.entries.pop_back();
+        // Anything written since the current instruction started
+        let pending_writes = bus_peripheral.take_write_journal();
+        bus_peripheral.undo_writes(&pending_writes);
+
+        let previous = self.entries.back_mut()?;
+        bus_peripheral.undo_writes(&previous.writes);
+        previous.writes.clear();
+        self.instruction_fetch_in_flight = false;
+        cpu_from_bus_mut(bus_peripheral).restore_checkpoint(&previous.cpu_checkpoint);
+        Some(previous.bus_assertions)
+    }
+}
@@ -14,19 +14,31 @@
 #![deny(warnings)]
 
 pub mod debug_adapter;
-mod debugger;
+pub mod debugger;
+pub mod history;
+pub mod save_state;
+pub mod trace;
 pub mod utils;
 
-use std::{cell::RefCell, collections::HashSet, fs::File, io::Write, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::PathBuf,
+};
 
-use debug_adapter::types::{BreakpointRef, VmChannels};
-use debugger::yield_to_debugger;
+use debug_adapter::types::{BreakpointRef, DataBreakpointRef, FaultDetails, VmChannels};
+use debugger::{check_data_breakpoints, check_exception_breakpoints, yield_to_debugger};
+use history::ExecutionHistory;
 use log::{error, info};
 use peripheral_bus::{
     device::{BusAssertions, Device},
     BusPeripheral,
 };
-use peripheral_cpu::CpuPeripheral;
+use peripheral_cpu::{coprocessors::exception_unit::definitions::Faults, CpuPeripheral};
+use save_state::SaveStateTrigger;
+use trace::{start_trace, TraceOptions};
 use utils::{cpu_from_bus::cpu_from_bus, frame_reporter::start_loop};
 
 #[cfg(feature = "video")]
@@ -39,6 +51,21 @@ const VSYNC_INTERRUPT: u8 = 0x0;
 pub struct DebugState {
     pub channels: VmChannels,
     pub breakpoints: HashSet<BreakpointRef>,
+    pub data_breakpoints: Vec<DataBreakpointRef>,
+    /// Set when a bus access hits a data breakpoint, the VM pauses at the start of the next instruction
+    pub triggered_data_breakpoint: Option<DataBreakpointRef>,
+    /// The fault classes to pause on when the CPU raises them
+    pub exception_breakpoints: Vec<Faults>,
+    /// The fault that was pending after the last poll, so that each fault is only caught once
+    pub previous_pending_fault: Option<Faults>,
+    /// Set when a fault in `exception_breakpoints` is raised, the VM pauses at the start of the next instruction
+    pub triggered_fault: Option<FaultDetails>,
+    /// How many times each breakpoint has been hit while its condition was met (for hit conditions).
+    /// Breakpoint IDs are never reused so the counts start again when breakpoints are replaced, and
+    /// the counts of breakpoints that have been removed are dropped.
+    pub hit_counts: HashMap<i64, u64>,
+    /// Set when the debug server has gone away. Detaching a debugger doesn't set this, the
+    /// server keeps listening for the next one.
     pub disconnected: bool,
     // TODO: Collapse multiple bools in DebugState into an enum
     // category=Refactor
@@ -47,11 +74,48 @@ pub struct DebugState {
     pub paused: bool,
     pub should_pause_for_init: bool,
     pub is_stepping: bool,
+    /// Set when stepping out, the VM pauses when it gets back to the caller
+    pub step_out_target: Option<StepOutTarget>,
+    pub history: ExecutionHistory,
+}
+
+impl DebugState {
+    #[must_use]
+    pub fn new(channels: VmChannels, pause_at_start: bool) -> Self {
+        Self {
+            breakpoints: HashSet::new(),
+            data_breakpoints: vec![],
+            triggered_data_breakpoint: None,
+            exception_breakpoints: vec![],
+            previous_pending_fault: None,
+            triggered_fault: None,
+            hit_counts: HashMap::new(),
+            channels,
+            disconnected: false,
+            paused: false,
+            should_pause_for_init: pause_at_start,
+            is_stepping: false,
+            step_out_target: None,
+            history: ExecutionHistory::default(),
+        }
+    }
+}
+
+/// Where the VM should pause when stepping out (see `ResumeCondition::UntilReturn`)
+#[derive(Debug, Clone, Copy)]
+pub struct StepOutTarget {
+    pub address: u32,
+    pub stack_pointer: u32,
 }
 
 pub struct Vm {
     pub bus_peripheral: RefCell<BusPeripheral>,
     pub vsync_frequency: f64,
+    /// The assertions fed into the first poll of the bus (e.g. restored from a save state)
+    pub bus_assertions: BusAssertions,
+    pub save_state: Option<SaveStateTrigger>,
+    /// Writes every instruction that is executed to a file
+    pub trace: Option<TraceOptions>,
 }
 
 #[allow(clippy::borrowed_box)]
@@ -65,35 +129,52 @@ fn dump_registers(dump_file: &PathBuf, device: &dyn Device) -> Result<(), std::i
 // Separate from run_vm so that performance is not affected in non-debug mode
 // TODO: Deduplicate `run_vm` functions
 // category=Refactoring
-pub fn run_vm_debug(vm: &Vm, register_dump_file: Option<PathBuf>, channels: VmChannels) {
+pub fn run_vm_debug(
+    vm: &Vm,
+    register_dump_file: Option<PathBuf>,
+    channels: VmChannels,
+    pause_at_start: bool,
+) {
     // TODO: Check if RefCell is required for VM state
     // category=Refactoring
     // Can we avoid RefCell if we know that `run_vm` is the only consumer of VM?
     let mut bus_peripheral = vm.bus_peripheral.borrow_mut();
 
-    let mut debug_state = DebugState {
-        breakpoints: HashSet::new(),
-        channels,
-        disconnected: false,
-        paused: false,
-        should_pause_for_init: true,
-        is_stepping: false,
-    };
+    let mut debug_state = DebugState::new(channels, pause_at_start);
 
-    let mut bus_assertions = BusAssertions::default();
+    let mut bus_assertions = vm.bus_assertions;
+    let mut frame: usize = 0;
+    let mut tracer = start_trace(vm.trace.as_ref());
     let execute = || {
         let mut clocks = 0;
         loop {
             bus_assertions = bus_peripheral.poll_all(bus_assertions);
+            if let Some(tracer) = tracer.as_mut() {
+                tracer.record_poll(&mut bus_peripheral, bus_assertions);
+            }
+
+            if !debug_state.disconnected {
+                check_data_breakpoints(&mut debug_state, &bus_assertions);
+                check_exception_breakpoints(&mut bus_peripheral, &mut debug_state);
 
-            if !debug_state.disconnected && bus_assertions.instruction_sync {
-                yield_to_debugger(&mut bus_peripheral, &mut debug_state);
+                // Pause at the same point that the history is recorded so that the VM can be rewound
+                // to anywhere it was paused
+                if debug_state
+                    .history
+                    .record_poll(&mut bus_peripheral, bus_assertions)
+                {
+                    yield_to_debugger(&mut bus_peripheral, &mut debug_state, &mut bus_assertions);
+                }
             }
 
             clocks += 1;
             if bus_assertions.interrupt_assertion & VSYNC_INTERRUPT > 0
                 || bus_assertions.exit_simulation
             {
+                frame += 1;
+                if let Some(save_state) = &vm.save_state {
+                    save_state.frame_completed(frame, &bus_peripheral, bus_assertions);
+                }
                 return (bus_assertions.exit_simulation, clocks);
             }
         }
@@ -104,6 +185,14 @@ pub fn run_vm_debug(vm: &Vm, register_dump_file: Option<PathBuf>, channels: VmCh
     // E.g. in the SNES the CPU ran 6 times slower than the master clock
     start_loop(vm.vsync_frequency, execute);
 
+    if let Some(tracer) = tracer.as_mut() {
+        tracer.finish(&mut bus_peripheral);
+    }
+
+    if let Some(save_state) = &vm.save_state {
+        save_state.vm_exited(frame, &bus_peripheral, bus_assertions);
+    }
+
     if let Some(register_dump_file) = register_dump_file {
         let cpu: &CpuPeripheral = cpu_from_bus(&mut bus_peripheral);
 
@@ -123,16 +212,25 @@ pub fn run_vm_debug(vm: &Vm, register_dump_file: Option<PathBuf>, channels: VmCh
 
 pub fn run_vm(vm: &Vm, register_dump_file: Option<PathBuf>) {
     let mut bus_peripheral = vm.bus_peripheral.borrow_mut();
-    let mut bus_assertions = BusAssertions::default();
+    let mut bus_assertions = vm.bus_assertions;
+    let mut frame: usize = 0;
+    let mut tracer = start_trace(vm.trace.as_ref());
     let execute = || {
         let mut clocks = 0;
         loop {
             bus_assertions = bus_peripheral.poll_all(bus_assertions);
+            if let Some(tracer) = tracer.as_mut() {
+                tracer.record_poll(&mut bus_peripheral, bus_assertions);
+            }
 
             clocks += 1;
             if bus_assertions.interrupt_assertion & VSYNC_INTERRUPT > 0
                 || bus_assertions.exit_simulation
             {
+                frame += 1;
+                if let Some(save_state) = &vm.save_state {
+                    save_state.frame_completed(frame, &bus_peripheral, bus_assertions);
+                }
                 return (bus_assertions.exit_simulation, clocks);
             }
         }
@@ -140,6 +238,14 @@ pub fn run_vm(vm: &Vm, register_dump_file: Option<PathBuf>) {
 
     start_loop(vm.vsync_frequency, execute);
 
+    if let Some(tracer) = tracer.as_mut() {
+        tracer.finish(&mut bus_peripheral);
+    }
+
+    if let Some(save_state) = &vm.save_state {
+        save_state.vm_exited(frame, &bus_peripheral, bus_assertions);
+    }
+
     if let Some(register_dump_file) = register_dump_file {
         let cpu: &CpuPeripheral = cpu_from_bus(&mut bus_peripheral);
 
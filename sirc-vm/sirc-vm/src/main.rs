@@ -13,13 +13,22 @@
 )]
 // #![deny(warnings)]
 
-use std::{cell::RefCell, path::PathBuf, process::exit, thread};
+use std::{
+    cell::RefCell,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    process::exit,
+    thread,
+};
 
 use clap::Parser;
 use log::{error, info, Level};
 
 use sirc_vm::debug_adapter::debug_map::read_debug_map;
+use sirc_vm::debug_adapter::gdb_server::start_gdb_server;
 use sirc_vm::debug_adapter::server::{create_server_channels, start_server};
+use sirc_vm::save_state::{read_save_state, SaveStateTrigger};
+use sirc_vm::trace::TraceOptions;
 use sirc_vm::{run_vm, run_vm_debug, Vm};
 
 use device_debug::new_debug_device;
@@ -27,11 +36,14 @@ use device_ram::{
     new_ram_device_file_mapped, new_ram_device_standard, new_ram_device_with_latency,
 };
 use device_terminal::new_terminal_device;
-use peripheral_bus::new_bus_peripheral;
-use peripheral_cpu::new_cpu_peripheral;
+use peripheral_bus::{device::BusAssertions, new_bus_peripheral};
+use peripheral_cpu::{new_cpu_peripheral_with_model, CpuModel};
 
 #[cfg(feature = "video")]
-use device_video::new_video_device;
+use device_video::{
+    headless::{FrameSelection, HeadlessFormat, HeadlessRenderer},
+    new_video_device, new_video_device_with_renderer,
+};
 
 static PROGRAM_SEGMENT: &str = "PROGRAM";
 static TERMINAL_SEGMENT: &str = "TERMINAL";
@@ -90,6 +102,55 @@ fn segment_arg_parser(s: &str) -> Result<SegmentArg, String> {
     }
 }
 
+fn cpu_model_arg_parser(s: &str) -> Result<CpuModel, String> {
+    match s.to_lowercase().as_str() {
+        "base" => Ok(CpuModel::Base),
+        "maths" => Ok(CpuModel::Maths),
+        _ => Err(format!(
+            "Unknown CPU model [{s}]. Should be one of: base, maths."
+        )),
+    }
+}
+
+/// The protocol that debuggers use to talk to the VM
+#[derive(Clone, Copy, Debug)]
+enum DebugProtocol {
+    /// The debug adapter protocol (e.g. VS Code)
+    Dap,
+    /// The GDB remote serial protocol
+    Gdb,
+}
+
+fn debug_protocol_arg_parser(s: &str) -> Result<DebugProtocol, String> {
+    match s.to_lowercase().as_str() {
+        "dap" => Ok(DebugProtocol::Dap),
+        "gdb" => Ok(DebugProtocol::Gdb),
+        _ => Err(format!(
+            "Unknown debug protocol [{s}]. Should be one of: dap, gdb."
+        )),
+    }
+}
+
+/// Where the frames from the video device end up
+#[cfg(feature = "video")]
+#[derive(Clone, Copy, Debug)]
+enum VideoRenderer {
+    Window,
+    Headless(HeadlessFormat),
+}
+
+#[cfg(feature = "video")]
+fn video_renderer_arg_parser(s: &str) -> Result<VideoRenderer, String> {
+    match s.to_lowercase().as_str() {
+        "window" => Ok(VideoRenderer::Window),
+        "png" => Ok(VideoRenderer::Headless(HeadlessFormat::Png)),
+        "raw" => Ok(VideoRenderer::Headless(HeadlessFormat::Raw)),
+        _ => Err(format!(
+            "Unknown video renderer [{s}]. Should be one of: window, png, raw."
+        )),
+    }
+}
+
 #[derive(Clone, Debug)]
 struct SegmentArg {
     pub label: String,
@@ -111,6 +172,10 @@ pub struct Args {
     #[clap(short, long, value_parser, value_name = "FILE")]
     register_dump_file: Option<PathBuf>,
 
+    /// The SIRC-1 model to emulate (base or maths). The maths model includes the integer maths unit.
+    #[clap(long, value_parser = cpu_model_arg_parser, default_value = "base")]
+    cpu_model: CpuModel,
+
     #[command(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
 
@@ -118,8 +183,62 @@ pub struct Args {
     #[clap(short, long)]
     enable_video: bool,
 
+    /// Where video frames are drawn (window, png or raw). The png and raw renderers don't open
+    /// a window, they write frames to --video-output (a directory of PNGs or a raw RGB24 file).
+    #[cfg(feature = "video")]
+    #[clap(long, value_parser = video_renderer_arg_parser, default_value = "window")]
+    video_renderer: VideoRenderer,
+
+    #[cfg(feature = "video")]
+    #[clap(long, value_parser, value_name = "PATH")]
+    video_output: Option<PathBuf>,
+
+    /// The frames to write with the png and raw renderers (e.g. "all" or "0,10-20,60")
+    #[cfg(feature = "video")]
+    #[clap(long, value_parser = FrameSelection::parse, default_value = "all")]
+    video_frames: FrameSelection,
+
+    /// Lets a debugger attach to the VM. Only one debugger can be attached at a time, any others
+    /// that connect are turned away until it detaches.
     #[clap(short, long)]
     debug: bool,
+
+    /// The protocol debuggers use to connect (dap for VS Code, or gdb for GDB remote serial
+    /// protocol clients)
+    #[clap(long, value_parser = debug_protocol_arg_parser, default_value = "dap", requires = "debug")]
+    debug_protocol: DebugProtocol,
+
+    /// The address the debug adapter listens on for debugger connections
+    #[clap(long, value_parser, default_value = "0.0.0.0", requires = "debug")]
+    debug_bind_address: IpAddr,
+
+    /// The port the debug adapter listens on for debugger connections
+    #[clap(long, value_parser, default_value = "9090", requires = "debug")]
+    debug_port: u16,
+
+    /// Starts running straight away instead of waiting for a debugger to attach and resume.
+    /// Debuggers can still attach (and detach) while the VM is running.
+    #[clap(long, requires = "debug")]
+    debug_no_wait: bool,
+
+    /// Restores a save state (written with --save-state) after the VM has been set up.
+    /// The VM must be set up with the same arguments as when the state was saved.
+    #[clap(long, value_parser, value_name = "FILE")]
+    load_state: Option<PathBuf>,
+
+    /// Writes a save state when the VM exits (or at --save-state-at-frame)
+    #[clap(long, value_parser, value_name = "FILE")]
+    save_state: Option<PathBuf>,
+
+    /// Writes the save state once this many frames have run instead of when the VM exits.
+    /// Frames are counted from the video device's vsync, so this needs --enable-video.
+    #[clap(long, value_parser, value_name = "N", requires = "save_state")]
+    save_state_at_frame: Option<usize>,
+
+    /// Writes every instruction that is executed to a file, with the registers it changed and
+    /// the bus transactions it made
+    #[clap(long, value_parser, value_name = "FILE")]
+    trace: Option<PathBuf>,
 }
 
 fn main() {
@@ -157,15 +276,26 @@ fn main() {
     if args.debug {
         let channels = create_server_channels();
 
-        let program_debug_info = read_debug_map(args.program_file).unwrap();
-        let debugger_join_handle = thread::spawn(move || {
-            let result = start_server(channels.debugger, &program_debug_info);
-            if let Err(error) = result {
-                error!("Error occurred in debug server: {error:?}");
+        let listener_address = SocketAddr::new(args.debug_bind_address, args.debug_port);
+        let debugger_join_handle = match args.debug_protocol {
+            DebugProtocol::Dap => {
+                let program_debug_info = read_debug_map(args.program_file).unwrap();
+                thread::spawn(move || {
+                    let result =
+                        start_server(channels.debugger, &program_debug_info, listener_address);
+                    if let Err(error) = result {
+                        error!("Error occurred in debug server: {error:?}");
+                    }
+                })
             }
-        });
-
-        run_vm_debug(&vm, dump_file, channels.vm);
+            DebugProtocol::Gdb => thread::spawn(move || {
+                if let Err(error) = start_gdb_server(channels.debugger, listener_address) {
+                    error!("Error occurred in GDB server: {error:?}");
+                }
+            }),
+        };
+
+        run_vm_debug(&vm, dump_file, channels.vm, !args.debug_no_wait);
         info!("Waiting on debugger thread...");
         debugger_join_handle.join().unwrap();
     } else {
@@ -183,7 +313,7 @@ fn main() {
 fn setup_vm(args: &Args) -> Vm {
     let master_clock_freq = 21_477_272;
 
-    let mut cpu_peripheral = new_cpu_peripheral(0x0);
+    let mut cpu_peripheral = new_cpu_peripheral_with_model(0x0, args.cpu_model);
     // Jump to reset vector
     cpu_peripheral.reset();
 
@@ -216,11 +346,24 @@ fn setup_vm(args: &Args) -> Vm {
 
     #[cfg(feature = "video")]
     let vsync_frequency = if args.enable_video {
-        let video_device = new_video_device(
-            // TODO: Check mix of u32 and usize for the clock and video device
-            // category=Refactoring
-            master_clock_freq as usize,
-        );
+        // TODO: Check mix of u32 and usize for the clock and video device
+        // category=Refactoring
+        let video_device = match args.video_renderer {
+            VideoRenderer::Window => new_video_device(master_clock_freq as usize),
+            VideoRenderer::Headless(format) => {
+                let Some(output_path) = args.video_output.clone() else {
+                    error!("--video-output is required for the {format:?} video renderer");
+                    exit(1);
+                };
+                let renderer =
+                    HeadlessRenderer::new(format, output_path, args.video_frames.clone())
+                        .unwrap_or_else(|error| {
+                            error!("Could not set up video output: {error}");
+                            exit(1);
+                        });
+                new_video_device_with_renderer(master_clock_freq as usize, Box::new(renderer))
+            }
+        };
         let vsync_frequency = video_device.vsync_frequency;
         bus_peripheral.map_segment(
             VIDEO_SEGMENT,
@@ -239,6 +382,15 @@ fn setup_vm(args: &Args) -> Vm {
     #[cfg(not(feature = "video"))]
     let vsync_frequency = 60f64;
 
+    #[cfg(feature = "video")]
+    let has_vsync = args.enable_video;
+    #[cfg(not(feature = "video"))]
+    let has_vsync = false;
+    if args.save_state_at_frame.is_some() && !has_vsync {
+        error!("--save-state-at-frame needs --enable-video (frames are counted from the video device's vsync)");
+        exit(1);
+    }
+
     bus_peripheral.load_binary_data_into_segment_from_file(PROGRAM_SEGMENT, &args.program_file);
 
     for segment in args.segment.clone() {
@@ -263,8 +415,31 @@ fn setup_vm(args: &Args) -> Vm {
         }
     }
 
+    let bus_assertions = args
+        .load_state
+        .as_ref()
+        .map_or_else(BusAssertions::default, |file| {
+            info!("Restoring save state from [{}]...", file.display());
+            read_save_state(&mut bus_peripheral, file).unwrap_or_else(|error| {
+                error!("{error}");
+                exit(1);
+            })
+        });
+
     Vm {
         bus_peripheral: RefCell::new(bus_peripheral),
         vsync_frequency,
+        bus_assertions,
+        save_state: args.save_state.clone().map(|file| SaveStateTrigger {
+            file,
+            at_frame: args.save_state_at_frame,
+        }),
+        trace: args.trace.clone().map(|file| TraceOptions {
+            file,
+            // Labels are only available if the program was linked with debug info
+            symbols: read_debug_map(args.program_file.clone())
+                .map(|program_debug_info| program_debug_info.symbols)
+                .unwrap_or_default(),
+        }),
     }
 }
@@ -0,0 +1,115 @@
+//!
+//! Reading and writing save state files (see `peripheral_bus::save_state`).
+//!
+//! A save state is only valid for a VM that was set up in the same way (same segments, same
+//! devices) as the one that saved it. The program file is still loaded as usual but the memory is
+//! overwritten when the state is restored.
+//!
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{error, info, warn};
+use peripheral_bus::{device::BusAssertions, save_state::SaveStateError, BusPeripheral};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SaveStateFileError {
+    #[error("Could not access save state file [{path}]: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Could not restore save state file [{path}]: {source}")]
+    State {
+        path: PathBuf,
+        source: SaveStateError,
+    },
+}
+
+/// When and where the VM should write a save state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveStateTrigger {
+    pub file: PathBuf,
+    /// Save once this many frames (vsyncs from the video device) have run. If `None`, the state
+    /// is saved when the VM exits.
+    pub at_frame: Option<usize>,
+}
+
+impl SaveStateTrigger {
+    /// Writes the save state if this is the frame that was requested
+    pub(crate) fn frame_completed(
+        &self,
+        frame: usize,
+        bus_peripheral: &BusPeripheral,
+        bus_assertions: BusAssertions,
+    ) {
+        if self.at_frame == Some(frame) {
+            self.save(bus_peripheral, bus_assertions);
+        }
+    }
+
+    /// Writes the save state if it was requested to be written on exit, or if the VM exited
+    /// before the requested frame (so that the run isn't wasted)
+    pub(crate) fn vm_exited(
+        &self,
+        frame: usize,
+        bus_peripheral: &BusPeripheral,
+        bus_assertions: BusAssertions,
+    ) {
+        match self.at_frame {
+            None => self.save(bus_peripheral, bus_assertions),
+            Some(at_frame) if at_frame > frame => {
+                warn!("The VM exited after {frame} frame(s), before frame {at_frame}. Saving the state on exit instead.");
+                self.save(bus_peripheral, bus_assertions);
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn save(&self, bus_peripheral: &BusPeripheral, bus_assertions: BusAssertions) {
+        info!("Writing save state to [{}]...", self.file.display());
+        if let Err(error) = write_save_state(bus_peripheral, bus_assertions, &self.file) {
+            error!("{error}");
+        }
+    }
+}
+
+///
+/// Writes the state of everything on the bus to a file.
+///
+/// `bus_assertions` should be the output of the last bus poll so that the VM can pick up exactly
+/// where it left off.
+///
+pub fn write_save_state(
+    bus_peripheral: &BusPeripheral,
+    bus_assertions: BusAssertions,
+    file: &Path,
+) -> Result<(), SaveStateFileError> {
+    fs::write(file, bus_peripheral.save_state(bus_assertions)).map_err(|source| {
+        SaveStateFileError::Io {
+            path: file.to_path_buf(),
+            source,
+        }
+    })
+}
+
+///
+/// Restores the state of everything on the bus from a file written by `write_save_state`.
+///
+/// Returns the bus assertions that should be fed into the next bus poll.
+///
+pub fn read_save_state(
+    bus_peripheral: &mut BusPeripheral,
+    file: &Path,
+) -> Result<BusAssertions, SaveStateFileError> {
+    let state = fs::read(file).map_err(|source| SaveStateFileError::Io {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    bus_peripheral
+        .restore_state(&state)
+        .map_err(|source| SaveStateFileError::State {
+            path: file.to_path_buf(),
+            source,
+        })
+}
@@ -0,0 +1,245 @@
+//!
+//! Writes a line to a file for every instruction that the CPU retires (see `--trace`).
+//!
+//! Each line has the address of the instruction, its encoding, the disassembly, the registers
+//! that it changed and the bus transactions that it made (apart from fetching the instruction).
+//! There are no timings in the trace so that traces from different runs (e.g. before and after a
+//! change to the CPU) can be compared with diff.
+//!
+//! Exception unit dispatches also start with `instruction_sync`, so they show up as lines without
+//! an instruction (with the vector fetch in the transactions).
+//!
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use log::error;
+use peripheral_bus::device::{BusAccessType, BusAssertions, BusOperation};
+use peripheral_bus::BusPeripheral;
+use peripheral_cpu::coprocessors::processing_unit::disassembly::disassemble_instruction;
+use peripheral_cpu::coprocessors::processing_unit::encoding::decode_instruction;
+use peripheral_cpu::registers::{register_index_to_name, FullAddressRegisterAccess, Registers};
+
+use crate::debug_adapter::symbols::format_symbolised_address;
+use crate::debug_adapter::types::SymbolTable;
+use crate::utils::cpu_from_bus::cpu_from_bus;
+
+/// Keeps the columns after the disassembly lined up
+const DISASSEMBLY_WIDTH: usize = 28;
+
+/// Where the trace should be written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceOptions {
+    pub file: PathBuf,
+    /// Used to show addresses relative to labels, can be empty
+    pub symbols: SymbolTable,
+}
+
+/// A bus operation that was acknowledged by a device
+#[derive(Debug, Clone, Copy)]
+pub struct BusTransaction {
+    pub access_type: BusAccessType,
+    pub op: BusOperation,
+    pub address: u32,
+    pub data: u16,
+}
+
+/// Everything recorded between the start of an instruction and the start of the next one
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    /// The address of the first word of the instruction
+    pub pc: u32,
+    /// The words that were fetched for the instruction (empty for an exception unit dispatch)
+    pub encoding: Vec<u16>,
+    pub start_registers: Registers,
+    pub transactions: Vec<BusTransaction>,
+}
+
+fn format_transaction(transaction: BusTransaction) -> String {
+    let tag = match (transaction.access_type, transaction.op) {
+        (BusAccessType::ExceptionVectorFetch, _) => "V",
+        (_, BusOperation::Read) => "R",
+        (_, BusOperation::Write) => "W",
+    };
+    format!(
+        "{tag}[{:06X}]={:04X}",
+        transaction.address, transaction.data
+    )
+}
+
+///
+/// Formats a line of the trace, given the registers at the start of the next instruction.
+///
+/// ```
+/// use peripheral_bus::device::{BusAccessType, BusOperation};
+/// use peripheral_cpu::registers::Registers;
+/// use sirc_vm::trace::{format_trace_entry, BusTransaction, TraceEntry};
+/// use std::collections::BTreeMap;
+///
+/// let entry = TraceEntry {
+///     pc: 0x20A,
+///     encoding: vec![0x4040, 0x03D0],
+///     start_registers: Registers { pl: 0x20A, ..Registers::default() },
+///     transactions: vec![BusTransaction {
+///         access_type: BusAccessType::DataWrite,
+///         op: BusOperation::Write,
+///         address: 0x08_AAFF,
+///         data: 0xCAFE,
+///     }],
+/// };
+/// let end_registers = Registers { pl: 0x20C, ..Registers::default() };
+/// let symbols = BTreeMap::from([("init".to_string(), 0x200)]);
+///
+/// assert_eq!(
+///     "00020A init+0xA     4040 03D0  STOR (#0x000F, a), r1        pl=020C  W[08AAFF]=CAFE",
+///     format_trace_entry(&entry, &end_registers, &symbols)
+/// );
+/// ```
+///
+#[must_use]
+pub fn format_trace_entry(
+    entry: &TraceEntry,
+    end_registers: &Registers,
+    symbols: &SymbolTable,
+) -> String {
+    let (encoding, disassembly) = match entry.encoding[..] {
+        [high, low] => {
+            let [a, b] = high.to_be_bytes();
+            let [c, d] = low.to_be_bytes();
+            (
+                format!("{high:04X} {low:04X}"),
+                disassemble_instruction(&decode_instruction([a, b, c, d])),
+            )
+        }
+        _ => ("---- ----".to_string(), "(exception unit)".to_string()),
+    };
+    let mut line = format!(
+        "{:06X} {:<12} {encoding}  {disassembly:<DISASSEMBLY_WIDTH$}",
+        entry.pc,
+        format_symbolised_address(symbols, entry.pc)
+    );
+
+    for index in 0..=u8::MAX {
+        let Some(name) = register_index_to_name(index) else {
+            break;
+        };
+        if entry.start_registers[index] != end_registers[index] {
+            let _ = write!(line, " {name}={:04X}", end_registers[index]);
+        }
+    }
+    for transaction in &entry.transactions {
+        let _ = write!(line, "  {}", format_transaction(*transaction));
+    }
+    line.trim_end().to_string()
+}
+
+pub struct InstructionTracer {
+    file: PathBuf,
+    /// Dropped if writing fails, the VM carries on without a trace
+    output: Option<BufWriter<File>>,
+    symbols: SymbolTable,
+    current: Option<TraceEntry>,
+    instruction_fetch_in_flight: bool,
+}
+
+impl InstructionTracer {
+    pub fn create(options: &TraceOptions) -> io::Result<Self> {
+        Ok(Self {
+            file: options.file.clone(),
+            output: Some(BufWriter::new(File::create(&options.file)?)),
+            symbols: options.symbols.clone(),
+            current: None,
+            instruction_fetch_in_flight: false,
+        })
+    }
+
+    ///
+    /// Should be called after every poll of the bus with its output.
+    ///
+    /// Like `ExecutionHistory::record_poll`, an instruction starts when the fetch that follows
+    /// `instruction_sync` is acknowledged (or straight away if the CPU doesn't fetch anything).
+    ///
+    pub fn record_poll(
+        &mut self,
+        bus_peripheral: &mut BusPeripheral,
+        bus_assertions: BusAssertions,
+    ) {
+        if bus_assertions.instruction_sync {
+            self.instruction_fetch_in_flight = true;
+        }
+        let completed = bus_assertions.bus_access_strobe && bus_assertions.bus_acknowledge;
+        let waiting_for_fetch = bus_assertions.bus_access_strobe && !completed;
+        if self.instruction_fetch_in_flight && !waiting_for_fetch {
+            self.instruction_fetch_in_flight = false;
+            let registers = cpu_from_bus(bus_peripheral).registers;
+            self.write_current(&registers);
+            self.current = Some(TraceEntry {
+                pc: registers.get_full_pc_address(),
+                encoding: vec![],
+                start_registers: registers,
+                transactions: vec![],
+            });
+        }
+
+        if !completed {
+            return;
+        }
+        if let Some(entry) = self.current.as_mut() {
+            if bus_assertions.bus_access_type == BusAccessType::InstructionFetch {
+                if entry.encoding.is_empty() {
+                    entry.pc = bus_assertions.address;
+                }
+                entry.encoding.push(bus_assertions.data);
+            } else {
+                entry.transactions.push(BusTransaction {
+                    access_type: bus_assertions.bus_access_type,
+                    op: bus_assertions.op,
+                    address: bus_assertions.address,
+                    data: bus_assertions.data,
+                });
+            }
+        }
+    }
+
+    fn write_current(&mut self, end_registers: &Registers) {
+        let (Some(entry), Some(output)) = (self.current.take(), self.output.as_mut()) else {
+            return;
+        };
+        let line = format_trace_entry(&entry, end_registers, &self.symbols);
+        if let Err(error) = writeln!(output, "{line}") {
+            self.trace_failed(&error);
+        }
+    }
+
+    fn trace_failed(&mut self, error: &io::Error) {
+        error!(
+            "Could not write trace to [{}]: {error}",
+            self.file.display()
+        );
+        self.output = None;
+    }
+
+    /// Writes the instruction that was running when the VM stopped
+    pub fn finish(&mut self, bus_peripheral: &mut BusPeripheral) {
+        let registers = cpu_from_bus(bus_peripheral).registers;
+        self.write_current(&registers);
+        if let Some(Err(error)) = self.output.as_mut().map(Write::flush) {
+            self.trace_failed(&error);
+        }
+    }
+}
+
+/// Opens the trace file if one was requested. Errors are logged so that the VM can still run.
+pub(crate) fn start_trace(options: Option<&TraceOptions>) -> Option<InstructionTracer> {
+    let options = options?;
+    InstructionTracer::create(options)
+        .inspect_err(|error| {
+            error!(
+                "Could not write trace to [{}]: {error}",
+                options.file.display()
+            );
+        })
+        .ok()
+}
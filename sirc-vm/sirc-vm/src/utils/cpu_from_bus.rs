@@ -19,3 +19,12 @@ pub fn cpu_from_bus(bus_peripheral: &mut BusPeripheral) -> &CpuPeripheral {
         .downcast_ref::<CpuPeripheral>()
         .expect("failed to downcast")
 }
+
+/// Gets a mutable reference to the CPU from the Bus (see `cpu_from_bus`)
+pub fn cpu_from_bus_mut(bus_peripheral: &mut BusPeripheral) -> &mut CpuPeripheral {
+    bus_peripheral
+        .bus_master
+        .as_any()
+        .downcast_mut::<CpuPeripheral>()
+        .expect("failed to downcast")
+}
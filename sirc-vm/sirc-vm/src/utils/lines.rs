@@ -2,13 +2,16 @@ use std::collections::BTreeMap;
 
 use line_col::LineColLookup;
 
-use crate::debug_adapter::types::{ProgramDebugInfo, ProgramPosition};
+use crate::debug_adapter::types::{ObjectDebugInfo, ProgramDebugInfo, ProgramPosition};
 
 fn flip_map(
     program_to_input_offset_mapping: &BTreeMap<ProgramPosition, usize>,
 ) -> BTreeMap<usize, ProgramPosition> {
+    // Reversed so that if more than one instruction comes from the same place (e.g. a macro), the
+    // first one is used
     program_to_input_offset_mapping
         .iter()
+        .rev()
         .map(|(k, v)| (*v, *k))
         .collect::<BTreeMap<_, _>>()
 }
@@ -19,11 +22,17 @@ pub fn translate_pc_to_line_column(
     pc: u32,
 ) -> Option<(i64, i64, String)> {
     // Find the matching file
-    let debug_info = program_debug_info
+    // (included files can be in the middle of other files, so they are all checked)
+    let (debug_info, input_position) = program_debug_info
         .debug_info_map
         .range(..=pc)
-        .next_back()
-        .map(|(_, f)| f)?;
+        .rev()
+        .find_map(|(_, debug_info)| {
+            debug_info
+                .program_to_input_offset_mapping
+                .get(&pc)
+                .map(|input_position| (debug_info, *input_position))
+        })?;
 
     // Then the matching line
     // TODO: Improve the performance of the PC <-> Line/Column lookups in the debugger
@@ -31,31 +40,18 @@ pub fn translate_pc_to_line_column(
     // - Should probably cache the lookup more
     // - See also the function that translates the other direction
     let lookup = LineColLookup::new(debug_info.original_input.as_str());
-    debug_info
-        .program_to_input_offset_mapping
-        .get(&pc)
-        .map(|pos| lookup.get(*pos))
-        .map(|(line, col)| {
-            (
-                line.try_into().unwrap(),
-                col.try_into().unwrap(),
-                debug_info.original_filename.clone(),
-            )
-        })
+    let (line, col) = lookup.get(input_position);
+    Some((
+        line.try_into().unwrap(),
+        col.try_into().unwrap(),
+        debug_info.original_filename.clone(),
+    ))
 }
 
-#[must_use]
-pub fn translate_line_column_to_pc(
-    program_debug_info: &ProgramDebugInfo,
-    original_filename: &str,
+fn translate_line_column_to_pc_in_file(
+    debug_info: &ObjectDebugInfo,
     (line, column): (i64, i64),
 ) -> Option<u32> {
-    // Find the matching file
-    let debug_info = program_debug_info
-        .debug_info_map
-        .values()
-        .find(|d| d.original_filename == original_filename)?;
-    // Then the matching line
     let input_offset_to_program_mapping = flip_map(&debug_info.program_to_input_offset_mapping);
     let mut lines_to_find = line - 1;
     let mut offset: usize = 0;
@@ -82,3 +78,18 @@ pub fn translate_line_column_to_pc(
         .next_back()
         .map(|(_, pc)| *pc)
 }
+
+#[must_use]
+pub fn translate_line_column_to_pc(
+    program_debug_info: &ProgramDebugInfo,
+    original_filename: &str,
+    line_column: (i64, i64),
+) -> Option<u32> {
+    // A file that is included by more than one object has debug info in each of them, and it might
+    // only have instructions in some of them, so every object is checked until the line is found
+    program_debug_info
+        .debug_info_map
+        .values()
+        .filter(|d| d.original_filename == original_filename)
+        .find_map(|debug_info| translate_line_column_to_pc_in_file(debug_info, line_column))
+}
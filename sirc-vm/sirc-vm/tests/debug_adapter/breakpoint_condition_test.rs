@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use peripheral_cpu::registers::Registers;
+use sirc_vm::debug_adapter::expression::{register_value, EvaluationContext};
+use sirc_vm::debug_adapter::server::{create_server_channels, parse_breakpoint_conditions};
+use sirc_vm::debug_adapter::types::{
+    BreakpointConditions, BreakpointRef, DebuggerMessage, VmMessage,
+};
+use sirc_vm::debugger::{handle_debug_message, should_pause_on_breakpoint};
+use sirc_vm::DebugState;
+
+const BREAKPOINT_ID: i64 = 7;
+
+/// Nothing is mapped in memory
+struct TestContext {
+    registers: Registers,
+}
+
+impl EvaluationContext for TestContext {
+    fn identifier(&self, name: &str) -> Option<u32> {
+        register_value(&self.registers, name)
+    }
+
+    fn read_memory(&mut self, _: u32) -> Option<u16> {
+        None
+    }
+}
+
+fn conditions(
+    condition: Option<&str>,
+    hit_condition: Option<&str>,
+    log_message: Option<&str>,
+) -> BreakpointConditions {
+    parse_breakpoint_conditions(condition, hit_condition, log_message, &BTreeMap::new()).unwrap()
+}
+
+/// Hits the breakpoint once for each value of r1, and returns which hits paused the VM and the
+/// messages that were logged
+fn hit_breakpoint(
+    conditions: &BreakpointConditions,
+    r1_values: &[u16],
+) -> (Vec<bool>, Vec<String>) {
+    let channels = create_server_channels();
+    let mut hit_counts = HashMap::new();
+    let paused = r1_values
+        .iter()
+        .map(|&r1| {
+            let mut context = TestContext {
+                registers: Registers {
+                    r1,
+                    ..Registers::default()
+                },
+            };
+            should_pause_on_breakpoint(
+                &channels.vm.tx,
+                &mut hit_counts,
+                BREAKPOINT_ID,
+                conditions,
+                &mut context,
+            )
+        })
+        .collect();
+    let messages = channels
+        .debugger
+        .rx
+        .try_iter()
+        .map(|message| match message {
+            VmMessage::Log(message) => message,
+            VmMessage::Paused(..) => panic!("Only log messages should be sent"),
+        })
+        .collect();
+    (paused, messages)
+}
+
+#[test]
+fn test_breakpoint_without_conditions_always_pauses() {
+    assert_eq!(
+        (vec![true, true], vec![]),
+        hit_breakpoint(&BreakpointConditions::default(), &[0, 0])
+    );
+}
+
+#[test]
+fn test_breakpoint_condition() {
+    let (paused, _) = hit_breakpoint(&conditions(Some("r1 == 2"), None, None), &[1, 2, 3]);
+    assert_eq!(vec![false, true, false], paused);
+
+    // Hits where the condition is false don't count towards the hit condition
+    let (paused, _) = hit_breakpoint(
+        &conditions(Some("r1 != 0"), Some(">= 2"), None),
+        &[1, 0, 0, 1, 1],
+    );
+    assert_eq!(vec![false, false, false, true, true], paused);
+}
+
+#[test]
+fn test_breakpoint_condition_that_cant_be_evaluated_pauses() {
+    let (paused, messages) = hit_breakpoint(&conditions(Some("[0x10] == 1"), None, None), &[0]);
+    assert_eq!(vec![true], paused);
+    assert_eq!(1, messages.len());
+    assert!(messages[0].contains("Could not evaluate the condition of breakpoint 7"));
+}
+
+#[test]
+fn test_breakpoint_hit_conditions() {
+    let (paused, _) = hit_breakpoint(&conditions(None, Some(">= 3"), None), &[0; 5]);
+    assert_eq!(vec![false, false, true, true, true], paused);
+
+    let (paused, _) = hit_breakpoint(&conditions(None, Some("% 2"), None), &[0; 5]);
+    assert_eq!(vec![false, true, false, true, false], paused);
+}
+
+#[test]
+fn test_logpoints_never_pause() {
+    let (paused, messages) = hit_breakpoint(
+        &conditions(None, Some("% 2"), Some("r1 is {r1}")),
+        &[1, 2, 3, 4],
+    );
+    assert_eq!(vec![false; 4], paused);
+    assert_eq!(vec!["r1 is 0x2", "r1 is 0x4"], messages);
+}
+
+#[test]
+fn test_hit_counts_are_pruned_when_breakpoints_are_updated() {
+    let mut debug_state = DebugState::new(create_server_channels().vm, false);
+    debug_state.hit_counts = HashMap::from([(1, 3), (2, 5)]);
+
+    handle_debug_message(
+        DebuggerMessage::UpdateBreakpoints(HashSet::from([BreakpointRef {
+            breakpoint_id: 2,
+            pc: 0x100,
+            conditions: BreakpointConditions::default(),
+        }])),
+        &mut debug_state,
+    );
+    assert_eq!(HashMap::from([(2, 5)]), debug_state.hit_counts);
+
+    handle_debug_message(
+        DebuggerMessage::UpdateBreakpoints(HashSet::new()),
+        &mut debug_state,
+    );
+    assert!(debug_state.hit_counts.is_empty());
+}
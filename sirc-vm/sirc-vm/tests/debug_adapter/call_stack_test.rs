@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use peripheral_cpu::{
+    coprocessors::processing_unit::{
+        definitions::{ConditionFlags, ImmediateInstructionData, Instruction, InstructionData},
+        encoding::encode_instruction,
+    },
+    registers::{
+        ExceptionLinkRegister, ExceptionUnitRegisters, FullAddressRegisterAccess, Registers,
+    },
+};
+use sirc_vm::debug_adapter::{
+    call_stack::{is_call_instruction, unwind_call_stack, CallFrame, FrameKind},
+    types::SymbolTable,
+};
+
+const STACK_POINTER: u32 = 0x80;
+
+fn branch(op_code: Instruction) -> [u16; 2] {
+    // BRSR/BRAN #4
+    let [a, b, c, d] = encode_instruction(&InstructionData::Immediate(ImmediateInstructionData {
+        op_code,
+        register: 3,
+        value: 4,
+        condition_flag: ConditionFlags::Always,
+        additional_flags: 3,
+    }));
+    [u16::from_be_bytes([a, b]), u16::from_be_bytes([c, d])]
+}
+
+fn symbols() -> SymbolTable {
+    BTreeMap::from([
+        ("main".to_string(), 0x00),
+        ("func_a".to_string(), 0x30),
+        ("func_b".to_string(), 0x40),
+        ("handler".to_string(), 0x50),
+    ])
+}
+
+///
+/// A program where main calls `func_a` (returning to 0x12), which saves the link register and calls
+/// `func_b` (returning to 0x36)
+///
+fn memory() -> Vec<u16> {
+    let mut memory = vec![0; 0x100];
+    let call = branch(Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate);
+    memory[0x10..0x12].copy_from_slice(&call);
+    memory[0x34..0x36].copy_from_slice(&call);
+    let stack_pointer = STACK_POINTER as usize;
+    memory[stack_pointer..stack_pointer + 2].copy_from_slice(&[0x0012, 0x0000]);
+    memory
+}
+
+fn registers(pc: u32, link: u32) -> Registers {
+    let mut registers = Registers::default();
+    registers.set_full_pc_address(pc);
+    registers.set_full_link_address(link);
+    registers.set_full_sp_address(STACK_POINTER);
+    registers
+}
+
+fn unwind(registers: &Registers, eu_registers: &ExceptionUnitRegisters) -> Vec<CallFrame> {
+    let memory = memory();
+    unwind_call_stack(registers, eu_registers, &symbols(), |address, count| {
+        memory
+            .iter()
+            .skip(address as usize)
+            .take(count as usize)
+            .copied()
+            .collect()
+    })
+}
+
+const fn frame(pc: u32, kind: FrameKind) -> CallFrame {
+    CallFrame { pc, kind }
+}
+
+#[test]
+fn test_is_call_instruction() {
+    assert!(is_call_instruction(branch(
+        Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate
+    )));
+    assert!(!is_call_instruction(branch(
+        Instruction::LoadEffectiveAddressFromIndirectImmediate
+    )));
+    assert!(!is_call_instruction([0, 0]));
+}
+
+#[test]
+fn test_unwind_nested_subroutines() {
+    assert_eq!(
+        vec![
+            frame(0x42, FrameKind::Current),
+            frame(0x36, FrameKind::Subroutine),
+            frame(0x12, FrameKind::Subroutine),
+        ],
+        unwind(&registers(0x42, 0x36), &ExceptionUnitRegisters::default())
+    );
+}
+
+#[test]
+fn test_unwind_link_register_saved_to_stack() {
+    // func_a has saved the link register but not called anything yet
+    assert_eq!(
+        vec![
+            frame(0x32, FrameKind::Current),
+            frame(0x12, FrameKind::Subroutine),
+        ],
+        unwind(&registers(0x32, 0x12), &ExceptionUnitRegisters::default())
+    );
+}
+
+#[test]
+fn test_unwind_ignores_stale_link_register() {
+    // func_a has already returned from func_b
+    assert_eq!(
+        vec![
+            frame(0x38, FrameKind::Current),
+            frame(0x12, FrameKind::Subroutine),
+        ],
+        unwind(&registers(0x38, 0x36), &ExceptionUnitRegisters::default())
+    );
+}
+
+#[test]
+fn test_unwind_exception() {
+    // A fault in func_a after it returned from func_b
+    let mut eu_registers = ExceptionUnitRegisters {
+        current_exception_level: 7,
+        ..ExceptionUnitRegisters::default()
+    };
+    eu_registers.link_registers[6] = ExceptionLinkRegister {
+        return_address: 0x38,
+        return_status_register: 0,
+        saved_exception_level: 0,
+    };
+    assert_eq!(
+        vec![
+            frame(0x50, FrameKind::Current),
+            frame(0x38, FrameKind::Exception(7)),
+            frame(0x12, FrameKind::Subroutine),
+        ],
+        unwind(&registers(0x50, 0x36), &eu_registers)
+    );
+}
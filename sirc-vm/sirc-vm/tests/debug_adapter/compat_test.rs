@@ -0,0 +1,32 @@
+use std::io::{BufReader, Read};
+
+use sirc_vm::debug_adapter::compat::NormalisingReader;
+
+fn frame(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+#[test]
+fn test_normalising_reader_fixes_content_length() {
+    let input = [
+        frame(r#"{"command":"next"}"#),
+        frame(r#"{"command":"setBreakpoints","arguments":{"breakpoints":[{"hitCondition":"2"}]}}"#),
+    ]
+    .concat();
+
+    let mut output = String::new();
+    NormalisingReader::new(BufReader::new(input.as_bytes()))
+        .read_to_string(&mut output)
+        .unwrap();
+
+    assert_eq!(
+        [
+            frame(r#"{"command":"next"}"#),
+            frame(
+                r#"{"arguments":{"breakpoints":[{"hit_condition":"2"}]},"command":"setBreakpoints"}"#
+            ),
+        ]
+        .concat(),
+        output
+    );
+}
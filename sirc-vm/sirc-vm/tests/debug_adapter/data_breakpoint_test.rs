@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+
+use dap::requests::{DataBreakpointInfoArguments, SetDataBreakpointsArguments};
+use dap::types::{DataBreakpoint, DataBreakpointAccessType};
+use peripheral_bus::device::{BusAccessType, BusAssertions, BusOperation};
+use sirc_vm::debug_adapter::server::{
+    create_server_channels, data_breakpoint_info, data_breakpoints,
+};
+use sirc_vm::debug_adapter::types::{BreakpointConditions, DataAccessType, DataBreakpointRef};
+use sirc_vm::debugger::check_data_breakpoints;
+use sirc_vm::DebugState;
+
+const WATCHED_ADDRESS: u32 = 0x00_1234;
+
+fn debug_state(access_type: DataAccessType) -> DebugState {
+    let mut debug_state = DebugState::new(create_server_channels().vm, false);
+    debug_state.data_breakpoints = vec![DataBreakpointRef {
+        breakpoint_id: 1,
+        address: WATCHED_ADDRESS,
+        access_type,
+        conditions: BreakpointConditions::default(),
+    }];
+    debug_state
+}
+
+/// The bus assertions after a data access to the given address has been acknowledged
+fn completed_access(address: u32, op: BusOperation) -> BusAssertions {
+    BusAssertions {
+        address,
+        op,
+        bus_access_strobe: true,
+        bus_acknowledge: true,
+        bus_access_type: match op {
+            BusOperation::Read => BusAccessType::DataRead,
+            BusOperation::Write => BusAccessType::DataWrite,
+        },
+        ..BusAssertions::default()
+    }
+}
+
+fn is_triggered(access_type: DataAccessType, bus_assertions: &BusAssertions) -> bool {
+    let mut debug_state = debug_state(access_type);
+    check_data_breakpoints(&mut debug_state, bus_assertions);
+    debug_state.triggered_data_breakpoint.is_some()
+}
+
+#[test]
+fn test_data_breakpoints_match_the_access_type() {
+    let read = completed_access(WATCHED_ADDRESS, BusOperation::Read);
+    let write = completed_access(WATCHED_ADDRESS, BusOperation::Write);
+
+    assert!(is_triggered(DataAccessType::Read, &read));
+    assert!(!is_triggered(DataAccessType::Read, &write));
+    assert!(!is_triggered(DataAccessType::Write, &read));
+    assert!(is_triggered(DataAccessType::Write, &write));
+    assert!(is_triggered(DataAccessType::ReadWrite, &read));
+    assert!(is_triggered(DataAccessType::ReadWrite, &write));
+}
+
+#[test]
+fn test_data_breakpoints_ignore_other_accesses() {
+    let other_address = completed_access(WATCHED_ADDRESS + 1, BusOperation::Write);
+    assert!(!is_triggered(DataAccessType::ReadWrite, &other_address));
+
+    // Instruction fetches are covered by instruction breakpoints
+    let instruction_fetch = BusAssertions {
+        bus_access_type: BusAccessType::InstructionFetch,
+        ..completed_access(WATCHED_ADDRESS, BusOperation::Read)
+    };
+    assert!(!is_triggered(DataAccessType::ReadWrite, &instruction_fetch));
+
+    // The access only counts once a device has acknowledged it
+    let in_flight = BusAssertions {
+        bus_acknowledge: false,
+        ..completed_access(WATCHED_ADDRESS, BusOperation::Write)
+    };
+    assert!(!is_triggered(DataAccessType::ReadWrite, &in_flight));
+}
+
+#[test]
+fn test_first_triggered_data_breakpoint_is_kept() {
+    let mut debug_state = debug_state(DataAccessType::ReadWrite);
+    debug_state.data_breakpoints.push(DataBreakpointRef {
+        breakpoint_id: 2,
+        address: WATCHED_ADDRESS + 1,
+        access_type: DataAccessType::ReadWrite,
+        conditions: BreakpointConditions::default(),
+    });
+
+    check_data_breakpoints(
+        &mut debug_state,
+        &completed_access(WATCHED_ADDRESS, BusOperation::Read),
+    );
+    check_data_breakpoints(
+        &mut debug_state,
+        &completed_access(WATCHED_ADDRESS + 1, BusOperation::Read),
+    );
+    assert_eq!(
+        Some(1),
+        debug_state
+            .triggered_data_breakpoint
+            .map(|b| b.breakpoint_id)
+    );
+}
+
+#[test]
+fn test_data_breakpoint_info() {
+    let info = data_breakpoint_info(&DataBreakpointInfoArguments {
+        variables_reference: None,
+        name: "0x001234".to_string(),
+        frame_id: None,
+    });
+    assert_eq!(Some("0x001234".to_string()), info.data_id);
+    assert_eq!(Some(true), info.can_persist);
+
+    // Expressions that aren't addresses (e.g. a register name) can't be watched
+    let info = data_breakpoint_info(&DataBreakpointInfoArguments {
+        variables_reference: None,
+        name: "r1 + 2".to_string(),
+        frame_id: None,
+    });
+    assert_eq!(None, info.data_id);
+    assert!(info.description.contains("[r1 + 2] is not an address"));
+
+    // Variables in a scope are registers, even if their name looks like a hex number
+    let info = data_breakpoint_info(&DataBreakpointInfoArguments {
+        variables_reference: Some(1),
+        name: "a".to_string(),
+        frame_id: None,
+    });
+    assert_eq!(None, info.data_id);
+}
+
+#[test]
+fn test_set_data_breakpoints() {
+    let mut next_id = 0;
+    let (breakpoints, refs) = data_breakpoints(
+        &SetDataBreakpointsArguments {
+            breakpoints: vec![
+                DataBreakpoint {
+                    data_id: "0x001234".to_string(),
+                    access_type: Some(DataBreakpointAccessType::Read),
+                    condition: None,
+                    hit_condition: None,
+                },
+                DataBreakpoint {
+                    data_id: "not_an_address".to_string(),
+                    access_type: None,
+                    condition: None,
+                    hit_condition: None,
+                },
+                DataBreakpoint {
+                    data_id: "0x000010".to_string(),
+                    access_type: None,
+                    condition: None,
+                    hit_condition: None,
+                },
+            ],
+        },
+        &BTreeMap::new(),
+        || {
+            next_id += 1;
+            next_id
+        },
+    );
+
+    assert_eq!(
+        vec![true, false, true],
+        breakpoints.iter().map(|b| b.verified).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![
+            (1, WATCHED_ADDRESS, DataAccessType::Read),
+            // Watching for writes is the default
+            (3, 0x10, DataAccessType::Write),
+        ],
+        refs.iter()
+            .map(|b| (b.breakpoint_id, b.address, b.access_type))
+            .collect::<Vec<_>>()
+    );
+}
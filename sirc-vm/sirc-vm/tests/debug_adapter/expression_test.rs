@@ -0,0 +1,192 @@
+use peripheral_cpu::registers::Registers;
+use sirc_vm::debug_adapter::expression::{
+    evaluate, format_log_message, parse_expression, parse_hit_condition, parse_log_message,
+    register_value, EvaluationContext, ExpressionError, HitCondition,
+};
+use sirc_vm::debug_adapter::types::MemoryAccessError;
+
+/// Only the first 0x100 words are mapped, and each word contains its own address
+struct TestContext {
+    registers: Registers,
+}
+
+impl EvaluationContext for TestContext {
+    fn identifier(&self, name: &str) -> Option<u32> {
+        register_value(&self.registers, name)
+    }
+
+    fn read_memory(&mut self, address: u32) -> Option<u16> {
+        (address < 0x100).then(|| u16::try_from(address).unwrap())
+    }
+}
+
+fn evaluate_str(input: &str) -> Result<u32, ExpressionError> {
+    let mut context = TestContext {
+        registers: Registers {
+            r1: 5,
+            r2: 0xFFFF,
+            ah: 0x00,
+            al: 0x10,
+            ..Registers::default()
+        },
+    };
+    evaluate(&parse_expression(input)?, &mut context)
+}
+
+#[test]
+fn test_evaluate_numbers() {
+    assert_eq!(Ok(10), evaluate_str("10"));
+    assert_eq!(Ok(0xCAFE), evaluate_str("#0xCAFE"));
+    assert_eq!(Ok(0b101), evaluate_str("0b101"));
+    assert_eq!(Ok(0x1234_5678), evaluate_str("0x1234_5678"));
+}
+
+#[test]
+fn test_evaluate_precedence() {
+    assert_eq!(Ok(7), evaluate_str("1 + 2 * 3"));
+    assert_eq!(Ok(9), evaluate_str("(1 + 2) * 3"));
+    assert_eq!(Ok(1), evaluate_str("1 + 1 == 2"));
+    assert_eq!(Ok(1), evaluate_str("1 == 2 || 2 == 2 && 3 == 3"));
+    assert_eq!(Ok(0x10), evaluate_str("1 << 2 + 2"));
+    assert_eq!(Ok(3), evaluate_str("10 - 4 - 3"));
+}
+
+#[test]
+fn test_evaluate_unary() {
+    assert_eq!(Ok(0xFFFF_FFFF), evaluate_str("-1"));
+    assert_eq!(Ok(1), evaluate_str("!0"));
+    assert_eq!(Ok(0xFFFF_FF00), evaluate_str("~0xFF"));
+    assert_eq!(Ok(1), evaluate_str("--1"));
+}
+
+#[test]
+fn test_evaluate_registers() {
+    assert_eq!(Ok(1), evaluate_str("r1 == 5"));
+    assert_eq!(Ok(1), evaluate_str("R2 == 0xFFFF"));
+    assert_eq!(Ok(0x10), evaluate_str("a"));
+    assert_eq!(
+        Err(ExpressionError::UnknownIdentifier("r9".to_string())),
+        evaluate_str("r9 == 1")
+    );
+}
+
+#[test]
+fn test_evaluate_memory() {
+    assert_eq!(Ok(0x14), evaluate_str("[a + 4]"));
+    assert_eq!(Ok(0x15), evaluate_str("[[a + 4] + 1]"));
+    assert_eq!(
+        Err(ExpressionError::Memory(MemoryAccessError::Unmapped {
+            address: 0x100
+        })),
+        evaluate_str("[0x100]")
+    );
+    // Addresses wrap at 24 bits like they do on the bus
+    assert_eq!(Ok(0x20), evaluate_str("[0x0100_0020]"));
+}
+
+#[test]
+fn test_evaluate_short_circuits() {
+    assert_eq!(Ok(0), evaluate_str("0 && [0x100]"));
+    assert_eq!(Ok(1), evaluate_str("1 || [0x100]"));
+    assert_eq!(
+        Err(ExpressionError::Memory(MemoryAccessError::Unmapped {
+            address: 0x100
+        })),
+        evaluate_str("1 && [0x100]")
+    );
+}
+
+#[test]
+fn test_evaluate_errors() {
+    assert_eq!(Err(ExpressionError::DivisionByZero), evaluate_str("1 / 0"));
+    assert_eq!(
+        Err(ExpressionError::DivisionByZero),
+        evaluate_str("1 % (r1 - 5)")
+    );
+    assert!(matches!(
+        parse_expression(""),
+        Err(ExpressionError::Syntax(_))
+    ));
+    assert!(matches!(
+        parse_expression("1 +"),
+        Err(ExpressionError::Syntax(_))
+    ));
+    assert!(matches!(
+        parse_expression("[a"),
+        Err(ExpressionError::Syntax(_))
+    ));
+    assert!(matches!(
+        parse_expression("1 2"),
+        Err(ExpressionError::Syntax(_))
+    ));
+    assert!(matches!(
+        parse_expression("r1 = 2"),
+        Err(ExpressionError::Syntax(_))
+    ));
+    assert!(matches!(
+        parse_expression("0xZZ"),
+        Err(ExpressionError::Syntax(_))
+    ));
+}
+
+#[test]
+fn test_parse_hit_condition() {
+    assert_eq!(
+        Ok(HitCondition::GreaterThanOrEqual(3)),
+        parse_hit_condition(" 3 ")
+    );
+    assert_eq!(Ok(HitCondition::Equal(3)), parse_hit_condition("==3"));
+    assert_eq!(Ok(HitCondition::GreaterThan(3)), parse_hit_condition("> 3"));
+    assert_eq!(
+        Ok(HitCondition::LessThanOrEqual(3)),
+        parse_hit_condition("<= 3")
+    );
+    assert_eq!(Ok(HitCondition::LessThan(3)), parse_hit_condition("<3"));
+    assert_eq!(Ok(HitCondition::Multiple(3)), parse_hit_condition("%3"));
+    assert!(parse_hit_condition("often").is_err());
+    assert!(parse_hit_condition("% 0").is_err());
+}
+
+#[test]
+fn test_hit_condition_is_met() {
+    let every_third: Vec<u64> = (1..=9)
+        .filter(|count| HitCondition::Multiple(3).is_met(*count))
+        .collect();
+    assert_eq!(vec![3, 6, 9], every_third);
+
+    assert!(!HitCondition::GreaterThanOrEqual(3).is_met(2));
+    assert!(HitCondition::GreaterThanOrEqual(3).is_met(3));
+    assert!(HitCondition::Equal(3).is_met(3));
+    assert!(!HitCondition::Equal(3).is_met(4));
+}
+
+#[test]
+fn test_format_log_message() {
+    let mut context = TestContext {
+        registers: Registers {
+            r1: 0xCAFE,
+            ..Registers::default()
+        },
+    };
+
+    let message = parse_log_message("r1={r1} [r1]={[r1]} {{literal}}").unwrap();
+    assert_eq!(
+        "r1=0xCAFE [r1]=<No segment mapped to address 0x00CAFE> {literal}",
+        format_log_message(&message, &mut context)
+    );
+    assert!(parse_log_message("{r1 +}").is_err());
+    assert!(parse_log_message("{r1").is_err());
+    assert!(parse_log_message("unmatched }").is_err());
+}
+
+#[test]
+fn test_is_assignable() {
+    let is_assignable = |input| parse_expression(input).unwrap().is_assignable();
+
+    assert!(is_assignable("r1"));
+    assert!(is_assignable("A"));
+    assert!(is_assignable("[a + 4]"));
+    assert!(!is_assignable("1"));
+    assert!(!is_assignable("r1 + 1"));
+    assert!(!is_assignable("some_label"));
+}
@@ -0,0 +1,429 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use peripheral_cpu::registers::{register_index_to_name, FullAddressRegisterAccess, Registers};
+use sirc_vm::debug_adapter::expression::Expression;
+use sirc_vm::debug_adapter::gdb_server::{
+    checksum, encode_registers, frame_packet, parse_breakpoint_packet, start_gdb_server,
+    target_description, ClientEvent, PacketDecoder, REGISTER_COUNT,
+};
+use sirc_vm::debug_adapter::server::create_server_channels;
+use sirc_vm::debug_adapter::types::{
+    BreakpointConditions, BreakpointRef, DataAccessType, DataBreakpointRef, DebuggerMessage,
+    ResumeCondition, VmChannels, VmMessage, VmPauseReason, VmState,
+};
+
+use crate::utils::network::{connect, free_address};
+
+fn decode(bytes: &[u8]) -> Vec<ClientEvent> {
+    let mut decoder = PacketDecoder::default();
+    bytes
+        .iter()
+        .filter_map(|byte| decoder.push(*byte))
+        .collect()
+}
+
+#[test]
+fn test_frame_packet() {
+    assert_eq!(0x9a, checksum(b"OK"));
+    assert_eq!("$OK#9a", frame_packet("OK"));
+    assert_eq!("$#00", frame_packet(""));
+}
+
+#[test]
+fn test_decode_packets() {
+    assert_eq!(
+        vec![
+            ClientEvent::Packet("g".to_string()),
+            ClientEvent::Interrupt,
+            ClientEvent::Packet("m400,8".to_string()),
+        ],
+        // Acknowledgements between packets are ignored
+        decode(b"+$g#67+\x03$m400,8#65")
+    );
+    assert_eq!(vec![ClientEvent::Corrupt], decode(b"$g#00"));
+}
+
+#[test]
+fn test_encode_registers() {
+    let mut registers = Registers {
+        r1: 0x1234,
+        ..Registers::default()
+    };
+    registers.set_full_pc_address(0x0A_BCDE);
+
+    assert_eq!(
+        "00001234000000000000000000000000000000000000000000000000000abcde",
+        encode_registers(&registers)
+    );
+}
+
+#[test]
+fn test_parse_breakpoint_packet() {
+    assert_eq!(Some((0, 0x404, 4)), parse_breakpoint_packet("0,404,4"));
+    // Conditions are ignored
+    assert_eq!(Some((2, 0x800, 2)), parse_breakpoint_packet("2,800,2;X1,0"));
+    assert_eq!(None, parse_breakpoint_packet("0,404"));
+    assert_eq!(None, parse_breakpoint_packet("x,404,4"));
+}
+
+#[test]
+fn test_target_description() {
+    let description = target_description();
+    assert_eq!(16, description.matches("<reg ").count());
+    assert!(description.contains("<reg name=\"pl\" bitsize=\"16\" type=\"uint16\"/>"));
+}
+
+///
+/// A GDB client connected to the server, where the test plays the part of the VM (so that it can
+/// check exactly what the server asks the VM to do).
+///
+struct TestSession {
+    stream: TcpStream,
+    vm: VmChannels,
+    server: JoinHandle<()>,
+    registers: Registers,
+    memory: Vec<u16>,
+}
+
+impl TestSession {
+    fn start() -> Self {
+        let channels = create_server_channels();
+        let address = free_address();
+        let server = thread::spawn(move || start_gdb_server(channels.debugger, address).unwrap());
+        let mut session = Self {
+            stream: connect(address),
+            vm: channels.vm,
+            server,
+            registers: Registers::default(),
+            memory: vec![0x1234, 0x5678, 0x9ABC, 0xDEF0],
+        };
+
+        // The VM is running when the client attaches, so asking why it stopped pauses it
+        session.send("?");
+        assert!(matches!(session.next_message(), DebuggerMessage::PauseVm));
+        session.pause(VmPauseReason::Init);
+        assert_eq!("T05thread:1;", session.reply());
+        session
+    }
+
+    fn send(&mut self, packet: &str) {
+        self.stream
+            .write_all(frame_packet(packet).as_bytes())
+            .unwrap();
+    }
+
+    /// The next packet from the server (acknowledgements are skipped)
+    fn reply(&mut self) -> String {
+        let mut decoder = PacketDecoder::default();
+        let mut byte = [0];
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            match decoder.push(byte[0]) {
+                Some(ClientEvent::Packet(packet)) => return packet,
+                Some(event) => panic!("Expected a packet from the server but got {event:?}"),
+                None => {}
+            }
+        }
+    }
+
+    fn next_message(&self) -> DebuggerMessage {
+        self.vm
+            .rx
+            .recv_timeout(Duration::from_secs(10))
+            .expect("The server should have sent a message to the VM")
+    }
+
+    fn vm_state(&self) -> VmState {
+        VmState {
+            registers: self.registers,
+            ..VmState::default()
+        }
+    }
+
+    fn pause(&self, reason: VmPauseReason) {
+        self.vm
+            .tx
+            .send(VmMessage::Paused(reason, Box::new(self.vm_state())))
+            .unwrap();
+    }
+
+    /// Answers a memory read, checking that the server asked for the expected words
+    fn serve_read(&self, expected_address: u32, expected_count: u32) {
+        match self.next_message() {
+            DebuggerMessage::ReadMemory {
+                address,
+                count,
+                reply,
+            } => {
+                assert_eq!((expected_address, expected_count), (address, count));
+                let words = &self.memory[address as usize..(address + count) as usize];
+                reply.send(Ok(words.to_vec())).unwrap();
+            }
+            message => panic!("Expected a memory read but got {message:?}"),
+        }
+    }
+
+    /// Answers a memory write, returning the address and words that were written
+    fn serve_write(&mut self) -> (u32, Vec<u16>) {
+        match self.next_message() {
+            DebuggerMessage::WriteMemory {
+                address,
+                words,
+                reply,
+            } => {
+                let start = address as usize;
+                self.memory[start..start + words.len()].copy_from_slice(&words);
+                reply.send(Ok(())).unwrap();
+                (address, words)
+            }
+            message => panic!("Expected a memory write but got {message:?}"),
+        }
+    }
+
+    /// Answers a register assignment, returning the name of the register and the value
+    fn serve_assign(&mut self) -> (String, u16) {
+        match self.next_message() {
+            DebuggerMessage::Assign {
+                target: Expression::Identifier(name),
+                value: Expression::Number(value),
+                reply,
+            } => {
+                let index = (0..REGISTER_COUNT)
+                    .find(|index| register_index_to_name(*index) == Some(name.as_str()))
+                    .unwrap();
+                let value = u16::try_from(value).unwrap();
+                self.registers[index] = value;
+                reply.send(Ok((u32::from(value), self.vm_state()))).unwrap();
+                (name, value)
+            }
+            message => panic!("Expected a register assignment but got {message:?}"),
+        }
+    }
+
+    /// The PCs of the breakpoints that the server sent to the VM
+    fn breakpoints(&self) -> Vec<u32> {
+        match self.next_message() {
+            DebuggerMessage::UpdateBreakpoints(breakpoints) => {
+                let mut pcs: Vec<u32> =
+                    breakpoints.iter().map(|breakpoint| breakpoint.pc).collect();
+                pcs.sort_unstable();
+                pcs
+            }
+            message => panic!("Expected breakpoints but got {message:?}"),
+        }
+    }
+
+    /// The word addresses and access types of the watchpoints that the server sent to the VM
+    fn watchpoints(&self) -> Vec<(u32, DataAccessType)> {
+        match self.next_message() {
+            DebuggerMessage::UpdateDataBreakpoints(watchpoints) => watchpoints
+                .iter()
+                .map(|watchpoint| (watchpoint.address, watchpoint.access_type))
+                .collect(),
+            message => panic!("Expected watchpoints but got {message:?}"),
+        }
+    }
+
+    /// Checks that the server didn't send anything else to the VM, and stops it
+    fn finish(self) {
+        assert!(self.vm.rx.try_recv().is_err());
+        drop(self.vm);
+        drop(self.stream);
+        self.server.join().unwrap();
+    }
+}
+
+#[test]
+fn test_gdb_memory_is_addressed_in_bytes() {
+    let mut session = TestSession::start();
+
+    // Starts with the low byte of the word at 0x1
+    session.send("m3,3");
+    session.serve_read(0x1, 2);
+    assert_eq!("789abc", session.reply());
+
+    session.send("m2,2");
+    session.serve_read(0x1, 1);
+    assert_eq!("5678", session.reply());
+
+    // The bytes either side of the write are kept
+    session.send("M1,2:aabb");
+    session.serve_read(0x0, 2);
+    assert_eq!((0x0, vec![0x12AA, 0xBB78]), session.serve_write());
+    assert_eq!("OK", session.reply());
+
+    session.send("m0,4");
+    session.serve_read(0x0, 2);
+    assert_eq!("12aabb78", session.reply());
+
+    // The length doesn't match the data
+    session.send("M0,4:aabb");
+    assert_eq!("E16", session.reply());
+
+    session.finish();
+}
+
+#[test]
+fn test_gdb_breakpoints_and_watchpoints() {
+    let mut session = TestSession::start();
+
+    session.send("Z0,400,4");
+    assert_eq!(vec![0x200], session.breakpoints());
+    assert_eq!("OK", session.reply());
+
+    // Hardware breakpoints are the same as software ones
+    session.send("Z1,404,4");
+    assert_eq!(vec![0x200, 0x202], session.breakpoints());
+    assert_eq!("OK", session.reply());
+
+    session.send("z0,400,4");
+    assert_eq!(vec![0x202], session.breakpoints());
+    assert_eq!("OK", session.reply());
+
+    // Instructions are always word aligned
+    session.send("Z0,401,4");
+    assert_eq!("E16", session.reply());
+
+    // Every word in the range is watched
+    session.send("Z2,800,4");
+    assert_eq!(
+        vec![
+            (0x400, DataAccessType::Write),
+            (0x401, DataAccessType::Write)
+        ],
+        session.watchpoints()
+    );
+    assert_eq!("OK", session.reply());
+
+    session.send("Z3,801,1");
+    assert_eq!(
+        vec![
+            (0x400, DataAccessType::Write),
+            (0x401, DataAccessType::Write),
+            (0x400, DataAccessType::Read),
+        ],
+        session.watchpoints()
+    );
+    assert_eq!("OK", session.reply());
+
+    session.send("Z4,804,2");
+    assert_eq!(
+        vec![
+            (0x400, DataAccessType::Write),
+            (0x401, DataAccessType::Write),
+            (0x400, DataAccessType::Read),
+            (0x402, DataAccessType::ReadWrite),
+        ],
+        session.watchpoints()
+    );
+    assert_eq!("OK", session.reply());
+
+    // Only the watchpoints of the same type are removed
+    session.send("z2,800,4");
+    assert_eq!(
+        vec![
+            (0x400, DataAccessType::Read),
+            (0x402, DataAccessType::ReadWrite)
+        ],
+        session.watchpoints()
+    );
+    assert_eq!("OK", session.reply());
+
+    session.finish();
+}
+
+#[test]
+fn test_gdb_stop_replies() {
+    let mut session = TestSession::start();
+
+    session.send("s");
+    assert!(matches!(
+        session.next_message(),
+        DebuggerMessage::ResumeVm(ResumeCondition::UntilNextStep)
+    ));
+    session.pause(VmPauseReason::Step);
+    assert_eq!("T05thread:1;", session.reply());
+
+    session.send("c");
+    assert!(matches!(
+        session.next_message(),
+        DebuggerMessage::ResumeVm(ResumeCondition::None)
+    ));
+    // The VM can't be resumed while it is running
+    session.send("c");
+    assert_eq!("E05", session.reply());
+    // Output is passed on while the client is waiting
+    session
+        .vm
+        .tx
+        .send(VmMessage::Log("Hi".to_string()))
+        .unwrap();
+    assert_eq!("O48690a", session.reply());
+    session.pause(VmPauseReason::DataBreakpoint(DataBreakpointRef {
+        breakpoint_id: 1,
+        address: 0x400,
+        access_type: DataAccessType::Write,
+        conditions: BreakpointConditions::default(),
+    }));
+    assert_eq!("T05watch:800;thread:1;", session.reply());
+
+    session.send("c");
+    assert!(matches!(
+        session.next_message(),
+        DebuggerMessage::ResumeVm(ResumeCondition::None)
+    ));
+    session.pause(VmPauseReason::Breakpoint(BreakpointRef {
+        breakpoint_id: 2,
+        pc: 0x200,
+        conditions: BreakpointConditions::default(),
+    }));
+    assert_eq!("T05thread:1;", session.reply());
+
+    // The last stop reply is repeated
+    session.send("?");
+    assert_eq!("T05thread:1;", session.reply());
+
+    session.finish();
+}
+
+#[test]
+fn test_gdb_registers_are_written() {
+    let mut session = TestSession::start();
+
+    session.send("P1=abcd");
+    assert_eq!(("r1".to_string(), 0xABCD), session.serve_assign());
+    assert_eq!("OK", session.reply());
+
+    session.send("p1");
+    assert_eq!("abcd", session.reply());
+
+    let registers = (0..REGISTER_COUNT).fold(Registers::default(), |mut registers, index| {
+        registers[index] = u16::from(index) * 0x1111;
+        registers
+    });
+    session.send(&format!("G{}", encode_registers(&registers)));
+    for index in 0..REGISTER_COUNT {
+        assert_eq!(
+            (
+                register_index_to_name(index).unwrap().to_string(),
+                registers[index]
+            ),
+            session.serve_assign()
+        );
+    }
+    assert_eq!("OK", session.reply());
+
+    session.send("g");
+    assert_eq!(encode_registers(&registers), session.reply());
+
+    // There are only 16 registers
+    session.send("P10=1");
+    assert_eq!("E05", session.reply());
+    session.send("G1234");
+    assert_eq!("E16", session.reply());
+
+    session.finish();
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+
+use sirc_vm::debug_adapter::gdb_server::{frame_packet, start_gdb_server};
+use sirc_vm::debug_adapter::server::{create_server_channels, start_server};
+use sirc_vm::debug_adapter::types::ProgramDebugInfo;
+
+use crate::utils::network::{connect, free_address};
+
+/// Everything that the server sends before it closes the connection
+fn read_until_closed(stream: &mut TcpStream) -> String {
+    let mut output = String::new();
+    stream.read_to_string(&mut output).unwrap();
+    output
+}
+
+#[test]
+fn test_second_debug_adapter_client_is_turned_away() {
+    let channels = create_server_channels();
+    let address = free_address();
+    let server = thread::spawn(move || {
+        let program_debug_info = ProgramDebugInfo {
+            debug_info_map: BTreeMap::new(),
+            symbols: BTreeMap::new(),
+        };
+        start_server(channels.debugger, &program_debug_info, address).unwrap();
+    });
+
+    let first = connect(address);
+    let mut second = connect(address);
+    let initialize =
+        r#"{"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"test"}}"#;
+    write!(
+        second,
+        "Content-Length: {}\r\n\r\n{initialize}",
+        initialize.len()
+    )
+    .unwrap();
+
+    let output = read_until_closed(&mut second);
+    assert!(output.contains(r#""success":false"#), "{output}");
+    assert!(
+        output.contains("Another debugger is already attached"),
+        "{output}"
+    );
+    assert!(output.contains(r#""event":"terminated""#), "{output}");
+
+    // The server stops once the first debugger has gone and the VM has exited
+    drop(first);
+    drop(channels.vm);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_second_gdb_client_is_turned_away() {
+    let channels = create_server_channels();
+    let address = free_address();
+    let server = thread::spawn(move || start_gdb_server(channels.debugger, address).unwrap());
+
+    let first = connect(address);
+    let mut second = connect(address);
+    second
+        .write_all(frame_packet("qSupported").as_bytes())
+        .unwrap();
+
+    assert_eq!(
+        format!("+{}", frame_packet("E05")),
+        read_until_closed(&mut second)
+    );
+
+    drop(first);
+    drop(channels.vm);
+    server.join().unwrap();
+}
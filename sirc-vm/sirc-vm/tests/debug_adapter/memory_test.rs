@@ -0,0 +1,80 @@
+use sirc_vm::debug_adapter::memory::{
+    bytes_from_words, format_memory_reference, merge_bytes_into_words, offset_address,
+    parse_memory_reference, word_range_for_bytes, WordRange,
+};
+
+#[test]
+fn test_format_memory_reference() {
+    assert_eq!("0x00FABC", format_memory_reference(0xFABC));
+}
+
+#[test]
+fn test_parse_memory_reference() {
+    assert_eq!(Some(0xFABC), parse_memory_reference("0x00FABC"));
+    assert_eq!(Some(0xFABC), parse_memory_reference("FABC"));
+    assert_eq!(None, parse_memory_reference("0x01000000"));
+    assert_eq!(None, parse_memory_reference("pc:ABC"));
+}
+
+#[test]
+fn test_word_range_aligned() {
+    assert_eq!(
+        Some(WordRange {
+            address: 0x100,
+            count: 2,
+            byte_offset: 0,
+            bytes_out_of_range: 0
+        }),
+        word_range_for_bytes(0x100, 0, 4)
+    );
+}
+
+#[test]
+fn test_word_range_negative_offset() {
+    assert_eq!(
+        Some(WordRange {
+            address: 0xFF,
+            count: 1,
+            byte_offset: 1,
+            bytes_out_of_range: 0
+        }),
+        word_range_for_bytes(0x100, -1, 1)
+    );
+    assert_eq!(None, word_range_for_bytes(0x0, -1, 1));
+}
+
+#[test]
+fn test_word_range_end_of_address_space() {
+    assert_eq!(
+        Some(WordRange {
+            address: 0xFF_FFFF,
+            count: 1,
+            byte_offset: 0,
+            bytes_out_of_range: 2
+        }),
+        word_range_for_bytes(0xFF_FFFF, 0, 4)
+    );
+    assert_eq!(None, word_range_for_bytes(0xFF_FFFF, 2, 4));
+}
+
+#[test]
+fn test_bytes_from_words() {
+    assert_eq!(vec![0x34, 0x56], bytes_from_words(&[0x1234, 0x5678], 1, 2));
+    // Reads that stop early (e.g. unmapped memory) are shorter than requested
+    assert_eq!(vec![0x34], bytes_from_words(&[0x1234], 1, 3));
+}
+
+#[test]
+fn test_merge_bytes_into_words() {
+    assert_eq!(
+        vec![0xABCD, 0x5678],
+        merge_bytes_into_words(&[0x1234, 0x5678], 0, &[0xAB, 0xCD])
+    );
+    assert_eq!(vec![0x12AB], merge_bytes_into_words(&[0x1234], 1, &[0xAB]));
+}
+
+#[test]
+fn test_offset_address_wraps() {
+    assert_eq!(0x00_0010, offset_address(0xFF_FFF0, 0x20));
+    assert_eq!(0xFF_FFF0, offset_address(0x00_0010, -0x20));
+}
@@ -1 +1,11 @@
+mod breakpoint_condition_test;
+mod call_stack_test;
+mod compat_test;
+mod data_breakpoint_test;
+mod expression_test;
+mod gdb_server_test;
+mod listener_test;
+mod memory_test;
+mod pause_test;
 mod server_test;
+mod symbols_test;
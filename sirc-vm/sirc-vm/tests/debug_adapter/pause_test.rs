@@ -0,0 +1,123 @@
+use std::thread;
+use std::time::Duration;
+
+use peripheral_bus::device::BusAccessType;
+use peripheral_bus::{device::BusAssertions, BusPeripheral};
+use peripheral_cpu::coprocessors::exception_unit::definitions::Faults;
+use peripheral_cpu::FAULT_METADATA_LINK_REGISTER_INDEX;
+use sirc_vm::debug_adapter::server::create_server_channels;
+use sirc_vm::debug_adapter::types::{FaultDetails, VmMessage, VmPauseReason, VmState};
+use sirc_vm::debugger::{check_exception_breakpoints, yield_to_debugger};
+use sirc_vm::utils::cpu_from_bus::cpu_from_bus_mut;
+use sirc_vm::DebugState;
+
+use crate::utils::vm::set_up_vm;
+
+///
+/// Runs the VM in the same way as `run_vm_debug` until it pauses, and returns why it paused.
+///
+/// The VM runs in its own thread (like it does in the real VM) because it blocks while it is
+/// paused. It stops when the debugger goes away.
+///
+fn run_until_paused(
+    set_up: impl FnOnce() -> BusPeripheral + Send + 'static,
+    set_up_debug_state: impl FnOnce(&mut DebugState) + Send + 'static,
+) -> (VmPauseReason, VmState) {
+    let channels = create_server_channels();
+    let vm_thread = thread::spawn(move || {
+        let mut bus_peripheral = set_up();
+        let mut debug_state = DebugState::new(channels.vm, false);
+        set_up_debug_state(&mut debug_state);
+        let mut bus_assertions = BusAssertions::default();
+        while !debug_state.disconnected {
+            bus_assertions = bus_peripheral.poll_all(bus_assertions);
+            check_exception_breakpoints(&mut bus_peripheral, &mut debug_state);
+            if debug_state
+                .history
+                .record_poll(&mut bus_peripheral, bus_assertions)
+            {
+                yield_to_debugger(&mut bus_peripheral, &mut debug_state, &mut bus_assertions);
+            }
+        }
+    });
+
+    let message = channels
+        .debugger
+        .rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("The VM should have paused");
+    drop(channels.debugger.tx);
+    vm_thread.join().expect("The VM thread shouldn't panic");
+
+    match message {
+        VmMessage::Paused(reason, vm_state) => (reason, *vm_state),
+        VmMessage::Log(message) => panic!("Expected the VM to pause but it logged [{message}]"),
+    }
+}
+
+fn variable<'a>(vm_state: &'a VmState, scope: &str, name: &str) -> &'a str {
+    vm_state
+        .scopes
+        .iter()
+        .find(|s| s.name == scope)
+        .and_then(|s| s.variables.iter().find(|(n, _)| n == name))
+        .map_or_else(
+            || panic!("Expected a [{name}] variable in the [{scope}] scope"),
+            |(_, value)| value.as_str(),
+        )
+}
+
+#[test]
+fn test_pause_with_invalid_fault_metadata() {
+    let (reason, vm_state) = run_until_paused(
+        || {
+            let mut bus_peripheral = set_up_vm();
+            // Programs can write anything to the fault metadata register (e.g. with ETTR). The
+            // fault fields are zero, which isn't a valid fault.
+            cpu_from_bus_mut(&mut bus_peripheral)
+                .eu_registers
+                .link_registers[FAULT_METADATA_LINK_REGISTER_INDEX]
+                .return_status_register = 0x0001;
+            bus_peripheral
+        },
+        |debug_state| debug_state.should_pause_for_init = true,
+    );
+
+    assert!(matches!(reason, VmPauseReason::Init));
+    assert_eq!("Unknown(0x0)", variable(&vm_state, "Last Fault", "fault"));
+    assert_eq!(
+        "Unknown(0x0)",
+        variable(&vm_state, "Last Fault", "original_fault")
+    );
+    assert_eq!(
+        "InstructionFetch",
+        variable(&vm_state, "Last Fault", "bus_access_type")
+    );
+}
+
+#[test]
+fn test_pause_on_fault_raised_by_the_bus() {
+    let (reason, vm_state) = run_until_paused(
+        || {
+            let mut bus_peripheral = set_up_vm();
+            // Nothing is mapped here, so the first store raises a bus fault
+            cpu_from_bus_mut(&mut bus_peripheral).registers.ah = 0x0002;
+            bus_peripheral
+        },
+        |debug_state| debug_state.exception_breakpoints = vec![Faults::Bus],
+    );
+
+    let VmPauseReason::Fault(fault_details) = reason else {
+        panic!("Expected the VM to pause on the fault");
+    };
+    assert_eq!(
+        FaultDetails {
+            fault: Faults::Bus,
+            original_fault: None,
+            address: 0x0002_0000,
+            bus_access_type: BusAccessType::DataWrite,
+        },
+        fault_details
+    );
+    assert_eq!("Bus", variable(&vm_state, "Last Fault", "fault"));
+}
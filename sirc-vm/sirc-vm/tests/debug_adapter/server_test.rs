@@ -1,4 +1,13 @@
-use sirc_vm::debug_adapter::server::{format_instruction_ref, parse_instruction_ref};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use peripheral_cpu::coprocessors::exception_unit::definitions::Faults;
+use sirc_vm::debug_adapter::expression::{BinaryOperator, Expression};
+use sirc_vm::debug_adapter::server::{
+    create_server_channels, fault_filter_id, format_instruction_ref, parse_breakpoint_conditions,
+    parse_fault_filter_id, parse_instruction_ref, parse_reference, start_server,
+};
+use sirc_vm::debug_adapter::types::ProgramDebugInfo;
 
 // TODO: Convert the debug server instruction ref tests to unit tests (or doc tests)
 // category=Testing
@@ -13,3 +22,62 @@ fn test_format_instruction_ref() {
 fn test_parse_instruction_ref() {
     assert_eq!(0xABC, parse_instruction_ref("pc:ABC"));
 }
+
+#[test]
+fn test_parse_reference() {
+    assert_eq!(Some(0xABC), parse_reference("pc:ABC"));
+    assert_eq!(Some(0xABC), parse_reference("0x000ABC"));
+    assert_eq!(None, parse_reference("pc:"));
+    assert_eq!(None, parse_reference("some_label"));
+}
+
+#[test]
+fn test_parse_breakpoint_conditions() {
+    let symbols = BTreeMap::from([("counter".to_string(), 0x400)]);
+
+    let conditions =
+        parse_breakpoint_conditions(Some("[counter] == 2"), Some(""), None, &symbols).unwrap();
+    assert_eq!(
+        Some(Expression::Binary(
+            BinaryOperator::Equal,
+            Box::new(Expression::Dereference(Box::new(Expression::Number(0x400)))),
+            Box::new(Expression::Number(2)),
+        )),
+        conditions.condition
+    );
+    assert_eq!(None, conditions.hit_condition);
+    assert_eq!(None, conditions.log_message);
+
+    assert!(parse_breakpoint_conditions(None, Some("% 0"), None, &symbols).is_err());
+    assert!(parse_breakpoint_conditions(None, None, Some("{"), &symbols).is_err());
+}
+
+#[test]
+fn test_parse_fault_filter_id() {
+    assert_eq!(Some(Faults::Bus), parse_fault_filter_id("Bus"));
+    assert_eq!(
+        Some(Faults::BusProtection),
+        parse_fault_filter_id(&fault_filter_id(Faults::BusProtection))
+    );
+    // Trace faults are raised after every instruction in trace mode so they can't be picked
+    assert_eq!(None, parse_fault_filter_id("InstructionTrace"));
+    assert_eq!(None, parse_fault_filter_id("bus"));
+}
+
+#[test]
+fn test_server_stops_when_vm_exits_without_debugger() {
+    let channels = create_server_channels();
+    let program_debug_info = ProgramDebugInfo {
+        debug_info_map: BTreeMap::new(),
+        symbols: BTreeMap::new(),
+    };
+    // The VM has already exited and no debugger ever attaches
+    drop(channels.vm);
+
+    start_server(
+        channels.debugger,
+        &program_debug_info,
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+    )
+    .unwrap();
+}
@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+
+use sirc_vm::debug_adapter::{
+    symbols::{format_symbolised_address, nearest_symbol},
+    types::SymbolTable,
+};
+
+fn symbols() -> SymbolTable {
+    BTreeMap::from([
+        ("main".to_string(), 0x00),
+        ("func_a".to_string(), 0x30),
+        ("alias".to_string(), 0x30),
+        ("handler".to_string(), 0x50),
+    ])
+}
+
+#[test]
+fn test_nearest_symbol() {
+    let symbols = symbols();
+    assert_eq!(Some(("main", 0x0)), nearest_symbol(&symbols, 0x0));
+    assert_eq!(Some(("alias", 0x4)), nearest_symbol(&symbols, 0x34));
+    assert_eq!(Some(("handler", 0x100)), nearest_symbol(&symbols, 0x150));
+    assert_eq!(None, nearest_symbol(&BTreeMap::new(), 0x10));
+}
+
+#[test]
+fn test_format_symbolised_address() {
+    let symbols = symbols();
+    assert_eq!("main+0x2F", format_symbolised_address(&symbols, 0x2F));
+    assert_eq!("alias", format_symbolised_address(&symbols, 0x30));
+    assert_eq!(
+        "0x000010",
+        format_symbolised_address(&BTreeMap::new(), 0x10)
+    );
+}
@@ -0,0 +1,131 @@
+use peripheral_bus::{device::BusAssertions, BusPeripheral};
+use peripheral_cpu::registers::Registers;
+use sirc_vm::history::ExecutionHistory;
+use sirc_vm::utils::cpu_from_bus::cpu_from_bus_mut;
+
+use crate::utils::vm::{set_up_vm, SCRATCH_ADDRESS, STORE_COUNT};
+
+fn capture_state(bus_peripheral: &mut BusPeripheral) -> (Registers, Vec<u16>) {
+    let registers = cpu_from_bus_mut(bus_peripheral).registers;
+    let scratch = (0..u32::from(STORE_COUNT))
+        .map(|offset| bus_peripheral.read_address(SCRATCH_ADDRESS + offset))
+        .collect();
+    (registers, scratch)
+}
+
+/// Runs until the given number of instructions have started, recording each one
+fn run_instructions(
+    bus_peripheral: &mut BusPeripheral,
+    history: &mut ExecutionHistory,
+    mut bus_assertions: BusAssertions,
+    instruction_count: usize,
+) -> (BusAssertions, Vec<(Registers, Vec<u16>)>) {
+    let mut states = vec![];
+    while states.len() < instruction_count {
+        bus_assertions = bus_peripheral.poll_all(bus_assertions);
+        if history.record_poll(bus_peripheral, bus_assertions) {
+            states.push(capture_state(bus_peripheral));
+        }
+    }
+    (bus_assertions, states)
+}
+
+#[test]
+fn test_step_back_restores_registers_and_memory() {
+    let mut bus_peripheral = set_up_vm();
+    let mut history = ExecutionHistory::default();
+    let instruction_count = usize::from(STORE_COUNT) * 2 + 1;
+
+    let (_, states) = run_instructions(
+        &mut bus_peripheral,
+        &mut history,
+        BusAssertions::default(),
+        instruction_count,
+    );
+    let (final_registers, final_scratch) = states.last().unwrap().clone();
+    assert_eq!(STORE_COUNT, final_registers.r1);
+    assert_eq!(vec![1, 2, 3], final_scratch);
+
+    for expected in states.iter().rev().skip(1) {
+        assert!(history.step_back(&mut bus_peripheral).is_some());
+        assert_eq!(*expected, capture_state(&mut bus_peripheral));
+    }
+    assert_eq!(1, history.len());
+    assert!(history.step_back(&mut bus_peripheral).is_none());
+    assert_eq!(states[0], capture_state(&mut bus_peripheral));
+}
+
+#[test]
+fn test_resume_after_step_back() {
+    let mut bus_peripheral = set_up_vm();
+    let mut history = ExecutionHistory::default();
+    let instruction_count = usize::from(STORE_COUNT) * 2 + 1;
+
+    let (_, states) = run_instructions(
+        &mut bus_peripheral,
+        &mut history,
+        BusAssertions::default(),
+        instruction_count,
+    );
+
+    let mut bus_assertions = BusAssertions::default();
+    for _ in 0..3 {
+        bus_assertions = history.step_back(&mut bus_peripheral).unwrap();
+    }
+
+    // Running forward again should end up in exactly the same place
+    let (_, replayed_states) =
+        run_instructions(&mut bus_peripheral, &mut history, bus_assertions, 3);
+    assert_eq!(states[states.len() - 3..], replayed_states[..]);
+    assert_eq!(instruction_count, history.len());
+}
+
+#[test]
+fn test_history_is_bounded() {
+    let mut bus_peripheral = set_up_vm();
+    let mut history = ExecutionHistory::new(3);
+    let instruction_count = usize::from(STORE_COUNT) * 2 + 1;
+
+    let (_, states) = run_instructions(
+        &mut bus_peripheral,
+        &mut history,
+        BusAssertions::default(),
+        instruction_count,
+    );
+    assert_eq!(3, history.len());
+
+    history.step_back(&mut bus_peripheral).unwrap();
+    history.step_back(&mut bus_peripheral).unwrap();
+    assert!(history.step_back(&mut bus_peripheral).is_none());
+    assert_eq!(
+        states[instruction_count - 3],
+        capture_state(&mut bus_peripheral)
+    );
+}
+
+#[test]
+fn test_instruction_start_is_recorded_when_the_fetch_is_acknowledged() {
+    let mut bus_peripheral = set_up_vm();
+    let mut history = ExecutionHistory::default();
+    let mut bus_assertions = BusAssertions::default();
+    let mut polls_since_sync = None;
+    let mut recorded_count = 0;
+
+    while recorded_count < usize::from(STORE_COUNT) * 2 {
+        bus_assertions = bus_peripheral.poll_all(bus_assertions);
+        if bus_assertions.instruction_sync && polls_since_sync.is_none() {
+            polls_since_sync = Some(0);
+        }
+        let recorded = history.record_poll(&mut bus_peripheral, bus_assertions);
+        if recorded {
+            // The program RAM is slow, so the fetch can't finish on the poll that starts it
+            let polls = polls_since_sync
+                .take()
+                .expect("An instruction should only start after a fetch has been requested");
+            assert!(polls > 0);
+            assert!(bus_assertions.bus_acknowledge);
+            recorded_count += 1;
+        }
+        polls_since_sync = polls_since_sync.map(|polls| polls + 1);
+    }
+}
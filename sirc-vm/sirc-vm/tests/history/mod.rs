@@ -0,0 +1 @@
+mod history_test;
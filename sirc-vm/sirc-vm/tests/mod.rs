@@ -16,4 +16,7 @@
 #![deny(warnings)]
 
 mod debug_adapter;
+mod history;
+mod save_state;
+mod trace;
 mod utils;
@@ -0,0 +1 @@
+mod save_state_test;
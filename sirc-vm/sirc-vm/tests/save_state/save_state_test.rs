@@ -0,0 +1,187 @@
+use std::fs::{read, write};
+
+use device_ram::{
+    new_ram_device_file_mapped, new_ram_device_standard, new_ram_device_with_latency,
+};
+use device_terminal::new_terminal_device;
+use peripheral_bus::device::{BusAssertions, Device};
+use peripheral_bus::memory_mapped_device::MemoryMapped;
+use peripheral_bus::save_state::SaveStateError;
+use peripheral_bus::{new_bus_peripheral, BusPeripheral};
+use peripheral_cpu::coprocessors::processing_unit::definitions::{
+    ConditionFlags, ImmediateInstructionData, Instruction, InstructionData,
+};
+use peripheral_cpu::coprocessors::processing_unit::encoding::encode_instruction;
+use peripheral_cpu::registers::AddressRegisterName;
+use peripheral_cpu::{new_cpu_peripheral, new_cpu_peripheral_with_model, CpuModel};
+use sirc_vm::utils::cpu_from_bus::{cpu_from_bus, cpu_from_bus_mut};
+use tempfile::tempdir;
+
+const STORE_COUNT: u16 = 40;
+/// Stops the tests from hanging if the program never exits
+const MAX_POLLS: usize = 100_000;
+
+fn immediate(op_code: Instruction, register: u8, value: u16, additional_flags: u8) -> [u8; 4] {
+    encode_instruction(&InstructionData::Immediate(ImmediateInstructionData {
+        op_code,
+        register,
+        value,
+        condition_flag: ConditionFlags::Always,
+        additional_flags,
+    }))
+}
+
+///
+/// A VM with RAM and a terminal, running a program that sets the terminal baud rate (so that the
+/// terminal counts clocks), stores a running total to RAM a number of times and then exits.
+///
+fn set_up_vm() -> BusPeripheral {
+    let address_register = AddressRegisterName::Address.to_register_index();
+    let stack_pointer = AddressRegisterName::StackPointer.to_register_index();
+    let program: Vec<u8> = [
+        immediate(Instruction::AddImmediate, 2, 0xFFFF, 0),
+        immediate(
+            Instruction::StoreRegisterToIndirectImmediate,
+            2,
+            0,
+            stack_pointer,
+        ),
+    ]
+    .into_iter()
+    .chain((0..STORE_COUNT).flat_map(|offset| {
+        [
+            immediate(Instruction::AddImmediate, 1, 3, 0),
+            immediate(
+                Instruction::StoreRegisterToIndirectImmediate,
+                1,
+                offset,
+                address_register,
+            ),
+        ]
+    }))
+    .chain([immediate(
+        Instruction::CoprocessorCallImmediate,
+        1,
+        0x14FF,
+        0,
+    )])
+    .flatten()
+    .collect();
+
+    let mut bus_peripheral = new_bus_peripheral(Box::new(new_cpu_peripheral(0x0)));
+    bus_peripheral.map_segment(
+        "PROGRAM",
+        0x0,
+        0xFFFF,
+        false,
+        Box::new(new_ram_device_with_latency(2)),
+    );
+    bus_peripheral.load_binary_data_into_segment("PROGRAM", &program);
+    bus_peripheral.map_segment(
+        "SCRATCH",
+        0x0001_0000,
+        0xFFFF,
+        true,
+        Box::new(new_ram_device_standard()),
+    );
+    bus_peripheral.map_segment(
+        "TERMINAL",
+        0x000A_0000,
+        0xF,
+        true,
+        Box::new(new_terminal_device(21_477_272)),
+    );
+    let registers = &mut cpu_from_bus_mut(&mut bus_peripheral).registers;
+    (registers.ah, registers.al) = (0x0001, 0x0000);
+    (registers.sh, registers.sl) = (0x000A, 0x0000);
+    bus_peripheral
+}
+
+/// Polls the bus until the program exits (or `max_polls` is reached), returning the last bus
+/// assertions and the number of polls
+fn run(
+    bus_peripheral: &mut BusPeripheral,
+    mut bus_assertions: BusAssertions,
+    max_polls: usize,
+) -> (BusAssertions, usize) {
+    for polls in 1..=max_polls {
+        bus_assertions = bus_peripheral.poll_all(bus_assertions);
+        if bus_assertions.exit_simulation {
+            return (bus_assertions, polls);
+        }
+    }
+    (bus_assertions, max_polls)
+}
+
+/// The register dump (as written by --register-dump-file) and the contents of the RAM
+fn final_state(bus_peripheral: &mut BusPeripheral) -> (String, Vec<u8>) {
+    (
+        cpu_from_bus(bus_peripheral).dump_diagnostic(),
+        bus_peripheral.dump_segment("SCRATCH"),
+    )
+}
+
+#[test]
+fn test_restored_vm_finishes_in_the_same_state() {
+    let mut uninterrupted = set_up_vm();
+    let (exit_assertions, total_polls) =
+        run(&mut uninterrupted, BusAssertions::default(), MAX_POLLS);
+    assert!(
+        exit_assertions.exit_simulation,
+        "The program should exit within {MAX_POLLS} polls"
+    );
+    let expected = final_state(&mut uninterrupted);
+    assert_eq!([0x0, 0x3, 0x0, 0x6], expected.1[..4]);
+    // The terminal is counting clocks against its baud rate
+    assert_eq!(0xFFFF, uninterrupted.read_address(0x000A_0000));
+
+    // Part way through the program, with bus requests in flight
+    let mut saved = set_up_vm();
+    let (save_assertions, _) = run(&mut saved, BusAssertions::default(), total_polls / 2);
+    let state = saved.save_state(save_assertions);
+
+    let mut restored = set_up_vm();
+    let restored_assertions = restored.restore_state(&state).unwrap();
+    let (exit_assertions, remaining_polls) = run(&mut restored, restored_assertions, MAX_POLLS);
+
+    assert!(exit_assertions.exit_simulation);
+    assert_eq!(total_polls, total_polls / 2 + remaining_polls);
+    assert_eq!(expected, final_state(&mut restored));
+    assert_eq!(
+        uninterrupted.save_state(BusAssertions::default()),
+        restored.save_state(BusAssertions::default())
+    );
+}
+
+#[test]
+fn test_cpu_state_from_another_model_is_rejected() {
+    let maths_cpu = new_cpu_peripheral_with_model(0x0, CpuModel::Maths);
+    let mut base_cpu = new_cpu_peripheral_with_model(0x0, CpuModel::Base);
+
+    let error = base_cpu
+        .restore_state(&maths_cpu.save_state())
+        .expect_err("The CPU model shouldn't change when a state is restored");
+    assert!(matches!(error, SaveStateError::DeviceMismatch(_)));
+    assert_eq!(CpuModel::Base, base_cpu.model());
+
+    let mut other_maths_cpu = new_cpu_peripheral_with_model(0x0, CpuModel::Maths);
+    other_maths_cpu
+        .restore_state(&maths_cpu.save_state())
+        .unwrap();
+}
+
+#[test]
+fn test_restoring_ram_state_leaves_mapped_files_alone() {
+    let dir = tempdir().unwrap();
+    let mapped_file = dir.path().join("mapped.bin");
+    write(&mapped_file, [0x12, 0x34, 0x56, 0x78]).unwrap();
+
+    let mut ram = new_ram_device_standard();
+    ram.write_address(0x0, 0xCAFE);
+    let mut mapped_ram = new_ram_device_file_mapped(mapped_file.clone());
+    mapped_ram.restore_state(&ram.save_state()).unwrap();
+
+    assert_eq!(0x1234, mapped_ram.read_address(0x0));
+    drop(mapped_ram);
+    assert_eq!(vec![0x12, 0x34, 0x56, 0x78], read(&mapped_file).unwrap());
+}
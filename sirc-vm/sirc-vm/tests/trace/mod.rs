@@ -0,0 +1 @@
+mod trace_test;
@@ -0,0 +1,45 @@
+use std::fs::read_to_string;
+
+use peripheral_bus::device::BusAssertions;
+use peripheral_cpu::registers::FullAddressRegisterAccess;
+use sirc_vm::trace::{InstructionTracer, TraceOptions};
+use sirc_vm::utils::cpu_from_bus::cpu_from_bus_mut;
+
+use crate::utils::vm::{set_up_vm, STORE_COUNT};
+
+#[test]
+fn test_trace_instructions() {
+    let mut bus_peripheral = set_up_vm();
+    let options = TraceOptions {
+        file: std::env::temp_dir().join("sirc_vm_trace_test.trace"),
+        symbols: [("start".to_string(), 0x0)].into(),
+    };
+    let mut tracer = InstructionTracer::create(&options).unwrap();
+
+    // Runs into the instruction after the program so that the last store has finished
+    let end_of_program = u32::from(STORE_COUNT) * 4;
+    let mut bus_assertions = BusAssertions::default();
+    while cpu_from_bus_mut(&mut bus_peripheral)
+        .registers
+        .get_full_pc_address()
+        <= end_of_program
+    {
+        bus_assertions = bus_peripheral.poll_all(bus_assertions);
+        tracer.record_poll(&mut bus_peripheral, bus_assertions);
+    }
+    tracer.finish(&mut bus_peripheral);
+
+    let trace = read_to_string(&options.file).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(
+        vec![
+            "000000 start        0040 0040  ADDI[N] r1, #0x0001          r1=0001 pl=0002",
+            "000002 start+0x2    4040 0010  STOR (#0x0000, a), r1        pl=0004  W[010000]=0001",
+            "000004 start+0x4    0040 0040  ADDI[N] r1, #0x0001          r1=0002 pl=0006",
+            "000006 start+0x6    4040 0050  STOR (#0x0001, a), r1        pl=0008  W[010001]=0002",
+            "000008 start+0x8    0040 0040  ADDI[N] r1, #0x0001          r1=0003 pl=000A",
+            "00000A start+0xA    4040 0090  STOR (#0x0002, a), r1        pl=000C  W[010002]=0003",
+        ],
+        lines[..6]
+    );
+}
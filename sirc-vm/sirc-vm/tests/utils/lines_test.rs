@@ -27,9 +27,6 @@ NOOP
 ; Halt CPU
 COPI    r1, #0x14FF";
 
-// TODO: Add tests for multiple source files for PC <-> Line/Column translation
-// category=Testing
-
 fn get_debug_info() -> ProgramDebugInfo {
     ProgramDebugInfo {
         debug_info_map: BTreeMap::from([(
@@ -51,6 +48,7 @@ fn get_debug_info() -> ProgramDebugInfo {
                 ]),
             },
         )]),
+        symbols: BTreeMap::new(),
     }
 }
 
@@ -118,3 +116,98 @@ pub fn test_translate_line_column_to_pc() {
         translate_line_column_to_pc(&program_debug_info, "UNIT_TEST.sasm", (22, 1))
     );
 }
+
+static TEST_INCLUDED_FILE_CONTENTS: &str = r"; Included in the middle of the main file
+ADDI    r1, #1
+ADDI    r2, #2";
+
+#[test]
+pub fn test_translate_with_included_file() {
+    let program_debug_info = ProgramDebugInfo {
+        debug_info_map: BTreeMap::from([
+            (
+                0x0200,
+                ObjectDebugInfo {
+                    original_filename: "UNIT_TEST.sasm".to_string(),
+                    original_input: TEST_SOURCE_FILE_CONTENTS.to_string(),
+                    program_to_input_offset_mapping: BTreeMap::from([
+                        (0x0200, 92),
+                        (0x0202, 107),
+                        (0x0208, 122),
+                    ]),
+                    ..ObjectDebugInfo::default()
+                },
+            ),
+            (
+                0x0204,
+                ObjectDebugInfo {
+                    original_filename: "INCLUDED.sasm".to_string(),
+                    original_input: TEST_INCLUDED_FILE_CONTENTS.to_string(),
+                    program_to_input_offset_mapping: BTreeMap::from([(0x0204, 42), (0x0206, 57)]),
+                    ..ObjectDebugInfo::default()
+                },
+            ),
+        ]),
+        symbols: BTreeMap::new(),
+    };
+
+    assert_eq!(
+        Some((2, 1, "INCLUDED.sasm".to_string())),
+        translate_pc_to_line_column(&program_debug_info, 0x0204)
+    );
+    assert_eq!(
+        Some((3, 1, "INCLUDED.sasm".to_string())),
+        translate_pc_to_line_column(&program_debug_info, 0x0206)
+    );
+    // After the included file, back in the main file
+    assert_eq!(
+        Some((10, 1, "UNIT_TEST.sasm".to_string())),
+        translate_pc_to_line_column(&program_debug_info, 0x0208)
+    );
+    assert_eq!(
+        Some(0x0206),
+        translate_line_column_to_pc(&program_debug_info, "INCLUDED.sasm", (3, 1))
+    );
+    assert_eq!(
+        Some(0x0208),
+        translate_line_column_to_pc(&program_debug_info, "UNIT_TEST.sasm", (10, 1))
+    );
+}
+
+#[test]
+pub fn test_translate_line_column_to_pc_with_file_included_by_two_objects() {
+    let program_debug_info = ProgramDebugInfo {
+        debug_info_map: BTreeMap::from([
+            (
+                0x0200,
+                ObjectDebugInfo {
+                    // The first object only uses the file for its placeholders/macros, so none of
+                    // its instructions are in this object
+                    original_filename: "INCLUDED.sasm".to_string(),
+                    original_input: TEST_INCLUDED_FILE_CONTENTS.to_string(),
+                    program_to_input_offset_mapping: BTreeMap::new(),
+                    ..ObjectDebugInfo::default()
+                },
+            ),
+            (
+                0x0400,
+                ObjectDebugInfo {
+                    original_filename: "INCLUDED.sasm".to_string(),
+                    original_input: TEST_INCLUDED_FILE_CONTENTS.to_string(),
+                    program_to_input_offset_mapping: BTreeMap::from([(0x0400, 42), (0x0402, 57)]),
+                    ..ObjectDebugInfo::default()
+                },
+            ),
+        ]),
+        symbols: BTreeMap::new(),
+    };
+
+    assert_eq!(
+        Some(0x0402),
+        translate_line_column_to_pc(&program_debug_info, "INCLUDED.sasm", (3, 1))
+    );
+    assert_eq!(
+        None,
+        translate_line_column_to_pc(&program_debug_info, "INCLUDED.sasm", (4, 1))
+    );
+}
@@ -1 +1,3 @@
 mod lines_test;
+pub mod network;
+pub mod vm;
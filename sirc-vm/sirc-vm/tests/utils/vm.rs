@@ -0,0 +1,60 @@
+use device_ram::{new_ram_device_standard, new_ram_device_with_latency};
+use peripheral_bus::{new_bus_peripheral, BusPeripheral};
+use peripheral_cpu::{
+    coprocessors::processing_unit::{
+        definitions::{ConditionFlags, ImmediateInstructionData, Instruction, InstructionData},
+        encoding::encode_instruction,
+    },
+    new_cpu_peripheral,
+    registers::AddressRegisterName,
+};
+use sirc_vm::utils::cpu_from_bus::cpu_from_bus_mut;
+
+pub const SCRATCH_ADDRESS: u32 = 0x0001_0000;
+pub const STORE_COUNT: u16 = 3;
+
+/// A program that increments r1 and stores it in a different scratch address a few times
+pub fn set_up_vm() -> BusPeripheral {
+    let program: Vec<u8> = (0..STORE_COUNT)
+        .flat_map(|offset| {
+            [
+                InstructionData::Immediate(ImmediateInstructionData {
+                    op_code: Instruction::AddImmediate,
+                    register: 1,
+                    value: 1,
+                    condition_flag: ConditionFlags::Always,
+                    additional_flags: 0,
+                }),
+                InstructionData::Immediate(ImmediateInstructionData {
+                    op_code: Instruction::StoreRegisterToIndirectImmediate,
+                    register: 1,
+                    value: offset,
+                    condition_flag: ConditionFlags::Always,
+                    additional_flags: AddressRegisterName::Address.to_register_index(),
+                }),
+            ]
+        })
+        .flat_map(|instruction| encode_instruction(&instruction))
+        .collect();
+
+    let mut bus_peripheral = new_bus_peripheral(Box::new(new_cpu_peripheral(0x0)));
+    // The program RAM is slow so that devices have requests in flight between polls
+    bus_peripheral.map_segment(
+        "PROGRAM",
+        0x0,
+        0xFFFF,
+        false,
+        Box::new(new_ram_device_with_latency(2)),
+    );
+    bus_peripheral.load_binary_data_into_segment("PROGRAM", &program);
+    bus_peripheral.map_segment(
+        "SCRATCH",
+        SCRATCH_ADDRESS,
+        0xFFFF,
+        true,
+        Box::new(new_ram_device_standard()),
+    );
+    let registers = &mut cpu_from_bus_mut(&mut bus_peripheral).registers;
+    (registers.ah, registers.al) = (0x0001, 0x0000);
+    bus_peripheral
+}
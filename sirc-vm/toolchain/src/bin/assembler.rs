@@ -19,10 +19,13 @@ use nom_supreme::error::ErrorTree;
 use nom_supreme::final_parser::{final_parser, Location};
 
 use toolchain::data::object::build_object;
+use toolchain::parsers::conditional::parse_define;
+use toolchain::parsers::include::read_source_file;
+use toolchain::parsers::preprocess::preprocess;
 use toolchain::utils::error_formatter::format_line_with_error;
 
 use core::panic;
-use std::fs::{read_to_string, write};
+use std::fs::write;
 use std::io;
 use std::path::PathBuf;
 use toolchain::parsers::shared::parse_tokens;
@@ -36,14 +39,22 @@ struct Args {
 
     #[clap(short, long, value_parser, value_name = "FILE")]
     output_file: PathBuf,
+
+    /// Where to look for files used by .INCLUDE/.INCBIN if they aren't next to the file that
+    /// includes them. Can be specified more than once.
+    #[clap(short = 'I', long, value_parser, value_name = "DIR")]
+    include_path: Vec<PathBuf>,
+
+    /// Defines a placeholder (e.g. `-D HAS_VIDEO=1`) as if it was defined with .EQU at the start of
+    /// the input file. The value defaults to 1. Can be specified more than once.
+    #[clap(short = 'D', long, value_parser = parse_define, value_name = "NAME=VALUE")]
+    define: Vec<Token>,
 }
 
 fn main() -> io::Result<()> {
     let args = Args::parse();
 
-    let file_contents = read_to_string(args.input_file.clone())?;
-    // Workaround for parsing errors where there isn't a newline at the end of the file
-    let file_contents_with_new_line = file_contents.trim_end().to_owned() + "\n";
+    let file_contents_with_new_line = read_source_file(&args.input_file)?;
     let tokens = match final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
         parse_tokens,
     )(file_contents_with_new_line.as_str())
@@ -58,6 +69,15 @@ fn main() -> io::Result<()> {
             panic!("Error parsing file:\n{error_message}\n{error}")
         }
     };
+    // The defines are put before the tokens from the file, so they can be used anywhere in it
+    let tokens = args.define.iter().cloned().chain(tokens).collect();
+    let tokens = preprocess(
+        tokens,
+        &args.input_file,
+        &file_contents_with_new_line,
+        &args.include_path,
+    )
+    .unwrap_or_else(|error| panic!("Error preprocessing file:\n{error}"));
     let object_definition = build_object(
         tokens,
         args.input_file.display().to_string(),
@@ -0,0 +1,80 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+#![allow(
+    // I don't like this rule
+    clippy::module_name_repetitions,
+    // Not sure what this is, will have to revisit
+    clippy::must_use_candidate,
+    // Will tackle this at the next clean up
+    clippy::too_many_lines,
+    // Might be good practice but too much work for now
+    clippy::missing_errors_doc,
+    // Not stable yet - try again later
+    clippy::missing_const_for_fn
+)]
+#![deny(warnings)]
+
+use clap::Parser;
+use sirc_vm::debug_adapter::types::{ProgramDebugInfo, SymbolTable};
+
+use std::fs::{read, write};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use toolchain::printers::program::{find_code, print_program, vector_table_entry_points};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// A linked program (the output of the linker)
+    #[clap(short, long, value_parser, value_name = "FILE")]
+    input_file: PathBuf,
+
+    /// Where to write the assembly (printed to stdout if not specified)
+    #[clap(short, long, value_parser, value_name = "FILE")]
+    output_file: Option<PathBuf>,
+
+    /// The segment offset that the program was linked with
+    #[clap(
+        short,
+        long,
+        value_parser,
+        value_name = "SEGMENT_OFFSET",
+        default_value_t = 0
+    )]
+    segment_offset: u32,
+
+    /// The debug map written by the linker (e.g. program.bin.dbg), used for labels and to find code
+    /// that can only be reached indirectly
+    #[clap(short, long, value_parser, value_name = "FILE")]
+    debug_file: Option<PathBuf>,
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+
+    let program = read(&args.input_file)?;
+    let mut entry_points = vector_table_entry_points(&program, args.segment_offset);
+    let symbols = match &args.debug_file {
+        Some(debug_file) => {
+            let program_debug_info: ProgramDebugInfo = postcard::from_bytes(&read(debug_file)?)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            // Every instruction in the source is in the debug info, no matter how it is reached
+            entry_points.extend(
+                program_debug_info
+                    .debug_info_map
+                    .values()
+                    .flat_map(|debug_info| debug_info.program_to_input_offset_mapping.keys()),
+            );
+            program_debug_info.symbols
+        }
+        None => SymbolTable::new(),
+    };
+
+    let code = find_code(&program, args.segment_offset, &entry_points);
+    let output = print_program(&program, args.segment_offset, &symbols, &code);
+
+    match args.output_file {
+        Some(output_file) => write(output_file, output),
+        None => io::stdout().write_all(output.as_bytes()),
+    }
+}
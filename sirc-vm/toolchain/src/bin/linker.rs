@@ -23,13 +23,17 @@ use peripheral_cpu::coprocessors::processing_unit::encoding::{
     decode_instruction, encode_instruction,
 };
 use sirc_vm::debug_adapter::debug_map::write_debug_map;
+use sirc_vm::utils::path::add_extension;
 
 use core::panic;
 use std::fs::{read, write};
 use std::io;
 use std::path::PathBuf;
 
-use toolchain::types::object::{merge_object_definitions, ObjectDefinition, RefType};
+use toolchain::printers::map::{print_link_map, LinkedObject};
+use toolchain::types::object::{
+    merge_object_definitions, relocate_debug_info, ObjectDefinition, RefType,
+};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -63,28 +67,68 @@ fn main() -> io::Result<()> {
         .collect();
 
     let (object_file, debug_info_map) = merge_object_definitions(&object_files);
+    let debug_info_map = relocate_debug_info(debug_info_map, args.segment_offset);
+
+    let mut next_object_address = args.segment_offset;
+    let linked_objects: Vec<LinkedObject> = args
+        .input_files
+        .iter()
+        .zip(&object_files)
+        .map(|(object_file_path, object_file)| {
+            let size_bytes: u32 = object_file
+                .program
+                .len()
+                .try_into()
+                .expect("Program length cannot be larger than 32 bits");
+            let size_words = size_bytes / 2;
+            let address = next_object_address;
+            next_object_address += size_words;
+            LinkedObject {
+                name: object_file_path.display().to_string(),
+                address,
+                size_words,
+            }
+        })
+        .collect();
 
     let mut linked_program = object_file.program.clone();
 
-    for symbol_ref in &object_file.symbol_refs {
-        // TODO: Better error handling when target symbol is not found in linker
-        // category=Refactoring
-        let target_symbol = object_file
+    // Positions in the program are in words, like the PC
+    let symbol_address = |name: &str| {
+        object_file
             .symbols
             .iter()
-            .find(|symbol| symbol.name == symbol_ref.name)
-            .unwrap_or_else(|| {
-                panic!(
-                    "Cannot find symbol [{}] in symbol definitions. Check you have a label with that name defined in your program.",
-                    symbol_ref.name
-                );
-            });
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| (symbol.offset / INSTRUCTION_SIZE_WORDS) + args.segment_offset)
+    };
 
+    for symbol_ref in &object_file.symbol_refs {
         // TODO: Clear up confusion between byte addressing and instruction addressing in linker
         // category=Refactoring
         // Sometimes we use words, sometimes bytes, sometimes even double words (see also debug info etc.)
-        let target_offset_words =
-            (target_symbol.offset / INSTRUCTION_SIZE_WORDS) + args.segment_offset;
+        let target_offset_words = symbol_ref.expression.as_ref().map_or_else(
+            || {
+                // TODO: Better error handling when target symbol is not found in linker
+                // category=Refactoring
+                symbol_address(&symbol_ref.name).unwrap_or_else(|| {
+                    panic!(
+                        "Cannot find symbol [{}] in symbol definitions. Check you have a label with that name defined in your program.",
+                        symbol_ref.name
+                    );
+                })
+            },
+            // e.g. @label + 2
+            |expression| {
+                expression
+                    .evaluate(&|_| None, &symbol_address)
+                    .unwrap_or_else(|error| {
+                        panic!(
+                            "Could not evaluate the expression that refers to [{}]: {error}",
+                            symbol_ref.name
+                        )
+                    })
+            },
+        );
         let program_offset_bytes = symbol_ref.offset as usize;
         let program_offset_words =
             (program_offset_bytes as u32 / INSTRUCTION_SIZE_WORDS) + args.segment_offset;
@@ -186,6 +230,13 @@ fn main() -> io::Result<()> {
 
     write(args.output_file.clone(), linked_program)?;
 
+    let mut map_path = args.output_file.clone();
+    add_extension(&mut map_path, "map");
+    write(
+        map_path,
+        print_link_map(&linked_objects, &debug_info_map.symbols),
+    )?;
+
     write_debug_map(&debug_info_map, args.output_file)?;
 
     Ok(())
@@ -1,4 +1,7 @@
+use crate::parsers::expression::fits_in_bits;
+use crate::printers::expression::print_operand_expression;
 use crate::types::data::{DataToken, DataType, DB_VALUE, DQ_VALUE, DW_VALUE};
+use crate::types::expression::Expression;
 use crate::types::object::{ObjectDefinition, SymbolDefinition, SymbolRef};
 use crate::types::shared::Token;
 
@@ -14,45 +17,52 @@ use sha2::{Digest, Sha256};
 use crate::types::shared::NumberToken;
 use std::collections::HashMap;
 
-fn resolve_placeholder(
+fn evaluate_expression(placeholders: &HashMap<String, u32>, expression: &Expression) -> u32 {
+    expression
+        .evaluate(&|name| placeholders.get(name).copied(), &|_| None)
+        .unwrap_or_else(|error| panic!("{error}"))
+}
+
+/// Replaces the placeholders in an expression that is evaluated by the linker
+fn resolve_placeholders(placeholders: &HashMap<String, u32>, expression: Expression) -> Expression {
+    expression
+        .resolve_placeholders(&|name| placeholders.get(name).copied())
+        .unwrap_or_else(|error| panic!("{error}"))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn resolve_expression(
     placeholders: &HashMap<String, u32>,
-    placeholder_name: &String,
+    expression: &Expression,
     instruction_data: &InstructionData,
 ) -> InstructionData {
-    let resolved_value = placeholders.get(placeholder_name)
-        .unwrap_or_else(|| panic!("Could not find a value for placeholder name [{placeholder_name}]. Make sure it is defined with the .EQU directive."));
+    let resolved_value = evaluate_expression(placeholders, expression);
+    let description = print_operand_expression(expression);
     match instruction_data {
         InstructionData::Immediate(immediate_instruction) => {
-            if resolved_value > &0xFFFF {
-                panic!("Immediate value (resolved from [{placeholder_name}] placeholder) can only be up to 16 bits ({resolved_value} > 0xFFFF)");
-            } else {
+            if fits_in_bits(resolved_value, 16) {
                 InstructionData::Immediate(ImmediateInstructionData {
-                    value: resolved_value
-                        .to_owned()
-                        .try_into()
-                        .expect("Value should fit into a u8 as it is checked above"),
+                    value: resolved_value as u16,
                     ..immediate_instruction.clone()
                 })
+            } else {
+                panic!("Immediate value (resolved from [{description}]) can only be up to 16 bits ({resolved_value} > 0xFFFF)");
             }
         }
         InstructionData::ShortImmediate(short_immediate_instruction) => {
-            if resolved_value > &0xFF {
-                panic!("Immediate value (resolved from [{placeholder_name}] placeholder) can only be up to 8 bits when using a shift definition ({resolved_value} > 0xFF)");
-            } else {
+            if fits_in_bits(resolved_value, 8) {
                 InstructionData::ShortImmediate(ShortImmediateInstructionData {
-                    value: resolved_value
-                        .to_owned()
-                        .try_into()
-                        .expect("Value should fit into a u8 as it is checked above"),
+                    value: resolved_value as u8,
                     ..short_immediate_instruction.clone()
                 })
+            } else {
+                panic!("Immediate value (resolved from [{description}]) can only be up to 8 bits when using a shift definition ({resolved_value} > 0xFF)");
             }
         }
         InstructionData::Register(_) => instruction_data.clone(),
     }
 }
 
-#[allow(clippy::cast_possible_truncation)]
 fn inject_data_value(
     data: DataToken,
     program: &mut [[u8; 4]],
@@ -63,22 +73,7 @@ fn inject_data_value(
 ) {
     match data.value {
         DataType::Value(NumberToken { value, .. }) => {
-            // TODO: Make packing smaller data sizes in assembled binaries more efficient
-            // category=Toolchain
-            // E.g. put 4 DBs in one 32 bit chunk
-            // TODO: Clean up the data packing code in the Assembler
-            // category=Refactoring
-            let bytes: [u8; 4] = match data.size_bytes {
-                DB_VALUE => [0x0, 0x0, 0x0, value as u8],
-                DW_VALUE => {
-                    let word_bytes = u16::to_be_bytes(value as u16);
-                    [0x0, 0x0, word_bytes[0], word_bytes[1]]
-                }
-                DQ_VALUE => u32::to_be_bytes(value),
-                _ => panic!("Unsupported data size bytes {}", data.size_bytes),
-            };
-
-            program[program_offset] = bytes;
+            inject_value(data.size_bytes, value, program, program_offset);
         }
         DataType::SymbolRef(symbol_ref) => {
             program[program_offset] = [0x0, 0x0, 0x0, 0x0];
@@ -87,19 +82,43 @@ fn inject_data_value(
                 offset,
                 ref_type: symbol_ref.ref_type,
                 data_only: true,
+                expression: symbol_ref
+                    .expression
+                    .map(|expression| resolve_placeholders(placeholders, expression)),
             });
         }
-        DataType::PlaceHolder(placeholder_name) => {
-            let resolved_value = placeholders.get(&placeholder_name)
-                .unwrap_or_else(|| panic!("Could not find a value for placeholder name [{placeholder_name}]. Make sure it is defined with the .EQU directive."));
-            program[program_offset] = u32::to_be_bytes(resolved_value.to_owned());
+        DataType::Expression(expression) => {
+            let value = evaluate_expression(placeholders, &expression);
+            inject_value(data.size_bytes, value, program, program_offset);
         }
     }
 }
 
+#[allow(clippy::cast_possible_truncation)]
+fn inject_value(size_bytes: u8, value: u32, program: &mut [[u8; 4]], program_offset: usize) {
+    // TODO: Make packing smaller data sizes in assembled binaries more efficient
+    // category=Toolchain
+    // E.g. put 4 DBs in one 32 bit chunk
+    // TODO: Clean up the data packing code in the Assembler
+    // category=Refactoring
+    let bytes: [u8; 4] = match size_bytes {
+        DB_VALUE => [0x0, 0x0, 0x0, value as u8],
+        DW_VALUE => {
+            let word_bytes = u16::to_be_bytes(value as u16);
+            [0x0, 0x0, word_bytes[0], word_bytes[1]]
+        }
+        DQ_VALUE => u32::to_be_bytes(value),
+        _ => panic!("Unsupported data size bytes {size_bytes}"),
+    };
+
+    program[program_offset] = bytes;
+}
+
+/// The program ends at the last instruction or data, so that it is the same size no matter what
+/// comes after it (e.g. labels or comments)
 fn ensure_program_size(program: &mut Vec<[u8; 4]>, min_size: usize) {
     if min_size > program.len() {
-        program.resize(min_size + 1, [0x0, 0x0, 0x0, 0x0]);
+        program.resize(min_size, [0x0, 0x0, 0x0, 0x0]);
     }
 }
 
@@ -108,42 +127,49 @@ pub fn build_object(
     original_filename: String,
     original_input: String,
 ) -> ObjectDefinition {
-    let original_input_length = original_input.len();
     let mut symbols: Vec<SymbolDefinition> = vec![];
     let mut symbol_refs: Vec<SymbolRef> = vec![];
     let mut placeholders: HashMap<String, u32> = HashMap::new();
     let mut offset: u32 = 0x0;
     let mut program: Vec<[u8; 4]> = vec![];
-    let mut debug_info = ObjectDebugInfo {
+    // One for each source file, starting with the main one
+    let mut debug_info = vec![ObjectDebugInfo {
         original_filename,
         original_input,
         ..Default::default()
-    };
+    }];
+    // The index of the debug info for each file that is being included
+    let mut source_file_stack: Vec<usize> = vec![0];
 
     for token in tokens {
         let program_offset: usize = offset as usize / 4;
 
-        ensure_program_size(&mut program, program_offset + 1);
-
         match token {
             Token::Instruction(data) => {
+                ensure_program_size(&mut program, program_offset + 1);
                 if let Some(symbol_ref) = data.symbol_ref {
                     symbol_refs.push(SymbolRef {
                         name: symbol_ref.name,
                         offset,
                         ref_type: symbol_ref.ref_type,
                         data_only: false,
+                        expression: symbol_ref
+                            .expression
+                            .map(|expression| resolve_placeholders(&placeholders, expression)),
                     });
                 }
 
-                let instruction = if let Some(placeholder_name) = data.placeholder_name {
-                    resolve_placeholder(&placeholders, &placeholder_name, &data.instruction)
+                let instruction = if let Some(expression) = data.expression {
+                    resolve_expression(&placeholders, &expression, &data.instruction)
                 } else {
                     data.instruction
                 };
 
-                let file_position = original_input_length - data.input_length;
-                debug_info.program_to_input_offset_mapping.insert(
+                let file_debug_info = &mut debug_info[*source_file_stack
+                    .last()
+                    .expect("There should always be a source file")];
+                let file_position = file_debug_info.original_input.len() - data.input_length;
+                file_debug_info.program_to_input_offset_mapping.insert(
                     // TODO: Improve error handling in assembler
                     // category=Refactoring
                     // Maybe remove this unwrap
@@ -167,6 +193,7 @@ pub fn build_object(
                 offset = data.offset * 2;
             }
             Token::Data(data) => {
+                ensure_program_size(&mut program, program_offset + 1);
                 inject_data_value(
                     data,
                     &mut program,
@@ -178,7 +205,39 @@ pub fn build_object(
                 offset += INSTRUCTION_SIZE_BYTES;
             }
             Token::Equ(data) => {
-                placeholders.insert(data.placeholder_name, data.number_token.value);
+                let value = evaluate_expression(&placeholders, &data.value);
+                placeholders.insert(data.placeholder_name, value);
+            }
+            Token::MacroDefinition(_)
+            | Token::MacroInvocation(_)
+            | Token::Include(_)
+            | Token::IncludeBinary(_)
+            | Token::If(_)
+            | Token::Else(_)
+            | Token::EndIf(_) => {
+                panic!(
+                    "The tokens should be preprocessed with preprocess before the object is built"
+                )
+            }
+            Token::SourceFileStart(source_file) => {
+                // A file that is included more than once shares its debug info
+                let index = debug_info
+                    .iter()
+                    .position(|file_debug_info| {
+                        file_debug_info.original_filename == source_file.filename
+                    })
+                    .unwrap_or_else(|| {
+                        debug_info.push(ObjectDebugInfo {
+                            original_filename: source_file.filename,
+                            original_input: source_file.input,
+                            ..Default::default()
+                        });
+                        debug_info.len() - 1
+                    });
+                source_file_stack.push(index);
+            }
+            Token::SourceFileEnd => {
+                source_file_stack.pop();
             }
         }
     }
@@ -191,12 +250,15 @@ pub fn build_object(
     // Calculate hash as a stable way to refer to sources
     let mut hasher = Sha256::new();
     hasher.update(&bytes);
-    debug_info.checksum = hex::encode(hasher.finalize());
+    let checksum = hex::encode(hasher.finalize());
+    for file_debug_info in &mut debug_info {
+        file_debug_info.checksum.clone_from(&checksum);
+    }
 
     ObjectDefinition {
         symbols,
         symbol_refs,
         program: bytes,
-        debug_info: Some(debug_info),
+        debug_info,
     }
 }
@@ -0,0 +1,376 @@
+//!
+//! Conditional assembly, so that different variants of a program (e.g. with and without a
+//! coprocessor) can be built from the same source.
+//!
+//! ```text
+//! .IFDEF $HAS_MATHS_COPROCESSOR
+//! MULU
+//! .ELSE
+//! BRSR @software_multiply
+//! .ENDIF
+//! ```
+//!
+//! `.IF` takes a number, placeholder or constant expression and assembles the block if it isn't
+//! zero. `.IFDEF`/`.IFNDEF` check whether a placeholder has been defined with `.EQU` (or with the
+//! `--define` assembler option). Blocks can be nested, but have to be closed in the same file that
+//! they are opened in.
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use nom::branch::alt;
+use nom::character::complete::space1;
+use nom::combinator::{all_consuming, cut, map};
+use nom::error::{ErrorKind, FromExternalError};
+use nom::sequence::preceded;
+use nom_supreme::error::ErrorTree;
+use nom_supreme::tag::complete::tag;
+use nom_supreme::ParserExt;
+
+use super::expression::parse_value_expression;
+use super::shared::{
+    lexeme, parse_bin_, parse_dec_, parse_hex_, parse_label_name_, parse_placeholder, AsmResult,
+};
+use crate::types::conditional::{
+    Condition, ConditionalToken, IfToken, ELSE_TOKEN, END_IF_TOKEN, IF_DEFINED_TOKEN,
+    IF_NOT_DEFINED_TOKEN, IF_TOKEN,
+};
+use crate::types::data::EquToken;
+use crate::types::expression::Expression;
+use crate::types::include::SourceFileToken;
+use crate::types::shared::{NumberToken, NumberType, Token};
+
+fn parse_condition_expression(i: &str) -> AsmResult<Condition> {
+    let (i_after_value, expression) = parse_value_expression(i)?;
+
+    if let Some(name) = expression.symbols().first() {
+        let error_string = format!(
+            "Conditions can't use symbols (found [{name}]) because they are only known when the program is linked"
+        );
+        return Err(nom::Err::Failure(ErrorTree::from_external_error(
+            i,
+            ErrorKind::Fail,
+            error_string.as_str(),
+        )));
+    }
+
+    Ok((i_after_value, Condition::Expression(expression)))
+}
+
+fn parse_if_token_(i: &str) -> AsmResult<Token> {
+    let input_length = i.len();
+    // The longer directives need to be first because .IF is a prefix of them
+    let (i, condition) = alt((
+        map(
+            preceded(
+                tag(IF_NOT_DEFINED_TOKEN),
+                cut(preceded(space1, parse_placeholder).context("placeholder")),
+            ),
+            Condition::NotDefined,
+        ),
+        map(
+            preceded(
+                tag(IF_DEFINED_TOKEN),
+                cut(preceded(space1, parse_placeholder).context("placeholder")),
+            ),
+            Condition::Defined,
+        ),
+        preceded(
+            tag(IF_TOKEN),
+            cut(preceded(space1, parse_condition_expression).context("condition")),
+        ),
+    ))(i)?;
+
+    Ok((
+        i,
+        Token::If(IfToken {
+            input_length,
+            condition,
+        }),
+    ))
+}
+
+pub fn parse_if_token(i: &str) -> AsmResult<Token> {
+    lexeme(parse_if_token_)(i)
+}
+
+fn parse_else_or_end_if_token_(i: &str) -> AsmResult<Token> {
+    let input_length = i.len();
+    let (i, directive) = alt((tag(ELSE_TOKEN), tag(END_IF_TOKEN)))(i)?;
+
+    let conditional_token = ConditionalToken { input_length };
+    Ok((
+        i,
+        if directive == ELSE_TOKEN {
+            Token::Else(conditional_token)
+        } else {
+            Token::EndIf(conditional_token)
+        },
+    ))
+}
+
+pub fn parse_else_or_end_if_token(i: &str) -> AsmResult<Token> {
+    lexeme(parse_else_or_end_if_token_)(i)
+}
+
+///
+/// Parses a placeholder definition from the command line (e.g. `HAS_VIDEO=1` or
+/// `TILE_BASE=0x1000`) into an `.EQU` token. If there is no value, it defaults to 1.
+///
+/// ```
+/// use toolchain::parsers::conditional::parse_define;
+/// use toolchain::types::shared::Token;
+///
+/// let Ok(Token::Equ(equ_token)) = parse_define("TILE_BASE=0x1000") else {
+///     panic!("Expected an .EQU token");
+/// };
+/// assert_eq!("TILE_BASE", equ_token.placeholder_name);
+/// assert!(parse_define("TILE BASE=1").is_err());
+/// ```
+///
+pub fn parse_define(definition: &str) -> Result<Token, String> {
+    let (name, value) = definition.split_once('=').unwrap_or((definition, "1"));
+    all_consuming(parse_label_name_)(name).map_err(|_| {
+        format!("[{name}] is not a valid placeholder name (it can only have letters, numbers and underscores)")
+    })?;
+    let (_, number_token) = all_consuming(alt((
+        map(parse_hex_, |value| NumberToken {
+            value,
+            number_type: NumberType::Hex,
+        }),
+        map(parse_bin_, |value| NumberToken {
+            value,
+            number_type: NumberType::Binary,
+        }),
+        map(parse_dec_, |value| NumberToken {
+            value,
+            number_type: NumberType::Decimal,
+        }),
+    )))(value.trim())
+    .map_err(|_: nom::Err<ErrorTree<&str>>| {
+        format!(
+            "[{value}] is not a valid value for [{name}] (expected a number like 1, 0x10 or 0b10)"
+        )
+    })?;
+
+    Ok(Token::Equ(EquToken {
+        placeholder_name: String::from(name),
+        value: Expression::Number(number_token),
+    }))
+}
+
+/// A conditional block that couldn't be resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalError {
+    /// Where the directive is, in the same format as `InstructionToken::input_length`
+    pub input_length: usize,
+    /// The included file that the error is in, or `None` if it is in the main file
+    pub file: Option<SourceFileToken>,
+    pub message: String,
+}
+
+impl fmt::Display for ConditionalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ConditionalError {}
+
+struct Block {
+    /// Where the `.IF` is, for errors
+    input_length: usize,
+    /// If the block that this one is in is being assembled
+    parent_active: bool,
+    condition_met: bool,
+    in_else: bool,
+}
+
+impl Block {
+    const fn active(&self) -> bool {
+        self.parent_active && self.condition_met != self.in_else
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ConditionalResolver {
+    placeholders: HashMap<String, u32>,
+    /// Every placeholder that has been defined, even if its value can't be worked out yet
+    defined: HashSet<String>,
+    blocks: Vec<Block>,
+    /// The included files that the tokens are in (see `resolve_includes`), with the number of
+    /// blocks that were open when each one started
+    source_files: Vec<(SourceFileToken, usize)>,
+}
+
+impl ConditionalResolver {
+    fn error(&self, input_length: usize, message: String) -> ConditionalError {
+        ConditionalError {
+            input_length,
+            file: self.source_files.last().map(|(file, _)| file.clone()),
+            message,
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.blocks.last().is_none_or(Block::active)
+    }
+
+    /// The number of blocks that were opened before the current file started
+    fn file_depth(&self) -> usize {
+        self.source_files.last().map_or(0, |(_, depth)| *depth)
+    }
+
+    fn evaluate(&self, if_token: &IfToken) -> Result<bool, ConditionalError> {
+        match &if_token.condition {
+            Condition::Expression(expression) => expression
+                .evaluate(&|name| self.placeholders.get(name).copied(), &|_| None)
+                .map(|value| value != 0)
+                .map_err(|message| self.error(if_token.input_length, message)),
+            Condition::Defined(name) => Ok(self.defined.contains(name)),
+            Condition::NotDefined(name) => Ok(!self.defined.contains(name)),
+        }
+    }
+
+    fn current_block(
+        &mut self,
+        conditional_token: &ConditionalToken,
+        directive: &str,
+    ) -> Result<&mut Block, ConditionalError> {
+        if self.blocks.len() <= self.file_depth() {
+            return Err(self.error(
+                conditional_token.input_length,
+                format!("Found [{directive}] without a matching [{IF_TOKEN}] in the same file"),
+            ));
+        }
+        Ok(self
+            .blocks
+            .last_mut()
+            .expect("There should be an open block"))
+    }
+
+    fn unclosed_block_error(&self) -> ConditionalError {
+        let block = self.blocks.last().expect("There should be an open block");
+        self.error(
+            block.input_length,
+            format!(
+                "This block needs to be closed with [{END_IF_TOKEN}] before the end of the file"
+            ),
+        )
+    }
+
+    ///
+    /// Returns the token if it is in a block that is being assembled. The conditional directives
+    /// themselves are never returned.
+    ///
+    pub(crate) fn resolve_token(
+        &mut self,
+        token: Token,
+    ) -> Result<Option<Token>, ConditionalError> {
+        match token {
+            Token::If(if_token) => {
+                let parent_active = self.active();
+                // Conditions in a block that isn't assembled might use placeholders that
+                // aren't defined, so they aren't evaluated
+                let condition_met = parent_active && self.evaluate(&if_token)?;
+                self.blocks.push(Block {
+                    input_length: if_token.input_length,
+                    parent_active,
+                    condition_met,
+                    in_else: false,
+                });
+                Ok(None)
+            }
+            Token::Else(conditional_token) => {
+                let block = self.current_block(&conditional_token, ELSE_TOKEN)?;
+                if block.in_else {
+                    return Err(self.error(
+                        conditional_token.input_length,
+                        format!("Found more than one [{ELSE_TOKEN}] in the same block"),
+                    ));
+                }
+                block.in_else = true;
+                Ok(None)
+            }
+            Token::EndIf(conditional_token) => {
+                self.current_block(&conditional_token, END_IF_TOKEN)?;
+                self.blocks.pop();
+                Ok(None)
+            }
+            Token::SourceFileStart(source_file) => {
+                let active = self.active();
+                self.source_files
+                    .push((source_file.clone(), self.blocks.len()));
+                Ok(active.then_some(Token::SourceFileStart(source_file)))
+            }
+            Token::SourceFileEnd => {
+                if self.blocks.len() > self.file_depth() {
+                    return Err(self.unclosed_block_error());
+                }
+                self.source_files.pop();
+                Ok(self.active().then_some(Token::SourceFileEnd))
+            }
+            Token::Equ(equ_token) if self.active() => {
+                // If the value can't be worked out yet (e.g. it uses a placeholder that
+                // isn't defined) the error is reported when the object is built
+                if let Ok(value) = equ_token
+                    .value
+                    .evaluate(&|name| self.placeholders.get(name).copied(), &|_| None)
+                {
+                    self.placeholders
+                        .insert(equ_token.placeholder_name.clone(), value);
+                }
+                self.defined.insert(equ_token.placeholder_name.clone());
+                Ok(Some(Token::Equ(equ_token)))
+            }
+            token => Ok(self.active().then_some(token)),
+        }
+    }
+
+    /// Checks that every block has been closed once all the tokens have been resolved
+    pub(crate) fn finish(&self) -> Result<(), ConditionalError> {
+        if self.blocks.is_empty() {
+            Ok(())
+        } else {
+            Err(self.unclosed_block_error())
+        }
+    }
+}
+
+///
+/// Removes the blocks of tokens whose conditions aren't met, along with the conditional
+/// directives themselves.
+///
+/// `resolve_includes` does this as it includes each file (see `preprocess` for the order of the
+/// passes). Conditional directives can't be used inside a macro.
+///
+/// ```
+/// use nom_supreme::error::ErrorTree;
+/// use nom_supreme::final_parser::{final_parser, Location};
+/// use toolchain::parsers::conditional::resolve_conditionals;
+/// use toolchain::parsers::shared::parse_tokens;
+/// use toolchain::types::shared::Token;
+///
+/// let input = ".EQU $HAS_VIDEO #0\n.IF $HAS_VIDEO\nLOAD r1, #1\n.ELSE\nLOAD r1, #2\nLOAD r2, #2\n.ENDIF\n";
+/// let tokens = final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
+///     parse_tokens,
+/// )(input)
+/// .unwrap();
+/// let resolved = resolve_conditionals(tokens).unwrap();
+///
+/// assert_eq!(3, resolved.len());
+/// assert!(matches!(resolved[0], Token::Equ(_)));
+/// assert!(matches!(resolved[1], Token::Instruction(_)));
+/// ```
+///
+pub fn resolve_conditionals(tokens: Vec<Token>) -> Result<Vec<Token>, ConditionalError> {
+    let mut resolver = ConditionalResolver::default();
+    let mut output = vec![];
+    for token in tokens {
+        output.extend(resolver.resolve_token(token)?);
+    }
+    resolver.finish()?;
+    Ok(output)
+}
@@ -7,11 +7,15 @@ use nom_supreme::error::ErrorTree;
 use nom_supreme::tag::complete::tag;
 use nom_supreme::ParserExt;
 
+use super::expression::{
+    evaluate_constant_expression, parse_constant_expression, parse_value_expression,
+};
 use super::shared::{lexeme, parse_number, parse_placeholder, parse_symbol_reference, AsmResult};
 use crate::types::data::{
     RefToken, DB_TOKEN, DB_VALUE, DQ_TOKEN, DQ_VALUE, DW_TOKEN, DW_VALUE, EQU_TOKEN,
 };
-use crate::types::shared::{NumberToken, Token};
+use crate::types::expression::Expression;
+use crate::types::shared::{NumberToken, NumberType, Token};
 use crate::types::{
     data::{DataToken, DataType, EquToken},
     object::RefType,
@@ -23,13 +27,10 @@ pub fn override_ref_token_type_if_implied(
 ) -> RefToken {
     match ref_token.ref_type {
         RefType::Implied => RefToken {
-            name: ref_token.name.clone(),
             ref_type: override_ref_type,
+            ..ref_token.clone()
         },
-        _ => RefToken {
-            name: ref_token.name.clone(),
-            ref_type: ref_token.ref_type,
-        },
+        _ => ref_token.clone(),
     }
 }
 
@@ -43,9 +44,35 @@ fn parse_data_type(i: &str) -> AsmResult<DataType> {
             DataType::SymbolRef(ref_token)
         })
         .context("symbol reference"),
+        map(parse_placeholder, |placeholder_name| {
+            DataType::Expression(Expression::PlaceHolder(placeholder_name))
+        })
+        .context("placeholder"),
+        parse_data_expression.context("constant expression"),
     ))(i)
 }
 
+fn parse_data_expression(i: &str) -> AsmResult<DataType> {
+    let (i_after_expression, expression) = parse_constant_expression(i)?;
+    let data_type = if let Some(name) = expression.symbols().first() {
+        // Evaluated by the linker
+        DataType::SymbolRef(RefToken {
+            name: String::from(*name),
+            ref_type: RefType::Implied,
+            expression: Some(expression),
+        })
+    } else if expression.has_placeholders() {
+        // Evaluated when the object is built
+        DataType::Expression(expression)
+    } else {
+        DataType::Value(NumberToken {
+            value: evaluate_constant_expression(i, &expression)?,
+            number_type: NumberType::Hex,
+        })
+    };
+    Ok((i_after_expression, data_type))
+}
+
 fn parse_data_(i: &str) -> AsmResult<(u8, DataType)> {
     let (i, tag) = lexeme(alt((tag(DB_TOKEN), tag(DW_TOKEN), tag(DQ_TOKEN))))(i)?;
     let (i, value) = parse_data_type(i)?;
@@ -73,15 +100,26 @@ fn parse_data(i: &str) -> AsmResult<(u8, DataType)> {
     lexeme(parse_data_)(i)
 }
 
-fn parse_equ_(i: &str) -> AsmResult<(String, NumberToken)> {
+fn parse_equ_(i: &str) -> AsmResult<(String, Expression)> {
     let (i, _) = lexeme(tag(EQU_TOKEN))(i)?;
     let (i, placeholder_name) = parse_placeholder(i)?;
-    let (i, value) = parse_number(i)?;
+    let (i_after_value, value) = parse_value_expression(i)?;
+
+    if let Some(name) = value.symbols().first() {
+        let error_string = format!(
+            "The value of [{placeholder_name}] can't use symbols (found [{name}]) because they are only known when the program is linked"
+        );
+        return Err(nom::Err::Failure(ErrorTree::from_external_error(
+            i,
+            ErrorKind::Fail,
+            error_string.as_str(),
+        )));
+    }
 
-    Ok((i, (placeholder_name, value)))
+    Ok((i_after_value, (placeholder_name, value)))
 }
 
-fn parse_equ(i: &str) -> AsmResult<(String, NumberToken)> {
+fn parse_equ(i: &str) -> AsmResult<(String, Expression)> {
     lexeme(parse_equ_)(i)
 }
 
@@ -117,7 +155,7 @@ pub fn parse_equ_token_(i: &str) -> AsmResult<Token> {
         i,
         Token::Equ(EquToken {
             placeholder_name,
-            number_token: value,
+            value,
         }),
     ))
 }
@@ -0,0 +1,213 @@
+//!
+//! Constant expressions, which can be used where a number can (e.g. `#($TILE_BASE + 16*4)`), or
+//! after a symbol ref (e.g. `@label + 2`).
+//!
+//! The operators have the same precedence as C (from lowest to highest):
+//!
+//! ```text
+//! |
+//! ^
+//! &
+//! << >>
+//! + -
+//! * / %
+//! - ~ (unary)
+//! ```
+//!
+//! `HIGH(x)` and `LOW(x)` get the upper and lower 16 bits of a value.
+//!
+
+use nom::branch::alt;
+use nom::character::complete::{char, space0};
+use nom::combinator::{cut, map};
+use nom::error::{ErrorKind, FromExternalError};
+use nom::sequence::{delimited, preceded, terminated};
+use nom_supreme::error::ErrorTree;
+use nom_supreme::tag::complete::tag;
+use nom_supreme::ParserExt;
+
+use super::shared::{
+    lexeme, parse_bin_, parse_dec_, parse_hex_, parse_label_name_, parse_number, parse_placeholder,
+    parse_placeholder_, AsmResult,
+};
+use crate::types::expression::{
+    BinaryOperator, Expression, Function, UnaryOperator, HIGH_FUNCTION_TOKEN, LOW_FUNCTION_TOKEN,
+};
+use crate::types::shared::{NumberToken, NumberType};
+
+/// From the lowest precedence to the highest
+const BINARY_OPERATORS: [&[(&str, BinaryOperator)]; 6] = [
+    &[("|", BinaryOperator::Or)],
+    &[("^", BinaryOperator::Xor)],
+    &[("&", BinaryOperator::And)],
+    &[
+        ("<<", BinaryOperator::ShiftLeft),
+        (">>", BinaryOperator::ShiftRight),
+    ],
+    &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)],
+    &[
+        ("*", BinaryOperator::Multiply),
+        ("/", BinaryOperator::Divide),
+        ("%", BinaryOperator::Remainder),
+    ],
+];
+
+/// Only skips spaces (not new lines), so that an expression can't run on to the next line
+fn token<'a, F, O>(inner: F) -> impl FnMut(&'a str) -> AsmResult<'a, 'a, O>
+where
+    F: FnMut(&'a str) -> AsmResult<'a, 'a, O>,
+{
+    terminated(inner, space0)
+}
+
+fn parse_number_literal(i: &str) -> AsmResult<Expression> {
+    map(
+        alt((
+            map(parse_hex_, |value| NumberToken {
+                value,
+                number_type: NumberType::Hex,
+            }),
+            map(parse_bin_, |value| NumberToken {
+                value,
+                number_type: NumberType::Binary,
+            }),
+            map(parse_dec_, |value| NumberToken {
+                value,
+                number_type: NumberType::Decimal,
+            }),
+        )),
+        Expression::Number,
+    )(i)
+}
+
+fn parse_function_call(i: &str) -> AsmResult<Expression> {
+    let (i, function) = alt((
+        map(tag(HIGH_FUNCTION_TOKEN), |_| Function::High),
+        map(tag(LOW_FUNCTION_TOKEN), |_| Function::Low),
+    ))(i)?;
+    let (i, operand) = preceded(space0, parse_brackets)(i)?;
+    Ok((i, Expression::Function(function, Box::new(operand))))
+}
+
+fn parse_brackets(i: &str) -> AsmResult<Expression> {
+    delimited(
+        token(char('(')),
+        cut(parse_expression),
+        cut(char(')').context("closing bracket")),
+    )(i)
+}
+
+fn parse_primary(i: &str) -> AsmResult<Expression> {
+    token(alt((
+        parse_function_call,
+        parse_brackets,
+        parse_number_literal,
+        map(parse_placeholder_, Expression::PlaceHolder),
+        map(preceded(char('@'), parse_label_name_), |name| {
+            Expression::SymbolRef(String::from(name))
+        }),
+    )))(i)
+}
+
+fn parse_unary(i: &str) -> AsmResult<Expression> {
+    alt((
+        map(preceded(token(char('-')), cut(parse_unary)), |operand| {
+            Expression::Unary(UnaryOperator::Negate, Box::new(operand))
+        }),
+        map(preceded(token(char('~')), cut(parse_unary)), |operand| {
+            Expression::Unary(UnaryOperator::Not, Box::new(operand))
+        }),
+        parse_primary,
+    ))(i)
+}
+
+fn parse_level(level: usize) -> impl FnMut(&str) -> AsmResult<Expression> {
+    move |i| {
+        if level == BINARY_OPERATORS.len() {
+            return parse_unary(i);
+        }
+        let (i, left) = parse_level(level + 1)(i)?;
+        continue_level(level, left, i)
+    }
+}
+
+fn continue_level(level: usize, mut left: Expression, mut i: &str) -> AsmResult<Expression> {
+    while let Some((after_operator, operator)) = BINARY_OPERATORS[level]
+        .iter()
+        .find_map(|(symbol, operator)| i.strip_prefix(symbol).map(|rest| (rest, *operator)))
+    {
+        let (after_operator, _) = space0(after_operator)?;
+        let (rest, right) = cut(parse_level(level + 1).context("operand"))(after_operator)?;
+        left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        i = rest;
+    }
+    Ok((i, left))
+}
+
+///
+/// Parses the rest of an expression that starts with `left` (e.g. the ` + 2` after `@label`)
+///
+pub fn continue_expression(left: Expression, i: &str) -> AsmResult<Expression> {
+    (0..BINARY_OPERATORS.len())
+        .rev()
+        .try_fold((i, left), |(i, left), level| continue_level(level, left, i))
+}
+
+pub fn parse_expression(i: &str) -> AsmResult<Expression> {
+    parse_level(0)(i)
+}
+
+fn parse_constant_expression_(i: &str) -> AsmResult<Expression> {
+    preceded(char('#'), token(alt((parse_function_call, parse_brackets))))(i)
+}
+
+///
+/// An expression after a `#` (e.g. `#($TILE_BASE + 16*4)` or `#HIGH(@label)`)
+///
+/// The expression has to be in brackets (unless it is just a function) so that it is clear where
+/// it ends.
+///
+pub fn parse_constant_expression(i: &str) -> AsmResult<Expression> {
+    lexeme(parse_constant_expression_)(i)
+}
+
+///
+/// A value for a directive, which can be a number (e.g. `#1`), a placeholder (e.g. `$FOO`) or a
+/// constant expression (e.g. `#($FOO + 1)`)
+///
+pub fn parse_value_expression(i: &str) -> AsmResult<Expression> {
+    alt((
+        map(parse_number, Expression::Number).context("number"),
+        map(parse_placeholder, Expression::PlaceHolder).context("placeholder"),
+        parse_constant_expression.context("constant expression"),
+    ))(i)
+}
+
+///
+/// Evaluates an expression that doesn't have any placeholders or symbols in it
+///
+pub fn evaluate_constant_expression<'a>(
+    i: &'a str,
+    expression: &Expression,
+) -> Result<u32, nom::Err<ErrorTree<&'a str>>> {
+    expression
+        .evaluate(&|_| None, &|_| None)
+        .map_err(|error_string| {
+            nom::Err::Failure(ErrorTree::from_external_error(
+                i,
+                ErrorKind::Fail,
+                error_string.as_str(),
+            ))
+        })
+}
+
+///
+/// If a value fits into `bits` bits, either as an unsigned number or as a (sign extended) negative
+/// number (e.g. `#-1`)
+///
+#[allow(clippy::cast_possible_wrap)]
+pub fn fits_in_bits(value: u32, bits: u32) -> bool {
+    let signed_value = value as i32;
+    let max = 1_i64 << bits;
+    i64::from(value) < max || (signed_value < 0 && i64::from(signed_value) >= -(max / 2))
+}
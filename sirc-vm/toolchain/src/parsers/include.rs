@@ -0,0 +1,270 @@
+//!
+//! `.INCLUDE "file.sasm"` pulls the tokens from another source file into the current one, and
+//! `.INCBIN "file.bin"` embeds the bytes of a file as data (e.g. tiles exported from an editor).
+//!
+//! Files are searched for relative to the file with the directive first, and then in each of the
+//! include paths (see the `--include-path` assembler option) in order.
+//!
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{read, read_to_string};
+use std::path::{Path, PathBuf};
+
+use nom::branch::alt;
+use nom::bytes::complete::is_not;
+use nom::character::complete::char;
+use nom::combinator::cut;
+use nom::sequence::delimited;
+use nom_supreme::error::ErrorTree;
+use nom_supreme::final_parser::{final_parser, Location};
+use nom_supreme::tag::complete::tag;
+use nom_supreme::ParserExt;
+
+use super::conditional::{ConditionalError, ConditionalResolver};
+use super::shared::{lexeme, parse_tokens, AsmResult};
+use crate::types::data::{DataToken, DataType, DQ_VALUE};
+use crate::types::include::{IncludeToken, SourceFileToken, INCLUDE_BINARY_TOKEN, INCLUDE_TOKEN};
+use crate::types::shared::{NumberToken, NumberType, Token};
+use crate::utils::error_formatter::{format_line_with_error, token_line};
+
+fn parse_quoted_path(i: &str) -> AsmResult<&str> {
+    lexeme(delimited(char('"'), is_not("\"\r\n"), char('"')))(i)
+}
+
+fn parse_include_token_(i: &str) -> AsmResult<Token> {
+    let input_length = i.len();
+    let (i, directive) = lexeme(alt((tag(INCLUDE_TOKEN), tag(INCLUDE_BINARY_TOKEN))))(i)?;
+    let (i, path) = cut(parse_quoted_path.context("quoted file path"))(i)?;
+
+    let include_token = IncludeToken {
+        input_length,
+        path: String::from(path),
+    };
+    Ok((
+        i,
+        if directive == INCLUDE_TOKEN {
+            Token::Include(include_token)
+        } else {
+            Token::IncludeBinary(include_token)
+        },
+    ))
+}
+
+pub fn parse_include_token(i: &str) -> AsmResult<Token> {
+    lexeme(parse_include_token_)(i)
+}
+
+/// A file that couldn't be included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeError {
+    pub message: String,
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for IncludeError {}
+
+/// The source of a file in the same form that the assembler reads it
+pub fn read_source_file(path: &Path) -> std::io::Result<String> {
+    // Workaround for parsing errors where there isn't a newline at the end of the file
+    Ok(read_to_string(path)?.trim_end().to_owned() + "\n")
+}
+
+/// Tokens for the bytes of a binary file, four to each `.DQ` (the last one is padded with zeros)
+fn binary_data_tokens(bytes: &[u8]) -> Vec<Token> {
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            let mut padded = [0x0; 4];
+            padded[..chunk.len()].copy_from_slice(chunk);
+            Token::Data(DataToken {
+                size_bytes: DQ_VALUE,
+                value: DataType::Value(NumberToken {
+                    value: u32::from_be_bytes(padded),
+                    number_type: NumberType::Hex,
+                }),
+            })
+        })
+        .collect()
+}
+
+struct IncludeResolver<'a> {
+    include_paths: &'a [PathBuf],
+    /// The files that are being included, to stop a file from including itself
+    stack: Vec<PathBuf>,
+    /// Conditional blocks are resolved as the files are included, so that files in blocks that
+    /// aren't assembled (e.g. behind an include guard) aren't included
+    conditionals: ConditionalResolver,
+    /// The file that the includes started from, for errors in conditional blocks
+    main_file: &'a Path,
+    main_input: &'a str,
+}
+
+impl IncludeResolver<'_> {
+    fn find(&self, name: &str, including_file: &Path) -> Option<PathBuf> {
+        let including_directory = including_file.parent().unwrap_or_else(|| Path::new(""));
+        std::iter::once(including_directory)
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// The token if it is in a block that is being assembled (see `ConditionalResolver`)
+    fn resolve_conditional(&mut self, token: Token) -> Result<Option<Token>, IncludeError> {
+        self.conditionals
+            .resolve_token(token)
+            .map_err(|error| self.conditional_error(&error))
+    }
+
+    fn resolve(
+        &mut self,
+        tokens: Vec<Token>,
+        file: &Path,
+        input: &str,
+        resolved: &mut Vec<Token>,
+    ) -> Result<(), IncludeError> {
+        for token in tokens {
+            match self.resolve_conditional(token)? {
+                Some(Token::Include(include_token)) => {
+                    let error = |message: String| error_at(file, input, &include_token, &message);
+                    let path = self
+                        .find(&include_token.path, file)
+                        .ok_or_else(|| error(self.not_found_message(&include_token.path)))?;
+                    let canonical_path = path
+                        .canonicalize()
+                        .map_err(|io_error| error(io_error.to_string()))?;
+                    if self.stack.contains(&canonical_path) {
+                        return Err(error(format!(
+                            "[{}] includes itself (possibly through other files)",
+                            path.display()
+                        )));
+                    }
+                    let contents = read_source_file(&path).map_err(|io_error| {
+                        error(format!("Could not read [{}]: {io_error}", path.display()))
+                    })?;
+                    let included_tokens = parse_file(&path, &contents)?;
+
+                    resolved.extend(self.resolve_conditional(Token::SourceFileStart(
+                        SourceFileToken {
+                            filename: path.display().to_string(),
+                            input: contents.clone(),
+                        },
+                    ))?);
+                    self.stack.push(canonical_path);
+                    self.resolve(included_tokens, &path, &contents, resolved)?;
+                    self.stack.pop();
+                    resolved.extend(self.resolve_conditional(Token::SourceFileEnd)?);
+                }
+                Some(Token::IncludeBinary(include_token)) => {
+                    let error = |message: String| error_at(file, input, &include_token, &message);
+                    let path = self
+                        .find(&include_token.path, file)
+                        .ok_or_else(|| error(self.not_found_message(&include_token.path)))?;
+                    let bytes = read(&path).map_err(|io_error| {
+                        error(format!("Could not read [{}]: {io_error}", path.display()))
+                    })?;
+                    resolved.extend(binary_data_tokens(&bytes));
+                }
+                Some(token) => resolved.push(token),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn not_found_message(&self, name: &str) -> String {
+        let searched: Vec<String> = self
+            .include_paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        format!(
+            "Could not find [{name}] next to the file that includes it or in the include paths [{}]",
+            searched.join(", ")
+        )
+    }
+
+    fn conditional_error(&self, error: &ConditionalError) -> IncludeError {
+        let (filename, input) = error.file.as_ref().map_or_else(
+            || (self.main_file.display().to_string(), self.main_input),
+            |file| (file.filename.clone(), file.input.as_str()),
+        );
+        error_at_position(&filename, input, error.input_length, &error.message)
+    }
+}
+
+fn error_at_position(
+    filename: &str,
+    input: &str,
+    input_length: usize,
+    message: &str,
+) -> IncludeError {
+    IncludeError {
+        message: format!("{filename}:{}: {message}", token_line(input, input_length)),
+    }
+}
+
+fn error_at(file: &Path, input: &str, include_token: &IncludeToken, message: &str) -> IncludeError {
+    error_at_position(
+        &file.display().to_string(),
+        input,
+        include_token.input_length,
+        message,
+    )
+}
+
+fn parse_file(path: &Path, contents: &str) -> Result<Vec<Token>, IncludeError> {
+    final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(parse_tokens)(contents)
+        .map_err(|error| {
+            let error_message =
+                format_line_with_error(&path.display().to_string(), contents, &error);
+            IncludeError {
+                message: format!("Error parsing file:\n{error_message}\n{error}"),
+            }
+        })
+}
+
+///
+/// Replaces the `.INCLUDE` and `.INCBIN` directives in the tokens from `file` (with the contents
+/// `input`) with the tokens/data from the files that they point to.
+///
+/// The tokens from each included file are between `SourceFileStart` and `SourceFileEnd` tokens so
+/// that the debug info can point at the right file.
+///
+/// Conditional blocks are resolved at the same time (see `resolve_conditionals`), so directives in
+/// blocks that aren't assembled don't need their files to exist. This means that include guards
+/// work:
+///
+/// ```text
+/// .IFNDEF $HARDWARE_SASM
+/// .EQU $HARDWARE_SASM #1
+/// ...
+/// .ENDIF
+/// ```
+///
+pub fn resolve_includes(
+    tokens: Vec<Token>,
+    file: &Path,
+    input: &str,
+    include_paths: &[PathBuf],
+) -> Result<Vec<Token>, IncludeError> {
+    let mut resolver = IncludeResolver {
+        include_paths,
+        stack: file.canonicalize().into_iter().collect(),
+        conditionals: ConditionalResolver::default(),
+        main_file: file,
+        main_input: input,
+    };
+    let mut output = vec![];
+    resolver.resolve(tokens, file, input, &mut output)?;
+    resolver
+        .conditionals
+        .finish()
+        .map_err(|error| resolver.conditional_error(&error))?;
+    Ok(output)
+}
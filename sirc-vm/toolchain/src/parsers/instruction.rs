@@ -15,13 +15,15 @@ use peripheral_cpu::coprocessors::processing_unit::definitions::{
 };
 use peripheral_cpu::registers::{AddressRegisterName, RegisterName};
 
+use super::expression::{evaluate_constant_expression, fits_in_bits, parse_constant_expression};
 use super::opcodes;
 use super::shared::{
     lexeme, parse_comma_sep, parse_number, parse_placeholder, parse_symbol_reference, AsmResult,
 };
 use crate::types::data::RefToken;
+use crate::types::expression::Expression;
 use crate::types::instruction::InstructionToken;
-use crate::types::object::SymbolDefinition;
+use crate::types::object::{RefType, SymbolDefinition};
 use crate::types::shared::{NumberToken, Token};
 
 impl Default for InstructionToken {
@@ -36,7 +38,7 @@ impl Default for InstructionToken {
                 additional_flags: 0x0,
             }),
             symbol_ref: None,
-            placeholder_name: None,
+            expression: None,
         }
     }
 }
@@ -67,7 +69,8 @@ pub fn extract_address_arguments(address: Address) -> (u32, Option<SymbolDefinit
 pub enum ImmediateType {
     Value(u16),
     SymbolRef(RefToken),
-    PlaceHolder(String),
+    /// A constant expression with placeholders in it (e.g. `$FOO` or `#($FOO + 1)`)
+    Expression(Expression),
 }
 
 pub enum OffsetType {
@@ -120,12 +123,42 @@ pub fn parse_value(i: &str) -> AsmResult<ImmediateType> {
         })
         .context("symbol reference"),
         map(parse_placeholder, |placeholder_name| {
-            ImmediateType::PlaceHolder(placeholder_name)
+            ImmediateType::Expression(Expression::PlaceHolder(placeholder_name))
         })
         .context("placeholder"),
+        parse_immediate_expression.context("constant expression"),
     ))(i)
 }
 
+fn parse_immediate_expression(i: &str) -> AsmResult<ImmediateType> {
+    let (i_after_expression, expression) = parse_constant_expression(i)?;
+    let immediate = if let Some(name) = expression.symbols().first() {
+        // Evaluated by the linker
+        ImmediateType::SymbolRef(RefToken {
+            name: String::from(*name),
+            ref_type: RefType::Implied,
+            expression: Some(expression),
+        })
+    } else if expression.has_placeholders() {
+        // Evaluated when the object is built
+        ImmediateType::Expression(expression)
+    } else {
+        let value = evaluate_constant_expression(i, &expression)?;
+        if !fits_in_bits(value, 16) {
+            let error_string =
+                format!("Immediate values can only be up to 16 bits (0x{value:X} > 0xFFFF)");
+            return Err(nom::Err::Failure(ErrorTree::from_external_error(
+                i,
+                ErrorKind::Fail,
+                error_string.as_str(),
+            )));
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        ImmediateType::Value(value as u16)
+    };
+    Ok((i_after_expression, immediate))
+}
+
 // Immediate | #n | BRAN #12
 fn parse_immediate_addressing(i: &str) -> AsmResult<ImmediateType> {
     parse_value(i)
@@ -0,0 +1,373 @@
+//!
+//! Macros are parsed into tokens that keep the source of their body, which is only parsed when the
+//! macro is used (once the arguments have been substituted for the parameters).
+//!
+//! ```text
+//! .MACRO PUSH reg
+//! STOR -(#1, s), \reg
+//! .ENDM
+//!
+//! PUSH r1
+//! ```
+//!
+//! Labels that are defined in a macro body are local to each use of the macro, so a macro with a
+//! loop in it can be used more than once.
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use nom::bytes::complete::{take_till, take_until};
+use nom::character::complete::{char, space0, space1};
+use nom::combinator::{cut, opt};
+use nom::multi::separated_list1;
+use nom::sequence::{delimited, preceded};
+use nom_supreme::error::ErrorTree;
+use nom_supreme::final_parser::{final_parser, Location};
+use nom_supreme::tag::complete::tag;
+use nom_supreme::ParserExt;
+
+use super::shared::{lexeme, parse_label_name_, parse_tokens, AsmResult};
+use crate::types::data::{DataToken, DataType, RefToken};
+use crate::types::include::SourceFileToken;
+use crate::types::instruction::InstructionToken;
+use crate::types::macros::{
+    MacroDefinitionToken, MacroInvocationToken, END_MACRO_TOKEN, MACRO_PARAMETER_PREFIX,
+    MACRO_TOKEN,
+};
+use crate::types::shared::{LabelToken, Token};
+
+/// Stops a macro that uses itself from being expanded forever
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+fn parse_macro_definition_token_(i: &str) -> AsmResult<Token> {
+    let input_length = i.len();
+    let (i, _) = tag(MACRO_TOKEN)(i)?;
+    let (i, name) = cut(preceded(space1, parse_label_name_).context("macro name"))(i)?;
+    let (i, parameters) = opt(preceded(
+        space1,
+        separated_list1(delimited(space0, char(','), space0), parse_label_name_),
+    ))(i)?;
+    let (i, body) = cut(take_until(END_MACRO_TOKEN).context("end of macro (.ENDM)"))(i)?;
+    let (i, _) = tag(END_MACRO_TOKEN)(i)?;
+
+    Ok((
+        i,
+        Token::MacroDefinition(MacroDefinitionToken {
+            input_length,
+            name: String::from(name),
+            parameters: parameters
+                .unwrap_or_default()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            body: String::from(body),
+        }),
+    ))
+}
+
+pub fn parse_macro_definition_token(i: &str) -> AsmResult<Token> {
+    lexeme(parse_macro_definition_token_)(i)
+}
+
+/// Splits on commas, apart from the ones in brackets (e.g. `(#1, s), r1` is two arguments)
+fn split_arguments(text: &str) -> Vec<String> {
+    if text.trim().is_empty() {
+        return vec![];
+    }
+    let mut arguments = vec![];
+    let mut current = String::new();
+    let mut depth: usize = 0;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                arguments.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    arguments.push(current.trim().to_string());
+    arguments
+}
+
+fn parse_macro_invocation_token_(i: &str) -> AsmResult<Token> {
+    let input_length = i.len();
+    let (i, name) = parse_label_name_(i)?;
+    let (i, arguments) = preceded(space0, take_till(|c| matches!(c, ';' | '\r' | '\n')))(i)?;
+
+    Ok((
+        i,
+        Token::MacroInvocation(MacroInvocationToken {
+            input_length,
+            name: String::from(name),
+            arguments: split_arguments(arguments),
+        }),
+    ))
+}
+
+pub fn parse_macro_invocation_token(i: &str) -> AsmResult<Token> {
+    lexeme(parse_macro_invocation_token_)(i)
+}
+
+/// A macro that couldn't be expanded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroError {
+    /// Where the macro was used (or defined), in the same format as `InstructionToken::input_length`
+    pub input_length: usize,
+    /// The included file that the error is in, or `None` if it is in the main file
+    pub file: Option<SourceFileToken>,
+    pub message: String,
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for MacroError {}
+
+fn substitute_parameters(
+    definition: &MacroDefinitionToken,
+    arguments: &[String],
+) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = definition.body.as_str();
+    while let Some(index) = rest.find(MACRO_PARAMETER_PREFIX) {
+        output.push_str(&rest[..index]);
+        let after_prefix = &rest[index + MACRO_PARAMETER_PREFIX.len_utf8()..];
+        let name_length = after_prefix
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(after_prefix.len());
+        let name = &after_prefix[..name_length];
+        let position = definition
+            .parameters
+            .iter()
+            .position(|parameter| parameter == name)
+            .ok_or_else(|| {
+                format!(
+                    "The [{}] macro doesn't have a parameter called [{name}]",
+                    definition.name
+                )
+            })?;
+        output.push_str(&arguments[position]);
+        rest = &after_prefix[name_length..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Gives the labels defined in an expanded macro (and the references to them) unique names
+fn localise_labels(tokens: Vec<Token>, prefix: &str) -> Vec<Token> {
+    let local_labels: HashSet<String> = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Label(LabelToken { name }) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let localise = |name: String| {
+        if local_labels.contains(&name) {
+            format!("{prefix}{name}")
+        } else {
+            name
+        }
+    };
+    let localise_ref = |ref_token: RefToken| RefToken {
+        name: localise(ref_token.name),
+        expression: ref_token
+            .expression
+            .map(|expression| expression.map_symbols(&localise)),
+        ..ref_token
+    };
+
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            Token::Label(LabelToken { name }) => Token::Label(LabelToken {
+                name: localise(name),
+            }),
+            Token::Instruction(instruction) => Token::Instruction(InstructionToken {
+                symbol_ref: instruction.symbol_ref.map(localise_ref),
+                ..instruction
+            }),
+            Token::Data(DataToken {
+                size_bytes,
+                value: DataType::SymbolRef(ref_token),
+            }) => Token::Data(DataToken {
+                size_bytes,
+                value: DataType::SymbolRef(localise_ref(ref_token)),
+            }),
+            token => token,
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct MacroExpander {
+    definitions: HashMap<String, MacroDefinitionToken>,
+    expansion_count: usize,
+    /// The included files that the tokens are in (see `resolve_includes`)
+    source_files: Vec<SourceFileToken>,
+}
+
+impl MacroExpander {
+    fn error(&self, input_length: usize, message: String) -> MacroError {
+        MacroError {
+            input_length,
+            file: self.source_files.last().cloned(),
+            message,
+        }
+    }
+
+    /// `call_site` is where the outermost macro was used, if these tokens came from a macro
+    fn expand(
+        &mut self,
+        tokens: Vec<Token>,
+        call_site: Option<usize>,
+        depth: usize,
+    ) -> Result<Vec<Token>, MacroError> {
+        let mut expanded = vec![];
+        for token in tokens {
+            match token {
+                Token::MacroDefinition(definition) => {
+                    let input_length = call_site.unwrap_or(definition.input_length);
+                    if call_site.is_some() {
+                        return Err(self.error(
+                            input_length,
+                            format!(
+                                "Macros can't be defined inside other macros (found [{}])",
+                                definition.name
+                            ),
+                        ));
+                    }
+                    if self.definitions.contains_key(&definition.name) {
+                        return Err(self.error(
+                            input_length,
+                            format!("The [{}] macro is defined more than once", definition.name),
+                        ));
+                    }
+                    self.definitions.insert(definition.name.clone(), definition);
+                }
+                Token::MacroInvocation(invocation) => {
+                    let input_length = call_site.unwrap_or(invocation.input_length);
+                    expanded.extend(self.expand_invocation(&invocation, input_length, depth)?);
+                }
+                // The debug info should point at the line that used the macro
+                Token::Instruction(instruction) => {
+                    expanded.push(Token::Instruction(InstructionToken {
+                        input_length: call_site.unwrap_or(instruction.input_length),
+                        ..instruction
+                    }));
+                }
+                Token::Include(include_token) | Token::IncludeBinary(include_token) => {
+                    return Err(self.error(
+                        call_site.unwrap_or(include_token.input_length),
+                        format!(
+                            "Files can't be included from inside a macro (found [{}])",
+                            include_token.path
+                        ),
+                    ));
+                }
+                Token::If(_) | Token::Else(_) | Token::EndIf(_) => {
+                    if let Some(call_site) = call_site {
+                        return Err(self.error(
+                            call_site,
+                            String::from("Conditional directives can't be used inside a macro"),
+                        ));
+                    }
+                    expanded.push(token);
+                }
+                Token::SourceFileStart(source_file) => {
+                    self.source_files.push(source_file.clone());
+                    expanded.push(Token::SourceFileStart(source_file));
+                }
+                Token::SourceFileEnd => {
+                    self.source_files.pop();
+                    expanded.push(Token::SourceFileEnd);
+                }
+                token => expanded.push(token),
+            }
+        }
+        Ok(expanded)
+    }
+
+    fn expand_invocation(
+        &mut self,
+        invocation: &MacroInvocationToken,
+        input_length: usize,
+        depth: usize,
+    ) -> Result<Vec<Token>, MacroError> {
+        let error = |message: String| self.error(input_length, message);
+        let name = &invocation.name;
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(error(format!(
+                "The [{name}] macro is nested more than {MAX_EXPANSION_DEPTH} levels deep (does it use itself?)"
+            )));
+        }
+        let definition = self.definitions.get(name).cloned().ok_or_else(|| {
+            error(format!(
+                "Unknown instruction or macro [{name}] (macros need to be defined with {MACRO_TOKEN} before they are used)"
+            ))
+        })?;
+        if invocation.arguments.len() != definition.parameters.len() {
+            return Err(error(format!(
+                "The [{name}] macro takes {} argument(s) but was given {}",
+                definition.parameters.len(),
+                invocation.arguments.len()
+            )));
+        }
+
+        let source = substitute_parameters(&definition, &invocation.arguments).map_err(error)?;
+        if source.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        let tokens = final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
+            parse_tokens,
+        )(source.as_str())
+        .map_err(|parse_error| {
+            error(format!(
+                "Could not parse the [{name}] macro with arguments [{}]:\n{parse_error}",
+                invocation.arguments.join(", ")
+            ))
+        })?;
+
+        self.expansion_count += 1;
+        let tokens = localise_labels(tokens, &format!("__{name}_{}_", self.expansion_count));
+        self.expand(tokens, Some(input_length), depth + 1)
+    }
+}
+
+///
+/// Replaces each use of a macro with its body, and removes the macro definitions.
+///
+/// Macros need to be defined before they are used. The instructions from a macro get the position
+/// of the line that used the macro, so that the debug info points there.
+///
+/// ```
+/// use nom_supreme::error::ErrorTree;
+/// use nom_supreme::final_parser::{final_parser, Location};
+/// use toolchain::parsers::macros::expand_macros;
+/// use toolchain::parsers::shared::parse_tokens;
+/// use toolchain::types::shared::Token;
+///
+/// let input = ".MACRO PUSH reg\nSTOR -(#1, s), \\reg\n.ENDM\nPUSH r1\nPUSH r2\n";
+/// let tokens = final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
+///     parse_tokens,
+/// )(input)
+/// .unwrap();
+/// let expanded = expand_macros(tokens).unwrap();
+///
+/// assert_eq!(2, expanded.len());
+/// assert!(matches!(expanded[0], Token::Instruction(_)));
+/// ```
+///
+pub fn expand_macros(tokens: Vec<Token>) -> Result<Vec<Token>, MacroError> {
+    MacroExpander::default().expand(tokens, None, 0)
+}
@@ -1,4 +1,9 @@
+pub mod conditional;
 pub mod data;
+pub mod expression;
+pub mod include;
 pub mod instruction;
+pub mod macros;
 pub mod opcodes;
+pub mod preprocess;
 pub mod shared;
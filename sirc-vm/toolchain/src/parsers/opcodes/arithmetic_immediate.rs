@@ -182,12 +182,12 @@ pub fn arithmetic_immediate(i: &str) -> AsmResult<InstructionToken> {
                             ..Default::default()
                         },
                     )),
-                    ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                    ImmediateType::Expression(expression) => Ok((
                         i,
                         InstructionToken {
                             input_length,
                             instruction: construct_immediate_instruction(0x0, dest_register),
-                            placeholder_name: Some(placeholder_name.clone()),
+                            expression: Some(expression.clone()),
                             ..Default::default()
                         },
                     )),
@@ -239,7 +239,7 @@ pub fn arithmetic_immediate(i: &str) -> AsmResult<InstructionToken> {
                         ))
                     })
                     }
-                    ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                    ImmediateType::Expression(expression) => Ok((
                         i,
                         InstructionToken {
                             input_length,
@@ -251,7 +251,7 @@ pub fn arithmetic_immediate(i: &str) -> AsmResult<InstructionToken> {
                                 shift_count,
                                 default_status_register_update_source,
                             ),
-                            placeholder_name: Some(placeholder_name.clone()),
+                            expression: Some(expression.clone()),
                             ..Default::default()
                         },
                     )),
@@ -105,12 +105,12 @@ pub fn branching(i: &str) -> AsmResult<InstructionToken> {
                     ..Default::default()
                 },
             )),
-            ImmediateType::PlaceHolder(placeholder_name) => Ok((
+            ImmediateType::Expression(expression) => Ok((
                 i,
                 InstructionToken {
                     input_length,
                     instruction: construct_branch_immediate_instruction(0x0), // 0x0 will be replaced by linker
-                    placeholder_name: Some(placeholder_name.clone()),
+                    expression: Some(expression.clone()),
                     ..Default::default()
                 },
             )),
@@ -75,7 +75,7 @@ pub fn coprocessor(i: &str) -> AsmResult<InstructionToken> {
                     ..Default::default()
                 },
             )),
-            ImmediateType::PlaceHolder(placeholder_name) => Ok((
+            ImmediateType::Expression(expression) => Ok((
                 i,
                 InstructionToken {
                     input_length,
@@ -86,7 +86,7 @@ pub fn coprocessor(i: &str) -> AsmResult<InstructionToken> {
                         condition_flag,
                         additional_flags: 0x0,
                     }),
-                    placeholder_name: Some(placeholder_name.clone()),
+                    expression: Some(expression.clone()),
                     ..Default::default()
                 },
             )),
@@ -94,7 +94,7 @@ pub fn ldea(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -103,7 +103,7 @@ pub fn ldea(i: &str) -> AsmResult<InstructionToken> {
                             dest_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -169,7 +169,7 @@ pub fn ldea(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -178,7 +178,7 @@ pub fn ldea(i: &str) -> AsmResult<InstructionToken> {
                             dest_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -87,7 +87,7 @@ pub fn ldel(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -97,7 +97,7 @@ pub fn ldel(i: &str) -> AsmResult<InstructionToken> {
                             dest_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -187,7 +187,7 @@ pub fn ldel(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -197,7 +197,7 @@ pub fn ldel(i: &str) -> AsmResult<InstructionToken> {
                             dest_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
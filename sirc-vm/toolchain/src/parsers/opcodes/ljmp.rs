@@ -106,12 +106,12 @@ pub fn ljmp(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
                         instruction: construct_immediate_instruction(0x0, source_register),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -117,12 +117,12 @@ pub fn ljsr(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
                         instruction: construct_immediate_instruction(0x0, source_register),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -193,7 +193,7 @@ pub fn ljsr(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -201,7 +201,7 @@ pub fn ljsr(i: &str) -> AsmResult<InstructionToken> {
                             0x0,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
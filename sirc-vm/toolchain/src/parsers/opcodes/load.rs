@@ -98,12 +98,12 @@ pub fn load(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
                         instruction: construct_immediate_instruction(0x0, dest_register),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -173,7 +173,7 @@ pub fn load(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -182,7 +182,7 @@ pub fn load(i: &str) -> AsmResult<InstructionToken> {
                             dest_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -274,7 +274,7 @@ pub fn load(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -283,7 +283,7 @@ pub fn load(i: &str) -> AsmResult<InstructionToken> {
                             dest_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
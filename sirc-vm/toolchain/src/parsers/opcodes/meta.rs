@@ -2,7 +2,8 @@ use super::super::shared::AsmResult;
 use crate::parsers::instruction::parse_instruction_tag;
 use crate::types::instruction::InstructionToken;
 use nom::branch::alt;
-use nom::character::complete::one_of;
+use nom::character::complete::{char, one_of};
+use nom::combinator::peek;
 use nom::error::{ErrorKind, FromExternalError};
 use nom_supreme::error::ErrorTree;
 use peripheral_cpu::coprocessors::processing_unit::definitions::{
@@ -47,16 +48,20 @@ pub fn meta(i: &str) -> AsmResult<InstructionToken> {
             error_string.as_str(),
         )));
     }
-    let (i, _) =
-        one_of::<&str, &str, ErrorTree<&str>>("\r\n")(i_after_instruction).map_err(|_| {
-            let error_string =
-                format!("The [{tag}] does not support any addressing modes (e.g. NOOP or RETE)");
-            nom::Err::Failure(ErrorTree::from_external_error(
-                i_after_instruction,
-                ErrorKind::Fail,
-                error_string.as_str(),
-            ))
-        })?;
+    // A comment can follow on the same line (it is left for the comment parser)
+    let (i, _) = alt((
+        one_of::<&str, &str, ErrorTree<&str>>("\r\n"),
+        peek(char(';')),
+    ))(i_after_instruction)
+    .map_err(|_: nom::Err<ErrorTree<&str>>| {
+        let error_string =
+            format!("The [{tag}] does not support any addressing modes (e.g. NOOP or RETE)");
+        nom::Err::Failure(ErrorTree::from_external_error(
+            i_after_instruction,
+            ErrorKind::Fail,
+            error_string.as_str(),
+        ))
+    })?;
 
     match tag.as_str() {
         // Returning from a subroutine is just loading the link register into the PC again
@@ -93,7 +93,7 @@ pub fn stor(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -102,7 +102,7 @@ pub fn stor(i: &str) -> AsmResult<InstructionToken> {
                             src_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -194,7 +194,7 @@ pub fn stor(i: &str) -> AsmResult<InstructionToken> {
                         ..Default::default()
                     },
                 )),
-                ImmediateType::PlaceHolder(placeholder_name) => Ok((
+                ImmediateType::Expression(expression) => Ok((
                     i,
                     InstructionToken {
                         input_length,
@@ -203,7 +203,7 @@ pub fn stor(i: &str) -> AsmResult<InstructionToken> {
                             src_register,
                             address_register,
                         ),
-                        placeholder_name: Some(placeholder_name.clone()),
+                        expression: Some(expression.clone()),
                         ..Default::default()
                     },
                 )),
@@ -0,0 +1,87 @@
+//!
+//! The passes that run on the tokens of a file after it is parsed and before the object is built.
+//!
+//! The order matters, so it is only written down here:
+//!
+//! 1. `resolve_includes` pulls in the included files, and resolves the conditional blocks as it
+//!    goes (see `resolve_conditionals`) so that files in blocks that aren't assembled aren't
+//!    included.
+//! 2. `expand_macros` replaces each use of a macro with its body. This is after the conditional
+//!    blocks are resolved so that a macro can be defined differently for each variant of a
+//!    program, and after the includes so that macros can be shared between files.
+//!
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::include::resolve_includes;
+use super::macros::expand_macros;
+use crate::types::shared::Token;
+use crate::utils::error_formatter::token_line;
+
+/// An error from one of the passes, with the file and line that it is in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    pub message: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for PreprocessError {}
+
+///
+/// Runs every pass on the tokens from `file` (with the contents `input`), so that they are ready
+/// to be built into an object with `build_object`.
+///
+/// Placeholders that are defined outside the file (e.g. with the `--define` assembler option)
+/// should be put before the tokens.
+///
+/// ```
+/// use std::path::Path;
+///
+/// use nom_supreme::error::ErrorTree;
+/// use nom_supreme::final_parser::{final_parser, Location};
+/// use toolchain::parsers::preprocess::preprocess;
+/// use toolchain::parsers::shared::parse_tokens;
+/// use toolchain::types::shared::Token;
+///
+/// let input = ".MACRO PUSH reg\nSTOR -(#1, s), \\reg\n.ENDM\n.IF #0\nPUSH r1\n.ENDIF\nPUSH r2\n";
+/// let tokens = final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
+///     parse_tokens,
+/// )(input)
+/// .unwrap();
+/// let preprocessed = preprocess(tokens, Path::new("main.sasm"), input, &[]).unwrap();
+///
+/// assert_eq!(1, preprocessed.len());
+/// assert!(matches!(preprocessed[0], Token::Instruction(_)));
+/// ```
+///
+pub fn preprocess(
+    tokens: Vec<Token>,
+    file: &Path,
+    input: &str,
+    include_paths: &[PathBuf],
+) -> Result<Vec<Token>, PreprocessError> {
+    let tokens =
+        resolve_includes(tokens, file, input, include_paths).map_err(|error| PreprocessError {
+            message: error.message,
+        })?;
+    expand_macros(tokens).map_err(|error| {
+        let (filename, input) = error.file.as_ref().map_or_else(
+            || (file.display().to_string(), input),
+            |file| (file.filename.clone(), file.input.as_str()),
+        );
+        PreprocessError {
+            message: format!(
+                "{filename}:{}: {}",
+                token_line(input, error.input_length),
+                error.message
+            ),
+        }
+    })
+}
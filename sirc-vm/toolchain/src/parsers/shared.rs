@@ -2,7 +2,7 @@ use nom::branch::alt;
 use nom::bytes::complete::{is_a, is_not};
 use nom::character::complete::{char, multispace0, one_of, space0};
 use nom::combinator::{cut, eof, map, map_res, opt, recognize};
-use nom::error::{ErrorKind, ParseError};
+use nom::error::{ErrorKind, FromExternalError, ParseError};
 use nom::sequence::{pair, preceded, terminated, tuple};
 use nom::{AsChar, IResult};
 use nom::{Err, InputTakeAtPosition, Parser};
@@ -13,8 +13,13 @@ use nom_supreme::tag::complete::tag;
 use nom_supreme::ParserExt;
 
 use super::instruction::{parse_instruction_token, ShiftDefinitionData};
+use crate::parsers::conditional::{parse_else_or_end_if_token, parse_if_token};
 use crate::parsers::data::{parse_data_token, parse_equ_token};
+use crate::parsers::expression::continue_expression;
+use crate::parsers::include::parse_include_token;
+use crate::parsers::macros::{parse_macro_definition_token, parse_macro_invocation_token};
 use crate::types::data::RefToken;
+use crate::types::expression::Expression;
 use crate::types::object::RefType;
 use crate::types::shared::{
     LabelToken, NumberToken, NumberType, OriginToken, Token, REF_TOKEN_LOWER_WORD_SUFFIX,
@@ -34,14 +39,14 @@ where
     terminated(inner, multispace0)
 }
 
-fn parse_label_name_(i: &str) -> AsmResult<&str> {
+pub fn parse_label_name_(i: &str) -> AsmResult<&str> {
     i.split_at_position1_complete(
         |item| !item.is_alphanum() && item != '_',
         ErrorKind::AlphaNumeric,
     )
 }
 
-fn parse_bin_<T: num_traits::Num>(i: &str) -> AsmResult<T> {
+pub fn parse_bin_<T: num_traits::Num>(i: &str) -> AsmResult<T> {
     let (i, _) = tag("0b")(i)?;
     let (i, raw_digits) = is_a(&b"01_"[..])(i)?;
     let digits_without_underscores = raw_digits.replace('_', "");
@@ -58,7 +63,7 @@ fn parse_bin_<T: num_traits::Num>(i: &str) -> AsmResult<T> {
     )
 }
 
-fn parse_hex_<T: num_traits::Num>(i: &str) -> AsmResult<T> {
+pub fn parse_hex_<T: num_traits::Num>(i: &str) -> AsmResult<T> {
     let (i, _) = tag("0x")(i)?;
     let (i, raw_digits) = is_a(&b"0123456789abcdefABCDEF_"[..])(i)?;
     let digits_without_underscores = raw_digits.replace('_', "");
@@ -75,7 +80,7 @@ fn parse_hex_<T: num_traits::Num>(i: &str) -> AsmResult<T> {
 }
 
 #[allow(clippy::cast_sign_loss)]
-fn parse_dec_(i: &str) -> AsmResult<u32> {
+pub fn parse_dec_(i: &str) -> AsmResult<u32> {
     map_res(
         tuple((opt(one_of("+-")), recognize(is_a("0123456789_")))),
         |(sign, number_string): (Option<char>, &str)| {
@@ -100,7 +105,7 @@ fn parse_number_(i: &str) -> AsmResult<NumberToken> {
     preceded(char('#'), alt((parse_hex, parse_bin, parse_dec)))(i)
 }
 
-fn parse_placeholder_(i: &str) -> AsmResult<String> {
+pub fn parse_placeholder_(i: &str) -> AsmResult<String> {
     map(preceded(char('$'), parse_label_name_), ToOwned::to_owned)(i)
 }
 
@@ -138,13 +143,37 @@ pub fn parse_symbol_reference_postamble_(i: &str) -> AsmResult<Option<RefType>>
 pub fn parse_symbol_reference_(i: &str) -> AsmResult<RefToken> {
     let (i, name) = preceded(char('@'), parse_label_name_)(i)?;
 
-    let (i, optional_postamble) = parse_symbol_reference_postamble_(i)?;
+    let (i_after_postamble, optional_postamble) = parse_symbol_reference_postamble_(i)?;
+    let postamble = &i[..i.len() - i_after_postamble.len()];
+    let i = i_after_postamble;
+
+    // e.g. @label + 2
+    let symbol = Expression::SymbolRef(String::from(name));
+    let (i_after_expression, expression) =
+        preceded(space0, |i| continue_expression(symbol.clone(), i))(i)?;
+    let (i, expression) = if expression == symbol {
+        (i, None)
+    } else if optional_postamble.is_some() {
+        // It isn't clear whether `@label.u + 2` means the upper word of the address plus two or
+        // the upper word of the address two words after the label, so HIGH/LOW have to be used
+        let error_string = format!(
+            "The [{postamble}] suffix can't be used in an expression (e.g. use #(HIGH(@{name}) + 2) or #HIGH(@{name} + 2) instead of @{name}.u + 2)"
+        );
+        return Err(nom::Err::Failure(ErrorTree::from_external_error(
+            i,
+            ErrorKind::Fail,
+            error_string.as_str(),
+        )));
+    } else {
+        (i_after_expression, Some(expression))
+    };
 
     Ok((
         i,
         RefToken {
             name: String::from(name),
             ref_type: optional_postamble.unwrap_or(RefType::Implied),
+            expression,
         },
     ))
 }
@@ -263,6 +292,12 @@ pub fn parse_tokens(i: &str) -> AsmResult<Vec<Token>> {
             parse_origin_token.context("origin"),
             parse_data_token.context("data directive"),
             parse_equ_token.context("equ directive"),
+            parse_include_token.context("include directive"),
+            parse_if_token.context("conditional directive"),
+            parse_else_or_end_if_token.context("end of conditional block"),
+            parse_macro_definition_token.context("macro definition"),
+            // Anything else that looks like an instruction might be a macro
+            parse_macro_invocation_token.context("macro invocation"),
         )),
         multispace0,
         eof,
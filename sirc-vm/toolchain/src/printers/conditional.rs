@@ -0,0 +1,39 @@
+use crate::printers::expression::print_operand_expression;
+use crate::types::conditional::{
+    Condition, IfToken, ELSE_TOKEN, END_IF_TOKEN, IF_DEFINED_TOKEN, IF_NOT_DEFINED_TOKEN, IF_TOKEN,
+};
+
+/// Prints the AST representation of an `.IF`, `.IFDEF` or `.IFNDEF` directive to a string
+///
+///```
+/// use toolchain::printers::conditional::print_if_token;
+/// use toolchain::types::conditional::{Condition, IfToken};
+/// use toolchain::types::expression::Expression;
+/// let printed = print_if_token(&IfToken {
+///    input_length: 0,
+///    condition: Condition::Expression(Expression::PlaceHolder(String::from("HAS_VIDEO"))),
+/// });
+/// assert_eq!(String::from(".IF $HAS_VIDEO"), printed);
+/// let printed = print_if_token(&IfToken {
+///    input_length: 0,
+///    condition: Condition::NotDefined(String::from("HAS_VIDEO")),
+/// });
+/// assert_eq!(String::from(".IFNDEF $HAS_VIDEO"), printed);
+/// ```
+pub fn print_if_token(if_token: &IfToken) -> String {
+    match &if_token.condition {
+        Condition::Expression(expression) => {
+            format!("{IF_TOKEN} {}", print_operand_expression(expression))
+        }
+        Condition::Defined(name) => format!("{IF_DEFINED_TOKEN} ${name}"),
+        Condition::NotDefined(name) => format!("{IF_NOT_DEFINED_TOKEN} ${name}"),
+    }
+}
+
+pub fn print_else_token() -> String {
+    String::from(ELSE_TOKEN)
+}
+
+pub fn print_end_if_token() -> String {
+    String::from(END_IF_TOKEN)
+}
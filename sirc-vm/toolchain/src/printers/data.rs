@@ -1,3 +1,4 @@
+use crate::printers::expression::print_operand_expression;
 use crate::printers::shared::{print_number_token, print_ref_token};
 use crate::types::data::{
     DataToken, DataType, EquToken, DB_TOKEN, DB_VALUE, DQ_TOKEN, DQ_VALUE, DW_TOKEN, DW_VALUE,
@@ -17,6 +18,7 @@ use crate::types::object::RefType;
 ///    value: DataType::SymbolRef(RefToken {
 ///        name: String::from("some_symbol"),
 ///        ref_type: RefType::FullAddress,
+///        expression: None,
 ///    })
 /// });
 /// assert_eq!(String::from(".DQ @some_symbol"), printed);
@@ -41,7 +43,7 @@ pub fn print_data_token(data_token: &DataToken) -> String {
                 ref_token.ref_type
             ),
         },
-        DataType::PlaceHolder(placeholder_name) => format!("${placeholder_name}"),
+        DataType::Expression(expression) => print_operand_expression(expression),
     };
 
     format!("{token_string} {value_string}")
@@ -52,13 +54,14 @@ pub fn print_data_token(data_token: &DataToken) -> String {
 ///```
 /// use toolchain::printers::data::print_equ_token;
 /// use toolchain::types::data::EquToken;
+/// use toolchain::types::expression::Expression;
 /// use toolchain::types::shared::{NumberToken, NumberType};
 /// let printed = print_equ_token(&EquToken {
 ///    placeholder_name: String::from("FOO"),
-///    number_token: NumberToken {
+///    value: Expression::Number(NumberToken {
 ///        number_type: NumberType::Hex,
 ///        value: 0xCAFE
-///    }
+///    })
 /// });
 /// assert_eq!(String::from(".EQU $FOO #0xCAFE"), printed);
 /// ```
@@ -66,6 +69,6 @@ pub fn print_equ_token(equ_token: &EquToken) -> String {
     format!(
         "{EQU_TOKEN} ${} {}",
         equ_token.placeholder_name,
-        print_number_token(&equ_token.number_token)
+        print_operand_expression(&equ_token.value)
     )
 }
@@ -0,0 +1,100 @@
+use crate::printers::shared::print_number_token;
+use crate::types::expression::{
+    BinaryOperator, Expression, Function, UnaryOperator, HIGH_FUNCTION_TOKEN, LOW_FUNCTION_TOKEN,
+};
+
+fn print_binary_operator(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Remainder => "%",
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+        BinaryOperator::And => "&",
+        BinaryOperator::Xor => "^",
+        BinaryOperator::Or => "|",
+    }
+}
+
+fn print_operand(expression: &Expression) -> String {
+    match expression {
+        Expression::Binary(..) => format!("({})", print_expression(expression)),
+        _ => print_expression(expression),
+    }
+}
+
+/// Prints the AST representation of an `Expression` to a string (without the `#` in front)
+///
+/// Nested operations are always put in brackets, so the precedence doesn't need to be known to
+/// read it.
+///
+///```
+/// use toolchain::printers::expression::print_expression;
+/// use toolchain::types::expression::{BinaryOperator, Expression};
+/// use toolchain::types::shared::{NumberToken, NumberType};
+/// let printed = print_expression(&Expression::Binary(
+///     BinaryOperator::Add,
+///     Box::new(Expression::PlaceHolder(String::from("TILE_BASE"))),
+///     Box::new(Expression::Binary(
+///         BinaryOperator::Multiply,
+///         Box::new(Expression::Number(NumberToken {
+///             value: 16,
+///             number_type: NumberType::Decimal,
+///         })),
+///         Box::new(Expression::SymbolRef(String::from("count"))),
+///     )),
+/// ));
+/// assert_eq!(String::from("$TILE_BASE + (16 * @count)"), printed);
+/// ```
+pub fn print_expression(expression: &Expression) -> String {
+    match expression {
+        Expression::Number(number_token) => print_number_token(number_token)
+            .trim_start_matches('#')
+            .to_owned(),
+        Expression::PlaceHolder(name) => format!("${name}"),
+        Expression::SymbolRef(name) => format!("@{name}"),
+        Expression::Unary(operator, operand) => {
+            let operator = match operator {
+                UnaryOperator::Negate => "-",
+                UnaryOperator::Not => "~",
+            };
+            format!("{operator}{}", print_operand(operand))
+        }
+        Expression::Binary(operator, left, right) => format!(
+            "{} {} {}",
+            print_operand(left),
+            print_binary_operator(*operator),
+            print_operand(right)
+        ),
+        Expression::Function(function, operand) => {
+            let function = match function {
+                Function::High => HIGH_FUNCTION_TOKEN,
+                Function::Low => LOW_FUNCTION_TOKEN,
+            };
+            format!("{function}({})", print_expression(operand))
+        }
+    }
+}
+
+/// Prints an `Expression` as it would be written as an operand (e.g. `#0x10`, `$FOO` or
+/// `#($FOO + 1)`)
+///
+///```
+/// use toolchain::printers::expression::print_operand_expression;
+/// use toolchain::types::expression::{Expression, Function};
+/// let printed = print_operand_expression(&Expression::Function(
+///     Function::High,
+///     Box::new(Expression::SymbolRef(String::from("label"))),
+/// ));
+/// assert_eq!(String::from("#HIGH(@label)"), printed);
+/// ```
+pub fn print_operand_expression(expression: &Expression) -> String {
+    match expression {
+        Expression::Number(number_token) => print_number_token(number_token),
+        Expression::PlaceHolder(name) => format!("${name}"),
+        Expression::Function(..) => format!("#{}", print_expression(expression)),
+        _ => format!("#({})", print_expression(expression)),
+    }
+}
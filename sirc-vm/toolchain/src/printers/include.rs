@@ -0,0 +1,41 @@
+use crate::types::include::{IncludeToken, SourceFileToken, INCLUDE_BINARY_TOKEN, INCLUDE_TOKEN};
+
+/// Prints the AST representation of an `.INCLUDE` directive to a string
+///
+///```
+/// use toolchain::printers::include::print_include_token;
+/// use toolchain::types::include::IncludeToken;
+/// let printed = print_include_token(&IncludeToken {
+///    input_length: 0,
+///    path: String::from("serial-handler.sasm"),
+/// });
+/// assert_eq!(String::from(".INCLUDE \"serial-handler.sasm\""), printed);
+/// ```
+pub fn print_include_token(include_token: &IncludeToken) -> String {
+    format!("{INCLUDE_TOKEN} \"{}\"", include_token.path)
+}
+
+/// Prints the AST representation of an `.INCBIN` directive to a string
+///
+///```
+/// use toolchain::printers::include::print_include_binary_token;
+/// use toolchain::types::include::IncludeToken;
+/// let printed = print_include_binary_token(&IncludeToken {
+///    input_length: 0,
+///    path: String::from("tiles.bin"),
+/// });
+/// assert_eq!(String::from(".INCBIN \"tiles.bin\""), printed);
+/// ```
+pub fn print_include_binary_token(include_token: &IncludeToken) -> String {
+    format!("{INCLUDE_BINARY_TOKEN} \"{}\"", include_token.path)
+}
+
+/// The markers around an included file can't be written in the source so they are printed as
+/// comments
+pub fn print_source_file_start_token(source_file_token: &SourceFileToken) -> String {
+    format!("; Start of [{}]", source_file_token.filename)
+}
+
+pub fn print_source_file_end_token() -> String {
+    String::from("; End of included file")
+}
@@ -0,0 +1,48 @@
+use crate::types::macros::{
+    MacroDefinitionToken, MacroInvocationToken, END_MACRO_TOKEN, MACRO_TOKEN,
+};
+
+/// Prints the AST representation of a `MacroDefinitionToken` to a string
+///
+///```
+/// use toolchain::printers::macros::print_macro_definition_token;
+/// use toolchain::types::macros::MacroDefinitionToken;
+/// let printed = print_macro_definition_token(&MacroDefinitionToken {
+///    input_length: 0,
+///    name: String::from("PUSH"),
+///    parameters: vec![String::from("reg")],
+///    body: String::from("\nSTOR -(#1, s), \\reg\n"),
+/// });
+/// assert_eq!(String::from(".MACRO PUSH reg\nSTOR -(#1, s), \\reg\n.ENDM"), printed);
+/// ```
+pub fn print_macro_definition_token(macro_token: &MacroDefinitionToken) -> String {
+    let parameters = if macro_token.parameters.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", macro_token.parameters.join(", "))
+    };
+    format!(
+        "{MACRO_TOKEN} {}{parameters}{}{END_MACRO_TOKEN}",
+        macro_token.name, macro_token.body
+    )
+}
+
+/// Prints the AST representation of a `MacroInvocationToken` to a string
+///
+///```
+/// use toolchain::printers::macros::print_macro_invocation_token;
+/// use toolchain::types::macros::MacroInvocationToken;
+/// let printed = print_macro_invocation_token(&MacroInvocationToken {
+///    input_length: 0,
+///    name: String::from("PUSH"),
+///    arguments: vec![String::from("r1")],
+/// });
+/// assert_eq!(String::from("PUSH r1"), printed);
+/// ```
+pub fn print_macro_invocation_token(macro_token: &MacroInvocationToken) -> String {
+    if macro_token.arguments.is_empty() {
+        macro_token.name.clone()
+    } else {
+        format!("{} {}", macro_token.name, macro_token.arguments.join(", "))
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+use sirc_vm::debug_adapter::{memory::format_memory_reference, types::SymbolTable};
+
+/// Where an object file ended up in the linked program
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinkedObject {
+    pub name: String,
+    /// The address of the first word of the object in the linked program
+    pub address: u32,
+    pub size_words: u32,
+}
+
+///
+/// Prints a plain text map of a linked program, with where each object was placed and the
+/// address of each symbol (sorted by address).
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use toolchain::printers::map::{print_link_map, LinkedObject};
+///
+/// let objects = vec![LinkedObject {
+///     name: "main.o".to_string(),
+///     address: 0x100,
+///     size_words: 0x20,
+/// }];
+/// let symbols = BTreeMap::from([("main".to_string(), 0x100), ("loop".to_string(), 0x104)]);
+/// assert_eq!(
+///     "Objects:\n0x000100-0x00011F main.o\n\nSymbols:\n0x000100 main\n0x000104 loop\n",
+///     print_link_map(&objects, &symbols)
+/// );
+/// ```
+///
+pub fn print_link_map(objects: &[LinkedObject], symbols: &SymbolTable) -> String {
+    let mut output = String::from("Objects:\n");
+    for object in objects {
+        // An empty object doesn't take up any space so there is no last address
+        let last_address = object.address + object.size_words.saturating_sub(1);
+        writeln!(
+            output,
+            "{}-{} {}",
+            format_memory_reference(object.address),
+            format_memory_reference(last_address),
+            object.name
+        )
+        .unwrap();
+    }
+
+    output.push_str("\nSymbols:\n");
+    let mut sorted_symbols: Vec<_> = symbols.iter().collect();
+    sorted_symbols.sort_by_key(|(name, address)| (**address, *name));
+    for (name, address) in sorted_symbols {
+        writeln!(output, "{} {name}", format_memory_reference(*address)).unwrap();
+    }
+    output
+}
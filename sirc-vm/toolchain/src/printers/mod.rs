@@ -1,2 +1,8 @@
+pub mod conditional;
 pub mod data;
+pub mod expression;
+pub mod include;
+pub mod macros;
+pub mod map;
+pub mod program;
 pub mod shared;
@@ -0,0 +1,323 @@
+//!
+//! Prints a linked program image back into assembly (see the `disassembler` binary).
+//!
+//! Instructions can't be told apart from data in an image, so only the words that can be reached
+//! by following the control flow from the entry points are disassembled. Everything else is
+//! printed as `.DW`/`.DQ` directives so that the output can be assembled back into the same image.
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use peripheral_cpu::coprocessors::processing_unit::definitions::{
+    ConditionFlags, Instruction, InstructionData, INSTRUCTION_SIZE_BYTES, INSTRUCTION_SIZE_WORDS,
+};
+use peripheral_cpu::coprocessors::processing_unit::disassembly::{
+    disassemble_instruction, is_documented_instruction,
+};
+use peripheral_cpu::coprocessors::processing_unit::encoding::decode_instruction;
+use sirc_vm::debug_adapter::memory::format_memory_reference;
+use sirc_vm::debug_adapter::symbols::{format_symbolised_address, nearest_symbol};
+use sirc_vm::debug_adapter::types::SymbolTable;
+
+const PROGRAM_COUNTER_INDEX: u8 = 3;
+/// 128 full addresses at the start of segment zero
+const VECTOR_TABLE_SIZE: usize = 128;
+/// Keeps the comments with the addresses lined up
+const INSTRUCTION_WIDTH: usize = 32;
+/// Runs of zeros at least this long (in 32 bit chunks) are skipped with an `.ORG`
+const MIN_SKIPPED_ZERO_CHUNKS: usize = 4;
+
+// Coprocessor calls that never fall through to the next instruction
+const EXIT_SIMULATION: u16 = 0x14FF;
+const RETURN_FROM_EXCEPTION: u16 = 0x1A00;
+const RESET: u16 = 0x1B00;
+
+enum ControlFlow {
+    /// Carries on to the next instruction
+    Continue,
+    /// Doesn't carry on to the next instruction, and the target isn't known (e.g. LJMP/RETS)
+    Stop,
+    /// PC relative branch (BRAN/BRSR), which returns to the next instruction if it is conditional
+    /// or a subroutine call
+    Branch { displacement: u16, returns: bool },
+}
+
+fn control_flow(instruction: &InstructionData) -> ControlFlow {
+    // Undocumented instructions would just fault
+    if !is_documented_instruction(instruction) {
+        return ControlFlow::Stop;
+    }
+    let stop_if_always = |condition_flag: ConditionFlags| {
+        if condition_flag == ConditionFlags::Always {
+            ControlFlow::Stop
+        } else {
+            ControlFlow::Continue
+        }
+    };
+
+    match instruction {
+        InstructionData::Immediate(data) => match data.op_code {
+            Instruction::CoprocessorCallImmediate
+                if matches!(data.value, EXIT_SIMULATION | RETURN_FROM_EXCEPTION | RESET) =>
+            {
+                stop_if_always(data.condition_flag)
+            }
+            Instruction::LoadEffectiveAddressFromIndirectImmediate
+            | Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate
+                if data.register == PROGRAM_COUNTER_INDEX
+                    && data.additional_flags == PROGRAM_COUNTER_INDEX =>
+            {
+                ControlFlow::Branch {
+                    displacement: data.value,
+                    returns: data.condition_flag != ConditionFlags::Always
+                        || data.op_code
+                            == Instruction::LoadEffectiveAddressAndLinkFromIndirectImmediate,
+                }
+            }
+            Instruction::LoadEffectiveAddressFromIndirectImmediate
+            | Instruction::LoadEffectiveAddressFromIndirectImmediatePreDecrement
+                if data.register == PROGRAM_COUNTER_INDEX =>
+            {
+                stop_if_always(data.condition_flag)
+            }
+            _ => ControlFlow::Continue,
+        },
+        InstructionData::Register(data) => match data.op_code {
+            Instruction::LoadEffectiveAddressFromIndirectRegister
+            | Instruction::LoadEffectiveAddressFromIndirectRegisterPreDecrement
+                if data.r1 == PROGRAM_COUNTER_INDEX =>
+            {
+                stop_if_always(data.condition_flag)
+            }
+            _ => ControlFlow::Continue,
+        },
+        InstructionData::ShortImmediate(_) => ControlFlow::Continue,
+    }
+}
+
+/// Branches only change the lower word of the PC, so they can't leave the segment
+#[allow(clippy::cast_possible_truncation)]
+fn branch_target(address: u32, displacement: u16) -> u32 {
+    (address & 0xFFFF_0000) | u32::from((address as u16).wrapping_add(displacement))
+}
+
+/// The image is made of 32 bit chunks (an instruction or a data directive each)
+fn chunks(program: &[u8]) -> Vec<[u8; 4]> {
+    program
+        .chunks(INSTRUCTION_SIZE_BYTES as usize)
+        .map(|chunk| {
+            let mut padded = [0x0; 4];
+            padded[..chunk.len()].copy_from_slice(chunk);
+            padded
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn chunk_address(segment_offset: u32, index: usize) -> u32 {
+    segment_offset + index as u32 * INSTRUCTION_SIZE_WORDS
+}
+
+///
+/// Finds where a program starts executing, which is the exception vectors if the program is at
+/// the start of memory (where the vector table is) or the first word of the program otherwise.
+///
+/// ```
+/// use toolchain::printers::program::vector_table_entry_points;
+///
+/// let program = [0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00];
+/// assert_eq!(vec![0x200], vector_table_entry_points(&program, 0x0));
+/// assert_eq!(vec![0x10000], vector_table_entry_points(&program, 0x10000));
+/// ```
+///
+pub fn vector_table_entry_points(program: &[u8], segment_offset: u32) -> Vec<u32> {
+    if segment_offset != 0 {
+        return vec![segment_offset];
+    }
+    chunks(program)
+        .into_iter()
+        .take(VECTOR_TABLE_SIZE)
+        .map(u32::from_be_bytes)
+        .filter(|vector| *vector != 0)
+        .collect()
+}
+
+///
+/// Follows the control flow from the entry points to find the addresses of all the instructions
+/// in a program that can be reached.
+///
+/// Indirect jumps (e.g. LJMP/RETS) can't be followed, so anything that is only reached that way
+/// needs its own entry point.
+///
+#[allow(clippy::cast_possible_truncation)]
+pub fn find_code(program: &[u8], segment_offset: u32, entry_points: &[u32]) -> BTreeSet<u32> {
+    let chunks = chunks(program);
+    let mut code = BTreeSet::new();
+    let mut pending = entry_points.to_vec();
+
+    while let Some(address) = pending.pop() {
+        let Some(offset) = address.checked_sub(segment_offset) else {
+            continue;
+        };
+        let index = (offset / INSTRUCTION_SIZE_WORDS) as usize;
+        if offset % INSTRUCTION_SIZE_WORDS != 0 || index >= chunks.len() || !code.insert(address) {
+            continue;
+        }
+
+        let next = address + INSTRUCTION_SIZE_WORDS;
+        match control_flow(&decode_instruction(chunks[index])) {
+            ControlFlow::Continue => pending.push(next),
+            ControlFlow::Stop => {}
+            ControlFlow::Branch {
+                displacement,
+                returns,
+            } => {
+                pending.push(branch_target(address, displacement));
+                if returns {
+                    pending.push(next);
+                }
+            }
+        }
+    }
+    code
+}
+
+fn print_labels(output: &mut String, labels: &BTreeMap<u32, Vec<&str>>, address: u32) {
+    for name in labels.get(&address).into_iter().flatten() {
+        writeln!(output, ":{name}").unwrap();
+    }
+}
+
+fn print_instruction(output: &mut String, symbols: &SymbolTable, address: u32, chunk: [u8; 4]) {
+    let instruction = decode_instruction(chunk);
+    let [a, b, c, d] = chunk;
+    let mut comment = format!(
+        "{} {:04X} {:04X}",
+        format_memory_reference(address),
+        u16::from_be_bytes([a, b]),
+        u16::from_be_bytes([c, d])
+    );
+    if let ControlFlow::Branch { displacement, .. } = control_flow(&instruction) {
+        let target = branch_target(address, displacement);
+        write!(
+            comment,
+            " -> {}",
+            format_symbolised_address(symbols, target)
+        )
+        .unwrap();
+    }
+    writeln!(
+        output,
+        "{:<INSTRUCTION_WIDTH$} ; {comment}",
+        disassemble_instruction(&instruction)
+    )
+    .unwrap();
+}
+
+fn print_data(output: &mut String, symbols: &SymbolTable, address: u32, chunk: [u8; 4]) {
+    let value = u32::from_be_bytes(chunk);
+    // .DW and .DB are also padded out to 32 bits by the assembler
+    let directive = if value > 0xFFFF {
+        format!(".DQ #0x{value:08X}")
+    } else {
+        format!(".DW #0x{value:04X}")
+    };
+    let mut comment = format_memory_reference(address);
+    // Full addresses (e.g. in the vector table) are usually pointers to labels
+    if let Some((name, 0)) = nearest_symbol(symbols, value) {
+        write!(comment, " -> {name}").unwrap();
+    }
+    writeln!(output, "{directive:<INSTRUCTION_WIDTH$} ; {comment}").unwrap();
+}
+
+///
+/// Prints a program image as assembly, with the instructions in `code` disassembled and the rest
+/// printed as data. Each line has a comment with its address (and encoding for instructions).
+///
+/// Labels are printed for the symbols that are in the program, and long runs of zeros are skipped
+/// with `.ORG` directives (relative to the segment offset, like in the original source).
+///
+/// ```
+/// use std::collections::{BTreeMap, BTreeSet};
+/// use toolchain::printers::program::print_program;
+///
+/// // LOAD r1, #0xCAFE / BRAN #0x0000 / some data
+/// let program = [
+///     0x1C, 0x72, 0xBF, 0x80, 0x60, 0xC0, 0x00, 0x30, 0x00, 0x00, 0x12, 0x34,
+/// ];
+/// let symbols = BTreeMap::from([("main".to_string(), 0x200), ("spin".to_string(), 0x202)]);
+/// let code = BTreeSet::from([0x200, 0x202]);
+/// assert_eq!(
+///     concat!(
+///         ":main\n",
+///         "LOAD r1, #0xCAFE                 ; 0x000200 1C72 BF80\n",
+///         ":spin\n",
+///         "BRAN #0x0000                     ; 0x000202 60C0 0030 -> spin\n",
+///         ".DW #0x1234                      ; 0x000204\n",
+///     ),
+///     print_program(&program, 0x200, &symbols, &code)
+/// );
+/// ```
+///
+pub fn print_program(
+    program: &[u8],
+    segment_offset: u32,
+    symbols: &SymbolTable,
+    code: &BTreeSet<u32>,
+) -> String {
+    let chunks = chunks(program);
+    let end_address = chunk_address(segment_offset, chunks.len());
+    let mut labels: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+    for (name, address) in symbols {
+        if (segment_offset..=end_address).contains(address) {
+            labels.entry(*address).or_default().push(name);
+        }
+    }
+
+    let mut output = String::new();
+    let mut skipped = false;
+    let mut index = 0;
+    while index < chunks.len() {
+        let address = chunk_address(segment_offset, index);
+        let zero_run = chunks[index..]
+            .iter()
+            .enumerate()
+            .take_while(|(run_index, chunk)| {
+                let run_address = chunk_address(segment_offset, index + run_index);
+                **chunk == [0x0; 4]
+                    && !code.contains(&run_address)
+                    && (*run_index == 0 || !labels.contains_key(&run_address))
+            })
+            .count();
+
+        if skipped && (zero_run < MIN_SKIPPED_ZERO_CHUNKS || labels.contains_key(&address)) {
+            writeln!(output, ".ORG 0x{:04X}", address - segment_offset).unwrap();
+            skipped = false;
+        }
+        print_labels(&mut output, &labels, address);
+
+        if zero_run >= MIN_SKIPPED_ZERO_CHUNKS {
+            skipped = true;
+            index += zero_run;
+            continue;
+        }
+        if code.contains(&address) {
+            print_instruction(&mut output, symbols, address, chunks[index]);
+        } else {
+            print_data(&mut output, symbols, address, chunks[index]);
+        }
+        index += 1;
+    }
+
+    // The program would end at the last thing printed, so the last chunk of a skipped run at the
+    // end is printed to keep the program the same size
+    if skipped {
+        let last_address = chunk_address(segment_offset, chunks.len() - 1);
+        writeln!(output, ".ORG 0x{:04X}", last_address - segment_offset).unwrap();
+        print_data(&mut output, symbols, last_address, [0x0; 4]);
+    }
+    // Labels can also point just past the end of the program
+    print_labels(&mut output, &labels, end_address);
+    output
+}
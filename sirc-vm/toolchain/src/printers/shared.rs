@@ -1,4 +1,11 @@
+use crate::printers::conditional::{print_else_token, print_end_if_token, print_if_token};
 use crate::printers::data::{print_data_token, print_equ_token};
+use crate::printers::expression::{print_expression, print_operand_expression};
+use crate::printers::include::{
+    print_include_binary_token, print_include_token, print_source_file_end_token,
+    print_source_file_start_token,
+};
+use crate::printers::macros::{print_macro_definition_token, print_macro_invocation_token};
 use crate::types::data::RefToken;
 use crate::types::object::RefType;
 use crate::types::shared::{
@@ -65,7 +72,8 @@ pub fn print_number_token(number_token: &NumberToken) -> String {
 /// use toolchain::types::shared::{NumberToken, NumberType};
 /// let printed = print_ref_token(&RefToken {
 ///    name: String::from("some_other_symbol"),
-///    ref_type: RefType::UpperWord
+///    ref_type: RefType::UpperWord,
+///    expression: None,
 /// });
 /// assert_eq!(String::from("@some_other_symbol.u"), printed);
 /// ```
@@ -77,7 +85,21 @@ pub fn print_ref_token(ref_token: &RefToken) -> String {
         _ => String::new(),
     };
 
-    format!("@{}{}", ref_token.name, optional_postamble)
+    let symbol = format!("@{}", ref_token.name);
+    ref_token.expression.as_ref().map_or_else(
+        || format!("{symbol}{optional_postamble}"),
+        |expression| {
+            // Keep the `@label + 2` form if the expression starts with the symbol (a postamble
+            // can't be used with an expression, see `parse_symbol_reference_`)
+            print_expression(expression)
+                .strip_prefix(&symbol)
+                .filter(|rest| optional_postamble.is_empty() && rest.starts_with(' '))
+                .map_or_else(
+                    || print_operand_expression(expression),
+                    |rest| format!("{symbol}{rest}"),
+                )
+        },
+    )
 }
 
 /// Prints an AST `Token` to a string
@@ -87,15 +109,16 @@ pub fn print_ref_token(ref_token: &RefToken) -> String {
 ///```
 /// use toolchain::printers::shared::print_token;
 /// use toolchain::types::data::{EquToken, RefToken};
+/// use toolchain::types::expression::Expression;
 /// use toolchain::types::object::RefType;
 /// use toolchain::types::shared::{NumberToken, NumberType, Token};
 /// let printed = print_token(&Token::Equ(
 ///    EquToken {
 ///        placeholder_name: String::from("BAR"),
-///        number_token: NumberToken {
+///        value: Expression::Number(NumberToken {
 ///            number_type: NumberType::Decimal,
 ///            value: 1234
-///        }
+///        })
 ///    }
 /// ));
 /// assert_eq!(String::from(".EQU $BAR #1234"), printed);
@@ -108,6 +131,17 @@ pub fn print_token(token: &Token) -> String {
         Token::Origin(_) => todo!(),
         Token::Data(data_token) => print_data_token(data_token),
         Token::Equ(equ_token) => print_equ_token(equ_token),
+        Token::MacroDefinition(macro_token) => print_macro_definition_token(macro_token),
+        Token::MacroInvocation(macro_token) => print_macro_invocation_token(macro_token),
+        Token::Include(include_token) => print_include_token(include_token),
+        Token::IncludeBinary(include_token) => print_include_binary_token(include_token),
+        Token::SourceFileStart(source_file_token) => {
+            print_source_file_start_token(source_file_token)
+        }
+        Token::SourceFileEnd => print_source_file_end_token(),
+        Token::If(if_token) => print_if_token(if_token),
+        Token::Else(_) => print_else_token(),
+        Token::EndIf(_) => print_end_if_token(),
     }
 }
 
@@ -116,25 +150,26 @@ pub fn print_token(token: &Token) -> String {
 ///```
 /// use toolchain::printers::shared::print_tokens;
 /// use toolchain::types::data::{EquToken, RefToken};
+/// use toolchain::types::expression::Expression;
 /// use toolchain::types::object::RefType;
 /// use toolchain::types::shared::{NumberToken, NumberType, Token};
 /// let printed = print_tokens(&vec![
 ///    Token::Equ(
 ///        EquToken {
 ///            placeholder_name: String::from("FOO"),
-///            number_token: NumberToken {
+///            value: Expression::Number(NumberToken {
 ///                number_type: NumberType::Decimal,
 ///                value: 1234
-///            }
+///            })
 ///        }
 ///    ),
 ///    Token::Equ(
 ///        EquToken {
 ///            placeholder_name: String::from("BAR"),
-///            number_token: NumberToken {
+///            value: Expression::Number(NumberToken {
 ///                number_type: NumberType::Hex,
 ///                value: 0xCAFE
-///            }
+///            })
 ///        }
 ///    ),
 /// ]);
@@ -0,0 +1,34 @@
+use serde::Serialize;
+
+use crate::types::expression::Expression;
+
+pub const IF_TOKEN: &str = ".IF";
+pub const IF_DEFINED_TOKEN: &str = ".IFDEF";
+pub const IF_NOT_DEFINED_TOKEN: &str = ".IFNDEF";
+pub const ELSE_TOKEN: &str = ".ELSE";
+pub const END_IF_TOKEN: &str = ".ENDIF";
+
+#[derive(Debug, Clone, Serialize)]
+pub enum Condition {
+    /// The block is assembled if the expression isn't zero (e.g. `.IF $HAS_VIDEO`)
+    Expression(Expression),
+    /// The block is assembled if the placeholder is defined (e.g. `.IFDEF $HAS_VIDEO`)
+    Defined(String),
+    /// The block is assembled if the placeholder isn't defined (e.g. `.IFNDEF $HAS_VIDEO`)
+    NotDefined(String),
+}
+
+/// An `.IF`, `.IFDEF` or `.IFNDEF` directive
+#[derive(Debug, Clone, Serialize)]
+pub struct IfToken {
+    /// The length of the parser input at the time of parsing, used to work out where the parser is in the file
+    pub input_length: usize,
+    pub condition: Condition,
+}
+
+/// An `.ELSE` or `.ENDIF` directive
+#[derive(Debug, Clone, Serialize)]
+pub struct ConditionalToken {
+    /// The length of the parser input at the time of parsing, used to work out where the parser is in the file
+    pub input_length: usize,
+}
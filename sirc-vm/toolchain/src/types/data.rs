@@ -1,3 +1,4 @@
+use crate::types::expression::Expression;
 use crate::types::object::RefType;
 use crate::types::shared::NumberToken;
 use serde::Serialize;
@@ -14,15 +15,21 @@ pub const DQ_VALUE: u8 = 4;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct RefToken {
+    /// The first symbol in the expression, if there is one
     pub name: String,
     pub ref_type: RefType,
+    /// Used instead of the address of the symbol when it is set (e.g. `@label + 2`), and is
+    /// evaluated by the linker. It is never set along with a suffix like `.u` (HIGH/LOW are part
+    /// of the expression instead)
+    pub expression: Option<Expression>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub enum DataType {
     Value(NumberToken),
     SymbolRef(RefToken),
-    PlaceHolder(String),
+    /// A constant expression with placeholders in it, which is evaluated when the object is built
+    Expression(Expression),
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -34,5 +41,5 @@ pub struct DataToken {
 #[derive(Debug, Clone, Serialize)]
 pub struct EquToken {
     pub placeholder_name: String,
-    pub number_token: NumberToken,
+    pub value: Expression,
 }
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::shared::{NumberToken, NumberType};
+
+pub const HIGH_FUNCTION_TOKEN: &str = "HIGH";
+pub const LOW_FUNCTION_TOKEN: &str = "LOW";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BinaryOperator {
+    Multiply,
+    Divide,
+    Remainder,
+    Add,
+    Subtract,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Xor,
+    Or,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Function {
+    /// The upper 16 bits of a value (e.g. the segment of an address)
+    High,
+    /// The lower 16 bits of a value
+    Low,
+}
+
+///
+/// A constant expression, e.g. `#($TILE_BASE + 16*4)` or `@label + 2`
+///
+/// Expressions that only use numbers and placeholders are evaluated by the assembler. Expressions
+/// that use symbols are stored in the object file and evaluated by the linker, where `@symbol` is
+/// the (word) address of the symbol.
+///
+/// Values are 32 bit and wrap around on overflow. Division and remainder are signed, so that
+/// negative numbers work as expected.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum Expression {
+    Number(NumberToken),
+    PlaceHolder(String),
+    SymbolRef(String),
+    Unary(UnaryOperator, Box<Self>),
+    Binary(BinaryOperator, Box<Self>, Box<Self>),
+    Function(Function, Box<Self>),
+}
+
+impl Expression {
+    /// The names of the symbols in the expression, in the order that they are used
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Self::Number(_) | Self::PlaceHolder(_) => vec![],
+            Self::SymbolRef(name) => vec![name.as_str()],
+            Self::Unary(_, operand) | Self::Function(_, operand) => operand.symbols(),
+            Self::Binary(_, left, right) => {
+                let mut symbols = left.symbols();
+                symbols.extend(right.symbols());
+                symbols
+            }
+        }
+    }
+
+    /// If the expression has any placeholders in it
+    pub fn has_placeholders(&self) -> bool {
+        match self {
+            Self::Number(_) | Self::SymbolRef(_) => false,
+            Self::PlaceHolder(_) => true,
+            Self::Unary(_, operand) | Self::Function(_, operand) => operand.has_placeholders(),
+            Self::Binary(_, left, right) => left.has_placeholders() || right.has_placeholders(),
+        }
+    }
+
+    /// Renames each symbol in the expression (e.g. to make the labels in a macro local)
+    #[must_use]
+    pub fn map_symbols(self, rename: &impl Fn(String) -> String) -> Self {
+        match self {
+            Self::SymbolRef(name) => Self::SymbolRef(rename(name)),
+            Self::Unary(operator, operand) => {
+                Self::Unary(operator, Box::new(operand.map_symbols(rename)))
+            }
+            Self::Function(function, operand) => {
+                Self::Function(function, Box::new(operand.map_symbols(rename)))
+            }
+            Self::Binary(operator, left, right) => Self::Binary(
+                operator,
+                Box::new(left.map_symbols(rename)),
+                Box::new(right.map_symbols(rename)),
+            ),
+            expression => expression,
+        }
+    }
+
+    /// Replaces the placeholders in the expression with their values, so that it only needs the
+    /// symbols to be evaluated
+    pub fn resolve_placeholders(
+        self,
+        placeholder_value: &impl Fn(&str) -> Option<u32>,
+    ) -> Result<Self, String> {
+        Ok(match self {
+            Self::PlaceHolder(name) => Self::Number(NumberToken {
+                value: placeholder_value(&name).ok_or_else(|| missing_placeholder(&name))?,
+                number_type: NumberType::Hex,
+            }),
+            Self::Unary(operator, operand) => Self::Unary(
+                operator,
+                Box::new(operand.resolve_placeholders(placeholder_value)?),
+            ),
+            Self::Function(function, operand) => Self::Function(
+                function,
+                Box::new(operand.resolve_placeholders(placeholder_value)?),
+            ),
+            Self::Binary(operator, left, right) => Self::Binary(
+                operator,
+                Box::new(left.resolve_placeholders(placeholder_value)?),
+                Box::new(right.resolve_placeholders(placeholder_value)?),
+            ),
+            expression => expression,
+        })
+    }
+
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    pub fn evaluate(
+        &self,
+        placeholder_value: &impl Fn(&str) -> Option<u32>,
+        symbol_value: &impl Fn(&str) -> Option<u32>,
+    ) -> Result<u32, String> {
+        let evaluate = |expression: &Self| expression.evaluate(placeholder_value, symbol_value);
+        match self {
+            Self::Number(number_token) => Ok(number_token.value),
+            Self::PlaceHolder(name) => placeholder_value(name).ok_or_else(|| missing_placeholder(name)),
+            Self::SymbolRef(name) => symbol_value(name).ok_or_else(|| {
+                format!("Cannot find symbol [{name}] in symbol definitions. Check you have a label with that name defined in your program.")
+            }),
+            Self::Unary(operator, operand) => {
+                let value = evaluate(operand)?;
+                Ok(match operator {
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Not => !value,
+                })
+            }
+            Self::Function(function, operand) => {
+                let value = evaluate(operand)?;
+                Ok(match function {
+                    Function::High => value >> 16,
+                    Function::Low => value & 0xFFFF,
+                })
+            }
+            Self::Binary(operator, left, right) => {
+                let left = evaluate(left)?;
+                let right = evaluate(right)?;
+                if matches!(operator, BinaryOperator::Divide | BinaryOperator::Remainder)
+                    && right == 0
+                {
+                    return Err(String::from("Division by zero in constant expression"));
+                }
+                Ok(match operator {
+                    BinaryOperator::Multiply => left.wrapping_mul(right),
+                    BinaryOperator::Divide => (left as i32).wrapping_div(right as i32) as u32,
+                    BinaryOperator::Remainder => (left as i32).wrapping_rem(right as i32) as u32,
+                    BinaryOperator::Add => left.wrapping_add(right),
+                    BinaryOperator::Subtract => left.wrapping_sub(right),
+                    BinaryOperator::ShiftLeft => left.wrapping_shl(right),
+                    BinaryOperator::ShiftRight => left.wrapping_shr(right),
+                    BinaryOperator::And => left & right,
+                    BinaryOperator::Xor => left ^ right,
+                    BinaryOperator::Or => left | right,
+                })
+            }
+        }
+    }
+}
+
+fn missing_placeholder(name: &str) -> String {
+    format!("Could not find a value for placeholder name [{name}]. Make sure it is defined with the .EQU directive.")
+}
@@ -0,0 +1,23 @@
+use serde::Serialize;
+
+pub const INCLUDE_TOKEN: &str = ".INCLUDE";
+pub const INCLUDE_BINARY_TOKEN: &str = ".INCBIN";
+
+/// An `.INCLUDE` or `.INCBIN` directive
+#[derive(Debug, Clone, Serialize)]
+pub struct IncludeToken {
+    /// The length of the parser input at the time of parsing, used to work out where the parser is in the file
+    pub input_length: usize,
+    /// As written in the source, which is searched for relative to the file with the directive and
+    /// then the include paths
+    pub path: String,
+}
+
+/// Marks where the tokens from an included file start (they end at the next `SourceFileEnd` at the
+/// same level), so that the debug info can point at the right file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceFileToken {
+    pub filename: String,
+    /// The contents of the file, which the `input_length` of the tokens in it are relative to
+    pub input: String,
+}
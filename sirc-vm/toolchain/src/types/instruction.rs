@@ -1,4 +1,5 @@
 use crate::types::data::RefToken;
+use crate::types::expression::Expression;
 use peripheral_cpu::coprocessors::processing_unit::definitions::InstructionData;
 
 #[derive(Debug, Clone)]
@@ -7,5 +8,6 @@ pub struct InstructionToken {
     pub input_length: usize,
     pub instruction: InstructionData,
     pub symbol_ref: Option<RefToken>,
-    pub placeholder_name: Option<String>,
+    /// A constant expression with placeholders in it, which is evaluated when the object is built
+    pub expression: Option<Expression>,
 }
@@ -0,0 +1,26 @@
+use serde::Serialize;
+
+pub const MACRO_TOKEN: &str = ".MACRO";
+pub const END_MACRO_TOKEN: &str = ".ENDM";
+
+/// Marks where a parameter is used in the body of a macro (e.g. `\reg`)
+pub const MACRO_PARAMETER_PREFIX: char = '\\';
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MacroDefinitionToken {
+    /// The length of the parser input at the time of parsing, used to work out where the parser is in the file
+    pub input_length: usize,
+    pub name: String,
+    pub parameters: Vec<String>,
+    /// The source between the `.MACRO` line and `.ENDM`, which is parsed when the macro is used
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MacroInvocationToken {
+    /// The length of the parser input at the time of parsing, used to work out where the parser is in the file
+    pub input_length: usize,
+    pub name: String,
+    /// The source text of each argument, which replaces the matching parameter in the body
+    pub arguments: Vec<String>,
+}
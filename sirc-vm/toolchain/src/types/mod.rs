@@ -1,4 +1,8 @@
+pub mod conditional;
 pub mod data;
+pub mod expression;
+pub mod include;
 pub mod instruction;
+pub mod macros;
 pub mod object;
 pub mod shared;
@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 
 use sirc_vm::debug_adapter::types::{ObjectDebugInfo, ObjectDebugInfoMap, ProgramDebugInfo};
 
+use crate::types::expression::Expression;
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 pub enum RefType {
     /// A 16-bit signed offset from the current PC
@@ -31,6 +33,10 @@ pub struct SymbolRef {
     pub offset: u32,
     pub ref_type: RefType,
     pub data_only: bool,
+    /// Evaluated by the linker to get the target instead of the address of the symbol (e.g.
+    /// `@label + 2`). It only has symbols left in it since the placeholders are resolved by the
+    /// assembler.
+    pub expression: Option<Expression>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
@@ -40,7 +46,8 @@ pub struct ObjectDefinition {
     // The offset in these definitions is the location of the ref in the program
     pub symbol_refs: Vec<SymbolRef>,
     pub program: Vec<u8>,
-    pub debug_info: Option<ObjectDebugInfo>,
+    /// One for each source file that the object was assembled from (the first is the main file)
+    pub debug_info: Vec<ObjectDebugInfo>,
 }
 
 pub fn merge_object_definitions(
@@ -70,34 +77,77 @@ pub fn merge_object_definitions(
             .symbol_refs
             .iter()
             .map(|s| SymbolRef {
-                name: s.name.clone(),
                 offset: prev_offset + s.offset,
-                ..*s
+                ..s.clone()
             })
             .collect();
 
         output.symbols.extend(offset_symbols);
         output.symbol_refs.extend(offset_symbol_refs);
         output.program.extend(object_definition.program.clone());
-        if let Some(debug_info) = &object_definition.debug_info {
-            let prev_offset_words = prev_offset / INSTRUCTION_SIZE_WORDS;
-            let offset_mapping = debug_info
+        let prev_offset_words = prev_offset / INSTRUCTION_SIZE_WORDS;
+        for debug_info in &object_definition.debug_info {
+            let offset_mapping: BTreeMap<_, _> = debug_info
                 .program_to_input_offset_mapping
                 .iter()
                 .map(|(k, v)| (k + prev_offset_words, *v))
                 .collect();
-            println!(
-                "object: {} prev_offset: {prev_offset}",
-                debug_info.original_filename
-            );
+            // Files are keyed by their first instruction, since the instructions from an included
+            // file can be in the middle of the file that includes it
+            let Some(first_position) = offset_mapping.keys().next().copied() else {
+                continue;
+            };
             let offset_debug_info = ObjectDebugInfo {
                 original_filename: debug_info.original_filename.clone(),
                 original_input: debug_info.original_input.clone(),
                 program_to_input_offset_mapping: offset_mapping,
                 checksum: debug_info.checksum.clone(),
             };
-            debug_info_map.insert(prev_offset_words, offset_debug_info);
+            debug_info_map.insert(first_position, offset_debug_info);
         }
     }
-    (output, ProgramDebugInfo { debug_info_map })
+    // Positions in the debug info are in words, like the PC
+    let symbols = output
+        .symbols
+        .iter()
+        .map(|s| (s.name.clone(), s.offset / INSTRUCTION_SIZE_WORDS))
+        .collect();
+    (
+        output,
+        ProgramDebugInfo {
+            debug_info_map,
+            symbols,
+        },
+    )
+}
+
+///
+/// Moves the positions in the debug info by the segment offset that the program is linked at, so
+/// that they match the PC when the program is running.
+///
+pub fn relocate_debug_info(debug_info: ProgramDebugInfo, segment_offset: u32) -> ProgramDebugInfo {
+    ProgramDebugInfo {
+        debug_info_map: debug_info
+            .debug_info_map
+            .into_iter()
+            .map(|(position, object_debug_info)| {
+                (
+                    position + segment_offset,
+                    ObjectDebugInfo {
+                        program_to_input_offset_mapping: object_debug_info
+                            .program_to_input_offset_mapping
+                            .into_iter()
+                            .map(|(k, v)| (k + segment_offset, v))
+                            .collect(),
+                        ..object_debug_info
+                    },
+                )
+            })
+            .collect(),
+        symbols: debug_info
+            .symbols
+            .into_iter()
+            .map(|(name, position)| (name, position + segment_offset))
+            .collect(),
+    }
 }
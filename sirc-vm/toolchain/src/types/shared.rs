@@ -1,19 +1,22 @@
+use crate::types::conditional::{ConditionalToken, IfToken};
 use crate::types::data::{DataToken, EquToken};
+use crate::types::include::{IncludeToken, SourceFileToken};
 use crate::types::instruction::InstructionToken;
-use serde::Serialize;
+use crate::types::macros::{MacroDefinitionToken, MacroInvocationToken};
+use serde::{Deserialize, Serialize};
 
 pub const REF_TOKEN_OFFSET_SUFFIX: &str = ".r";
 pub const REF_TOKEN_LOWER_WORD_SUFFIX: &str = ".l";
 pub const REF_TOKEN_UPPER_WORD_SUFFIX: &str = ".u";
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum NumberType {
     Hex,
     Decimal,
     Binary,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct NumberToken {
     pub value: u32,
     pub number_type: NumberType,
@@ -37,4 +40,13 @@ pub enum Token {
     Origin(OriginToken),
     Data(DataToken),
     Equ(EquToken),
+    MacroDefinition(MacroDefinitionToken),
+    MacroInvocation(MacroInvocationToken),
+    Include(IncludeToken),
+    IncludeBinary(IncludeToken),
+    SourceFileStart(SourceFileToken),
+    SourceFileEnd,
+    If(IfToken),
+    Else(ConditionalToken),
+    EndIf(ConditionalToken),
 }
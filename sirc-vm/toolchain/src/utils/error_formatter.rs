@@ -105,3 +105,10 @@ pub fn format_line_with_error(
     }
     "Unknown Line".to_string()
 }
+
+/// The line (starting from 1) that a token is on, from its position in the same format as
+/// `InstructionToken::input_length`
+pub fn token_line(input: &str, input_length: usize) -> usize {
+    let position = input.len() - input_length;
+    input[..position].matches('\n').count() + 1
+}
@@ -0,0 +1,219 @@
+use std::fs::write;
+use std::path::Path;
+
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+};
+use peripheral_cpu::coprocessors::processing_unit::definitions::{
+    ImmediateInstructionData, InstructionData,
+};
+use peripheral_cpu::coprocessors::processing_unit::encoding::decode_instruction;
+use tempfile::tempdir;
+use toolchain::data::object::build_object;
+use toolchain::parsers::conditional::{parse_define, resolve_conditionals, ConditionalError};
+use toolchain::parsers::include::read_source_file;
+use toolchain::parsers::macros::expand_macros;
+use toolchain::parsers::preprocess::{preprocess, PreprocessError};
+use toolchain::parsers::shared::parse_tokens;
+use toolchain::types::shared::Token;
+
+// The .IF $HAS_VIDEO condition is only evaluated if the placeholder is defined
+static PARSER_INPUT: &str = r"
+.IFNDEF $VARIANT
+.EQU $VARIANT #1
+.ENDIF
+
+.IFDEF $HAS_MATHS
+.MACRO MULTIPLY
+LOAD r1, #0xAA
+.ENDM
+.ELSE
+.MACRO MULTIPLY
+LOAD r1, #0xBB
+.ENDM
+.ENDIF
+
+:main
+MULTIPLY
+.IF #($VARIANT & 2)
+LOAD r2, #0x22
+.IF #($VARIANT - 2)
+LOAD r3, #0x33
+.ELSE
+LOAD r3, #0x32
+.ENDIF
+.ENDIF
+
+.IFDEF $HAS_VIDEO
+.IF $HAS_VIDEO
+LOAD r4, #0x44
+.ENDIF
+.ENDIF
+";
+
+fn parse(input: &str) -> Result<Vec<Token>, String> {
+    final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(parse_tokens)(input)
+        .map_err(|error| error.to_string())
+}
+
+/// The tokens from the input, with the defines before them
+fn parse_with_defines(input: &str, defines: &[&str]) -> Vec<Token> {
+    let tokens = parse(input).unwrap_or_else(|error| panic!("Error parsing file:\n{error}"));
+    let defines = defines
+        .iter()
+        .map(|define| parse_define(define).unwrap_or_else(|error| panic!("{error}")));
+    defines.chain(tokens).collect()
+}
+
+fn resolve(input: &str, defines: &[&str]) -> Result<Vec<Token>, ConditionalError> {
+    resolve_conditionals(parse_with_defines(input, defines))
+}
+
+fn preprocess_file(main_file: &Path, defines: &[&str]) -> Result<Vec<Token>, PreprocessError> {
+    let input = read_source_file(main_file).unwrap();
+    preprocess(parse_with_defines(&input, defines), main_file, &input, &[])
+}
+
+/// The immediate values of the instructions, which are different for each line
+fn assemble(input: &str, defines: &[&str]) -> Vec<u16> {
+    let tokens = preprocess(
+        parse_with_defines(input, defines),
+        Path::new("UNIT_TEST"),
+        input,
+        &[],
+    )
+    .unwrap_or_else(|error| panic!("{error}"));
+    immediate_values(tokens, input)
+}
+
+fn immediate_values(tokens: Vec<Token>, input: &str) -> Vec<u16> {
+    let object = build_object(tokens, "UNIT_TEST".to_string(), input.to_string());
+    object
+        .program
+        .chunks(4)
+        // The end of the program is padded with zeros
+        .filter(|chunk| chunk != &[0x0; 4])
+        .map(
+            |chunk| match decode_instruction(chunk.try_into().unwrap()) {
+                InstructionData::Immediate(ImmediateInstructionData { value, .. }) => value,
+                instruction => panic!("Expected an immediate instruction but got {instruction:?}"),
+            },
+        )
+        .collect()
+}
+
+#[test]
+fn test_conditional_blocks() {
+    assert_eq!(vec![0xBB], assemble(PARSER_INPUT, &[]));
+    assert_eq!(vec![0xAA], assemble(PARSER_INPUT, &["HAS_MATHS=0"]));
+    assert_eq!(
+        vec![0xBB, 0x22, 0x32],
+        assemble(PARSER_INPUT, &["VARIANT=2"])
+    );
+    assert_eq!(
+        vec![0xAA, 0x22, 0x33],
+        assemble(PARSER_INPUT, &["VARIANT=0b11", "HAS_MATHS"])
+    );
+}
+
+#[test]
+fn test_conditional_errors() {
+    let error = |input: &str| {
+        resolve(input, &[])
+            .expect_err("Expected the conditional block to fail")
+            .message
+    };
+
+    assert!(error(".IF #1\nNOOP\n").contains("needs to be closed with [.ENDIF]"));
+    assert!(error("NOOP\n.ENDIF\n").contains("Found [.ENDIF] without a matching [.IF]"));
+    assert!(error(".ELSE\n").contains("Found [.ELSE] without a matching [.IF]"));
+    assert!(error(".IF #1\n.ELSE\n.ELSE\n.ENDIF\n").contains("more than one [.ELSE]"));
+    assert!(error(".IF $MISSING\n.ENDIF\n")
+        .contains("Could not find a value for placeholder name [MISSING]"));
+
+    let error = parse(".IF #(@main + 1)\n.ENDIF\n").expect_err("Expected parsing to fail");
+    assert!(
+        error.contains("Conditions can't use symbols (found [main])"),
+        "Unexpected error:\n{error}"
+    );
+
+    assert!(parse_define("HAS VIDEO").is_err());
+    assert!(parse_define("HAS_VIDEO=yes").is_err());
+}
+
+#[test]
+fn test_conditional_blocks_in_included_files() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+    write(
+        &main_file,
+        ".INCLUDE \"header.sasm\"\n.INCLUDE \"header.sasm\"\nLOAD r2, $HEADER_VALUE\n",
+    )
+    .unwrap();
+    write(
+        dir.path().join("header.sasm"),
+        ".IFNDEF $HEADER_VALUE\n.EQU $HEADER_VALUE #2\nLOAD r1, #1\n.ENDIF\n",
+    )
+    .unwrap();
+
+    // The include guard stops the second copy from being assembled
+    let tokens = preprocess_file(&main_file, &[]).unwrap_or_else(|error| panic!("{error}"));
+    let input = read_source_file(&main_file).unwrap();
+    assert_eq!(vec![0x1, 0x2], immediate_values(tokens, &input));
+}
+
+#[test]
+fn test_files_in_inactive_blocks_are_not_included() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+    write(
+        &main_file,
+        ".IFDEF $HAS_VIDEO\n.INCLUDE \"video.sasm\"\n.INCBIN \"tiles.bin\"\n.ENDIF\nLOAD r1, #1\n",
+    )
+    .unwrap();
+
+    // Neither file exists, which is only a problem if the block is assembled
+    let tokens = preprocess_file(&main_file, &[]).unwrap_or_else(|error| panic!("{error}"));
+    let input = read_source_file(&main_file).unwrap();
+    assert_eq!(vec![0x1], immediate_values(tokens, &input));
+
+    let error =
+        preprocess_file(&main_file, &["HAS_VIDEO"]).expect_err("Expected the include to fail");
+    assert!(error.message.contains("main.sasm:2:"), "{error}");
+    assert!(
+        error.message.contains("Could not find [video.sasm]"),
+        "{error}"
+    );
+}
+
+#[test]
+fn test_conditional_block_errors_in_included_files() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+    write(&main_file, ".INCLUDE \"unclosed.sasm\"\n.ENDIF\n").unwrap();
+    write(dir.path().join("unclosed.sasm"), "NOOP\n.IF #1\nNOOP\n").unwrap();
+
+    // A block can't be closed in a different file to the one that it was opened in
+    let error =
+        preprocess_file(&main_file, &[]).expect_err("Expected the conditional block to fail");
+    assert!(error.message.contains("unclosed.sasm:2:"), "{error}");
+    assert!(
+        error.message.contains("needs to be closed with [.ENDIF]"),
+        "{error}"
+    );
+
+    write(&main_file, "NOOP\n.IF #1\n").unwrap();
+    let error =
+        preprocess_file(&main_file, &[]).expect_err("Expected the conditional block to fail");
+    assert!(error.message.contains("main.sasm:2:"), "{error}");
+}
+
+#[test]
+fn test_conditional_inside_macro_is_rejected() {
+    let tokens = resolve(".MACRO BAD\n.IF #1\nNOOP\n.ENDIF\n.ENDM\nBAD\n", &[]).unwrap();
+    let error = expand_macros(tokens).expect_err("Expected the macro to be rejected");
+    assert!(error
+        .message
+        .contains("Conditional directives can't be used inside a macro"));
+}
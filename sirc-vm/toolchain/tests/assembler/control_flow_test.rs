@@ -308,3 +308,22 @@ fn auto_update_forms_reject_aliased_register_writes() {
     assert!(parse_result("STOR -(#0, a), al\n").is_err());
     assert!(parse_result("STOR -(r3, a), ah\n").is_err());
 }
+
+#[test]
+fn instructions_without_operands_allow_trailing_comments() {
+    for instruction in ["RETS", "NOOP", "RETE"] {
+        let tokens = parse_result(&format!("{instruction} ; Back to the caller\n"))
+            .unwrap_or_else(|error| panic!("Error parsing [{instruction}]:\n{error}"));
+        let [Token::Instruction(with_comment), Token::Comment(comment)] = tokens.as_slice() else {
+            panic!("Expected an instruction and a comment, got {tokens:?}");
+        };
+        assert_eq!(
+            parse_instruction(&format!("{instruction}\n")).instruction,
+            with_comment.instruction
+        );
+        assert_eq!("Back to the caller", comment.trim());
+    }
+
+    // Anything else after the instruction is still an error
+    assert!(parse_result("RETS r1\n").is_err());
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+};
+use peripheral_cpu::coprocessors::processing_unit::definitions::{
+    ImmediateInstructionData, InstructionData,
+};
+use peripheral_cpu::coprocessors::processing_unit::encoding::decode_instruction;
+use toolchain::data::object::build_object;
+use toolchain::parsers::shared::parse_tokens;
+use toolchain::types::object::{ObjectDefinition, RefType};
+use toolchain::types::shared::Token;
+
+static PARSER_INPUT: &str = r"
+.EQU $TILE_BASE #0x100
+.EQU $TILE_COUNT #($TILE_BASE / 0x10 + 1)
+
+:main
+LOAD r1, #($TILE_BASE + 16*4)
+LOAD r2, #(-2 * 3)
+LOAD r3, $TILE_COUNT
+LOAD r4, #(1 << 4 | 0b11 & ~1)
+LOAD r5, #LOW(0x12345)
+LOAD r6, #HIGH(@data)
+LOAD r7, #(HIGH(@data) + 2)
+BRAN @main + 4
+.DQ @data + 3
+.DW #(~0 & 0xFF)
+.DQ #($TILE_COUNT << 2)
+:data
+.DQ #((@end - @main) / 2)
+:end
+";
+
+fn parse(input: &str) -> Result<Vec<Token>, String> {
+    final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(parse_tokens)(input)
+        .map_err(|error| error.to_string())
+}
+
+fn assemble(input: &str) -> ObjectDefinition {
+    let tokens = parse(input).unwrap_or_else(|error| panic!("Error parsing file:\n{error}"));
+    build_object(tokens, "UNIT_TEST".to_string(), input.to_string())
+}
+
+fn chunk(object: &ObjectDefinition, index: usize) -> [u8; 4] {
+    object.program[index * 4..index * 4 + 4]
+        .try_into()
+        .expect("Chunk should be four bytes")
+}
+
+fn immediate_value(object: &ObjectDefinition, index: usize) -> u16 {
+    match decode_instruction(chunk(object, index)) {
+        InstructionData::Immediate(ImmediateInstructionData { value, .. }) => value,
+        instruction => panic!("Expected an immediate instruction but got {instruction:?}"),
+    }
+}
+
+#[test]
+fn test_expressions_are_evaluated_by_the_assembler() {
+    let object = assemble(PARSER_INPUT);
+
+    assert_eq!(0x140, immediate_value(&object, 0));
+    assert_eq!(0xFFFA, immediate_value(&object, 1));
+    assert_eq!(0x11, immediate_value(&object, 2));
+    assert_eq!(0x12, immediate_value(&object, 3));
+    assert_eq!(0x2345, immediate_value(&object, 4));
+    assert_eq!([0x0, 0x0, 0x0, 0xFF], chunk(&object, 9));
+    assert_eq!([0x0, 0x0, 0x0, 0x44], chunk(&object, 10));
+}
+
+#[test]
+fn test_expressions_with_symbols_are_left_for_the_linker() {
+    let object = assemble(PARSER_INPUT);
+
+    let symbol_refs: Vec<(&str, u32, RefType)> = object
+        .symbol_refs
+        .iter()
+        .map(|symbol_ref| {
+            (
+                symbol_ref.name.as_str(),
+                symbol_ref.offset,
+                symbol_ref.ref_type,
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            ("data", 0x14, RefType::LowerWord),
+            ("data", 0x18, RefType::LowerWord),
+            ("main", 0x1C, RefType::Offset),
+            ("data", 0x20, RefType::FullAddress),
+            ("end", 0x2C, RefType::FullAddress),
+        ],
+        symbol_refs
+    );
+
+    // Evaluated the same way as the linker (where symbols are word addresses)
+    let symbols: HashMap<&str, u32> = object
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), 0x1_0000 + symbol.offset / 2))
+        .collect();
+    let values: Vec<u32> = object
+        .symbol_refs
+        .iter()
+        .map(|symbol_ref| {
+            symbol_ref
+                .expression
+                .as_ref()
+                .expect("Expected an expression")
+                .evaluate(&|_| None, &|name| symbols.get(name).copied())
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(vec![0x1, 0x3, 0x1_0004, 0x1_0019, 0xC], values);
+}
+
+#[test]
+fn test_expression_errors() {
+    let error = parse("LOAD r1, #(0xFFFF + 1)\n").expect_err("Expected parsing to fail");
+    assert!(
+        error.contains("can only be up to 16 bits"),
+        "Unexpected error:\n{error}"
+    );
+
+    let error = parse("LOAD r1, #(1 / 0)\n").expect_err("Expected parsing to fail");
+    assert!(
+        error.contains("Division by zero"),
+        "Unexpected error:\n{error}"
+    );
+
+    let error = parse(".EQU $FOO #(@main + 1)\n").expect_err("Expected parsing to fail");
+    assert!(
+        error.contains("can't use symbols (found [main])"),
+        "Unexpected error:\n{error}"
+    );
+
+    let error = parse("LOAD r1, #(1 +)\n").expect_err("Expected parsing to fail");
+    assert!(error.contains("operand"), "Unexpected error:\n{error}");
+
+    // Could mean the upper word plus two, or the upper word of the address plus two
+    let error = parse("LOAD r1, @data.u + 2\n").expect_err("Expected parsing to fail");
+    assert!(
+        error.contains("The [.u] suffix can't be used in an expression"),
+        "Unexpected error:\n{error}"
+    );
+    assert!(
+        error.contains("#(HIGH(@data) + 2)"),
+        "Unexpected error:\n{error}"
+    );
+}
+
+#[test]
+#[should_panic(expected = "Could not find a value for placeholder name [MISSING]")]
+fn test_expression_with_missing_placeholder() {
+    assemble("LOAD r1, #($MISSING + 1)\n");
+}
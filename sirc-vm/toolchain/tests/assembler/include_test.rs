@@ -0,0 +1,189 @@
+use std::fs::{create_dir, write};
+use std::path::{Path, PathBuf};
+
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+};
+use tempfile::tempdir;
+use toolchain::data::object::build_object;
+use toolchain::parsers::include::{read_source_file, resolve_includes, IncludeError};
+use toolchain::parsers::preprocess::preprocess;
+use toolchain::parsers::shared::parse_tokens;
+use toolchain::types::object::ObjectDefinition;
+use toolchain::types::shared::Token;
+
+static MAIN_INPUT: &str = r#"
+.INCLUDE "macros.sasm"
+
+:main
+LOAD r1, #1
+.INCLUDE "handler.sasm"
+PUSH r1
+BRAN @main
+
+:tiles
+.INCBIN "tiles.bin"
+"#;
+
+static MACROS_INPUT: &str = r"
+.MACRO PUSH reg
+STOR -(#1, s), \reg
+.ENDM
+";
+
+static HANDLER_INPUT: &str = r"
+:handler
+LOAD r2, #2
+RETS
+";
+
+fn parse(input: &str) -> Vec<Token> {
+    match final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(parse_tokens)(
+        input,
+    ) {
+        Ok(tokens) => tokens,
+        Err(error) => panic!("Error parsing file:\n{error}"),
+    }
+}
+
+fn include(main_file: &Path, include_paths: &[PathBuf]) -> Result<Vec<Token>, IncludeError> {
+    let input = read_source_file(main_file).unwrap();
+    resolve_includes(parse(&input), main_file, &input, include_paths)
+}
+
+fn assemble(main_file: &Path, include_paths: &[PathBuf]) -> ObjectDefinition {
+    let input = read_source_file(main_file).unwrap();
+    let tokens = preprocess(parse(&input), main_file, &input, include_paths)
+        .unwrap_or_else(|error| panic!("{error}"));
+    build_object(tokens, main_file.display().to_string(), input)
+}
+
+#[test]
+fn test_include_uses_include_paths() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+    let common_dir = dir.path().join("common");
+    create_dir(&common_dir).unwrap();
+    write(&main_file, MAIN_INPUT).unwrap();
+    write(common_dir.join("macros.sasm"), MACROS_INPUT).unwrap();
+    write(common_dir.join("handler.sasm"), HANDLER_INPUT).unwrap();
+    write(dir.path().join("tiles.bin"), [0x12, 0x34, 0x56, 0x78, 0x9A]).unwrap();
+
+    let object = assemble(&main_file, &[common_dir]);
+
+    let symbols: Vec<(&str, u32)> = object
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.offset))
+        .collect();
+    assert_eq!(
+        vec![("main", 0x0), ("handler", 0x4), ("tiles", 0x14)],
+        symbols
+    );
+    // The binary file is padded to a multiple of four bytes
+    assert_eq!(
+        &[0x12, 0x34, 0x56, 0x78, 0x9A, 0x00, 0x00, 0x00],
+        &object.program[0x14..0x1C]
+    );
+}
+
+#[test]
+fn test_include_debug_info_points_at_each_file() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+    write(&main_file, MAIN_INPUT).unwrap();
+    write(dir.path().join("macros.sasm"), MACROS_INPUT).unwrap();
+    write(dir.path().join("handler.sasm"), HANDLER_INPUT).unwrap();
+    write(dir.path().join("tiles.bin"), [0xFF; 8]).unwrap();
+
+    let object = assemble(&main_file, &[]);
+
+    // The macros file doesn't have any instructions of its own (they are at the call site)
+    let files: Vec<(String, Vec<(u32, &str)>)> = object
+        .debug_info
+        .iter()
+        .map(|debug_info| {
+            (
+                Path::new(&debug_info.original_filename)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+                debug_info
+                    .program_to_input_offset_mapping
+                    .iter()
+                    .map(|(program_position, input_position)| {
+                        (
+                            *program_position,
+                            debug_info.original_input[*input_position..]
+                                .lines()
+                                .next()
+                                .unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                String::from("main.sasm"),
+                vec![(0x0, "LOAD r1, #1"), (0x6, "PUSH r1"), (0x8, "BRAN @main")]
+            ),
+            (String::from("macros.sasm"), vec![]),
+            (
+                String::from("handler.sasm"),
+                vec![(0x2, "LOAD r2, #2"), (0x4, "RETS")]
+            ),
+        ],
+        files
+    );
+}
+
+#[test]
+fn test_include_errors() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+
+    write(&main_file, "NOOP\n.INCLUDE \"missing.sasm\"\n").unwrap();
+    let error = include(&main_file, &[]).expect_err("Expected the include to fail");
+    assert!(error.message.contains("main.sasm:2:"), "{error}");
+    assert!(
+        error.message.contains("Could not find [missing.sasm]"),
+        "{error}"
+    );
+
+    write(&main_file, ".INCLUDE \"other.sasm\"\n").unwrap();
+    write(dir.path().join("other.sasm"), ".INCLUDE \"main.sasm\"\n").unwrap();
+    let error = include(&main_file, &[]).expect_err("Expected the include to fail");
+    assert!(error.message.contains("includes itself"), "{error}");
+
+    write(dir.path().join("other.sasm"), "LOAD r1, q9\n").unwrap();
+    let error = include(&main_file, &[]).expect_err("Expected the include to fail");
+    assert!(error.message.contains("Error parsing file"), "{error}");
+    assert!(error.message.contains("other.sasm"), "{error}");
+}
+
+#[test]
+fn test_include_inside_macro_is_rejected() {
+    let dir = tempdir().unwrap();
+    let main_file = dir.path().join("main.sasm");
+    write(
+        &main_file,
+        ".MACRO BAD\n.INCLUDE \"other.sasm\"\n.ENDM\nBAD\n",
+    )
+    .unwrap();
+
+    let input = read_source_file(&main_file).unwrap();
+    let error = preprocess(parse(&input), &main_file, &input, &[])
+        .expect_err("Expected the macro to be rejected");
+    assert!(error.message.contains("main.sasm:4:"), "{error}");
+    assert!(
+        error
+            .message
+            .contains("Files can't be included from inside a macro"),
+        "{error}"
+    );
+}
@@ -0,0 +1,177 @@
+use std::path::Path;
+
+use insta::assert_snapshot;
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+};
+use pretty_hex::{config_hex, HexConfig};
+use toolchain::data::object::build_object;
+use toolchain::parsers::macros::{expand_macros, MacroError};
+use toolchain::parsers::preprocess::preprocess;
+use toolchain::parsers::shared::parse_tokens;
+use toolchain::types::object::ObjectDefinition;
+use toolchain::types::shared::Token;
+
+static PARSER_INPUT: &str = r"
+.MACRO PUSH reg
+STOR -(#1, s), \reg
+.ENDM
+
+.MACRO POP reg
+LOAD \reg, (#0, s)+
+.ENDM
+
+; Macros can use other macros
+.MACRO SWAP first, second
+PUSH \first
+PUSH \second
+POP \first
+POP \second
+.ENDM
+
+; The loop label is local to each use of the macro
+.MACRO DELAY count
+LOAD r7, \count
+:loop
+SUBI r7, #1
+BRAN|!= @loop
+.ENDM
+
+:main
+SWAP r1, r2
+DELAY #0x10
+DELAY #0x20 ; Comments are allowed after macros
+BRAN @main
+";
+
+fn parse(input: &str) -> Vec<Token> {
+    match final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(parse_tokens)(
+        input,
+    ) {
+        Ok(tokens) => tokens,
+        Err(error) => panic!("Error parsing file:\n{error}"),
+    }
+}
+
+fn assemble(input: &str) -> ObjectDefinition {
+    let tokens = preprocess(parse(input), Path::new("UNIT_TEST"), input, &[])
+        .unwrap_or_else(|error| panic!("{error}"));
+    build_object(tokens, "UNIT_TEST".to_string(), input.to_string())
+}
+
+fn expansion_error(input: &str) -> MacroError {
+    expand_macros(parse(input)).expect_err("Expected the macro to be rejected")
+}
+
+#[test]
+fn test_assembler_macros() {
+    let hex_config = HexConfig {
+        width: 4,
+        ..HexConfig::default()
+    };
+
+    let object = assemble(PARSER_INPUT);
+    assert_snapshot!(config_hex(&object.program.as_slice(), hex_config));
+}
+
+#[test]
+fn test_macro_labels_are_local_to_each_use() {
+    let object = assemble(PARSER_INPUT);
+
+    let symbols: Vec<(&str, u32)> = object
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.offset))
+        .collect();
+    assert_eq!(
+        vec![
+            ("main", 0x0),
+            ("__DELAY_6_loop", 0x14),
+            ("__DELAY_7_loop", 0x20)
+        ],
+        symbols
+    );
+    let symbol_refs: Vec<&str> = object
+        .symbol_refs
+        .iter()
+        .map(|symbol_ref| symbol_ref.name.as_str())
+        .collect();
+    assert_eq!(
+        vec!["__DELAY_6_loop", "__DELAY_7_loop", "main"],
+        symbol_refs
+    );
+}
+
+#[test]
+fn test_macro_debug_info_points_at_call_site() {
+    let object = assemble(PARSER_INPUT);
+    let lines: Vec<&str> = object.debug_info[0]
+        .program_to_input_offset_mapping
+        .values()
+        .map(|position| PARSER_INPUT[*position..].lines().next().unwrap_or_default())
+        .collect();
+    assert_eq!(
+        vec![
+            "SWAP r1, r2",
+            "SWAP r1, r2",
+            "SWAP r1, r2",
+            "SWAP r1, r2",
+            "DELAY #0x10",
+            "DELAY #0x10",
+            "DELAY #0x10",
+            "DELAY #0x20 ; Comments are allowed after macros",
+            "DELAY #0x20 ; Comments are allowed after macros",
+            "DELAY #0x20 ; Comments are allowed after macros",
+            "BRAN @main",
+        ],
+        lines
+    );
+}
+
+#[test]
+fn test_macro_errors() {
+    let input = "NOOP\nPUSH r1\n";
+    let error = expansion_error(input);
+    assert_eq!("PUSH r1\n", &input[input.len() - error.input_length..]);
+    assert!(error
+        .message
+        .contains("Unknown instruction or macro [PUSH]"));
+
+    assert!(
+        expansion_error(".MACRO PUSH reg\nSTOR -(#1, s), \\reg\n.ENDM\nPUSH\n")
+            .message
+            .contains("takes 1 argument(s) but was given 0")
+    );
+    assert!(
+        expansion_error(".MACRO PUSH reg\nSTOR -(#1, s), \\register\n.ENDM\nPUSH r1\n")
+            .message
+            .contains("doesn't have a parameter called [register]")
+    );
+    assert!(
+        expansion_error(".MACRO A\nNOOP\n.ENDM\n.MACRO A\nNOOP\n.ENDM\n")
+            .message
+            .contains("defined more than once")
+    );
+    assert!(expansion_error(".MACRO LOOP\nLOOP\n.ENDM\nLOOP\n")
+        .message
+        .contains("nested more than 64 levels deep"));
+    assert!(
+        expansion_error(".MACRO BAD reg\nLOAD \\reg, #1\n.ENDM\nBAD q9\n")
+            .message
+            .contains("Could not parse the [BAD] macro with arguments [q9]")
+    );
+}
+
+#[test]
+fn test_unterminated_macro_is_rejected() {
+    let result = final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
+        parse_tokens,
+    )(".MACRO PUSH reg\nSTOR -(#1, s), \\reg\n");
+
+    let error_string = result.expect_err("Expected parsing to fail").to_string();
+    assert!(
+        error_string.contains("end of macro (.ENDM)"),
+        "Expected error to mention .ENDM, got:\n{error_string}"
+    );
+}
@@ -1,5 +1,9 @@
 pub mod addressing_mode_test;
 pub mod arithmetic_immediate_test;
 pub mod arithmetic_register_test;
+pub mod conditional_test;
 pub mod control_flow_test;
 pub mod coprocessor_test;
+pub mod expression_test;
+pub mod include_test;
+pub mod macro_test;
@@ -0,0 +1,134 @@
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+};
+use peripheral_cpu::coprocessors::processing_unit::definitions::InstructionData;
+use peripheral_cpu::coprocessors::processing_unit::disassembly::disassemble_instruction;
+use peripheral_cpu::coprocessors::processing_unit::encoding::decode_instruction;
+use toolchain::parsers::instruction::parse_instruction_token;
+use toolchain::types::shared::Token;
+
+// Each line is already in the format that the disassembler prints
+static CANONICAL_INSTRUCTIONS: &[&str] = &[
+    "NOOP",
+    "NOOP|NV",
+    "ADDI r1, #0xCAFE",
+    "SBCI[N]|>= r7, #0x0001",
+    "CMPI|== ah, #0x0000",
+    "TSXI[S] pl, #0xFFFF",
+    "ADDI r2, #0x12, LSL #3",
+    "ORRI r2, #0x00, RTR r4",
+    "SUBI[N]|<< sr, #0xFF, NUL #0",
+    "SHFT r3, ASR #15",
+    "SHFT|!= r3, LSR r1",
+    "ADDR r1, r3",
+    "ADCR|CS r1, r2, r3",
+    "XORR[N] r4, r5, ASL #2",
+    "TSAR|HI r4, r5, r6, RTL sl",
+    "LOAD r1, #0x1234",
+    "LOAD|LO r1, r2",
+    "LOAD r1, (#0x0004, a)",
+    "LOAD r1, (#0xFFFF, s)+",
+    "LOAD r1, (r2, l)",
+    "LOAD|OS r1, (r2, a)+, LSL #1",
+    "STOR (#0x0004, a), r1",
+    "STOR -(#0x0001, s), r7",
+    "STOR (r2, l), r1",
+    "STOR|OC -(r2, s), r1, LSR #2",
+    "BRAN #0x0010",
+    "BRAN|<= #0xFFFC",
+    "BRSR #0x0004",
+    "RETS",
+    "RETS|==",
+    "LJMP a",
+    "LJMP s, #0x0002",
+    "LJMP a, r1",
+    "LJSR a",
+    "LJSR l, #0x0010",
+    "LJSR a, r1",
+    "LJSR (#0x0001, s)+",
+    "LJSR (r1, s)+",
+    "LDEA s, (#0x0010, a)",
+    "LDEA a, -(#0x0002, s)",
+    "LDEA a, (r1, s)",
+    "LDEA a, -(r1, s)",
+    "LDEL a, (#0x0010, s)",
+    "LDEL a, (#0x0001, s)+",
+    "LDEL a, (r1, s)",
+    "LDEL a, (r1, s)+",
+    "COPI #0x1234",
+    "COPR r1",
+    "EXCP #5",
+    "EXCP|NC #255",
+    "WAIT",
+    "RETE",
+    "RSET",
+    "ETFR #2",
+    "ETFR r7, #2",
+    "ETFR a, #2",
+    "ETTR #3",
+    "ETTR #3, r7",
+    "ETTR #3, a",
+    "DMAR a, #7",
+    "DMAW l, #-3",
+    "DMAT a, l, #16",
+    "MULU",
+    "MULS",
+    "DIVU",
+    "DIVS",
+];
+
+fn parse_instruction(source: &str) -> Result<InstructionData, String> {
+    // Some instructions (e.g. NOOP) need the line ending to be parsed
+    let line = format!("{source}\n");
+    let parsed = final_parser::<&str, Token, ErrorTree<&str>, ErrorTree<Location>>(
+        parse_instruction_token,
+    )(line.as_str());
+    match parsed {
+        Ok(Token::Instruction(instruction_token)) => Ok(instruction_token.instruction),
+        Ok(token) => Err(format!(
+            "[{source}] was not parsed as an instruction: {token:?}"
+        )),
+        Err(error) => Err(format!("[{source}] could not be parsed: {error}")),
+    }
+}
+
+#[test]
+fn test_disassembly_matches_source() {
+    for source in CANONICAL_INSTRUCTIONS {
+        let instruction = parse_instruction(source).unwrap();
+
+        assert_eq!(*source, disassemble_instruction(&instruction));
+    }
+}
+
+#[test]
+fn test_disassembly_round_trip_fuzz() {
+    // Simple LCG so that the test is deterministic without pulling in a random number crate
+    let mut seed: u32 = 0x1234_5678;
+    let mut next_raw_instruction = || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        seed
+    };
+
+    for _ in 0..20_000 {
+        let raw_instruction = next_raw_instruction();
+        let instruction = decode_instruction(raw_instruction.to_be_bytes());
+        let disassembled = disassemble_instruction(&instruction);
+
+        if disassembled.starts_with(".DQ") {
+            assert_eq!(format!(".DQ #0x{raw_instruction:08X}"), disassembled);
+            continue;
+        }
+
+        // The assembler rejects some combinations that the CPU can still execute (e.g. loading
+        // into a register that aliases the address register being incremented) so only
+        // instructions that the assembler accepts can be checked.
+        if let Ok(reassembled) = parse_instruction(&disassembled) {
+            assert_eq!(
+                instruction, reassembled,
+                "[{disassembled}] did not reassemble to the same instruction (0x{raw_instruction:08X})"
+            );
+        }
+    }
+}
@@ -1 +1,3 @@
+pub mod disassembly;
+pub mod program;
 pub mod round_trip;
@@ -0,0 +1,97 @@
+use insta::assert_snapshot;
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+};
+use peripheral_cpu::coprocessors::processing_unit::definitions::INSTRUCTION_SIZE_WORDS;
+use sirc_vm::debug_adapter::types::SymbolTable;
+use toolchain::data::object::build_object;
+use toolchain::parsers::shared::parse_tokens;
+use toolchain::printers::program::{find_code, print_program, vector_table_entry_points};
+use toolchain::types::object::ObjectDefinition;
+use toolchain::types::shared::Token;
+
+static PARSER_INPUT: &str = r"
+.ORG 0x0000
+.DQ #0x00000200
+
+.ORG 0x0200
+:main
+LOAD r1, #0x0003
+:loop
+SUBI r1, #0x0001
+BRAN|!= #0xFFFE
+BRSR #0x0008
+COPI #0x14FF
+
+:data
+.DW #0xCAFE
+.DQ #0xDEADBEEF
+
+:subroutine
+RETS
+
+; Can't be reached
+LOAD r2, #0x1234
+";
+
+// Ends in a run of zeros long enough to be skipped
+static ZERO_PADDED_INPUT: &str = r"
+.ORG 0x0000
+.DQ #0x00000200
+
+.ORG 0x0200
+:main
+COPI #0x14FF
+:buffer
+.DQ #0
+.DQ #0
+.DQ #0
+.DQ #0
+.DQ #0
+:buffer_end
+";
+
+fn assemble(input: &str) -> ObjectDefinition {
+    let tokens = match final_parser::<&str, Vec<Token>, ErrorTree<&str>, ErrorTree<Location>>(
+        parse_tokens,
+    )(input)
+    {
+        Ok(tokens) => tokens,
+        Err(error) => panic!("Error parsing file:\n{error}"),
+    };
+    build_object(tokens, "UNIT_TEST".to_string(), input.to_string())
+}
+
+fn disassemble(object: &ObjectDefinition) -> String {
+    let symbols: SymbolTable = object
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.clone(), symbol.offset / INSTRUCTION_SIZE_WORDS))
+        .collect();
+    let entry_points = vector_table_entry_points(&object.program, 0x0);
+    let code = find_code(&object.program, 0x0, &entry_points);
+    print_program(&object.program, 0x0, &symbols, &code)
+}
+
+#[test]
+fn test_disassembling_program() {
+    assert_snapshot!(disassemble(&assemble(PARSER_INPUT)));
+}
+
+#[test]
+fn test_disassembling_zero_padded_program() {
+    assert_snapshot!(disassemble(&assemble(ZERO_PADDED_INPUT)));
+}
+
+#[test]
+fn test_disassembled_program_assembles_to_the_same_program() {
+    for input in [PARSER_INPUT, ZERO_PADDED_INPUT] {
+        let object = assemble(input);
+        let disassembled = disassemble(&object);
+        let reassembled = assemble(&disassembled);
+
+        assert_eq!(object.program, reassembled.program);
+        assert_eq!(object.symbols, reassembled.symbols);
+    }
+}
@@ -30,6 +30,25 @@ static PARSER_INPUT: &str = r"
 .DW #0b1111_11
 .DW #0b1111_0011
 .DW #0b1011100111101010
+
+; Expression Tests
+.EQU $TILE_BASE                 #($SOME_PLACEHOLDER + 16*4)
+.EQU $TILE_COUNT                #(($TILE_BASE - 0x10) >> 2)
+.DQ @some_label + 2
+.DW #HIGH(@some_label)
+.DQ #(~$TILE_BASE & 0xFF)
+
+; Conditional Tests
+.IFNDEF $HAS_VIDEO
+.EQU $HAS_VIDEO #0
+.ENDIF
+.IF #($TILE_BASE & 0x10)
+.DW #1
+.ELSE
+.IFDEF $HAS_VIDEO
+.DW $HAS_VIDEO
+.ENDIF
+.ENDIF
 ";
 
 #[test]
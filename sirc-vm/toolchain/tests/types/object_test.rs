@@ -1,8 +1,9 @@
 use std::collections::BTreeMap;
 
-use sirc_vm::debug_adapter::types::ObjectDebugInfo;
+use sirc_vm::debug_adapter::types::{ObjectDebugInfo, ProgramDebugInfo};
 use toolchain::types::object::{
-    merge_object_definitions, ObjectDefinition, RefType, SymbolDefinition, SymbolRef,
+    merge_object_definitions, relocate_debug_info, ObjectDefinition, RefType, SymbolDefinition,
+    SymbolRef,
 };
 
 #[test]
@@ -24,18 +25,20 @@ fn test_merge_object_definition_single_definition_untouched() {
                 offset: 7,
                 ref_type: RefType::LowerWord,
                 data_only: false,
+                expression: None,
             },
             SymbolRef {
                 name: "second_first".to_string(),
                 offset: 0,
                 ref_type: RefType::LowerWord,
                 data_only: true,
+                expression: None,
             },
         ],
         program: vec![
             0xF, 0xF, 0xE, 0xE, 0xD, 0xD, 0xC, 0xC, 0xB, 0xB, 0xA, 0xA, 0x9, 0x9, 0x8, 0x8,
         ],
-        debug_info: None,
+        debug_info: vec![],
     };
 
     let (merged, merged_debug_info) = merge_object_definitions(std::slice::from_ref(&first_def));
@@ -63,23 +66,25 @@ fn test_merge_object_definitions_counts() {
                 offset: 7,
                 ref_type: RefType::LowerWord,
                 data_only: false,
+                expression: None,
             },
             SymbolRef {
                 name: "second_first".to_string(),
                 offset: 0,
                 ref_type: RefType::LowerWord,
                 data_only: true,
+                expression: None,
             },
         ],
         program: vec![
             0xF, 0xF, 0xE, 0xE, 0xD, 0xD, 0xC, 0xC, 0xB, 0xB, 0xA, 0xA, 0x9, 0x9, 0x8, 0x8,
         ],
-        debug_info: Some(ObjectDebugInfo {
+        debug_info: vec![ObjectDebugInfo {
             original_filename: "UNIT_TEST_FIRST_DEF".to_string(),
             original_input: "some original input".to_string(),
             program_to_input_offset_mapping: BTreeMap::from([(1, 2), (3, 4)]),
             checksum: "ABCD".to_string(),
-        }),
+        }],
     };
     let second_def = ObjectDefinition {
         symbols: vec![
@@ -98,23 +103,25 @@ fn test_merge_object_definitions_counts() {
                 offset: 7,
                 ref_type: RefType::LowerWord,
                 data_only: false,
+                expression: None,
             },
             SymbolRef {
                 name: "first_first".to_string(),
                 offset: 0,
                 ref_type: RefType::LowerWord,
                 data_only: true,
+                expression: None,
             },
         ],
         program: vec![
             0x0, 0x0, 0x1, 0x1, 0x2, 0x2, 0x3, 0x3, 0x4, 0x4, 0x5, 0x5, 0x6, 0x6, 0x7, 0x7,
         ],
-        debug_info: Some(ObjectDebugInfo {
+        debug_info: vec![ObjectDebugInfo {
             original_filename: "UNIT_TEST_SECOND_DEF".to_string(),
             original_input: "some original input".to_string(),
             program_to_input_offset_mapping: BTreeMap::from([(6, 7), (8, 9)]),
             checksum: "ABCD".to_string(),
-        }),
+        }],
     };
     let (merged, merged_debug_info) = merge_object_definitions(&[first_def, second_def]);
 
@@ -122,4 +129,76 @@ fn test_merge_object_definitions_counts() {
     assert_eq!(4, merged.symbol_refs.len());
     assert_eq!(32, merged.program.len());
     assert_eq!(2, merged_debug_info.debug_info_map.keys().len());
+    assert_eq!(4, merged_debug_info.symbols.len());
+    // Symbol offsets are in bytes but the debug info is in words
+    assert_eq!(Some(&7), merged_debug_info.symbols.get("first_second"));
+    assert_eq!(Some(&15), merged_debug_info.symbols.get("second_second"));
+}
+
+#[test]
+fn test_merge_object_definitions_with_included_files() {
+    let main_def = ObjectDefinition {
+        program: vec![0x0; 8],
+        ..ObjectDefinition::default()
+    };
+    let def_with_include = ObjectDefinition {
+        program: vec![0x0; 16],
+        debug_info: vec![
+            ObjectDebugInfo {
+                original_filename: "MAIN".to_string(),
+                program_to_input_offset_mapping: BTreeMap::from([(0, 0), (6, 20)]),
+                ..ObjectDebugInfo::default()
+            },
+            // Included files without any instructions are skipped
+            ObjectDebugInfo {
+                original_filename: "MACROS".to_string(),
+                ..ObjectDebugInfo::default()
+            },
+            ObjectDebugInfo {
+                original_filename: "INCLUDED".to_string(),
+                program_to_input_offset_mapping: BTreeMap::from([(2, 0), (4, 10)]),
+                ..ObjectDebugInfo::default()
+            },
+        ],
+        ..ObjectDefinition::default()
+    };
+
+    let (_, merged_debug_info) = merge_object_definitions(&[main_def, def_with_include]);
+
+    // Each file is keyed by its first instruction
+    let files: Vec<(u32, &str)> = merged_debug_info
+        .debug_info_map
+        .iter()
+        .map(|(position, debug_info)| (*position, debug_info.original_filename.as_str()))
+        .collect();
+    assert_eq!(vec![(4, "MAIN"), (6, "INCLUDED")], files);
+    assert_eq!(
+        BTreeMap::from([(6, 0), (8, 10)]),
+        merged_debug_info.debug_info_map[&6].program_to_input_offset_mapping
+    );
+}
+
+#[test]
+fn test_relocate_debug_info() {
+    let debug_info = ProgramDebugInfo {
+        debug_info_map: BTreeMap::from([(
+            0x10,
+            ObjectDebugInfo {
+                original_filename: "UNIT_TEST".to_string(),
+                original_input: "some original input".to_string(),
+                program_to_input_offset_mapping: BTreeMap::from([(0x10, 0), (0x12, 5)]),
+                checksum: "ABCD".to_string(),
+            },
+        )]),
+        symbols: BTreeMap::from([("main".to_string(), 0x10)]),
+    };
+
+    let relocated = relocate_debug_info(debug_info, 0x100);
+
+    let object_debug_info = relocated.debug_info_map.get(&0x110).unwrap();
+    assert_eq!(
+        BTreeMap::from([(0x110, 0), (0x112, 5)]),
+        object_debug_info.program_to_input_offset_mapping
+    );
+    assert_eq!(Some(&0x110), relocated.symbols.get("main"));
 }
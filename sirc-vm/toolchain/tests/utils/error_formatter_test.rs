@@ -111,7 +111,24 @@ fn test_no_input() {
         expected ".DW" at line 2, column 1, or
         expected ".DQ" at line 2, column 1, or
       in section "equ directive" at line 2, column 1,
-      expected ".EQU" at line 2, column 1
+      expected ".EQU" at line 2, column 1, or
+      in section "include directive" at line 2, column 1,
+      one of:
+        expected ".INCLUDE" at line 2, column 1, or
+        expected ".INCBIN" at line 2, column 1, or
+      in section "conditional directive" at line 2, column 1,
+      one of:
+        expected ".IFNDEF" at line 2, column 1, or
+        expected ".IFDEF" at line 2, column 1, or
+        expected ".IF" at line 2, column 1, or
+      in section "end of conditional block" at line 2, column 1,
+      one of:
+        expected ".ELSE" at line 2, column 1, or
+        expected ".ENDIF" at line 2, column 1, or
+      in section "macro definition" at line 2, column 1,
+      expected ".MACRO" at line 2, column 1, or
+      in section "macro invocation" at line 2, column 1,
+      expected an ascii alphanumeric character at line 2, column 1
     "#);
 }
 